@@ -0,0 +1,298 @@
+//! Benchmarks for the lexer and the parser, on a few kinds of source text
+//! that stress them differently. Each benchmark sets the size of its input,
+//! so the results are in MB/s as well as time per iteration; the lexer
+//! benchmarks' names say how many tokens their input has.
+//!
+//! These use the unstable test crate, so they need nightly:
+//!
+//!     cargo +nightly bench --features unstable
+//!
+//! With `--features unstable,simd`, the lexer uses its vectorized fast
+//! paths; compare the two to see what they're worth.
+//!
+//! There's no emitter yet to benchmark alongside them.
+
+#![feature(test)]
+
+extern crate test;
+
+use client::ast_arena::Arena;
+use client::lexer::Lexer;
+use client::parallel::parse_script_parallel;
+use client::parser::Parser;
+use client::parser_generated::{reduce, DefaultHandler, EntryPoint, StackValue, SyntaxOnlyHandler, TerminalId, TABLES};
+use client::parser_runtime::{NonterminalId, ReduceStack, SourceLocation, TokenStream};
+use test::Bencher;
+
+const SOURCE: &str = "
+    function fib(n) {
+        if (n < 2) {
+            return n;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+    var total = 0, names = ['a', 'b', 'c'];
+    for (let i = 0; i < 10; i++) {
+        total += fib(i) * (i % 3 ? 1 : -1);
+        names[i % 3] = 'name' + i;
+    }
+    while (total > 100) total = total / 2;
+    var f = (a, b) => a < b ? a : b;
+";
+
+/// Code written to be read, like a library's source: comments, long
+/// names, and plenty of whitespace.
+fn library() -> String {
+    let mut src = String::new();
+    for i in 0..200 {
+        src.push_str(&format!(
+            "
+/**
+ * Find the elements of `list` that match `selector`, caching the result.
+ */
+function findMatching{i}(list, selector, options) {{
+    // Look in the cache first.
+    var cacheKey = selector + ':' + (options && options.deep ? 'deep' : 'shallow');
+    if (matchCache.hasOwnProperty(cacheKey)) {{
+        return matchCache[cacheKey];
+    }}
+    var results = [];
+    for (var index = 0; index < list.length; index++) {{
+        var element = list[index];
+        if (element.nodeType === 1 && matches(element, selector)) {{
+            results.push(element);
+        }} else if (options.deep && element.childNodes) {{
+            results = results.concat(findMatching{i}(element.childNodes, selector, options));
+        }}
+    }}
+    matchCache[cacheKey] = results;
+    return results;
+}}
+",
+            i = i
+        ));
+    }
+    src
+}
+
+/// The same sort of code, minified: short names and no whitespace to
+/// speak of, so there are more tokens per byte.
+fn minified() -> String {
+    let mut src = String::new();
+    for i in 0..400 {
+        src.push_str(&format!(
+            "function f{i}(a,b,c){{var d=b+\":\"+(c&&c.d?\"d\":\"s\");if(m.hasOwnProperty(d))return m[d];\
+             for(var e=[],g=0;g<a.length;g++){{var h=a[g];1===h.nodeType&&t(h,b)?e.push(h):c.d&&h.c&&\
+             (e=e.concat(f{i}(h.c,b,c)))}}return m[d]=e,e}}",
+            i = i
+        ));
+    }
+    src
+}
+
+/// A big literal of data, like a JSON file assigned to a variable.
+fn data() -> String {
+    let mut src = String::from("var data = [\n");
+    for i in 0..2000 {
+        src.push_str(&format!(
+            "    {{\"id\": {}, \"name\": \"item {}\", \"tags\": [\"a\", \"b\", \"c\"], \"score\": {}.5, \
+             \"active\": {}, \"parent\": null}},\n",
+            i,
+            i,
+            i % 100,
+            i % 2 == 0
+        ));
+    }
+    src.push_str("];\n");
+    src
+}
+
+/// Long comments, strings, and templates, like a bundle with license
+/// headers and inlined text, where the lexer's runs are longest.
+fn long_text() -> String {
+    let mut src = String::new();
+    for i in 0..300 {
+        src.push_str(&format!(
+            "
+/*
+ * Permission is hereby granted, free of charge, to any person obtaining a copy of this software and
+ * associated documentation files, to deal in the Software without restriction, including without
+ * limitation the rights to use, copy, modify, merge, publish, distribute, sublicense, and/or sell copies.
+ */
+// Messages shown to the user when something goes wrong while loading module {i}.
+var messages{i} = [
+    'The module could not be loaded because the server did not respond in time; try again later.',
+    \"The module's dependencies could not be resolved; check that they are installed and up to date.\",
+];
+var template{i} = `<div class=\"error\"><p>Something went wrong while loading this part of the page.</p></div>`;
+",
+            i = i
+        ));
+    }
+    src
+}
+
+/// Expressions nested hundreds deep, which make for tall parser stacks.
+fn nested() -> String {
+    let depth = 500;
+    let mut src = String::new();
+    for _ in 0..20 {
+        src.push_str("x = ");
+        for i in 0..depth {
+            src.push_str(["(", "a + (", "[", "f("][i % 4]);
+        }
+        src.push('0');
+        for i in (0..depth).rev() {
+            src.push_str([")", ")", "]", ")"][i % 4]);
+        }
+        src.push_str(";\n");
+    }
+    src
+}
+
+fn lex(src: &str) -> usize {
+    let mut lexer = Lexer::new(src);
+    let mut count = 0;
+    while TokenStream::take(&mut lexer).get_id() != TerminalId::End {
+        count += 1;
+    }
+    count
+}
+
+fn bench_lexer(b: &mut Bencher, src: &str) {
+    b.bytes = src.len() as u64;
+    b.iter(|| test::black_box(lex(src)));
+}
+
+fn bench_parser(b: &mut Bencher, src: &str) {
+    b.bytes = src.len() as u64;
+    b.iter(|| {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        assert!(Parser::new_script(&handler).parse_script(src).is_ok());
+    });
+}
+
+#[bench]
+fn bench_parse_script(b: &mut Bencher) {
+    let src = SOURCE.repeat(100);
+    b.iter(|| {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        assert!(Parser::new_script(&handler).parse_script(&src).is_ok());
+    });
+}
+
+#[bench]
+fn lex_library_29k_tokens(b: &mut Bencher) {
+    bench_lexer(b, &library());
+}
+
+#[bench]
+fn lex_minified_53k_tokens(b: &mut Bencher) {
+    bench_lexer(b, &minified());
+}
+
+#[bench]
+fn lex_data_64k_tokens(b: &mut Bencher) {
+    bench_lexer(b, &data());
+}
+
+#[bench]
+fn lex_long_text_4k_tokens(b: &mut Bencher) {
+    bench_lexer(b, &long_text());
+}
+
+#[bench]
+fn lex_nested_28k_tokens(b: &mut Bencher) {
+    bench_lexer(b, &nested());
+}
+
+#[bench]
+fn parse_library(b: &mut Bencher) {
+    bench_parser(b, &library());
+}
+
+#[bench]
+fn parse_minified(b: &mut Bencher) {
+    bench_parser(b, &minified());
+}
+
+#[bench]
+fn parse_data(b: &mut Bencher) {
+    bench_parser(b, &data());
+}
+
+#[bench]
+fn parse_nested(b: &mut Bencher) {
+    bench_parser(b, &nested());
+}
+
+/// Parsing without building a tree, to see how much of the time is the
+/// tables and how much is the handler.
+#[bench]
+fn parse_library_syntax_only(b: &mut Bencher) {
+    let src = library();
+    b.bytes = src.len() as u64;
+    b.iter(|| {
+        assert!(Parser::new_script(&SyntaxOnlyHandler).parse_script(&src).is_ok());
+    });
+}
+
+/// Parsing with reduce() called through a function pointer, as a closure
+/// or a `fn` value would be, rather than by the generated Reduce; compare
+/// with parse_library.
+#[bench]
+fn parse_library_indirect_reduce(b: &mut Bencher) {
+    type IndirectReduce<'a> = fn(
+        &DefaultHandler<'a>,
+        usize,
+        SourceLocation,
+        &mut ReduceStack<StackValue<'a, DefaultHandler<'a>>>,
+    ) -> NonterminalId;
+    let src = library();
+    b.bytes = src.len() as u64;
+    b.iter(|| {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let reduce: IndirectReduce = test::black_box(reduce);
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), false);
+        assert!(parser.parse_script(&src).is_ok());
+    });
+}
+
+/// Parsing function bodies on a thread per core; compare with
+/// parse_library.
+#[bench]
+fn parse_library_parallel(b: &mut Bencher) {
+    let src = library();
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    b.bytes = src.len() as u64;
+    b.iter(|| {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut workers: Vec<Arena> = (0..threads).map(|_| Arena::new()).collect();
+        assert!(parse_script_parallel(&handler, &mut workers, &src).is_ok());
+    });
+}
+
+/// Look up actions in an order that jumps all over the table, the way a
+/// parser moving between unrelated states does, so that the time is
+/// mostly cache misses.
+#[bench]
+fn bench_action_lookups(b: &mut Bencher) {
+    let mut state = 0;
+    let lookups: Vec<(usize, usize)> = (0..100_000)
+        .map(|i| {
+            state = (state * 7919 + 13) % TABLES.state_count;
+            (state, i % TABLES.action_width)
+        })
+        .collect();
+    b.iter(|| {
+        let mut sum = 0;
+        for &(state, t) in &lookups {
+            sum += TABLES.action(state, t) as i64;
+        }
+        test::black_box(sum)
+    });
+}
@@ -0,0 +1,87 @@
+#ifndef JSPARAGUS_H
+#define JSPARAGUS_H
+
+/* Generated by cbindgen from src/ffi.rs. Don't edit this file by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// What jsparagus_parse() returns. Opaque to C.
+typedef struct JsparagusResult JsparagusResult;
+
+// How jsparagus_parse() parses.
+typedef struct JsparagusParseOptions {
+  // Parse a module instead of a script.
+  bool module;
+  // Keep going after syntax errors and malformed tokens, reporting each
+  // one.
+  bool recover;
+  // Keep the tree, as ESTree JSON, for jsparagus_result_estree_json().
+  bool estree_json;
+} JsparagusParseOptions;
+
+// One error in a result. `message` is a NUL-terminated UTF-8 string.
+// `start` and `end` are byte offsets into the source, and `line` (1-based)
+// and `column` (0-based, in characters) give the position of `start`. If
+// the error has no position, `has_location` is false and the rest are 0.
+typedef struct JsparagusDiagnostic {
+  const char *message;
+  bool has_location;
+  uintptr_t start;
+  uintptr_t end;
+  uint32_t line;
+  uint32_t column;
+} JsparagusDiagnostic;
+
+// Parse the `length` bytes of UTF-8 at `source`. `options` may be null,
+// for all options off. Never returns null.
+//
+// # Safety
+//
+// `source` must point to `length` readable bytes, and `options` must be
+// null or point to a JsparagusParseOptions.
+JsparagusResult *jsparagus_parse(const uint8_t *source,
+                                 uintptr_t length,
+                                 const JsparagusParseOptions *options);
+
+// Free a result from jsparagus_parse(). Does nothing if `result` is null.
+//
+// # Safety
+//
+// `result` must be null or a result from jsparagus_parse() that hasn't
+// been freed.
+void jsparagus_free_result(JsparagusResult *result);
+
+// True if the source parsed, possibly with errors that recovery mode got
+// past.
+//
+// # Safety
+//
+// `result` must be a live result from jsparagus_parse().
+bool jsparagus_result_ok(const JsparagusResult *result);
+
+// The number of diagnostics in `result`.
+//
+// # Safety
+//
+// `result` must be a live result from jsparagus_parse().
+uintptr_t jsparagus_diagnostic_count(const JsparagusResult *result);
+
+// The diagnostic at `index`, or null if `index` is out of range.
+//
+// # Safety
+//
+// `result` must be a live result from jsparagus_parse().
+const JsparagusDiagnostic *jsparagus_diagnostic(const JsparagusResult *result, uintptr_t index);
+
+// The tree as a NUL-terminated ESTree JSON string, or null if the source
+// didn't parse or the `estree_json` option was off.
+//
+// # Safety
+//
+// `result` must be a live result from jsparagus_parse().
+const char *jsparagus_result_estree_json(const JsparagusResult *result);
+
+#endif /* JSPARAGUS_H */
@@ -0,0 +1,232 @@
+//! A bump allocator for AST nodes.
+//!
+//! Allocating a node is a pointer bump in the current chunk, and all the
+//! nodes go away at once when the arena is dropped, so a failed parse doesn't
+//! have to take apart whatever it had built so far.
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::ptr;
+
+use crate::parser_runtime::SourceLocation;
+
+/// The size of each chunk of memory the arena gets from the system
+/// allocator, unless a single value needs more.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A value in the arena, and the function that drops it.
+type DropEntry = (*mut u8, unsafe fn(*mut u8));
+
+pub struct Arena {
+    /// Every chunk allocated so far, with the layout it was allocated with.
+    chunks: RefCell<Vec<(*mut u8, Layout)>>,
+    /// The free part of the newest chunk.
+    next: Cell<*mut u8>,
+    end: Cell<*mut u8>,
+    /// Values that need to be dropped along with the arena, oldest first.
+    drops: RefCell<Vec<DropEntry>>,
+}
+
+impl Arena {
+    pub fn new() -> Arena {
+        Arena {
+            chunks: RefCell::new(vec![]),
+            next: Cell::new(ptr::null_mut()),
+            end: Cell::new(ptr::null_mut()),
+            drops: RefCell::new(vec![]),
+        }
+    }
+
+    /// Move `value` into the arena.
+    ///
+    /// Values are dropped when the arena is, newest first. A `Drop` impl for
+    /// a value allocated here must not use anything the value borrows,
+    /// including other values in the arena, since they may already be gone.
+    ///
+    /// Values have to be Send, since the arena can be sent to another thread
+    /// and dropped there.
+    pub fn alloc<T: Send>(&self, value: T) -> ArenaRef<'_, T> {
+        let p = self.alloc_raw(Layout::new::<T>()) as *mut T;
+        unsafe {
+            // alloc_raw returned memory that is suitably sized and aligned
+            // for T, and that nothing else uses.
+            ptr::write(p, value);
+        }
+        if std::mem::needs_drop::<T>() {
+            self.drops.borrow_mut().push((p as *mut u8, drop_value::<T>));
+        }
+        ArenaRef {
+            // The value lives until the arena is dropped, and the returned
+            // reference can't outlive the borrow of `self`.
+            value: unsafe { &*p },
+        }
+    }
+
+    /// The number of bytes of memory the arena has taken from the system.
+    pub fn allocated_bytes(&self) -> usize {
+        self.chunks.borrow().iter().map(|(_, layout)| layout.size()).sum()
+    }
+
+    fn alloc_raw(&self, layout: Layout) -> *mut u8 {
+        // A zero-sized value still gets a byte of its own, so that no two
+        // values share an address: NodeLocations and ArenaRef::ptr_eq tell
+        // nodes apart by it.
+        let layout = Layout::from_size_align(layout.size().max(1), layout.align()).unwrap();
+        let next = self.next.get();
+        if !next.is_null() {
+            let pad = next.align_offset(layout.align());
+            let free = self.end.get() as usize - next as usize;
+            if pad <= free && layout.size() <= free - pad {
+                let p = next.wrapping_add(pad);
+                self.next.set(p.wrapping_add(layout.size()));
+                return p;
+            }
+        }
+        self.new_chunk(layout)
+    }
+
+    /// Start a new chunk and allocate `layout` at the beginning of it.
+    fn new_chunk(&self, layout: Layout) -> *mut u8 {
+        let chunk_layout = Layout::from_size_align(
+            layout.size().max(CHUNK_SIZE),
+            layout.align().max(std::mem::align_of::<usize>()),
+        )
+        .expect("arena chunk size overflow");
+        let start = unsafe { alloc::alloc(chunk_layout) };
+        if start.is_null() {
+            alloc::handle_alloc_error(chunk_layout);
+        }
+        self.chunks.borrow_mut().push((start, chunk_layout));
+        self.next.set(start.wrapping_add(layout.size()));
+        self.end.set(start.wrapping_add(chunk_layout.size()));
+        start
+    }
+}
+
+// Sending an arena sends the values in it, and alloc() only takes values that
+// can be sent; nothing else can be using them, since an arena can't be sent
+// while it's borrowed. It isn't Sync, though: only one thread at a time can
+// allocate from it.
+unsafe impl Send for Arena {}
+
+impl Default for Arena {
+    fn default() -> Arena {
+        Arena::new()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        for &(p, drop_fn) in self.drops.get_mut().iter().rev() {
+            unsafe {
+                drop_fn(p);
+            }
+        }
+        for &(start, layout) in self.chunks.get_mut().iter() {
+            unsafe {
+                alloc::dealloc(start, layout);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Arena({} bytes)", self.allocated_bytes())
+    }
+}
+
+unsafe fn drop_value<T>(p: *mut u8) {
+    ptr::drop_in_place(p as *mut T);
+}
+
+/// A reference to a value in an `Arena`. Copying it doesn't copy the value.
+pub struct ArenaRef<'alloc, T> {
+    value: &'alloc T,
+}
+
+impl<'alloc, T> ArenaRef<'alloc, T> {
+    /// The value, borrowed for as long as the arena is rather than for as
+    /// long as this reference is.
+    pub fn get(self) -> &'alloc T {
+        self.value
+    }
+
+    /// True if `a` and `b` refer to the same value, rather than to two
+    /// values that may be equal.
+    pub fn ptr_eq(a: ArenaRef<'alloc, T>, b: ArenaRef<'alloc, T>) -> bool {
+        ptr::eq(a.value, b.value)
+    }
+}
+
+impl<'alloc, T> Clone for ArenaRef<'alloc, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'alloc, T> Copy for ArenaRef<'alloc, T> {}
+
+impl<'alloc, T> Deref for ArenaRef<'alloc, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'alloc, T: fmt::Debug> fmt::Debug for ArenaRef<'alloc, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// The source location of each node a handler allocated, for consumers that
+/// need positions the tree itself doesn't store.
+///
+/// Nodes are looked up by address, so a table is only meaningful while the
+/// arena holding its nodes is alive.
+#[derive(Debug, Default)]
+pub struct NodeLocations {
+    locations: RefCell<HashMap<usize, SourceLocation>>,
+}
+
+impl NodeLocations {
+    pub fn new() -> NodeLocations {
+        NodeLocations::default()
+    }
+
+    pub fn insert<T>(&self, node: &T, loc: SourceLocation) {
+        self.locations.borrow_mut().insert(node as *const T as usize, loc);
+    }
+
+    pub fn get<T>(&self, node: &T) -> Option<SourceLocation> {
+        self.locations.borrow().get(&(node as *const T as usize)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arena, ArenaRef, NodeLocations};
+    use crate::parser_runtime::SourceLocation;
+
+    #[test]
+    fn zero_sized_nodes() {
+        #[derive(Debug)]
+        struct Empty;
+        let arena = Arena::new();
+        let (a, b) = (arena.alloc(Empty), arena.alloc(Empty));
+        assert!(!ArenaRef::ptr_eq(a, b));
+        assert!(ArenaRef::ptr_eq(a, a));
+
+        let locations = NodeLocations::new();
+        locations.insert(a.get(), SourceLocation::new(0, 1, 1, 0));
+        locations.insert(b.get(), SourceLocation::new(2, 3, 1, 2));
+        assert_eq!(locations.get(a.get()).map(|loc| loc.start), Some(0));
+        assert_eq!(locations.get(b.get()).map(|loc| loc.start), Some(2));
+        assert_eq!(locations.get(arena.alloc(Empty).get()), None);
+    }
+}
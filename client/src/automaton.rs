@@ -0,0 +1,157 @@
+//! The LR automaton that a set of ParserTables encodes, drawn as a
+//! Graphviz graph, for debugging the grammar without the Python side of
+//! the generator: a node for each state, labeled with the productions it
+//! reduces, an edge for each terminal it shifts, and a dashed edge for
+//! each nonterminal it goes to after a reduction.
+//!
+//! All of TABLES is too big a graph to read, so to_dot() draws only the
+//! states it's given. states_near() picks out the neighborhood of a state.
+
+use crate::parser_runtime::{ParserTables, ACCEPT, ERROR};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// The state's edges, as (target, label, is_goto), with the terminals or
+/// nonterminals that lead to the same target joined into one label.
+fn edges(tables: &ParserTables, state: usize) -> Vec<(usize, String, bool)> {
+    let name = |names: &[&str], index: usize| match names.get(index) {
+        Some(name) => name.to_string(),
+        None => index.to_string(),
+    };
+    let mut shifts: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for terminal in 0..tables.action_width {
+        let action = tables.action(state, terminal);
+        if action >= 0 {
+            shifts.entry(action as usize).or_default().push(name(tables.terminal_names, terminal));
+        }
+    }
+    let mut gotos: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for nonterminal in 0..tables.goto_width {
+        let target = tables.goto_table[state * tables.goto_width + nonterminal];
+        if target != 0 {
+            gotos.entry(target).or_default().push(name(tables.nonterminal_names, nonterminal));
+        }
+    }
+    let shifts = shifts.into_iter().map(|(target, names)| (target, names.join(", "), false));
+    let gotos = gotos.into_iter().map(|(target, names)| (target, names.join(", "), true));
+    shifts.chain(gotos).collect()
+}
+
+/// `states`, and the states that can be reached from them by following
+/// at most `distance` shift or goto edges, in order.
+pub fn states_near(tables: &ParserTables, states: &[usize], distance: usize) -> Vec<usize> {
+    let mut found: Vec<bool> = vec![false; tables.state_count];
+    let mut frontier: Vec<usize> = states.iter().copied().filter(|&s| s < tables.state_count).collect();
+    for &state in &frontier {
+        found[state] = true;
+    }
+    for _ in 0..distance {
+        let mut next = vec![];
+        for state in frontier {
+            for (target, _, _) in edges(tables, state) {
+                if target < tables.state_count && !found[target] {
+                    found[target] = true;
+                    next.push(target);
+                }
+            }
+        }
+        frontier = next;
+    }
+    (0..tables.state_count).filter(|&s| found[s]).collect()
+}
+
+/// Quote `s` as a dot string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\l"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A dot graph of `states` and their edges. Targets of the edges that
+/// aren't among `states` are drawn as gray nodes, without their own edges.
+pub fn to_dot(tables: &ParserTables, states: &[usize]) -> String {
+    let mut dot = String::from("digraph automaton {\n    node [shape=box, fontname=monospace];\n");
+    let mut drawn: Vec<usize> = states.iter().copied().filter(|&s| s < tables.state_count).collect();
+    drawn.sort_unstable();
+    drawn.dedup();
+    let mut outside = vec![];
+    let mut lines = String::new();
+    for &state in &drawn {
+        let mut label = format!("state {}\n", state);
+        let mut reduced = vec![];
+        for terminal in 0..tables.action_width {
+            let action = tables.action(state, terminal);
+            if action == ACCEPT {
+                label.push_str("accept\n");
+            } else if action < 0 && action != ERROR && !reduced.contains(&action) {
+                reduced.push(action);
+                let production = (-action - 1) as usize;
+                let name = tables.production_names.get(production).copied().unwrap_or("?");
+                let _ = writeln!(label, "reduce {}. {}", production, name);
+            }
+        }
+        let _ = writeln!(dot, "    s{} [label={}];", state, quote(&label));
+        for (target, names, is_goto) in edges(tables, state) {
+            let style = if is_goto { ", style=dashed" } else { "" };
+            let _ = writeln!(lines, "    s{} -> s{} [label={}{}];", state, target, quote(&names), style);
+            if drawn.binary_search(&target).is_err() && !outside.contains(&target) {
+                outside.push(target);
+            }
+        }
+    }
+    outside.sort_unstable();
+    for state in outside {
+        let _ = writeln!(dot, "    s{} [label=\"state {}\", color=gray, fontcolor=gray];", state, state);
+    }
+    dot.push_str(&lines);
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser_generated::{EntryPoint, TerminalId, TABLES};
+
+    #[test]
+    fn automaton_dot() {
+        use super::{states_near, to_dot};
+        use crate::parser_generated::NonterminalId;
+        let start = EntryPoint::Script.state();
+        let next = TABLES.action(start, TerminalId::Semicolon as usize) as usize;
+        assert_eq!(states_near(&TABLES, &[start], 0), [start]);
+        let near = states_near(&TABLES, &[start], 1);
+        assert!(near.contains(&next) && near.len() > 2);
+
+        let dot = to_dot(&TABLES, &[next]);
+        assert!(dot.starts_with("digraph automaton {\n"));
+        assert!(dot.contains(&format!("s{} [label=\"state {}\\lreduce ", next, next)));
+        assert!(dot.contains("EmptyStatement ::= \\\";\\\""));
+        let dot = to_dot(&TABLES, &[start]);
+        assert!(dot.contains(&format!("s{} -> s{} [label=\"`;`", start, next)));
+        assert!(dot.contains(&format!("s{} [label=\"state {}\", color=gray", next, next)));
+        let list = TABLES.goto_table[start * TABLES.goto_width + NonterminalId::StatementList as usize];
+        let edge = dot.lines().find(|line| line.starts_with(&format!("    s{} -> s{} ", start, list))).unwrap();
+        assert!(edge.contains("StatementList") && edge.ends_with("style=dashed];"));
+    }
+
+    #[test]
+    fn states_out_of_range() {
+        use super::{states_near, to_dot};
+        let past = TABLES.state_count;
+        assert!(states_near(&TABLES, &[past], 3).is_empty());
+        assert!(states_near(&TABLES, &[], 3).is_empty());
+        assert_eq!(to_dot(&TABLES, &[past]), "digraph automaton {\n    node [shape=box, fontname=monospace];\n}\n");
+        let start = EntryPoint::Script.state();
+        assert_eq!(to_dot(&TABLES, &[start, start, past]), to_dot(&TABLES, &[start]));
+    }
+}
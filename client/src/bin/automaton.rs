@@ -0,0 +1,42 @@
+//! Print part of the parser's LR automaton as a Graphviz dot graph.
+//!
+//! Usage: automaton [--distance N] STATE...
+//!
+//! The graph has the STATEs, and with `--distance`, the states that can be
+//! reached from them in up to N shifts and gotos. With no STATEs, it has
+//! every state. Render it with, say, `dot -Tsvg`.
+
+use client::automaton::{states_near, to_dot};
+use client::parser_generated::TABLES;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: automaton [--distance N] STATE...");
+    process::exit(2);
+}
+
+fn main() {
+    let mut distance = 0;
+    let mut states = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--distance" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => distance = n,
+                None => usage(),
+            },
+            _ => match arg.parse::<usize>() {
+                Ok(state) if state < TABLES.state_count => states.push(state),
+                Ok(state) => {
+                    eprintln!("automaton: there is no state {}; the last is {}", state, TABLES.state_count - 1);
+                    process::exit(2);
+                }
+                Err(_) => usage(),
+            },
+        }
+    }
+    if states.is_empty() {
+        states = (0..TABLES.state_count).collect();
+    }
+    print!("{}", to_dot(&TABLES, &states_near(&TABLES, &states, distance)));
+}
@@ -0,0 +1,39 @@
+// Reads file paths from stdin, one per line, each prefixed with "script " or
+// "module ", and writes one line for each: "ok" if the reference parser
+// accepts the file, or "error " and the message if it doesn't.
+//
+// The reference is acorn if it can be required, and otherwise V8 itself.
+// V8 needs --experimental-vm-modules to parse modules without running them.
+
+const fs = require("fs");
+const readline = require("readline");
+const vm = require("vm");
+
+let acorn = null;
+try {
+    acorn = require("acorn");
+} catch (e) {}
+
+function check(goal, src) {
+    if (acorn !== null) {
+        acorn.parse(src, {ecmaVersion: "latest", sourceType: goal});
+    } else if (goal === "module") {
+        new vm.SourceTextModule(src);
+    } else {
+        new vm.Script(src);
+    }
+}
+
+const lines = readline.createInterface({input: process.stdin});
+lines.on("line", line => {
+    const space = line.indexOf(" ");
+    const goal = line.slice(0, space);
+    const path = line.slice(space + 1);
+    let result = "ok";
+    try {
+        check(goal, fs.readFileSync(path, "utf8"));
+    } catch (e) {
+        result = "error " + String(e.message).split("\n")[0];
+    }
+    process.stdout.write(result + "\n");
+});
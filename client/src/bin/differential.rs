@@ -0,0 +1,151 @@
+//! Parse a corpus of JS files with this parser and with a reference parser,
+//! and report every file the two disagree about accepting.
+//!
+//! Usage: differential [--module] PATH...
+//!
+//! Each PATH is a file or a directory to search for `.js` and `.mjs` files.
+//! `.mjs` files are always parsed as modules, the rest as scripts unless
+//! `--module` is given. The reference runs in Node, using `differential.js`;
+//! see that file for which parser it is.
+
+use client::ast_arena::Arena;
+use client::parser::Parser;
+use client::parser_generated::DefaultHandler;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+const REFERENCE: &str = include_str!("differential.js");
+
+#[derive(Clone, Copy)]
+enum Goal {
+    Script,
+    Module,
+}
+
+impl Goal {
+    fn name(self) -> &'static str {
+        match self {
+            Goal::Script => "script",
+            Goal::Module => "module",
+        }
+    }
+}
+
+/// Add the files to test under `path` to `files`, in a stable order.
+fn find_files(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "js" || ext == "mjs") {
+                find_files(&entry, files)?;
+            }
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+/// Parse `src` with this parser, returning the error message if it's
+/// rejected.
+fn parse(goal: Goal, src: &str) -> Result<(), String> {
+    let arena = Arena::new();
+    let handler = DefaultHandler::new(&arena);
+    let mut parser = match goal {
+        Goal::Script => Parser::new_script(&handler),
+        Goal::Module => Parser::new_module(&handler),
+    };
+    parser.parse_script(src).map(|_| ()).map_err(|err| err.message())
+}
+
+fn run(module: bool, paths: &[String]) -> Result<usize, Box<dyn Error>> {
+    let mut files = vec![];
+    for path in paths {
+        find_files(Path::new(path), &mut files)?;
+    }
+
+    let mut node = Command::new("node")
+        .args(["--experimental-vm-modules", "--no-warnings", "-e", REFERENCE])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("can't run node: {}", err))?;
+    let mut to_node = node.stdin.take().unwrap();
+    let mut from_node = BufReader::new(node.stdout.take().unwrap());
+
+    let mut divergences = 0;
+    for file in &files {
+        let goal = if module || file.extension().is_some_and(|ext| ext == "mjs") {
+            Goal::Module
+        } else {
+            Goal::Script
+        };
+        let src = match fs::read_to_string(file) {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("{}: skipped: {}", file.display(), err);
+                continue;
+            }
+        };
+        let ours = parse(goal, &src);
+
+        writeln!(to_node, "{} {}", goal.name(), file.display())?;
+        to_node.flush()?;
+        let mut line = String::new();
+        if from_node.read_line(&mut line)? == 0 {
+            return Err("node exited before checking every file".into());
+        }
+        let theirs = match line.trim_end().strip_prefix("error ") {
+            Some(message) => Err(message.to_string()),
+            None => Ok(()),
+        };
+
+        match (ours, theirs) {
+            (Ok(()), Err(message)) => {
+                divergences += 1;
+                println!("{}: accepted, but the reference says: {}", file.display(), message);
+            }
+            (Err(message), Ok(())) => {
+                divergences += 1;
+                println!("{}: rejected, but the reference accepts it: {}", file.display(), message);
+            }
+            _ => {}
+        }
+    }
+    drop(to_node);
+    node.wait()?;
+
+    println!("{} files, {} divergences", files.len(), divergences);
+    Ok(divergences)
+}
+
+fn main() {
+    let mut module = false;
+    let mut paths = vec![];
+    for arg in std::env::args().skip(1) {
+        if arg == "--module" {
+            module = true;
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("usage: differential [--module] PATH...");
+        process::exit(2);
+    }
+
+    match run(module, &paths) {
+        Ok(0) => {}
+        Ok(_) => process::exit(1),
+        Err(err) => {
+            eprintln!("differential: {}", err);
+            process::exit(2);
+        }
+    }
+}
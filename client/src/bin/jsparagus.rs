@@ -0,0 +1,161 @@
+//! Parse JS from the command line.
+//!
+//! Usage: jsparagus COMMAND [--module] [--strict] [--lint] [--trace] [--json] [FILE]
+//!
+//! Commands:
+//!     parse   print the tree, or with --json, the tree as ESTree JSON
+//!     check   print only the errors, or with --json, a JSON array of them
+//!
+//! FILE defaults to standard input. `--module` parses a module instead of a
+//! script, and `--strict` parses a script as strict mode code. With
+//! `--lint`, check also prints warnings about code that parses but is
+//! probably a mistake. `--trace` prints each shift, reduce and goto the
+//! parser does to stderr, for debugging the parser tables. The exit status
+//! is 1 if the source doesn't parse; warnings don't change it.
+
+use client::ast_arena::{Arena, NodeLocations};
+use client::diagnostics::{Diagnostic, Diagnostics};
+use client::estree::{diagnostics_json, module_to_estree_json, to_estree_json, warnings_json};
+use client::lint::{lint_module, lint_script};
+use client::parser::Parser;
+use client::parser_generated::DefaultHandler;
+use std::io::{self, Read};
+use std::process;
+
+const USAGE: &str = "usage: jsparagus (parse | check) [--module] [--strict] [--lint] [--trace] [--json] [FILE]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Parse,
+    Check,
+}
+
+struct Options {
+    command: Command,
+    module: bool,
+    strict: bool,
+    lint: bool,
+    trace: bool,
+    json: bool,
+    file: Option<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let command = match args.next().as_deref() {
+        Some("parse") => Command::Parse,
+        Some("check") => Command::Check,
+        Some(other) => return Err(format!("unknown command `{}`", other)),
+        None => return Err("no command given".to_string()),
+    };
+    let mut options = Options {
+        command,
+        module: false,
+        strict: false,
+        lint: false,
+        trace: false,
+        json: false,
+        file: None,
+    };
+    for arg in args {
+        match arg.as_str() {
+            "--module" => options.module = true,
+            "--strict" => options.strict = true,
+            "--lint" => options.lint = true,
+            "--trace" => options.trace = true,
+            "--json" => options.json = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option `{}`", arg)),
+            _ if options.file.is_none() => options.file = Some(arg),
+            _ => return Err("more than one file given".to_string()),
+        }
+    }
+    Ok(options)
+}
+
+fn read_source(file: Option<&str>) -> io::Result<String> {
+    match file {
+        Some("-") | None => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+        Some(path) => std::fs::read_to_string(path),
+    }
+}
+
+/// Run the command, returning true if the source parsed.
+fn run(options: &Options, source: &str) -> bool {
+    let arena = Arena::new();
+    let locations = NodeLocations::new();
+    let handler = DefaultHandler::with_locations(&arena, &locations);
+    let mut parser = if options.module {
+        Parser::new_module(&handler)
+    } else {
+        Parser::new_script(&handler)
+    };
+    if options.strict {
+        parser.set_strict(true);
+    }
+    parser.set_trace(options.trace);
+    let result = parser.parse_script(source);
+    for event in parser.take_trace().unwrap_or_default() {
+        eprintln!("{}", event);
+    }
+    let name = options.file.as_deref().unwrap_or("<stdin>");
+
+    match result {
+        Ok(value) => {
+            match (options.command, options.json) {
+                (Command::Check, _) => {
+                    let warnings = match (options.lint, options.module) {
+                        (false, _) => Diagnostics::new(),
+                        (true, true) => lint_module(value.into_module().get(), &locations),
+                        (true, false) => lint_script(value.into_script().get(), &locations),
+                    };
+                    if options.json {
+                        println!("{}", warnings_json(&warnings, source));
+                    } else {
+                        for warning in &warnings {
+                            eprintln!("{}: {}", name, warning);
+                        }
+                    }
+                }
+                (Command::Parse, false) if options.module => println!("{:#?}", value.into_module()),
+                (Command::Parse, false) => println!("{:#?}", value.into_script()),
+                (Command::Parse, true) if options.module => {
+                    println!("{}", module_to_estree_json(&value.into_module(), &locations, source))
+                }
+                (Command::Parse, true) => println!("{}", to_estree_json(&value.into_script(), &locations, source)),
+            }
+            true
+        }
+        Err(error) => {
+            if options.json {
+                println!("{}", diagnostics_json(&[error], source));
+            } else {
+                eprintln!("{}: {}", name, Diagnostic::from(&error));
+            }
+            false
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("jsparagus: {}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let source = match read_source(options.file.as_deref()) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("jsparagus: {}: {}", options.file.as_deref().unwrap_or("<stdin>"), err);
+            process::exit(2);
+        }
+    };
+    if !run(&options, &source) {
+        process::exit(1);
+    }
+}
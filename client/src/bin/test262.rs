@@ -0,0 +1,285 @@
+//! Run the parser over a checkout of test262 and check that it accepts and
+//! rejects what each test says it should.
+//!
+//! Usage: test262 [--verbose] [--baseline FILE [--update-baseline]] [--coverage FILE] TEST262_DIR
+//!
+//! A test passes if it's expected to parse and does, or has a negative
+//! expectation in the parse phase and fails to. With `--baseline`, the tests
+//! that fail are compared against the ones listed in FILE, and any test that
+//! fails but isn't listed is a regression. `--update-baseline` writes the
+//! current list of failures to FILE instead. `--coverage` writes a report
+//! to FILE of which grammar productions the tests reduced, and how often.
+
+use client::ast_arena::Arena;
+use client::coverage::Coverage;
+use client::parser::Parser;
+use client::parser_generated::{DefaultHandler, TABLES};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Tests that need any of these features are skipped, because the grammar
+/// doesn't have them yet.
+const UNSUPPORTED_FEATURES: &[&str] = &[
+    "arbitrary-module-namespace-names",
+    "class-static-block",
+    "decorators",
+    "import-assertions",
+    "import-attributes",
+    "logical-assignment-operators",
+    "source-phase-imports",
+];
+
+/// What a test's frontmatter says about how to run it.
+#[derive(Default)]
+struct Metadata {
+    flags: Vec<String>,
+    features: Vec<String>,
+    negative_phase: Option<String>,
+}
+
+impl Metadata {
+    /// Read the YAML between `/*---` and `---*/`. Only the few keys this
+    /// runner needs are understood, and only in the forms test262 uses.
+    fn parse(src: &str) -> Metadata {
+        let mut metadata = Metadata::default();
+        let yaml = match (src.find("/*---"), src.find("---*/")) {
+            (Some(start), Some(end)) if start < end => &src[start + 5..end],
+            _ => return metadata,
+        };
+        let mut key = "";
+        for line in yaml.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if !line.starts_with(char::is_whitespace) {
+                let (name, value) = match trimmed.find(':') {
+                    Some(colon) => (&trimmed[..colon], trimmed[colon + 1..].trim()),
+                    None => continue,
+                };
+                key = name;
+                if let Some(list) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                    let items = list.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty());
+                    if let Some(list) = metadata.list(key) {
+                        list.extend(items);
+                    }
+                }
+            } else if let Some(item) = trimmed.strip_prefix("- ") {
+                if let Some(list) = metadata.list(key) {
+                    list.push(item.trim().to_string());
+                }
+            } else if key == "negative" {
+                if let Some(phase) = trimmed.strip_prefix("phase:") {
+                    metadata.negative_phase = Some(phase.trim().to_string());
+                }
+            }
+        }
+        metadata
+    }
+
+    /// The list under `key`, or None for lists that don't matter here,
+    /// like `includes`.
+    fn list(&mut self, key: &str) -> Option<&mut Vec<String>> {
+        match key {
+            "flags" => Some(&mut self.flags),
+            "features" => Some(&mut self.features),
+            _ => None,
+        }
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skip,
+}
+
+/// Parse `src`, returning the error message if it's rejected. A panic
+/// counts as being rejected, with the message "panicked". The productions
+/// reduced are added to `coverage`, if given.
+fn parse(module: bool, src: &str, coverage: Option<&mut Coverage>) -> Result<(), String> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = if module {
+            Parser::new_module(&handler)
+        } else {
+            Parser::new_script(&handler)
+        };
+        parser.set_record_coverage(coverage.is_some());
+        let result = parser.parse_script(src).map(|_| ()).map_err(|err| err.message());
+        if let (Some(coverage), Some(counts)) = (coverage, parser.take_coverage()) {
+            coverage.merge(&counts);
+        }
+        result
+    }));
+    result.unwrap_or_else(|_| Err("panicked".to_string()))
+}
+
+fn run_test(src: &str, mut coverage: Option<&mut Coverage>) -> Outcome {
+    let metadata = Metadata::parse(src);
+    if metadata.features.iter().any(|f| UNSUPPORTED_FEATURES.contains(&f.as_str())) {
+        return Outcome::Skip;
+    }
+    let module = metadata.has_flag("module");
+    // Tests with neither flag are run both ways, like test262 says to.
+    let mut runs = vec![];
+    if module || metadata.has_flag("raw") || !metadata.has_flag("onlyStrict") {
+        runs.push(src.to_string());
+    }
+    if !module && !metadata.has_flag("raw") && !metadata.has_flag("noStrict") {
+        runs.push(format!("\"use strict\";\n{}", src));
+    }
+    // Older versions of test262 say "early" for what's now "parse".
+    let negative = matches!(metadata.negative_phase.as_deref(), Some("parse") | Some("early"));
+    for run in runs {
+        match (parse(module, &run, coverage.as_deref_mut()), negative) {
+            (Ok(()), true) => return Outcome::Fail("parsed, but it should be a SyntaxError".to_string()),
+            (Err(message), false) => return Outcome::Fail(message),
+            _ => {}
+        }
+    }
+    Outcome::Pass
+}
+
+/// Add the tests under `path` to `files`, in a stable order. Fixtures are
+/// files that tests import, not tests.
+fn find_tests(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            find_tests(&entry, files)?;
+        } else {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            if name.ends_with(".js") && !name.contains("_FIXTURE") {
+                files.push(entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Options {
+    verbose: bool,
+    baseline: Option<PathBuf>,
+    update_baseline: bool,
+    coverage: Option<PathBuf>,
+    dir: PathBuf,
+}
+
+fn run(options: &Options) -> Result<bool, Box<dyn Error>> {
+    let test_dir = options.dir.join("test");
+    let root = if test_dir.is_dir() { test_dir } else { options.dir.clone() };
+    let mut files = vec![];
+    find_tests(&root, &mut files)?;
+
+    // A panic is reported as a failure; the message would only be noise.
+    panic::set_hook(Box::new(|_| {}));
+    let (mut passed, mut skipped) = (0, 0);
+    let mut failures = BTreeSet::new();
+    let mut coverage = options.coverage.as_ref().map(|_| Coverage::new());
+    for file in &files {
+        let name = file.strip_prefix(&root).unwrap_or(file).to_string_lossy().replace('\\', "/");
+        let src = fs::read_to_string(file)?;
+        match run_test(&src, coverage.as_mut()) {
+            Outcome::Pass => passed += 1,
+            Outcome::Skip => skipped += 1,
+            Outcome::Fail(message) => {
+                if options.verbose {
+                    println!("FAIL {}: {}", name, message);
+                }
+                failures.insert(name);
+            }
+        }
+    }
+    let _ = panic::take_hook();
+    println!("{} passed, {} failed, {} skipped", passed, failures.len(), skipped);
+    if let (Some(path), Some(coverage)) = (&options.coverage, &coverage) {
+        let names = TABLES.production_names;
+        fs::write(path, coverage.report(names))?;
+        println!("{} of {} productions reduced; wrote {}", coverage.covered(names.len()), names.len(), path.display());
+    }
+
+    let baseline = match &options.baseline {
+        Some(baseline) => baseline,
+        None => return Ok(true),
+    };
+    if options.update_baseline {
+        let mut text = String::new();
+        for name in &failures {
+            text.push_str(name);
+            text.push('\n');
+        }
+        fs::write(baseline, text)?;
+        println!("wrote {}", baseline.display());
+        return Ok(true);
+    }
+    let expected: BTreeSet<String> = fs::read_to_string(baseline)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    let regressions: Vec<_> = failures.difference(&expected).collect();
+    let fixed = expected.difference(&failures).count();
+    for name in &regressions {
+        println!("REGRESSION {}", name);
+    }
+    println!("{} regressions, {} newly passing", regressions.len(), fixed);
+    Ok(regressions.is_empty())
+}
+
+fn usage() -> ! {
+    eprintln!("usage: test262 [--verbose] [--baseline FILE [--update-baseline]] [--coverage FILE] TEST262_DIR");
+    process::exit(2);
+}
+
+fn main() {
+    let mut verbose = false;
+    let mut baseline = None;
+    let mut update_baseline = false;
+    let mut coverage = None;
+    let mut dir = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--verbose" => verbose = true,
+            "--baseline" => baseline = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--update-baseline" => update_baseline = true,
+            "--coverage" => coverage = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            _ if dir.is_none() && !arg.starts_with("--") => dir = Some(PathBuf::from(arg)),
+            _ => usage(),
+        }
+    }
+    let dir = dir.unwrap_or_else(|| usage());
+    if update_baseline && baseline.is_none() {
+        usage();
+    }
+
+    let options = Options {
+        verbose,
+        baseline,
+        update_baseline,
+        coverage,
+        dir,
+    };
+    match run(&options) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("test262: {}", err);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::early_errors::EarlyErrors;
+use crate::glr::Branch;
+use crate::parser_runtime::{SourceLocation, Token};
+use crate::stack::Stack;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// Replace the text `source[start..end]` with `text`. Offsets are in bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(start: usize, end: usize, text: &str) -> TextEdit {
+        TextEdit {
+            start,
+            end,
+            text: text.to_string(),
+        }
+    }
+
+    /// Return the edited text, or None if the edit doesn't fit `source`.
+    pub fn apply(&self, source: &str) -> Option<String> {
+        if self.start > self.end
+            || !source.is_char_boundary(self.start)
+            || !source.is_char_boundary(self.end)
+        {
+            return None;
+        }
+        let mut result = String::with_capacity(source.len() - (self.end - self.start) + self.text.len());
+        result.push_str(&source[..self.start]);
+        result.push_str(&self.text);
+        result.push_str(&source[self.end..]);
+        Some(result)
+    }
+}
+
+/// A copy of a parser's state, from Parser::checkpoint() or taken by an
+/// incremental parse. For the latter, the parser was about to be given the
+/// token starting at `resume`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint<'src, V> {
+    pub(crate) stack: Stack<V>,
+    /// How many errors recovery mode had recorded, and whether it was still
+    /// recovering from the last one: how many times in a row, and whether
+    /// it was skipping tokens.
+    pub(crate) error_count: usize,
+    pub(crate) recovering: bool,
+    pub(crate) recoveries: usize,
+    pub(crate) skipping: bool,
+    pub(crate) early: EarlyErrors,
+    /// An undecided GLR parse's branches, and the tokens they've been given.
+    pub(crate) branches: Vec<Branch>,
+    pub(crate) pending: Vec<Token<'src>>,
+    pub(crate) replay: VecDeque<usize>,
+    pub(crate) resume: SourceLocation,
+}
+
+/// Take a checkpoint after this many tokens. Each checkpoint clones the
+/// node stack, so this trades memory and parse time against how much
+/// reparsing an edit costs.
+pub const CHECKPOINT_INTERVAL: usize = 64;
+
+/// The source text of the last incremental parse, and the checkpoints taken
+/// while parsing it, in source order. The first checkpoint is always the
+/// parser's initial state.
+pub struct CheckpointLog<'src, V> {
+    pub(crate) source: Rc<str>,
+    pub(crate) checkpoints: Vec<Checkpoint<'src, V>>,
+}
+
+impl<'src, V> CheckpointLog<'src, V> {
+    pub fn new(source: &str, initial: Checkpoint<'src, V>) -> CheckpointLog<'src, V> {
+        CheckpointLog {
+            source: Rc::from(source),
+            checkpoints: vec![initial],
+        }
+    }
+
+    /// Forget every checkpoint that `edit` could invalidate, and return the
+    /// last one that's left.
+    ///
+    /// A checkpoint is still good if its token starts strictly before the
+    /// edit: then none of the earlier tokens, nor the lookahead character
+    /// the lexer peeked at after each of them, has changed.
+    pub fn rewind_for(&mut self, edit: &TextEdit) -> &Checkpoint<'src, V> {
+        let keep = self
+            .checkpoints
+            .iter()
+            .skip(1)
+            .take_while(|c| c.resume.start < edit.start)
+            .count();
+        self.checkpoints.truncate(keep + 1);
+        &self.checkpoints[keep]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, Reduce, TABLES};
+
+    #[test]
+    fn checkpoints() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        type ScriptParser<'a> = Parser<'a, 'a, DefaultHandler<'a>, Reduce<'a, DefaultHandler<'a>>>;
+        fn finish<'a>(parser: &mut ScriptParser<'a>, rest: &'a str) -> Result<String, String> {
+            let result = parser.write_source(rest).and_then(|end| parser.close(end));
+            result.map(|script| format!("{:?}", script.into_script())).map_err(|error| error.message())
+        }
+
+        // Try an arrow function, then back off and parse the same head as
+        // a parenthesized expression instead.
+        let mut parser = Parser::new_script(&handler);
+        assert!(parser.write_source("x = (a, b)").is_ok());
+        let checkpoint = parser.checkpoint();
+        assert!(finish(&mut parser, " => a;").unwrap().contains("ArrowFunction"));
+        parser.rewind(checkpoint);
+        assert!(!finish(&mut parser, ";").unwrap().contains("ArrowFunction"));
+
+        // What the early error checks have seen goes back too.
+        let mut parser = Parser::new_script(&handler);
+        assert!(parser.write_source("let a;").is_ok());
+        let checkpoint = parser.checkpoint();
+        assert!(finish(&mut parser, "let a;").unwrap_err().starts_with("redeclaration"));
+        parser.rewind(checkpoint.clone());
+        assert!(finish(&mut parser, "let b;").is_ok());
+
+        // So do the errors recovery mode recorded.
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+        let checkpoint = parser.checkpoint();
+        assert!(parser.write_source("a = ;").is_ok());
+        assert_eq!(parser.errors().len(), 1);
+        parser.rewind(checkpoint);
+        assert!(parser.errors().is_empty());
+    }
+
+    #[test]
+    fn incremental_reparse() {
+        use super::TextEdit;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let parse = |src: &str| match Parser::new_script(&handler).parse_script(src) {
+            Ok(script) => Ok(format!("{:?}", script.into_script())),
+            Err(error) => Err(error.message()),
+        };
+
+        // Enough tokens for several checkpoints.
+        let src: String = (0..100).map(|i| format!("let a{} = {};\n", i, i)).collect();
+        let mut parser = Parser::new_script(&handler);
+        let script = parser.parse_script_incremental(&src).ok().unwrap();
+        assert_eq!(Ok(format!("{:?}", script.into_script())), parse(&src));
+
+        // Each edit applies to the source as the last one left it: after
+        // the last checkpoint, in between, and before the first. Working
+        // back from the end keeps the offsets in `src` right. One edit
+        // makes a redeclaration, and the next takes it back out.
+        let mut edited = src.clone();
+        let end = src.len() - "99;\n".len();
+        let middle = src.find("let a50").unwrap();
+        let edits = [
+            (TextEdit::new(end, end + 2, "x + 1"), true),
+            (TextEdit::new(middle, middle, "let c = b;"), true),
+            (TextEdit::new(4, 6, "b"), true),
+            (TextEdit::new(0, 0, "let b;"), false),
+            (TextEdit::new(0, 6, ""), true),
+        ];
+        for (edit, ok) in edits {
+            edited = edit.apply(&edited).unwrap();
+            let reparsed = parser.reparse(&edit).map(|script| format!("{:?}", script.into_script()));
+            assert_eq!(reparsed.is_ok(), ok, "{}", edited);
+            assert_eq!(reparsed.map_err(|error| error.message()), parse(&edited), "{}", edited);
+        }
+    }
+
+    #[test]
+    fn edits_that_dont_fit() {
+        use super::TextEdit;
+        let src = "h\u{e9}llo";
+        assert_eq!(TextEdit::new(1, 3, "e").apply(src).as_deref(), Some("hello"));
+        assert_eq!(TextEdit::new(6, 6, "!").apply(src).as_deref(), Some("h\u{e9}llo!"));
+        // Inside the \u{e9}, backwards, and past the end.
+        assert_eq!(TextEdit::new(2, 3, "e").apply(src), None);
+        assert_eq!(TextEdit::new(3, 1, "e").apply(src), None);
+        assert_eq!(TextEdit::new(6, 7, "").apply(src), None);
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        let message = |result: crate::parser::Result<_>| result.err().map(|error| error.message());
+        assert_eq!(
+            message(parser.reparse(&TextEdit::new(0, 0, "x"))).as_deref(),
+            Some("internal error: reparse() called before parse_script_incremental()")
+        );
+        assert!(parser.parse_script_incremental("a;").is_ok());
+        assert_eq!(
+            message(parser.reparse(&TextEdit::new(1, 5, ""))).as_deref(),
+            Some("internal error: edit is out of range for the source text")
+        );
+        // The failed edit left the source alone.
+        let reparsed = parser.reparse(&TextEdit::new(1, 1, "b")).map(|script| format!("{:?}", script.into_script()));
+        let full = Parser::new_script(&handler).parse_script("ab;").map(|script| format!("{:?}", script.into_script()));
+        assert_eq!(reparsed.ok(), full.ok());
+    }
+}
@@ -0,0 +1,475 @@
+//! Turning a concrete syntax tree back into JavaScript source.
+//!
+//! The generated `unparse` methods hand a node's tokens to an Unparser in
+//! source order. The Printer here lays them out: it puts spaces between
+//! tokens that would otherwise run together, and parentheses around any
+//! expression that doesn't fit where the grammar puts it, or that starts
+//! with a token a lookahead restriction forbids there, like an object
+//! literal at the start of an expression statement. Statements always get
+//! their `;`, so the output never relies on automatic semicolon insertion.
+//! Parsing the output gives back the same tree, apart from locations.
+
+use crate::lexer::is_identifier_part;
+use crate::parser_generated::{concrete, derives_by_unit_productions, NonterminalId, Unparser};
+use crate::parser_runtime::{SourceLocation, TerminalId, Token};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Options {
+    /// Put everything on one line, with only the spaces the lexer needs to
+    /// tell tokens apart. Otherwise each statement gets its own line,
+    /// indented by how deeply it's nested, and operators get spaces around
+    /// them.
+    pub compact: bool,
+    /// One level of indentation, when not compact.
+    pub indent: &'static str,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            compact: false,
+            indent: "    ",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodegenError {
+    pub message: &'static str,
+    /// Where the node that couldn't be printed came from, if anywhere.
+    pub loc: Option<SourceLocation>,
+}
+
+pub fn print_script(script: &concrete::Script, options: &Options) -> Result<String, CodegenError> {
+    let mut printer = Printer::new(options);
+    script.unparse(&mut printer);
+    printer.finish()
+}
+
+pub fn print_module(module: &concrete::Module, options: &Options) -> Result<String, CodegenError> {
+    let mut printer = Printer::new(options);
+    module.unparse(&mut printer);
+    printer.finish()
+}
+
+/// The source text of a terminal that carries a value.
+fn token_text(token: &Token) -> String {
+    let value = || token.value.as_deref().unwrap_or_default();
+    let raw = || token.raw.as_deref().unwrap_or_default();
+    match token.get_id() {
+        TerminalId::StringLiteral => {
+            // The raw text is as written, escapes and all. Quote it with
+            // whichever quote it has no unescaped copies of.
+            let raw = raw();
+            let mut chars = raw.chars();
+            let mut quote = '"';
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '"' => quote = '\'',
+                    _ => {}
+                }
+            }
+            format!("{}{}{}", quote, raw, quote)
+        }
+        TerminalId::NoSubstitutionTemplate => format!("`{}`", raw()),
+        TerminalId::TemplateHead => format!("`{}${{", raw()),
+        TerminalId::TemplateMiddle => format!("}}{}${{", raw()),
+        TerminalId::TemplateTail => format!("}}{}`", raw()),
+        TerminalId::BigIntLiteral => format!("{}n", value()),
+        _ => token.value.as_deref().or(token.raw.as_deref()).unwrap_or_default().to_string(),
+    }
+}
+
+/// Reserved words that can end an expression, like `this` in `this + 1`.
+fn is_operand_word(text: &str) -> bool {
+    matches!(text, "this" | "null" | "super")
+}
+
+/// Operators that are always binary, which readable output puts spaces
+/// around. `+`, `-` and `*` are binary only after an operand.
+fn is_binary_operator(text: &str) -> bool {
+    matches!(
+        text,
+        "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "**=" | "<<=" | ">>=" | ">>>=" | "&=" | "|=" | "^=" | "&&=" | "||="
+            | "??=" | "==" | "!=" | "===" | "!==" | "<" | ">" | "<=" | ">=" | "&&" | "||" | "??" | "?" | "=>" | "/"
+            | "%" | "**" | "<<" | ">>" | ">>>" | "&" | "|" | "^" | "instanceof" | "in" | "of"
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bracket {
+    Paren,
+    Square,
+    /// A block, function body, class body or switch body: its contents go
+    /// on their own lines. `after_do` is true for the body of a do-while.
+    Block { after_do: bool },
+    /// An object literal, pattern or import or export clause.
+    Braces,
+    /// The `?` of a conditional expression, waiting for its `:`.
+    Conditional,
+}
+
+pub struct Printer<'a> {
+    options: &'a Options,
+    out: String,
+    /// The last token written, and whether it ended an operand.
+    last: String,
+    last_was_operand: bool,
+    /// The last token was a decimal integer like `1`, which a `.` right
+    /// after would turn into a decimal point.
+    last_was_integer: bool,
+    last_was_regexp: bool,
+    last_was_binary: bool,
+    /// Readable output wants a space before the next token.
+    space_next: bool,
+    /// A newline is owed before the next token, after a `{` or `;` or `}`.
+    /// After a `{`, it's dropped if the next token is the `}`.
+    newline_next: bool,
+    brackets: Vec<Bracket>,
+    /// The brace just closed, for deciding what can follow it on its line.
+    closed: Option<Bracket>,
+    error: Option<CodegenError>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(options: &'a Options) -> Printer<'a> {
+        Printer {
+            options,
+            out: String::new(),
+            last: String::new(),
+            last_was_operand: false,
+            last_was_binary: false,
+            last_was_integer: false,
+            last_was_regexp: false,
+            space_next: false,
+            newline_next: false,
+            brackets: vec![],
+            closed: None,
+            error: None,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<String, CodegenError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => {
+                if !self.options.compact && !self.out.is_empty() {
+                    self.out.push('\n');
+                }
+                Ok(self.out)
+            }
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.brackets.iter().filter(|b| matches!(b, Bracket::Block { .. })).count()
+    }
+
+    /// True if the lexer would read `self.last` and `next` differently
+    /// without a space between them.
+    fn needs_space(&self, next: &str) -> bool {
+        let (a, b) = match (self.last.chars().last(), next.chars().next()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return false,
+        };
+        let word = |c: char| is_identifier_part(c) || c == '\\';
+        (word(a) && (word(b) || b == '#'))
+            || (self.last_was_regexp && word(b))
+            || (self.last_was_integer && b == '.')
+            || matches!((a, b), ('+', '+') | ('-', '-') | ('/', '/') | ('/', '*') | ('<', '!'))
+    }
+
+    /// Whether the token after the `}` of a block can go on the same line as
+    /// it. `closed` is the block.
+    fn same_line_after_block(&self, closed: Bracket, next: &str, next_is_token: bool) -> bool {
+        if matches!(self.brackets.last(), Some(Bracket::Paren | Bracket::Square | Bracket::Braces)) {
+            // The block is part of an expression, like a function body.
+            return true;
+        }
+        if next_is_token {
+            return false;
+        }
+        match next {
+            "else" | "catch" | "finally" => true,
+            "while" => closed == Bracket::Block { after_do: true },
+            ")" | "]" | "," | ";" | "." | "?." | "(" | "[" | ":" => true,
+            _ => is_binary_operator(next),
+        }
+    }
+
+    fn write(&mut self, text: &str, is_token: bool) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let closes = !is_token && matches!(text, ")" | "]" | "}");
+        if closes {
+            while self.brackets.last() == Some(&Bracket::Conditional) {
+                self.brackets.pop();
+            }
+        }
+        let conditional_colon = text == ":" && self.brackets.last() == Some(&Bracket::Conditional);
+        let binary = !is_token
+            && (is_binary_operator(text) || (matches!(text, "+" | "-" | "*") && self.last_was_operand) || conditional_colon);
+
+        // Whitespace goes after the block's brackets are closed, so that a
+        // `}` lines up with the line that opened its block.
+        if let Some(closed) = self.closed.take() {
+            if !self.same_line_after_block(closed, text, is_token) {
+                self.newline_next = true;
+            }
+        }
+        if closes || conditional_colon {
+            let bracket = self.brackets.pop();
+            if let Some(block @ Bracket::Block { .. }) = bracket.filter(|_| text == "}") {
+                // `{}` stays on one line.
+                self.newline_next = self.last != "{";
+                self.closed = Some(block);
+            }
+        }
+        if self.newline_next && !self.options.compact && !self.out.is_empty() {
+            self.out.push('\n');
+            for _ in 0..self.depth() {
+                self.out.push_str(self.options.indent);
+            }
+        } else {
+            let readable = !self.options.compact
+                && !self.out.is_empty()
+                && ((self.space_next && !(self.last == ";" && matches!(text, ";" | ")")))
+                    || binary
+                    || self.readable_space(text));
+            if readable || self.needs_space(text) {
+                self.out.push(' ');
+            }
+        }
+        self.newline_next = false;
+        self.space_next = binary;
+        self.out.push_str(text);
+
+        if is_token {
+            // A template's substitutions are bracketed by its pieces.
+            if text.starts_with('}') {
+                self.brackets.pop();
+            }
+            if text.ends_with("${") {
+                self.brackets.push(Bracket::Braces);
+            }
+        } else {
+            match text {
+                "(" => self.brackets.push(Bracket::Paren),
+                "[" => self.brackets.push(Bracket::Square),
+                "{" => {
+                    let bracket = if self.opens_block() {
+                        self.newline_next = true;
+                        Bracket::Block { after_do: self.last == "do" }
+                    } else {
+                        Bracket::Braces
+                    };
+                    self.brackets.push(bracket);
+                }
+                "?" => self.brackets.push(Bracket::Conditional),
+                ";" if self.brackets.last() != Some(&Bracket::Paren) => self.newline_next = true,
+                "," | ";" | ":" => self.space_next = true,
+                "*" if matches!(self.last.as_str(), "function" | "yield") => self.space_next = true,
+                _ => {}
+            }
+        }
+
+        // Every terminal written with text() that's made of letters is a
+        // keyword; names come through token().
+        self.last_was_operand = is_token || is_operand_word(text) || matches!(text, ")" | "]" | "}" | "++" | "--");
+        self.last_was_binary = binary;
+        self.last_was_integer = false;
+        self.last_was_regexp = false;
+        self.last = text.to_string();
+    }
+
+    /// Whether a `{` right after `self.last` starts a block, rather than an
+    /// object literal or the like.
+    fn opens_block(&self) -> bool {
+        match self.last.as_str() {
+            "" | ")" | "=>" | ";" | "else" | "try" | "finally" | "do" => true,
+            "{" | ":" => !self.last_was_binary && matches!(self.brackets.last(), Some(Bracket::Block { .. })),
+            "}" => self.closed.is_some(),
+            "return" | "typeof" | "void" | "delete" | "throw" | "in" | "of" | "new" | "yield" | "await" | "case"
+            | "instanceof" | "export" | "import" | "default" | "var" | "let" | "const" => false,
+            last => last.starts_with(|c: char| is_identifier_part(c)),
+        }
+    }
+
+    /// Readable output's spaces besides those around binary operators.
+    fn readable_space(&self, next: &str) -> bool {
+        let last = self.last.as_str();
+        if next == "{" {
+            return last == ")" || (last.starts_with(|c: char| is_identifier_part(c)) && !last.ends_with("${"));
+        }
+        if next == "(" {
+            return matches!(last, "if" | "for" | "while" | "with" | "switch" | "catch");
+        }
+        if matches!(last, ")" | "]" | "}") && next.starts_with(|c: char| is_identifier_part(c)) {
+            return true;
+        }
+        last.starts_with(|c: char| c.is_ascii_lowercase())
+            && !self.last_was_operand
+            && !matches!(next, ")" | "." | ";" | "," | "]" | ":" | "(" | "*")
+    }
+}
+
+impl<'a> Unparser for Printer<'a> {
+    fn text(&mut self, text: &'static str) {
+        self.write(text, false);
+    }
+
+    fn token(&mut self, token: &Token) {
+        let text = token_text(token);
+        self.write(&text, true);
+        self.last_was_integer = token.get_id() == TerminalId::NumericLiteral
+            && text.chars().all(|c| c.is_ascii_digit() || c == '_');
+        self.last_was_regexp = token.get_id() == TerminalId::RegularExpressionLiteral;
+    }
+
+    fn expression(&mut self, expr: &concrete::Expression, slot: NonterminalId, not_first: &[&'static str]) {
+        let fits = expr.nonterminal().is_none_or(|nt| derives_by_unit_productions(slot, nt));
+        let parens = !fits || (!not_first.is_empty() && not_first.contains(&first_token(expr).as_str()));
+        if parens {
+            self.text("(");
+            expr.unparse(self);
+            self.text(")");
+        } else {
+            expr.unparse(self);
+        }
+    }
+
+    fn missing(&mut self, loc: Option<SourceLocation>) {
+        if self.error.is_none() {
+            self.error = Some(CodegenError {
+                message: match loc {
+                    Some(_) => "can't print a node that failed to parse or wasn't parsed",
+                    None => "can't print a node that no production makes",
+                },
+                loc,
+            });
+        }
+    }
+}
+
+/// The text of the first token of `expr`, as the Printer would write it
+/// without parentheses.
+fn first_token(expr: &concrete::Expression) -> String {
+    struct First(Option<String>);
+
+    impl Unparser for First {
+        fn text(&mut self, text: &'static str) {
+            if self.0.is_none() {
+                self.0 = Some(text.to_string());
+            }
+        }
+
+        fn token(&mut self, token: &Token) {
+            if self.0.is_none() {
+                self.0 = Some(token_text(token));
+            }
+        }
+
+        fn expression(&mut self, expr: &concrete::Expression, slot: NonterminalId, _not_first: &[&'static str]) {
+            if self.0.is_none() {
+                match expr.nonterminal() {
+                    Some(nt) if !derives_by_unit_productions(slot, nt) => self.text("("),
+                    _ => expr.unparse(self),
+                }
+            }
+        }
+
+        fn missing(&mut self, _loc: Option<SourceLocation>) {}
+    }
+
+    let mut first = First(None);
+    expr.unparse(&mut first);
+    first.0.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::{concrete, reduce, DefaultHandler, EntryPoint, TABLES};
+    use crate::testing::expression;
+
+    #[test]
+    fn codegen() {
+        use super::{print_script, Options, Printer};
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let parse = |src: &str| {
+            let src: &str = arena.alloc(src.to_string()).get();
+            let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), false);
+            match parser.parse_script(src) {
+                Ok(script) => script.into_script(),
+                Err(error) => panic!("{}: {}", src, error.message()),
+            }
+        };
+        let compact = Options {
+            compact: true,
+            ..Options::default()
+        };
+
+        // Printing is stable: printed output parses back into a tree that
+        // prints the same.
+        let sources = [
+            "var a = 1, b = 'it\\'s', c = \"x\", d = `t${a}u${b}v`, e = 0x1_0n, f = /a\\/b/g;",
+            "function* g(x = 1, ...[y]) { yield* x; return -x - -y + +y + ++y - y--; }",
+            "if (a) b; else if (c) { d; } else {}\ndo x++; while (x < 3) for (;;) break;",
+            "for (let [k, v] of m) for (var i in o) for (const {a: b = 1} = x; i; i--) continue;",
+            "label: try { throw new Error(`no`); } catch ({message}) {} finally { debugger; }",
+            "switch (x) { case 1: case 2: { f(); break; } default: g?.h?.[i]?.(j); }",
+            "class A extends B { static #x = 1; get y() { return this.#x; } m() { super.m(); } }",
+            "x = a ? b ? c : d : (e) => ({e}); y = async function () { await z; };",
+            "(function () {})(); ({}).x; (class {}); a = 1..toString() + 1.5.toFixed();",
+            "tag`a${1}b`; x = /re/.test(s) / 2 / /re/g.lastIndex; void typeof delete a[b];",
+            "a = b ** -c; d = (-e) ** f; g = (h, i); j = k < l instanceof m; n = o ?? p; n |= o || p && q;",
+        ];
+        for options in &[Options::default(), compact] {
+            for src in &sources {
+                let printed = print_script(&parse(src), options).unwrap();
+                match print_script(&parse(&printed), options) {
+                    Ok(again) => assert_eq!(printed, again, "{}", src),
+                    Err(error) => panic!("{}: {:?}", printed, error),
+                }
+            }
+        }
+
+        assert_eq!(
+            print_script(&parse("if(a){b}else{c(d,e=>{})}"), &Options::default()).unwrap(),
+            "if (a) {\n    b;\n} else {\n    c(d, e => {});\n}\n"
+        );
+        assert_eq!(
+            print_script(&parse("x = a + +b - -c - (-1) + 1 .toString() < d;"), &compact).unwrap(),
+            "x=a+ +b- -c-(-1)+1 .toString()<d;"
+        );
+
+        // Parentheses go where the tree's shape needs them, and where a
+        // statement would otherwise start with an object literal.
+        let sum = expression(parse("(a + b);"));
+        let object = expression(parse("({a: 1});"));
+        let print = |item: concrete::ModuleItem| {
+            let mut printer = Printer::new(&compact);
+            item.unparse(&mut printer);
+            printer.finish().unwrap()
+        };
+        let plus = |left, right| arena.alloc(concrete::Expression::AdditiveExpressionP1(left, right));
+        assert_eq!(print(concrete::ModuleItem::ExpressionStatement(plus(sum, sum))), "a+b+(a+b);");
+        assert_eq!(print(concrete::ModuleItem::ExpressionStatement(plus(object, sum))), "({a:1}+(a+b));");
+
+        // Nodes the parser skipped or couldn't parse can't be printed.
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), false);
+        parser.set_lazy(true);
+        let lazy = match parser.parse_script("function f() { return 1; }") {
+            Ok(script) => script.into_script(),
+            Err(error) => panic!("{}", error.message()),
+        };
+        assert!(print_script(&lazy, &Options::default()).is_err());
+    }
+}
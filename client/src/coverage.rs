@@ -0,0 +1,128 @@
+//! Grammar coverage: which productions the parser reduced while parsing a
+//! body of tests, and how often, for finding the parts of the grammar that
+//! no test exercises.
+//!
+//! Parser::set_record_coverage() turns the counting on. The counts from
+//! many parses can be merged, and report() turns them into text that names
+//! each production, using the tables' production_names.
+
+use std::fmt::Write;
+
+/// How many times each production was reduced, by the index the tables'
+/// reduce actions give it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    counts: Vec<u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub(crate) fn record(&mut self, prod_index: usize) {
+        if prod_index >= self.counts.len() {
+            self.counts.resize(prod_index + 1, 0);
+        }
+        self.counts[prod_index] += 1;
+    }
+
+    /// The number of times the production at `prod_index` was reduced.
+    pub fn count(&self, prod_index: usize) -> u64 {
+        self.counts.get(prod_index).copied().unwrap_or(0)
+    }
+
+    /// Add the counts in `other` to these.
+    pub fn merge(&mut self, other: &Coverage) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    /// How many of the first `production_count` productions were reduced
+    /// at least once.
+    pub fn covered(&self, production_count: usize) -> usize {
+        self.counts.iter().take(production_count).filter(|&&count| count > 0).count()
+    }
+
+    /// A report on the productions in `names`, which gives the name of
+    /// each by index: a summary line, the productions that were never
+    /// reduced, then the count for each production.
+    pub fn report(&self, names: &[&str]) -> String {
+        let covered = self.covered(names.len());
+        let percent = if names.is_empty() {
+            100.0
+        } else {
+            covered as f64 * 100.0 / names.len() as f64
+        };
+        let mut report = String::new();
+        let _ = writeln!(report, "{} of {} productions reduced ({:.1}%)", covered, names.len(), percent);
+        if covered < names.len() {
+            let _ = writeln!(report, "\nnever reduced:");
+            for (i, name) in names.iter().enumerate() {
+                if self.count(i) == 0 {
+                    let _ = writeln!(report, "    {}. {}", i, name);
+                }
+            }
+        }
+        let _ = writeln!(report, "\nreduced:");
+        for (i, name) in names.iter().enumerate() {
+            let _ = writeln!(report, "{:>10}  {}. {}", self.count(i), i, name);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::DefaultHandler;
+
+    #[test]
+    fn coverage() {
+        use crate::parser_generated::PRODUCTION_NAMES;
+        let index = |name: &str| PRODUCTION_NAMES.iter().position(|&n| n == name).unwrap();
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        parser.set_record_coverage(true);
+        assert!(parser.parse_script("x; y; function f() { return; }").is_ok());
+        let coverage = parser.take_coverage().unwrap();
+        assert_eq!(coverage.count(index("StatementList ::= StatementList StatementListItem")), 2);
+        // The body a lazy parse only checks is counted too.
+        assert_eq!(coverage.count(index("ReturnStatement ::= \"return\" \";\"")), 1);
+        assert_eq!(coverage.count(index("Script ::= [empty]")), 0);
+
+        let mut total = coverage.clone();
+        total.merge(&coverage);
+        assert_eq!(total.count(index("StatementList ::= StatementList StatementListItem")), 4);
+        let report = total.report(&PRODUCTION_NAMES);
+        let count = PRODUCTION_NAMES.len();
+        let summary = format!("{} of {} productions reduced", total.covered(count), count);
+        assert!(report.starts_with(&summary));
+        assert!(report.contains("never reduced:\n    0. Script ::= [empty]\n"));
+    }
+
+    #[test]
+    fn coverage_off() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        assert!(parser.parse_script("x;").is_ok());
+        assert!(parser.take_coverage().is_none());
+
+        // A parse that fails still counts what it reduced, and taking the
+        // counts starts again from zero.
+        let mut parser = Parser::new_script(&handler);
+        parser.set_record_coverage(true);
+        assert!(parser.parse_script("x; y)").is_err());
+        let coverage = parser.take_coverage().unwrap();
+        assert!(coverage.covered(crate::parser_generated::PRODUCTION_NAMES.len()) > 0);
+        assert_eq!(parser.take_coverage().unwrap().covered(crate::parser_generated::PRODUCTION_NAMES.len()), 0);
+    }
+}
@@ -0,0 +1,183 @@
+//! Concrete syntax trees, for tools like formatters that need all of the
+//! source text: a node for each production the parser reduced, with every
+//! token and every piece of trivia under it as a leaf.
+//!
+//! Parsing with CstHandler builds the nodes, but the parser only passes a
+//! handler the tokens that have a value, like identifiers and literals.
+//! complete() fills in the rest, the punctuators, keywords, whitespace and
+//! comments, by lexing the text between each node's children.
+
+use crate::lexer::{Lexer, Trivia};
+use crate::parser_runtime::{SourceLocation, TerminalId, Token, TokenStream};
+use crate::source_mapper::{ColumnUnit, SourceMapper};
+
+#[derive(Clone, Debug)]
+pub enum CstElement<'src> {
+    Node(CstNode<'src>),
+    Token(Token<'src>),
+    Trivia(Trivia),
+}
+
+impl CstElement<'_> {
+    pub fn loc(&self) -> SourceLocation {
+        match self {
+            CstElement::Node(node) => node.loc,
+            CstElement::Token(token) => token.loc,
+            CstElement::Trivia(trivia) => trivia.loc,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CstNode<'src> {
+    /// The name of the Handler method that built the node, like
+    /// `statement_list_p1`. A placeholder for something that failed to
+    /// parse is an `error`, and a function body that a lazy parse skipped
+    /// is a `lazy_function_body`.
+    pub kind: &'static str,
+    pub loc: SourceLocation,
+    /// Nodes and leaves, in source order.
+    pub children: Vec<CstElement<'src>>,
+}
+
+impl<'src> CstNode<'src> {
+    pub fn new(kind: &'static str, loc: SourceLocation, children: Vec<CstElement<'src>>) -> CstNode<'src> {
+        CstNode { kind, loc, children }
+    }
+
+    /// The text of the leaves under the node, in order. For a tree that
+    /// complete() filled in, that's the node's span of `source`.
+    pub fn text(&self, source: &str) -> String {
+        let mut text = String::new();
+        self.write_text(source, &mut text);
+        text
+    }
+
+    fn write_text(&self, source: &str, text: &mut String) {
+        for child in &self.children {
+            match child {
+                CstElement::Node(node) => node.write_text(source, text),
+                leaf => {
+                    let loc = leaf.loc();
+                    text.push_str(&source[loc.start..loc.end]);
+                }
+            }
+        }
+    }
+}
+
+/// Fill in the tree that parsing `source` with CstHandler returned, so that
+/// its leaves cover all of `source`. The root is made to span the whole
+/// text, so that the trivia before the first token and after the last
+/// belongs to it. The body of a function that a lazy parse skipped is
+/// filled in with its tokens, but without nodes.
+pub fn complete<'src>(mut root: CstNode<'src>, source: &'src str) -> CstNode<'src> {
+    let mapper = SourceMapper::new(source);
+    root.loc = SourceLocation::new(0, source.len(), 1, 0);
+    fill(&mut root, source, &mapper);
+    root
+}
+
+fn fill<'src>(node: &mut CstNode<'src>, source: &'src str, mapper: &SourceMapper) {
+    let mut offset = node.loc.start;
+    let mut children = Vec::with_capacity(node.children.len());
+    for mut child in std::mem::take(&mut node.children) {
+        let loc = child.loc();
+        lex_gap(source, mapper, offset, loc.start, &mut children);
+        if let CstElement::Node(node) = &mut child {
+            fill(node, source, mapper);
+        }
+        offset = offset.max(loc.end);
+        children.push(child);
+    }
+    lex_gap(source, mapper, offset, node.loc.end, &mut children);
+    node.children = children;
+}
+
+/// Push the tokens and trivia of `source[start..end]`, which holds no
+/// value tokens, in order. Without those, a `/` or `}` can only be a
+/// punctuator, which is how the lexer reads them by itself.
+fn lex_gap<'src>(source: &'src str, mapper: &SourceMapper, start: usize, end: usize, out: &mut Vec<CstElement<'src>>) {
+    if start >= end {
+        return;
+    }
+    let (line, column) = mapper.position(start, ColumnUnit::CodePoints);
+    let at = SourceLocation::new(start, start, line, column);
+    let mut lexer = Lexer::new_at(&source[start..end], at);
+    lexer.record_trivia();
+    lexer.recover_errors();
+    let mut tokens = vec![];
+    loop {
+        let token = TokenStream::take(&mut lexer);
+        if token.get_id() == TerminalId::End {
+            break;
+        }
+        tokens.push(token);
+    }
+    let mut trivia = lexer.take_trivia().into_iter().peekable();
+    for token in tokens {
+        while let Some(t) = trivia.next_if(|t| t.loc.start < token.loc.start) {
+            out.push(CstElement::Trivia(t));
+        }
+        out.push(CstElement::Token(token));
+    }
+    out.extend(trivia.map(CstElement::Trivia));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use crate::parser_generated::TerminalId;
+
+    #[test]
+    fn concrete_syntax_tree() {
+        use super::{complete, CstElement, CstNode};
+        use crate::parser_generated::CstHandler;
+        let src = "// f\nfunction f(a, b = 1) { return a /* c */ / b }\nlet re = /x+/g, t = `a${f(1)}b`;\nx = {y, [z]: 3}\n";
+        let handler = CstHandler;
+        let root = Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script();
+        let root = complete(root, src);
+        assert_eq!(root.kind, "script");
+        assert_eq!(root.text(src), src);
+
+        // The leaves tile the source, and include the punctuators and
+        // keywords the handler wasn't given.
+        fn leaves<'n, 's>(node: &'n CstNode<'s>, out: &mut Vec<&'n CstElement<'s>>) {
+            for child in &node.children {
+                match child {
+                    CstElement::Node(node) => leaves(node, out),
+                    leaf => out.push(leaf),
+                }
+            }
+        }
+        let mut all = vec![];
+        leaves(&root, &mut all);
+        let mut offset = 0;
+        for leaf in &all {
+            assert_eq!(leaf.loc().start, offset);
+            offset = leaf.loc().end;
+        }
+        assert_eq!(offset, src.len());
+        let ids: Vec<_> = all
+            .iter()
+            .filter_map(|leaf| match leaf {
+                CstElement::Token(token) => Some(token.get_id()),
+                _ => None,
+            })
+            .take(6)
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                TerminalId::Function,
+                TerminalId::Identifier,
+                TerminalId::LeftParenthesis,
+                TerminalId::Identifier,
+                TerminalId::Comma,
+                TerminalId::Identifier,
+            ]
+        );
+        let trivia = all.iter().filter(|leaf| matches!(leaf, CstElement::Trivia(_))).count();
+        assert_eq!(trivia, 27);
+    }
+}
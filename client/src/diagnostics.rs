@@ -0,0 +1,363 @@
+//! Problems with a source text, in a form for tools to show: an error or a
+//! warning, with a code, the span it's about, spans related to it, and a
+//! fix to suggest, if there's an obvious one.
+//!
+//! The parser reports what stops it as a ParseError, and in recovery mode
+//! collects the ones it gets past. A Diagnostics sink gathers all of what
+//! one parse found, converted into Diagnostics.
+
+use crate::checkpoint::TextEdit;
+use crate::early_errors::EarlyErrorKind;
+use crate::lexer::{keyword, LexError};
+use crate::parser::{describe_expected, describe_location, ParseError};
+use crate::parser_generated::TerminalId;
+use crate::parser_runtime::SourceLocation;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A span that helps explain a diagnostic, like where a name that's
+/// declared twice was declared first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub loc: SourceLocation,
+    pub message: String,
+}
+
+/// A change that would fix the problem, like inserting a missing `)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fix {
+    /// What the fix does, like "insert `)`".
+    pub message: String,
+    pub edit: TextEdit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A short name for the kind of problem, like `syntax-error` or
+    /// `redeclaration`, for tools to match on.
+    pub code: &'static str,
+    /// What's wrong, without where; that's `loc`.
+    pub message: String,
+    /// The span the problem is in, if it has one. Internal errors don't.
+    pub loc: Option<SourceLocation>,
+    pub labels: Vec<Label>,
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: String, loc: Option<SourceLocation>) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message,
+            loc,
+            labels: vec![],
+            fix: None,
+        }
+    }
+
+    pub fn with_label(mut self, loc: SourceLocation, message: &str) -> Diagnostic {
+        self.labels.push(Label {
+            loc,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_fix(mut self, message: String, edit: TextEdit) -> Diagnostic {
+        self.fix = Some(Fix { message, edit });
+        self
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::new(Severity::Error, code(error), describe(error), error.loc());
+        let diagnostic = match error {
+            ParseError::SyntaxError { token, expected } => with_insertion(diagnostic, expected, token.loc.start),
+            ParseError::UnexpectedEnd { loc, expected } => with_insertion(diagnostic, expected, loc.start),
+            ParseError::EarlyError { first: Some(first), .. } => diagnostic.with_label(*first, "first declared here"),
+            _ => diagnostic,
+        };
+        match did_you_mean(error) {
+            Some(fix) => Diagnostic {
+                fix: Some(fix),
+                ..diagnostic
+            },
+            None => diagnostic,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    /// One line for the diagnostic, like `error[redeclaration]: redeclaration
+    /// of `x` at line 1, column 11`, then one per label.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(loc) = &self.loc {
+            write!(f, " at {}", describe_location(loc))?;
+        }
+        for label in &self.labels {
+            write!(f, "\n    note: {} at {}", label.message, describe_location(&label.loc))?;
+        }
+        if let Some(fix) = &self.fix {
+            write!(f, "\n    help: {}", fix.message)?;
+        }
+        Ok(())
+    }
+}
+
+fn code(error: &ParseError) -> &'static str {
+    match error {
+        ParseError::SyntaxError { .. } => "syntax-error",
+        ParseError::UnexpectedEnd { .. } => "unexpected-end",
+        ParseError::LexError(_) => "lex-error",
+        ParseError::EarlyError { kind, .. } => kind.code(),
+        ParseError::InternalError(_) => "internal-error",
+    }
+}
+
+/// Like ParseError::message(), but without the location.
+fn describe(error: &ParseError) -> String {
+    match error {
+        ParseError::SyntaxError { token, expected } => {
+            format!("{} but found {}", describe_expected(expected), token.get_id())
+        }
+        ParseError::UnexpectedEnd { expected, .. } => {
+            format!("unexpected end of input ({})", describe_expected(expected))
+        }
+        ParseError::LexError(LexError { message, .. }) => message.to_string(),
+        ParseError::EarlyError { kind, name: Some(name), .. } => format!("{} `{}`", kind, name),
+        ParseError::EarlyError { kind, name: None, .. } => kind.to_string(),
+        ParseError::InternalError(message) => format!("internal error: {}", message),
+    }
+}
+
+/// The punctuators worth suggesting inserting where a syntax error is, in
+/// order of preference: closing what's open first.
+const INSERTABLE: &[(TerminalId, &str)] = &[
+    (TerminalId::RightParenthesis, ")"),
+    (TerminalId::RightSquareBracket, "]"),
+    (TerminalId::RightCurlyBracket, "}"),
+    (TerminalId::Semicolon, ";"),
+];
+
+/// Suggest inserting a punctuator at `offset` if one the parser expected
+/// there is worth suggesting.
+fn with_insertion(diagnostic: Diagnostic, expected: &[TerminalId], offset: usize) -> Diagnostic {
+    match INSERTABLE.iter().find(|(t, _)| expected.contains(t)) {
+        Some(&(_, text)) => {
+            diagnostic.with_fix(format!("insert `{}`", text), TextEdit::new(offset, offset, text))
+        }
+        None => diagnostic,
+    }
+}
+
+/// A fix for `error` if it looks like one of the common mistakes: `=` where
+/// an equality operator could go, as in `if (a + b = c)`, or a reserved word
+/// used as the name of a variable, function, class or parameter. This is a
+/// better guess than with_insertion()'s.
+fn did_you_mean(error: &ParseError) -> Option<Fix> {
+    match error {
+        ParseError::SyntaxError { token, expected } => match token.get_id() {
+            TerminalId::EqualsSign if expected.contains(&TerminalId::EqualsSignEqualsSignEqualsSign) => Some(Fix {
+                message: "replace `=` with `===`".to_string(),
+                edit: TextEdit::new(token.loc.start, token.loc.end, "==="),
+            }),
+            _ if in_binding_position(expected) => {
+                let word = token.raw.as_deref().filter(|&word| keyword(word).is_some())?;
+                Some(rename(word, token.loc))
+            }
+            _ => None,
+        },
+        ParseError::EarlyError {
+            kind: EarlyErrorKind::ReservedWord,
+            name: Some(name),
+            loc,
+            ..
+        } => Some(rename(name, *loc)),
+        _ => None,
+    }
+}
+
+/// The terminals that can come where a name is being declared, after `var`,
+/// `function` or `class`, or in a parameter list: the name, a pattern, or
+/// what can go instead of either, like the `*` of `function*`.
+const BINDING_POSITION: &[TerminalId] = &[
+    TerminalId::Identifier,
+    TerminalId::Yield,
+    TerminalId::Await,
+    TerminalId::LeftCurlyBracket,
+    TerminalId::LeftSquareBracket,
+    TerminalId::FullStopFullStopFullStop,
+    TerminalId::LeftParenthesis,
+    TerminalId::RightParenthesis,
+    TerminalId::RightSquareBracket,
+    TerminalId::Comma,
+    TerminalId::Asterisk,
+    TerminalId::Extends,
+];
+
+/// True if a syntax error where the parser `expected` these terminals is
+/// where a name is being declared. Where a statement or an expression could
+/// start, a reserved word is more likely the right word in the wrong place.
+fn in_binding_position(expected: &[TerminalId]) -> bool {
+    expected.contains(&TerminalId::Identifier) && expected.iter().all(|t| BINDING_POSITION.contains(t))
+}
+
+/// Suggest renaming the reserved word `word`, at `loc`, to a name that isn't
+/// one.
+fn rename(word: &str, loc: SourceLocation) -> Fix {
+    let name = format!("{}_", word);
+    Fix {
+        message: format!("rename `{}`, which is a reserved word, to `{}`", word, name),
+        edit: TextEdit::new(loc.start, loc.end, &name),
+    }
+}
+
+/// The diagnostics from one parse, in the order they were reported.
+#[derive(Clone, Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    /// The diagnostics for `errors`, as from Parser::errors().
+    pub fn from_errors(errors: &[ParseError]) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
+        for error in errors {
+            diagnostics.report(error);
+        }
+        diagnostics
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn report(&mut self, error: &ParseError) {
+        self.push(Diagnostic::from(error));
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// True if any of the diagnostics is an error, not just a warning.
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    /// Put the diagnostics in source order. Ones without a location go
+    /// last; otherwise, the order they were reported in is kept.
+    pub fn sort(&mut self) {
+        self.diagnostics.sort_by_key(|d| d.loc.map_or(usize::MAX, |loc| loc.start));
+    }
+
+    pub fn into_vec(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.diagnostics.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TABLES};
+
+    #[test]
+    fn diagnostics() {
+        use super::{Diagnostic, Diagnostics, Severity};
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let diagnostic = |src: &str| match Parser::new_script(&handler).parse_script(src) {
+            Ok(_) => panic!("expected an error: {}", src),
+            Err(error) => Diagnostic::from(&error),
+        };
+
+        let d = diagnostic("let x = 1;\nvar x;");
+        assert_eq!((d.severity, d.code), (Severity::Error, "redeclaration"));
+        assert_eq!(d.message, "redeclaration of `x`");
+        assert_eq!(d.loc.map(|loc| loc.start), Some(15));
+        assert_eq!(d.labels.len(), 1);
+        assert_eq!((d.labels[0].loc.start, d.labels[0].message.as_str()), (4, "first declared here"));
+        assert_eq!(
+            d.to_string(),
+            "error[redeclaration]: redeclaration of `x` at line 2, column 4\n    \
+             note: first declared here at line 1, column 4"
+        );
+        assert_eq!(diagnostic("function f(a, a) { 'use strict'; }").labels[0].loc.start, 11);
+
+        let d = diagnostic("f(a;");
+        assert_eq!(d.code, "syntax-error");
+        let fix = d.fix.unwrap();
+        assert_eq!(fix.message, "insert `)`");
+        assert_eq!(fix.edit.apply("f(a;").as_deref(), Some("f(a);"));
+        assert_eq!(diagnostic("{ a").fix.unwrap().edit.apply("{ a").as_deref(), Some("{ a}"));
+        assert_eq!(diagnostic("x = '\\u';").code, "lex-error");
+
+        // Common mistakes get a fix of their own.
+        let fixed = |src: &str| {
+            let fix = diagnostic(src).fix.unwrap();
+            (fix.message, fix.edit.apply(src).unwrap())
+        };
+        let (message, src) = fixed("if (a + b = c) {}");
+        assert_eq!((message.as_str(), src.as_str()), ("replace `=` with `===`", "if (a + b === c) {}"));
+        assert_eq!(fixed("if (a{}").1, "if (a){}");
+        let (message, src) = fixed("var class = 1;");
+        assert_eq!(message, "rename `class`, which is a reserved word, to `class_`");
+        assert_eq!(src, "var class_ = 1;");
+        assert_eq!(fixed("'use strict'; var yield = 1;").1, "'use strict'; var yield_ = 1;");
+        assert_eq!(fixed("function f(a, class) {}").1, "function f(a, class_) {}");
+        // Where a statement could start, renaming isn't the fix.
+        for src in ["if (1) function f() {}", "while (1) function f() {}", "a in b"] {
+            assert!(!diagnostic(src).fix.is_some_and(|fix| fix.message.starts_with("rename")), "{}", src);
+        }
+
+        // One parse in recovery mode reports every problem.
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+        assert!(parser.parse_script("a = ;\nb = ;\nlet c; let c;").is_ok());
+        let mut diagnostics = parser.diagnostics();
+        diagnostics.sort();
+        let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["syntax-error", "syntax-error", "redeclaration"]);
+        assert!(diagnostics.has_errors());
+        assert!(!Diagnostics::new().has_errors());
+    }
+}
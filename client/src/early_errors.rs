@@ -0,0 +1,1960 @@
+//! Early errors that the grammar doesn't rule out by itself.
+//!
+//! The parser tells `EarlyErrors` about every token it shifts and every
+//! nonterminal it reduces. From that it keeps track of which code is strict
+//! mode code, which names each scope declares, and which labels are in
+//! effect, and reports the errors that breaks. It also feeds scope
+//! analysis, if that's turned on, and collects what a module imports and
+//! exports.
+
+use crate::lexer;
+use crate::parser_generated::{NonterminalId, TerminalId};
+use crate::modules::{ModuleLog, ModuleSummary, DEFAULT_LOCAL_NAME};
+use crate::parser_runtime::{SourceLocation, Token};
+use crate::patterns::PatternLog;
+use crate::regexp;
+use crate::scope::{BindingKind, ScopeKind, ScopeLog};
+use crate::stack::States;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EarlyErrorKind {
+    /// A `with` statement in strict mode code.
+    With,
+    /// A legacy octal literal like `010` in strict mode code.
+    OctalLiteral,
+    /// A decimal literal with a leading zero, like `08`, in strict mode code.
+    LeadingZero,
+    /// A legacy octal escape like `"\01"`, or `\8` or `\9`, in strict mode
+    /// code.
+    OctalEscape,
+    /// `eval` or `arguments` as a binding name in strict mode code.
+    BindEvalOrArguments,
+    /// An assignment to `eval` or `arguments` in strict mode code.
+    AssignEvalOrArguments,
+    DuplicateParameter,
+    /// A name declared twice in the same scope, where at least one of the
+    /// declarations is lexical: `let`, `const`, `class`, and so on.
+    Redeclaration,
+    /// A labelled statement inside another with the same label.
+    DuplicateLabel,
+    /// `break` or `continue` to a label that isn't on an enclosing
+    /// statement, or, for `continue`, isn't on a loop.
+    UndefinedLabel,
+    /// Something in the parentheses before an arrow function's `=>` that
+    /// isn't a parameter, like `a + b`.
+    InvalidParameter,
+    /// Something on the left of an assignment that can't be assigned to,
+    /// like `f()` or `1`.
+    InvalidAssignmentTarget,
+    /// `yield` used as a name in a generator or in strict mode code,
+    /// `await` used as one in an async function or in module code, or
+    /// `let` as a name that `let`, `const` or `class` declares.
+    ReservedWord,
+    /// A yield expression in a generator's parameters, or an await
+    /// expression in an async function's.
+    YieldOrAwaitInParameters,
+    /// A module that exports the same name twice.
+    DuplicateExport,
+    /// A local export, like `export {x}`, of a name that the module
+    /// doesn't declare.
+    UndeclaredExport,
+    /// `import.meta` in a script.
+    ImportMetaOutsideModule,
+    /// A private name, like the `#x` in `this.#x`, that no enclosing class
+    /// declares.
+    UndeclaredPrivateName,
+    /// A class that declares the same private name twice, other than as a
+    /// getter and a setter.
+    DuplicatePrivateName,
+    /// `#constructor` as the name of a class element.
+    PrivateConstructor,
+    /// A private name for a method in an object literal.
+    PrivateNameOutsideClass,
+    /// A class with more than one method named `constructor`.
+    DuplicateConstructor,
+    /// A shorthand property with a default value, like `{a = 1}`, in an
+    /// object literal that isn't a destructuring pattern.
+    ShorthandInitializer,
+    /// An object literal that sets `__proto__: value` more than once, and
+    /// isn't a destructuring pattern.
+    DuplicateProto,
+    /// A rest parameter in parentheses that aren't an arrow function's
+    /// parameters, like `(a, ...b);`.
+    RestOutsideParameters,
+    /// `??` and `&&` or `||` in the same expression without parentheses,
+    /// like `a ?? b || c`.
+    MixedCoalesce,
+    /// A tagged template in an optional chain, like a?.b`c`.
+    TaggedTemplateInOptionalChain,
+    /// A regular expression literal that doesn't match the RegExp grammar,
+    /// or has bad flags, and why.
+    InvalidRegExp(&'static str),
+    /// An escape sequence with no cooked value, like `\x` or `\01`, in a
+    /// template that isn't tagged.
+    InvalidTemplateEscape,
+    /// A `\u` escape in a name that stands for a character the name can't
+    /// have there, like `\u0031x` or `a\u002eb`.
+    InvalidIdentifierEscape,
+    /// A "use strict" directive in a function whose parameters aren't
+    /// simple: with default values, a rest parameter, or patterns.
+    UseStrictWithNonSimpleParameters,
+}
+
+impl EarlyErrorKind {
+    /// A short name for the kind, like `redeclaration`, that stays the same
+    /// when the message changes. See Diagnostic::code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EarlyErrorKind::With => "with",
+            EarlyErrorKind::OctalLiteral => "octal-literal",
+            EarlyErrorKind::LeadingZero => "leading-zero",
+            EarlyErrorKind::OctalEscape => "octal-escape",
+            EarlyErrorKind::BindEvalOrArguments => "bind-eval-or-arguments",
+            EarlyErrorKind::AssignEvalOrArguments => "assign-eval-or-arguments",
+            EarlyErrorKind::DuplicateParameter => "duplicate-parameter",
+            EarlyErrorKind::Redeclaration => "redeclaration",
+            EarlyErrorKind::DuplicateLabel => "duplicate-label",
+            EarlyErrorKind::UndefinedLabel => "undefined-label",
+            EarlyErrorKind::InvalidParameter => "invalid-parameter",
+            EarlyErrorKind::InvalidAssignmentTarget => "invalid-assignment-target",
+            EarlyErrorKind::ReservedWord => "reserved-word",
+            EarlyErrorKind::YieldOrAwaitInParameters => "yield-or-await-in-parameters",
+            EarlyErrorKind::DuplicateExport => "duplicate-export",
+            EarlyErrorKind::UndeclaredExport => "undeclared-export",
+            EarlyErrorKind::ImportMetaOutsideModule => "import-meta-outside-module",
+            EarlyErrorKind::UndeclaredPrivateName => "undeclared-private-name",
+            EarlyErrorKind::DuplicatePrivateName => "duplicate-private-name",
+            EarlyErrorKind::PrivateConstructor => "private-constructor",
+            EarlyErrorKind::PrivateNameOutsideClass => "private-name-outside-class",
+            EarlyErrorKind::DuplicateConstructor => "duplicate-constructor",
+            EarlyErrorKind::ShorthandInitializer => "shorthand-initializer",
+            EarlyErrorKind::DuplicateProto => "duplicate-proto",
+            EarlyErrorKind::RestOutsideParameters => "rest-outside-parameters",
+            EarlyErrorKind::MixedCoalesce => "mixed-coalesce",
+            EarlyErrorKind::TaggedTemplateInOptionalChain => "tagged-template-in-optional-chain",
+            EarlyErrorKind::InvalidRegExp(_) => "invalid-regexp",
+            EarlyErrorKind::InvalidTemplateEscape => "invalid-template-escape",
+            EarlyErrorKind::InvalidIdentifierEscape => "invalid-identifier-escape",
+            EarlyErrorKind::UseStrictWithNonSimpleParameters => "use-strict-with-non-simple-parameters",
+        }
+    }
+}
+
+impl fmt::Display for EarlyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let EarlyErrorKind::InvalidRegExp(reason) = self {
+            return write!(f, "invalid regular expression: {}", reason);
+        }
+        f.write_str(match self {
+            EarlyErrorKind::With => "with statements are not allowed in strict mode code",
+            EarlyErrorKind::OctalLiteral => "octal literals are not allowed in strict mode code",
+            EarlyErrorKind::LeadingZero => {
+                "decimal literals with a leading zero are not allowed in strict mode code"
+            }
+            EarlyErrorKind::OctalEscape => {
+                "octal escape sequences are not allowed in strict mode code"
+            }
+            EarlyErrorKind::BindEvalOrArguments => {
+                "eval and arguments can't be bound in strict mode code"
+            }
+            EarlyErrorKind::AssignEvalOrArguments => {
+                "eval and arguments can't be assigned to in strict mode code"
+            }
+            EarlyErrorKind::DuplicateParameter => "duplicate parameter name",
+            EarlyErrorKind::Redeclaration => "redeclaration of",
+            EarlyErrorKind::DuplicateLabel => "duplicate label",
+            EarlyErrorKind::UndefinedLabel => "undefined label",
+            EarlyErrorKind::InvalidParameter => "invalid arrow function parameter",
+            EarlyErrorKind::InvalidAssignmentTarget => "invalid assignment target",
+            EarlyErrorKind::ReservedWord => "unexpected reserved word",
+            EarlyErrorKind::YieldOrAwaitInParameters => "parameters can't contain",
+            EarlyErrorKind::DuplicateExport => "duplicate export",
+            EarlyErrorKind::UndeclaredExport => "export of undeclared",
+            EarlyErrorKind::ImportMetaOutsideModule => "import.meta is only allowed in modules",
+            EarlyErrorKind::UndeclaredPrivateName => "reference to undeclared private name",
+            EarlyErrorKind::DuplicatePrivateName => "duplicate private name",
+            EarlyErrorKind::PrivateConstructor => "a class element can't be named #constructor",
+            EarlyErrorKind::PrivateNameOutsideClass => "private names are only allowed in classes",
+            EarlyErrorKind::DuplicateConstructor => "a class can't have more than one constructor",
+            EarlyErrorKind::ShorthandInitializer => {
+                "shorthand properties can only have a default value in a destructuring pattern"
+            }
+            EarlyErrorKind::RestOutsideParameters => {
+                "a rest parameter in parentheses must be followed by =>"
+            }
+            EarlyErrorKind::DuplicateProto => "an object literal can only set __proto__ once",
+            EarlyErrorKind::MixedCoalesce => "?? can't be mixed with && or || without parentheses",
+            EarlyErrorKind::TaggedTemplateInOptionalChain => {
+                "tagged templates aren't allowed in optional chains"
+            }
+            EarlyErrorKind::InvalidRegExp(reason) => reason,
+            EarlyErrorKind::InvalidTemplateEscape => "invalid escape sequences are only allowed in tagged templates",
+            EarlyErrorKind::InvalidIdentifierEscape => "escape sequence for a character that can't be in a name here",
+            EarlyErrorKind::UseStrictWithNonSimpleParameters => {
+                "\"use strict\" isn't allowed in a function with non-simple parameters"
+            }
+        })
+    }
+}
+
+/// What `yield` and `await` mean in a function. These are the grammar's
+/// [Yield] and [Await] parameters, which the parser tables leave out:
+/// outside generators `yield` is an identifier, and so is `await` outside
+/// async functions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FunctionKind {
+    pub generator: bool,
+    pub is_async: bool,
+}
+
+/// An early error, with the name it's about for the kinds that have one,
+/// and for a name declared twice, where it was declared first.
+pub type EarlyError = (EarlyErrorKind, Option<String>, SourceLocation, Option<SourceLocation>);
+
+#[derive(Clone, Debug)]
+struct Binding {
+    name: String,
+    loc: SourceLocation,
+}
+
+impl Binding {
+    fn is_eval_or_arguments(&self) -> bool {
+        self.name == "eval" || self.name == "arguments"
+    }
+}
+
+/// How far the parser has got through a directive prologue: the string
+/// literal statements at the start of a function body or a script.
+#[derive(Clone, Copy, Debug)]
+enum Prologue {
+    /// The next token might start a directive.
+    Start,
+    /// Just after the string literal at `loc`, which is a directive if the
+    /// statement ends here.
+    AfterString { use_strict: bool, loc: SourceLocation },
+    Done,
+}
+
+/// The names declared directly in a block, function body, or script.
+#[derive(Clone, Debug, Default)]
+struct Scope {
+    id: usize,
+    /// Names declared with let, const or class, or by a function
+    /// declaration in a block. The flag is true for functions.
+    lexical: Vec<(Binding, bool)>,
+    /// Names declared with var, here or in a block inside this scope.
+    vars: Vec<Binding>,
+    /// Names bound just outside the scope that a lexical declaration in it
+    /// can't reuse: the function's parameters, or the catch clause's.
+    params: Vec<Binding>,
+    /// True for the scope of a catch clause's parameter.
+    catch: bool,
+}
+
+/// What a private name names. A getter and a setter can share a name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PrivateKind {
+    Getter,
+    Setter,
+    Other,
+}
+
+/// The private names in a class body, and its constructor.
+#[derive(Clone, Debug, Default)]
+struct ClassBody {
+    declared: Vec<(String, PrivateKind)>,
+    /// Names used in the class, which it or an enclosing class has to
+    /// declare. A class can use a name before its declaration, so they're
+    /// checked at the end of the class.
+    references: Vec<Binding>,
+    /// Where the name of the first method named `constructor` is.
+    constructor: Option<SourceLocation>,
+}
+
+#[derive(Clone, Debug)]
+struct Function {
+    strict: bool,
+    kind: FunctionKind,
+    prologue: Prologue,
+    params: Vec<Binding>,
+    /// True if the parameters can never have duplicates, as for methods.
+    unique_params: bool,
+    /// False if the parameters have default values, a rest parameter, or
+    /// patterns, which rules out a "use strict" directive.
+    simple_params: bool,
+    /// An octal escape in an earlier directive. It's an error if a later
+    /// directive is "use strict".
+    octal_directive: Option<SourceLocation>,
+    scope: Scope,
+}
+
+impl Function {
+    fn new(strict: bool, kind: FunctionKind, scope_id: usize) -> Function {
+        Function {
+            strict,
+            kind,
+            prologue: Prologue::Start,
+            params: vec![],
+            unique_params: false,
+            simple_params: true,
+            octal_directive: None,
+            scope: Scope {
+                id: scope_id,
+                ..Scope::default()
+            },
+        }
+    }
+}
+
+/// A syntactic construct that affects early errors. Each one starts at a
+/// token: `depth` is the length of the parser's state stack just after that
+/// token was shifted, and `state` is the state it pushed. The construct is
+/// over once that state is popped.
+#[derive(Clone, Debug)]
+struct Frame {
+    depth: usize,
+    state: usize,
+    kind: Kind,
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    /// A function body, or the whole script or module.
+    Function(Function),
+    /// A block, or the head of a `for` statement or a catch clause.
+    Block(Scope),
+    /// A class, all of which is strict mode code.
+    Class(ClassBody),
+    /// A function's parameter list, the id of the function's scope, what
+    /// kind of function it is, and whether the list is simple: without
+    /// default values, a rest parameter, or patterns.
+    Params(Vec<Binding>, usize, FunctionKind, bool),
+    /// An arrow function's body, the names its parameters bind, and whether
+    /// they're simple. If the body is a block, the Function frame for it is
+    /// inside this one.
+    ArrowBody(FunctionKind, Vec<Binding>, bool),
+    /// The body of a labelled statement, and whether it's a loop, once the
+    /// tokens it starts with tell.
+    Label(String, Option<bool>),
+}
+
+impl Frame {
+    fn new(state_stack: &impl States, kind: Kind) -> Frame {
+        Frame {
+            depth: state_stack.depth(),
+            state: state_stack.state_at(state_stack.depth() - 1).unwrap_or(0),
+            kind,
+        }
+    }
+
+    fn is_over(&self, state_stack: &impl States) -> bool {
+        state_stack.state_at(self.depth.wrapping_sub(1)) != Some(self.state)
+    }
+
+    fn scope_mut(&mut self) -> Option<&mut Scope> {
+        match &mut self.kind {
+            Kind::Function(Function { scope, .. }) | Kind::Block(scope) => Some(scope),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EarlyErrors {
+    /// The innermost construct is last. The first frame, for the script or
+    /// module, is never popped.
+    frames: Vec<Frame>,
+    next_scope_id: usize,
+    /// The parameters of the function whose body comes next, its scope id,
+    /// whether the parameters have to be unique, and whether they're simple.
+    pending_params: Option<(Vec<Binding>, usize, bool, bool)>,
+    /// Bound names whose declaration hasn't been reduced yet, with the id
+    /// of the scope they're in.
+    pending_bindings: Vec<(Binding, usize)>,
+    /// The label just reduced, if the next token might be its `:`.
+    pending_label: Option<Binding>,
+    /// The last two terminals shifted, newest first.
+    previous: [Option<TerminalId>; 2],
+    /// The last token shifted, if it was an identifier.
+    last_identifier: Option<Binding>,
+    /// The last token shifted, if it was a private name.
+    last_private_name: Option<Binding>,
+    /// Private names that name class elements whose ClassElement hasn't
+    /// been reduced yet.
+    pending_private_names: Vec<(Binding, PrivateKind)>,
+    /// The last token shifted, if it was `constructor`, as a name or a
+    /// string.
+    last_constructor: Option<SourceLocation>,
+    /// Where each class element or method named `constructor` is named,
+    /// until its ClassElement or PropertyDefinition is reduced, and the
+    /// method just reduced, with its name, if it's one of them.
+    pending_constructors: Vec<SourceLocation>,
+    constructor_method: Option<(SourceLocation, SourceLocation)>,
+    /// True for a function body parsed apart from the code around it.
+    /// Private names it uses but doesn't declare belong to the classes
+    /// around it, which this can't see; they go in `outer_private_names`
+    /// instead of being errors.
+    nested: bool,
+    outer_private_names: Vec<Binding>,
+    /// `eval` or `arguments` just after `++` or `--`. It's an error unless
+    /// the next token makes it part of a bigger expression.
+    prefix_update: Option<SourceLocation>,
+    errors: Vec<EarlyError>,
+    /// Everything scope analysis needs, if it's turned on.
+    scopes: Option<ScopeLog>,
+    /// Where the last token shifted starts.
+    last_start: usize,
+    /// For scope analysis: where each `let` or `const` whose declaration
+    /// hasn't been reduced yet starts, and which it is.
+    lexical_keywords: Vec<(usize, BindingKind)>,
+    /// For scope analysis: the parameters of each arrow function whose
+    /// body hasn't been reduced yet, and where its `=>` starts.
+    arrows: Vec<(SourceLocation, Option<usize>)>,
+    /// What the module imports and exports, if this is module code.
+    module: Option<ModuleLog>,
+    /// The expressions that might turn out to be destructuring patterns.
+    patterns: PatternLog,
+    /// Template pieces with an escape sequence that has no cooked value.
+    /// They're errors unless the template turns out to be tagged.
+    bad_templates: Vec<SourceLocation>,
+}
+
+impl EarlyErrors {
+    pub fn new(strict: bool) -> EarlyErrors {
+        EarlyErrors {
+            nested: false,
+            ..EarlyErrors::new_function(strict, FunctionKind::default())
+        }
+    }
+
+    /// Start in the body of a function of the given kind, as for a function
+    /// body that a lazy parse skipped. Private names that the body uses
+    /// aren't checked, since that needs the classes around it.
+    pub fn new_function(strict: bool, kind: FunctionKind) -> EarlyErrors {
+        EarlyErrors {
+            frames: vec![Frame {
+                depth: 0,
+                state: 0,
+                kind: Kind::Function(Function::new(strict, kind, 0)),
+            }],
+            next_scope_id: 1,
+            pending_params: None,
+            pending_bindings: vec![],
+            pending_label: None,
+            previous: [None, None],
+            last_identifier: None,
+            last_private_name: None,
+            pending_private_names: vec![],
+            last_constructor: None,
+            pending_constructors: vec![],
+            constructor_method: None,
+            nested: true,
+            outer_private_names: vec![],
+            prefix_update: None,
+            errors: vec![],
+            scopes: None,
+            last_start: 0,
+            lexical_keywords: vec![],
+            arrows: vec![],
+            module: None,
+            patterns: PatternLog::new(),
+            bad_templates: vec![],
+        }
+    }
+
+    /// Start recording what scope analysis needs. Call this before the
+    /// first token is shifted.
+    pub fn record_scopes(&mut self) {
+        self.scopes = Some(ScopeLog::new());
+    }
+
+    pub fn is_recording_scopes(&self) -> bool {
+        self.scopes.is_some()
+    }
+
+    /// Take what scope analysis has recorded.
+    pub fn take_scopes(&mut self) -> Option<ScopeLog> {
+        self.scopes.take()
+    }
+
+    /// Treat the code as module code, and start recording what the module
+    /// imports and exports. Call this before the first token is shifted.
+    pub fn record_module(&mut self) {
+        self.module = Some(ModuleLog::new());
+    }
+
+    pub fn is_module(&self) -> bool {
+        self.module.is_some()
+    }
+
+    /// Take what the module imports and exports.
+    pub fn take_module(&mut self) -> Option<ModuleSummary> {
+        self.module.take().map(ModuleLog::finish)
+    }
+
+    /// What kind of function the code at the top of `state_stack` is in.
+    pub fn function_kind(&self, state_stack: &impl States) -> FunctionKind {
+        for (i, frame) in self.frames.iter().enumerate().rev() {
+            if i > 0 && frame.is_over(state_stack) {
+                continue;
+            }
+            match &frame.kind {
+                Kind::Function(Function { kind, .. }) | Kind::Params(_, _, kind, _) | Kind::ArrowBody(kind, ..) => {
+                    return *kind
+                }
+                _ => {}
+            }
+        }
+        FunctionKind::default()
+    }
+
+    /// True if the code at the current position is strict mode code.
+    pub fn is_strict(&self) -> bool {
+        for frame in self.frames.iter().rev() {
+            match &frame.kind {
+                Kind::Function(f) => return f.strict,
+                Kind::Class(_) => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Take the errors found so far, oldest first.
+    pub fn take_errors(&mut self) -> Vec<EarlyError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Check `token`, which is about to be shifted onto `state_stack`.
+    /// `asi` is true if a semicolon was inserted before it.
+    pub fn shift(&mut self, token: &Token, state_stack: &impl States, asi: bool) {
+        while self.frames.len() > 1 && self.frames.last().is_some_and(|f| f.is_over(state_stack)) {
+            self.frames.pop();
+        }
+        self.directive_prologue(token, asi);
+        if self.is_strict() {
+            self.check_strict_token(token);
+        }
+        match token.get_id() {
+            TerminalId::RegularExpressionLiteral => self.check_regexp(token),
+            TerminalId::Identifier | TerminalId::IdentifierName | TerminalId::PrivateIdentifier
+                if token.raw.is_some() =>
+            {
+                self.check_escaped_name(token);
+            }
+            TerminalId::NoSubstitutionTemplate
+            | TerminalId::TemplateHead
+            | TerminalId::TemplateMiddle
+            | TerminalId::TemplateTail
+                if token.value.is_none() =>
+            {
+                self.bad_templates.push(token.loc);
+            }
+            _ => {}
+        }
+
+        // A default value, a rest parameter or a pattern makes a parameter
+        // list not simple. Anything nested in a parameter comes after one.
+        let not_simple = match token.get_id() {
+            TerminalId::EqualsSign | TerminalId::FullStopFullStopFullStop => true,
+            TerminalId::LeftSquareBracket | TerminalId::LeftCurlyBracket => {
+                matches!(self.previous[0], Some(TerminalId::LeftParenthesis) | Some(TerminalId::Comma))
+            }
+            _ => false,
+        };
+        if let (true, Some(Frame { kind: Kind::Params(_, _, _, simple), .. })) = (not_simple, self.frames.last_mut()) {
+            *simple = false;
+        }
+
+        if token.get_id() != TerminalId::Colon {
+            self.pending_label = None;
+        }
+        // A labelled statement's body is a loop if it starts with one, once
+        // any more labels are skipped. An identifier there is a label if the
+        // next token is `:`.
+        for frame in self.frames.iter_mut().rev() {
+            let is_loop = match &mut frame.kind {
+                Kind::Label(_, is_loop @ None) => is_loop,
+                _ => break,
+            };
+            match (self.previous[0], token.get_id()) {
+                (_, TerminalId::Colon) => {}
+                (Some(TerminalId::Colon), TerminalId::For | TerminalId::While | TerminalId::Do) => *is_loop = Some(true),
+                (
+                    Some(TerminalId::Colon),
+                    TerminalId::Identifier
+                    | TerminalId::Yield
+                    | TerminalId::Await
+                    | TerminalId::Let
+                    | TerminalId::Async,
+                ) => {}
+                _ => *is_loop = Some(false),
+            }
+        }
+        self.patterns.shift(token, self.is_strict());
+        let errors = self.patterns.take_errors();
+        self.errors.extend(errors);
+        if self.scopes.is_some() {
+            match token.get_id() {
+                TerminalId::Let => self.lexical_keywords.push((token.loc.start, BindingKind::Let)),
+                TerminalId::Const => self.lexical_keywords.push((token.loc.start, BindingKind::Const)),
+                TerminalId::Arrow => {
+                    if let Some((_, arrow @ None)) = self.arrows.last_mut() {
+                        *arrow = Some(token.loc.start);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(log) = self.module.as_mut() {
+            log.shift(token);
+        }
+        self.last_start = token.loc.start;
+        self.previous = [Some(token.get_id()), self.previous[0]];
+        self.last_identifier = match (token.get_id(), &token.value) {
+            (TerminalId::Identifier, Some(name)) => Some(Binding {
+                name: name.to_string(),
+                loc: token.loc,
+            }),
+            _ => None,
+        };
+        // A private name before `in` is a use too, as in `#x in obj`.
+        if token.get_id() == TerminalId::In {
+            if let Some(binding) = self.last_private_name.take() {
+                self.use_private_name(binding);
+            }
+        }
+        self.last_private_name = match (token.get_id(), &token.value) {
+            (TerminalId::PrivateIdentifier, Some(name)) => Some(Binding {
+                name: name.to_string(),
+                loc: token.loc,
+            }),
+            _ => None,
+        };
+        self.last_constructor = match (token.get_id(), token.value.as_deref()) {
+            (TerminalId::Identifier | TerminalId::IdentifierName | TerminalId::StringLiteral, Some("constructor")) => {
+                Some(token.loc)
+            }
+            _ => None,
+        };
+        // After a `.` or `?.`, it's a use, as in `this.#x`, and otherwise
+        // the name of a class element.
+        let after_dot = matches!(
+            self.previous[1],
+            Some(TerminalId::FullStop) | Some(TerminalId::QuestionMarkFullStop)
+        );
+        if let (true, Some(binding)) = (after_dot, &self.last_private_name) {
+            let binding = binding.clone();
+            self.use_private_name(binding);
+        }
+    }
+
+    /// `{`, the start of the body of a function of the given kind, was just
+    /// shifted onto `state_stack`.
+    pub fn enter_function(&mut self, state_stack: &impl States, kind: FunctionKind) {
+        let arrow_params = match (self.previous[1], self.frames.last()) {
+            (Some(TerminalId::Arrow), Some(Frame { kind: Kind::ArrowBody(_, params, simple), .. })) => {
+                Some((params.clone(), *simple))
+            }
+            _ => None,
+        };
+        let arrow = arrow_params.is_some();
+        let (params, id, unique, simple) = match (self.pending_params.take(), arrow_params) {
+            (Some(pending), _) => pending,
+            (None, Some((params, simple))) => {
+                let arrow = self.arrows.last().and_then(|&(_, arrow)| arrow);
+                (params, self.new_scope_id(ScopeKind::Function, state_stack, arrow), true, simple)
+            }
+            // An empty parameter list is never reduced, so its frame is
+            // still open.
+            (None, None) => match self.pop_params() {
+                Some(params) => params,
+                // A getter.
+                None => (vec![], self.new_scope_id(ScopeKind::Function, state_stack, None), false, true),
+            },
+        };
+        let strict = self.is_strict();
+        // An arrow function's parameters were checked at its `=>`, and
+        // scope analysis declares them once the arrow function is reduced.
+        if (strict || unique) && !arrow {
+            // Bindings of eval and arguments were already reported if the
+            // function is in strict mode code.
+            self.check_params(&params, true, false);
+        }
+        if let (Some(log), false) = (self.scopes.as_mut(), arrow) {
+            for param in &params {
+                log.declare(id, &param.name, BindingKind::Param, param.loc);
+            }
+        }
+        let mut function = Function::new(strict, kind, id);
+        function.scope.params = params.clone();
+        function.params = params;
+        function.unique_params = unique;
+        function.simple_params = simple;
+        self.frames.push(Frame::new(state_stack, Kind::Function(function)));
+    }
+
+    /// `{`, the start of a block, or the `(` of a `for` statement or catch
+    /// clause was just shifted onto `state_stack`. `catch` is true for the
+    /// `(` of a catch clause.
+    pub fn enter_block(&mut self, state_stack: &impl States, catch: bool) {
+        let kind = if catch { ScopeKind::Catch } else { ScopeKind::Block };
+        let mut scope = Scope {
+            id: self.new_scope_id(kind, state_stack, None),
+            catch,
+            ..Scope::default()
+        };
+        if let Some(Frame { kind: Kind::Block(outer), .. }) = self.frames.last() {
+            if outer.catch {
+                // This is the catch clause's block.
+                scope.params = outer.lexical.iter().map(|(binding, _)| binding.clone()).collect();
+            }
+        }
+        self.frames.push(Frame::new(state_stack, Kind::Block(scope)));
+    }
+
+    /// `class` was just shifted onto `state_stack`.
+    pub fn enter_class(&mut self, state_stack: &impl States) {
+        self.frames.push(Frame::new(state_stack, Kind::Class(ClassBody::default())));
+    }
+
+    /// `(`, the start of the parameter list of a function of the given
+    /// kind, was just shifted onto `state_stack`.
+    pub fn enter_params(&mut self, state_stack: &impl States, kind: FunctionKind) {
+        let id = self.new_scope_id(ScopeKind::Function, state_stack, None);
+        self.frames.push(Frame::new(state_stack, Kind::Params(vec![], id, kind, true)));
+    }
+
+    /// `=>` was just shifted onto `state_stack`. If the arrow function's
+    /// body is an expression, it's in a function of the given kind.
+    pub fn enter_arrow_body(&mut self, state_stack: &impl States, kind: FunctionKind) {
+        // An arrow function's parameters can never have duplicates.
+        let mut params = vec![];
+        let simple = self.patterns.arrow_parameters_simple();
+        if let Some((loc, names)) = self.patterns.arrow_parameter_names() {
+            params.extend(names.into_iter().map(|(name, loc)| Binding { name, loc }));
+            let bound = self.pending_bindings.iter().map(|(b, _)| b);
+            params.extend(bound.filter(|b| loc.start <= b.loc.start && b.loc.end <= loc.end).cloned());
+            params.sort_by_key(|b| b.loc.start);
+        }
+        self.check_params(&params, true, false);
+        self.frames.push(Frame::new(state_stack, Kind::ArrowBody(kind, params, simple)));
+    }
+
+    /// `:` was just shifted onto `state_stack`. If it follows a label, the
+    /// labelled statement's body starts here.
+    pub fn enter_label(&mut self, state_stack: &impl States) {
+        let label = match self.pending_label.take() {
+            Some(label) => label,
+            None => return,
+        };
+        for frame in self.frames.iter().rev() {
+            match &frame.kind {
+                Kind::Label(name, _) if *name == label.name => {
+                    self.error(EarlyErrorKind::DuplicateLabel, Some(&label.name), label.loc);
+                    break;
+                }
+                // Labels don't reach into functions.
+                Kind::Function(_) | Kind::Params(..) => break,
+                _ => {}
+            }
+        }
+        self.frames.push(Frame::new(state_stack, Kind::Label(label.name, None)));
+    }
+
+    /// `label` follows `break` or `continue` (`jump`). It has to name an
+    /// enclosing labelled statement, and for `continue`, a loop.
+    fn check_jump_target(&mut self, jump: TerminalId, label: &Binding) {
+        for frame in self.frames.iter().rev() {
+            match &frame.kind {
+                Kind::Label(name, is_loop) if *name == label.name => {
+                    if jump == TerminalId::Break || *is_loop == Some(true) {
+                        return;
+                    }
+                    break;
+                }
+                Kind::Function(_) | Kind::Params(..) => break,
+                _ => {}
+            }
+        }
+        self.error(EarlyErrorKind::UndefinedLabel, Some(&label.name), label.loc);
+    }
+
+    /// The parser reduced `nt`, spanning `loc`, leaving `state_stack`.
+    pub fn reduced(&mut self, nt: NonterminalId, loc: SourceLocation, state_stack: &impl States) {
+        self.pending_label = None;
+        self.patterns.reduced(nt, loc, self.is_strict());
+        let errors = self.patterns.take_errors();
+        self.errors.extend(errors);
+        if !self.bad_templates.is_empty() {
+            self.check_templates(nt, loc);
+        }
+        match nt {
+            NonterminalId::BindingIdentifier => {
+                let binding = match &self.last_identifier {
+                    Some(binding) if binding.loc == loc => binding.clone(),
+                    // `yield` or `await`, where it's a keyword.
+                    _ => {
+                        let name = match self.previous[0] {
+                            Some(TerminalId::Yield) => "yield",
+                            _ => "await",
+                        };
+                        self.error(EarlyErrorKind::ReservedWord, Some(name), loc);
+                        return;
+                    }
+                };
+                if self.is_strict() && binding.is_eval_or_arguments() {
+                    self.error(EarlyErrorKind::BindEvalOrArguments, None, loc);
+                }
+                self.bind(binding);
+            }
+            NonterminalId::IdentifierReference if self.scopes.is_some() => {
+                if let Some(binding) = self.last_identifier.clone().filter(|id| id.loc == loc) {
+                    let scope = self.current_scope(state_stack);
+                    if let Some(log) = self.scopes.as_mut() {
+                        log.reference(scope, &binding.name, loc);
+                    }
+                }
+            }
+            NonterminalId::ArrowParameters if self.scopes.is_some() => self.arrows.push((loc, None)),
+            NonterminalId::ImportedBinding
+            | NonterminalId::ImportedDefaultBinding
+            | NonterminalId::NameSpaceImport
+            | NonterminalId::ImportSpecifier
+            | NonterminalId::ExportSpecifier
+            | NonterminalId::ModuleSpecifier
+            | NonterminalId::ExportDeclaration
+            | NonterminalId::Module => self.module_item(nt, loc),
+            NonterminalId::ClassElementName => {
+                if self.last_constructor == Some(loc) {
+                    self.pending_constructors.push(loc);
+                }
+                if let Some(binding) = self.last_private_name.clone().filter(|b| b.loc == loc) {
+                    if binding.name == "#constructor" {
+                        self.error(EarlyErrorKind::PrivateConstructor, None, loc);
+                    }
+                    let kind = match self.previous[1] {
+                        Some(TerminalId::Get) => PrivateKind::Getter,
+                        Some(TerminalId::Set) => PrivateKind::Setter,
+                        _ => PrivateKind::Other,
+                    };
+                    self.pending_private_names.push((binding, kind));
+                }
+            }
+            // The names of the methods inside this one were taken when they
+            // were reduced, so any name left is this one's.
+            NonterminalId::MethodDefinition => {
+                self.constructor_method = self.take_constructors(loc).first().map(|&name| (loc, name));
+            }
+            NonterminalId::ClassElement => {
+                for (binding, kind) in self.take_private_names(loc) {
+                    self.declare_private_name(binding, kind, state_stack);
+                }
+                // A static method can be named `constructor`, and a field
+                // named that is an error of its own.
+                self.take_constructors(loc);
+                match self.constructor_method.take() {
+                    Some((method, name)) if method.start == loc.start => self.declare_constructor(name, state_stack),
+                    _ => {}
+                }
+            }
+            NonterminalId::PropertyDefinition => {
+                self.take_constructors(loc);
+                self.constructor_method = None;
+                for (binding, _) in self.take_private_names(loc) {
+                    self.error(EarlyErrorKind::PrivateNameOutsideClass, Some(&binding.name), binding.loc);
+                }
+            }
+            NonterminalId::ClassTail => self.check_private_names(state_stack),
+            NonterminalId::OptionalChain
+                if matches!(
+                    self.previous[0],
+                    Some(TerminalId::NoSubstitutionTemplate) | Some(TerminalId::TemplateTail)
+                ) =>
+            {
+                self.error(EarlyErrorKind::TaggedTemplateInOptionalChain, None, loc);
+            }
+            NonterminalId::ImportMeta if self.module.is_none() => {
+                self.error(EarlyErrorKind::ImportMetaOutsideModule, None, loc);
+            }
+            NonterminalId::YieldExpression | NonterminalId::AwaitExpression => {
+                let in_params = self
+                    .frames
+                    .iter()
+                    .rev()
+                    .find(|f| !f.is_over(state_stack))
+                    .is_some_and(|f| matches!(f.kind, Kind::Params(..)));
+                if in_params {
+                    let name = if nt == NonterminalId::YieldExpression { "yield" } else { "await" };
+                    self.error(EarlyErrorKind::YieldOrAwaitInParameters, Some(name), loc);
+                }
+                // `await` is a keyword throughout module code, but it's only
+                // an await expression at the top level and in async functions.
+                let in_function = self.frames.iter().skip(1).any(|f| {
+                    let function = matches!(f.kind, Kind::Function(_) | Kind::Params(..) | Kind::ArrowBody(..));
+                    function && !f.is_over(state_stack)
+                });
+                if nt == NonterminalId::AwaitExpression
+                    && self.module.is_some()
+                    && in_function
+                    && !self.function_kind(state_stack).is_async
+                {
+                    self.error(EarlyErrorKind::ReservedWord, Some("await"), loc);
+                }
+            }
+            NonterminalId::LabelIdentifier => {
+                // It's a label if the next token is `:`, and otherwise the
+                // target of a break or continue.
+                self.pending_label = self.last_identifier.clone().filter(|id| id.loc == loc);
+                if let (Some(jump @ (TerminalId::Break | TerminalId::Continue)), Some(label)) =
+                    (self.previous[1], self.pending_label.clone())
+                {
+                    self.check_jump_target(jump, &label);
+                }
+            }
+            NonterminalId::FormalParameters | NonterminalId::PropertySetParameterList => {
+                if let Some(params) = self.pop_params() {
+                    self.pending_params = Some(params);
+                }
+            }
+            NonterminalId::UniqueFormalParameters => {
+                if let Some((_, _, unique, _)) = self.pending_params.as_mut() {
+                    *unique = true;
+                }
+            }
+            NonterminalId::LexicalDeclaration
+            | NonterminalId::ForDeclaration
+            | NonterminalId::ClassDeclaration
+            | NonterminalId::CatchParameter
+            | NonterminalId::ImportDeclaration => {
+                let kind = match nt {
+                    NonterminalId::ClassDeclaration => BindingKind::Class,
+                    NonterminalId::CatchParameter => BindingKind::CatchParameter,
+                    NonterminalId::ImportDeclaration => BindingKind::Import,
+                    _ => self.lexical_keyword(loc),
+                };
+                for (binding, scope) in self.take_bindings(loc) {
+                    // In sloppy mode code `let` is only an identifier where
+                    // it can't be read as a keyword, so it can get here.
+                    let binds_let = matches!(kind, BindingKind::Let | BindingKind::Const | BindingKind::Class);
+                    if binds_let && binding.name == "let" {
+                        self.error(EarlyErrorKind::ReservedWord, Some("let"), binding.loc);
+                    }
+                    self.declare_lexical(binding, scope, kind);
+                }
+                if let (NonterminalId::ImportDeclaration, Some(log)) = (nt, self.module.as_mut()) {
+                    log.import_declaration();
+                }
+            }
+            // Every other name in an iteration statement was claimed by its
+            // own declaration, so what's left is from `for (var x in ...)`.
+            NonterminalId::VariableDeclarationList | NonterminalId::IterationStatement => {
+                for (binding, scope) in self.take_bindings(loc) {
+                    self.declare_var(binding, scope, BindingKind::Var);
+                }
+            }
+            NonterminalId::FunctionDeclaration
+            | NonterminalId::GeneratorDeclaration
+            | NonterminalId::AsyncFunctionDeclaration
+            | NonterminalId::AsyncGeneratorDeclaration => {
+                for (binding, scope) in self.take_bindings(loc) {
+                    self.declare_function(binding, scope);
+                }
+            }
+            // These names are only bound inside the function or class.
+            NonterminalId::FunctionExpression
+            | NonterminalId::GeneratorExpression
+            | NonterminalId::AsyncFunctionExpression
+            | NonterminalId::AsyncGeneratorExpression => {
+                let names = self.take_bindings(loc);
+                let outer = self.current_scope(state_stack);
+                if let Some(log) = self.scopes.as_mut() {
+                    if let Some(function) = log.first_scope_after(outer, loc.start) {
+                        for (binding, _) in names {
+                            log.declare(function, &binding.name, BindingKind::Callee, binding.loc);
+                        }
+                    }
+                }
+            }
+            NonterminalId::ClassExpression => {
+                self.take_bindings(loc);
+            }
+            NonterminalId::ArrowFunction | NonterminalId::AsyncArrowFunction | NonterminalId::AsyncArrowStatement => {
+                let params = self.take_bindings(loc);
+                if self.scopes.is_some() {
+                    self.arrow_function(loc, params, state_stack);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// If the innermost frame is a parameter list, pop it and return the
+    /// names in it, the function's scope id, whether the names have to be
+    /// unique, as they do in a list that isn't simple, and whether it is.
+    fn pop_params(&mut self) -> Option<(Vec<Binding>, usize, bool, bool)> {
+        if let Some(Frame { kind: Kind::Params(..), .. }) = self.frames.last() {
+            if let Some(Frame { kind: Kind::Params(names, id, _, simple), .. }) = self.frames.pop() {
+                return Some((names, id, !simple, simple));
+            }
+        }
+        None
+    }
+
+    /// Split off the function body that was just entered, for a separate
+    /// parser that starts with a state stack of length 1.
+    pub fn split_off_function(&mut self) -> EarlyErrors {
+        let mut early = EarlyErrors::new(self.is_strict());
+        early.next_scope_id = self.next_scope_id;
+        early.scopes = self.scopes.take();
+        early.lexical_keywords = std::mem::take(&mut self.lexical_keywords);
+        early.module = self.module.take();
+        early.nested = true;
+        if self.frames.len() > 1 {
+            if let Some(mut frame) = self.frames.pop() {
+                frame.depth = 1;
+                early.frames = vec![frame];
+            }
+        }
+        early
+    }
+
+    /// Take back what scope analysis and the module log recorded in `body`,
+    /// a function body split off by split_off_function(), and the private
+    /// names it used.
+    pub fn join_function(&mut self, body: EarlyErrors) {
+        self.next_scope_id = body.next_scope_id;
+        self.scopes = body.scopes;
+        self.module = body.module;
+        for binding in body.outer_private_names {
+            self.use_private_name(binding);
+        }
+    }
+
+    /// Make up an id for a new scope, which starts at the token just
+    /// shifted onto `state_stack`. `arrow` is where the `=>` starts, if the
+    /// scope is an arrow function's body.
+    fn new_scope_id(&mut self, kind: ScopeKind, state_stack: &impl States, arrow: Option<usize>) -> usize {
+        let parent = self.current_scope(state_stack);
+        if let Some(log) = self.scopes.as_mut() {
+            debug_assert_eq!(log.next_scope(), self.next_scope_id);
+            log.add_scope(kind, parent, self.last_start, arrow);
+        }
+        self.next_scope_id += 1;
+        self.next_scope_id - 1
+    }
+
+    /// The id of the innermost scope that isn't over, given `state_stack`.
+    fn current_scope(&self, state_stack: &impl States) -> usize {
+        for (i, frame) in self.frames.iter().enumerate().rev() {
+            if i > 0 && frame.is_over(state_stack) {
+                continue;
+            }
+            match &frame.kind {
+                Kind::Function(Function { scope, .. }) | Kind::Block(scope) => return scope.id,
+                Kind::Params(_, id, ..) => return *id,
+                _ => {}
+            }
+        }
+        0
+    }
+
+    /// Whether the lexical declaration at `loc` starts with `let` or
+    /// `const`.
+    fn lexical_keyword(&mut self, loc: SourceLocation) -> BindingKind {
+        // Skip any `let` that turned out to be an identifier.
+        while self.lexical_keywords.last().is_some_and(|&(start, _)| start > loc.start) {
+            self.lexical_keywords.pop();
+        }
+        match self.lexical_keywords.last() {
+            Some(&(start, kind)) if start == loc.start => {
+                self.lexical_keywords.pop();
+                kind
+            }
+            _ => BindingKind::Let,
+        }
+    }
+
+    /// Give the arrow function at `loc` a scope of its own. `params` are
+    /// the names bound in it so far, which are its parameter if it has just
+    /// one without parentheses.
+    fn arrow_function(&mut self, loc: SourceLocation, params: Vec<(Binding, usize)>, state_stack: &impl States) {
+        while self.arrows.last().is_some_and(|(p, _)| p.start > loc.start) {
+            self.arrows.pop();
+        }
+        let (params_loc, arrow) = match self.arrows.last() {
+            Some(&(p, arrow)) if p.start == loc.start => {
+                self.arrows.pop();
+                (p, arrow)
+            }
+            _ => return,
+        };
+        let outer = self.current_scope(state_stack);
+        let log = match self.scopes.as_mut() {
+            Some(log) => log,
+            None => return,
+        };
+        // If the body is a block, it already has a function scope.
+        let id = match arrow.and_then(|arrow| log.arrow_body(arrow)) {
+            Some(id) => id,
+            None => {
+                log.add_scope(ScopeKind::Function, outer, loc.start, None);
+                self.next_scope_id += 1;
+                self.next_scope_id - 1
+            }
+        };
+        log.arrow_function(outer, id, loc, params_loc);
+        for (binding, _) in params {
+            log.declare(id, &binding.name, BindingKind::Param, binding.loc);
+        }
+    }
+
+    /// Hold on to a bound name until the parser reduces the declaration
+    /// it's in, unless it's a parameter.
+    fn bind(&mut self, binding: Binding) {
+        for frame in self.frames.iter_mut().rev() {
+            match &mut frame.kind {
+                Kind::Params(names, ..) => {
+                    names.push(binding);
+                    return;
+                }
+                Kind::Function(Function { scope, .. }) | Kind::Block(scope) => {
+                    self.pending_bindings.push((binding, scope.id));
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Remove the pending bindings inside `loc` and return them.
+    fn take_bindings(&mut self, loc: SourceLocation) -> Vec<(Binding, usize)> {
+        let (taken, kept) = self
+            .pending_bindings
+            .drain(..)
+            .partition(|(b, _)| loc.start <= b.loc.start && b.loc.end <= loc.end);
+        self.pending_bindings = kept;
+        taken
+    }
+
+    /// The index in self.frames of the scope with the given id.
+    fn find_scope(&mut self, id: usize) -> Option<usize> {
+        self.frames
+            .iter_mut()
+            .rposition(|f| f.scope_mut().is_some_and(|s| s.id == id))
+    }
+
+    fn declare_lexical(&mut self, binding: Binding, scope_id: usize, kind: BindingKind) {
+        if let Some(log) = self.scopes.as_mut() {
+            log.declare(scope_id, &binding.name, kind, binding.loc);
+        }
+        if let (0, Some(log)) = (scope_id, self.module.as_mut()) {
+            log.declared(&binding.name, binding.loc);
+        }
+        let function = kind == BindingKind::Function;
+        let strict = self.is_strict();
+        let scope = match self.find_scope(scope_id) {
+            Some(i) => self.frames[i].scope_mut(),
+            None => None,
+        };
+        let scope = match scope {
+            Some(scope) => scope,
+            None => return,
+        };
+        let first = scope
+            .vars
+            .iter()
+            .chain(&scope.params)
+            .find(|b| b.name == binding.name)
+            .or_else(|| {
+                scope.lexical.iter().find_map(|(b, f)| {
+                    // Sloppy mode code can declare a function twice in a block.
+                    Some(b).filter(|b| b.name == binding.name && !(*f && function && !strict))
+                })
+            })
+            .map(|b| b.loc);
+        scope.lexical.push((binding.clone(), function));
+        if let Some(first) = first {
+            self.duplicate(EarlyErrorKind::Redeclaration, &binding, first);
+        }
+    }
+
+    /// A var belongs to the nearest function scope, but it can't have the
+    /// same name as a lexical declaration in any scope on the way there.
+    fn declare_var(&mut self, binding: Binding, scope_id: usize, kind: BindingKind) {
+        let start = match self.find_scope(scope_id) {
+            Some(i) => i,
+            None => return,
+        };
+        let mut clash = None;
+        let mut function_scope = None;
+        for frame in self.frames[..=start].iter_mut().rev() {
+            let is_function = matches!(frame.kind, Kind::Function(_));
+            if let Some(scope) = frame.scope_mut() {
+                // A var can redeclare a catch clause's parameter.
+                if !scope.catch {
+                    if let Some((first, _)) = scope.lexical.iter().find(|(b, _)| b.name == binding.name) {
+                        clash = clash.or(Some(first.loc));
+                    }
+                }
+                scope.vars.push(binding.clone());
+                if is_function {
+                    function_scope = Some(scope.id);
+                    break;
+                }
+            }
+        }
+        if let (Some(log), Some(id)) = (self.scopes.as_mut(), function_scope) {
+            log.declare(id, &binding.name, kind, binding.loc);
+        }
+        if let (Some(0), Some(log)) = (function_scope, self.module.as_mut()) {
+            log.declared(&binding.name, binding.loc);
+        }
+        if let Some(first) = clash {
+            self.duplicate(EarlyErrorKind::Redeclaration, &binding, first);
+        }
+    }
+
+    /// A function declared at the top level of a function or script is
+    /// declared like a var, and one in a block like a let.
+    fn declare_function(&mut self, binding: Binding, scope_id: usize) {
+        match self.find_scope(scope_id).map(|i| &self.frames[i].kind) {
+            Some(Kind::Function(_)) => self.declare_var(binding, scope_id, BindingKind::Function),
+            Some(_) => self.declare_lexical(binding, scope_id, BindingKind::Function),
+            None => {}
+        }
+    }
+
+    /// `binding` is a private name used just now, in the innermost class.
+    fn use_private_name(&mut self, binding: Binding) {
+        // shift() already popped the frames that are over.
+        for frame in self.frames.iter_mut().rev() {
+            if let Kind::Class(names) = &mut frame.kind {
+                names.references.push(binding);
+                return;
+            }
+        }
+        if self.nested {
+            self.outer_private_names.push(binding);
+        } else {
+            self.error(EarlyErrorKind::UndeclaredPrivateName, Some(&binding.name), binding.loc);
+        }
+    }
+
+    /// Remove the pending private names inside `loc` and return them.
+    fn take_private_names(&mut self, loc: SourceLocation) -> Vec<(Binding, PrivateKind)> {
+        let (taken, kept) = self
+            .pending_private_names
+            .drain(..)
+            .partition(|(b, _)| loc.start <= b.loc.start && b.loc.end <= loc.end);
+        self.pending_private_names = kept;
+        taken
+    }
+
+    /// Remove the pending names `constructor` inside `loc` and return them.
+    fn take_constructors(&mut self, loc: SourceLocation) -> Vec<SourceLocation> {
+        let (taken, kept) = self
+            .pending_constructors
+            .drain(..)
+            .partition(|name| loc.start <= name.start && name.end <= loc.end);
+        self.pending_constructors = kept;
+        taken
+    }
+
+    /// `name` names the constructor of the innermost class.
+    fn declare_constructor(&mut self, name: SourceLocation, state_stack: &impl States) {
+        let class = match self.current_class(state_stack) {
+            Some(class) => class,
+            None => return,
+        };
+        match class.constructor {
+            Some(first) => self.errors.push((EarlyErrorKind::DuplicateConstructor, None, name, Some(first))),
+            None => class.constructor = Some(name),
+        }
+    }
+
+    /// The innermost class that isn't over, given `state_stack`.
+    fn current_class(&mut self, state_stack: &impl States) -> Option<&mut ClassBody> {
+        self.frames
+            .iter_mut()
+            .rev()
+            .filter(|f| !f.is_over(state_stack))
+            .find_map(|f| match &mut f.kind {
+                Kind::Class(names) => Some(names),
+                _ => None,
+            })
+    }
+
+    fn declare_private_name(&mut self, binding: Binding, kind: PrivateKind, state_stack: &impl States) {
+        let names = match self.current_class(state_stack) {
+            Some(names) => names,
+            None => return,
+        };
+        let clash = names.declared.iter().any(|(name, k)| {
+            *name == binding.name
+                && !matches!(
+                    (k, kind),
+                    (PrivateKind::Getter, PrivateKind::Setter) | (PrivateKind::Setter, PrivateKind::Getter)
+                )
+        });
+        names.declared.push((binding.name.clone(), kind));
+        if clash {
+            self.error(EarlyErrorKind::DuplicatePrivateName, Some(&binding.name), binding.loc);
+        }
+    }
+
+    /// The innermost class body just ended. Pass the private names it used
+    /// but didn't declare to the class around it.
+    fn check_private_names(&mut self, state_stack: &impl States) {
+        let i = match self
+            .frames
+            .iter()
+            .rposition(|f| !f.is_over(state_stack) && matches!(f.kind, Kind::Class(_)))
+        {
+            Some(i) => i,
+            None => return,
+        };
+        let ClassBody { declared, references, .. } = match &mut self.frames[i].kind {
+            Kind::Class(names) => std::mem::take(names),
+            _ => return,
+        };
+        for binding in references {
+            if declared.iter().any(|(name, _)| *name == binding.name) {
+                continue;
+            }
+            let outer = self.frames[..i].iter_mut().rev().find_map(|f| match &mut f.kind {
+                Kind::Class(outer) => Some(outer),
+                _ => None,
+            });
+            match outer {
+                Some(outer) => outer.references.push(binding),
+                None if self.nested => self.outer_private_names.push(binding),
+                None => self.error(EarlyErrorKind::UndeclaredPrivateName, Some(&binding.name), binding.loc),
+            }
+        }
+    }
+
+    /// Pass `nt`, an import or export declaration or a piece of one, to the
+    /// module log.
+    fn module_item(&mut self, nt: NonterminalId, loc: SourceLocation) {
+        let log = match self.module.as_mut() {
+            Some(log) => log,
+            None => return,
+        };
+        match nt {
+            NonterminalId::ImportedBinding => {
+                if let Some(binding) = self.last_identifier.as_ref().filter(|id| id.loc == loc) {
+                    log.imported_binding(&binding.name, loc);
+                }
+            }
+            NonterminalId::ImportedDefaultBinding => log.import_specifier(true, false),
+            NonterminalId::NameSpaceImport => log.import_specifier(false, true),
+            NonterminalId::ImportSpecifier => log.import_specifier(false, false),
+            NonterminalId::ExportSpecifier => log.export_specifier(),
+            NonterminalId::ModuleSpecifier => log.module_specifier(),
+            NonterminalId::ExportDeclaration => {
+                for (name, loc) in log.export_declaration() {
+                    self.error(EarlyErrorKind::DuplicateExport, Some(&name), loc);
+                }
+            }
+            NonterminalId::Module => self.check_exports(),
+            _ => {}
+        }
+    }
+
+    /// Report local exports of names that the module doesn't declare.
+    fn check_exports(&mut self) {
+        let (top, log) = match (self.frames.first(), &self.module) {
+            (Some(Frame { kind: Kind::Function(Function { scope, .. }), .. }), Some(log)) => (scope, log),
+            _ => return,
+        };
+        let undeclared: Vec<(String, SourceLocation)> = log
+            .local_exports()
+            .filter(|&(name, _)| {
+                name != DEFAULT_LOCAL_NAME
+                    && !top.vars.iter().any(|v| v.name == name)
+                    && !top.lexical.iter().any(|(l, _)| l.name == name)
+            })
+            .map(|(name, loc)| (name.to_string(), loc))
+            .collect();
+        for (name, loc) in undeclared {
+            self.error(EarlyErrorKind::UndeclaredExport, Some(&name), loc);
+        }
+    }
+
+    fn directive_prologue(&mut self, token: &Token, asi: bool) {
+        let t = token.get_id();
+        let function = match self.frames.last_mut() {
+            Some(Frame { kind: Kind::Function(function), .. }) => function,
+            _ => return,
+        };
+        let mut use_strict = None;
+        let mut semicolon = false;
+        if let Prologue::AfterString { use_strict: u, loc } = function.prologue {
+            if t == TerminalId::Semicolon || asi {
+                use_strict = Some(loc).filter(|_| u);
+                semicolon = t == TerminalId::Semicolon;
+                function.prologue = Prologue::Start;
+            } else {
+                // The string was the start of an expression.
+                function.prologue = Prologue::Done;
+            }
+        }
+        if let (Prologue::Start, false) = (function.prologue, semicolon) {
+            function.prologue = if t == TerminalId::StringLiteral {
+                if has_octal_escape(token) && function.octal_directive.is_none() {
+                    function.octal_directive = Some(token.loc);
+                }
+                Prologue::AfterString {
+                    // No escapes or line continuations allowed.
+                    use_strict: token.raw.as_deref() == Some("use strict"),
+                    loc: token.loc,
+                }
+            } else {
+                Prologue::Done
+            }
+        }
+
+        let loc = match use_strict {
+            Some(loc) => loc,
+            None => return,
+        };
+        if !function.simple_params {
+            self.error(EarlyErrorKind::UseStrictWithNonSimpleParameters, None, loc);
+            return;
+        }
+        if !function.strict {
+            function.strict = true;
+            let octal = function.octal_directive;
+            let params = function.params.clone();
+            let unique = function.unique_params;
+            if let Some(loc) = octal {
+                self.error(EarlyErrorKind::OctalEscape, None, loc);
+            }
+            self.check_params(&params, !unique, true);
+        }
+    }
+
+    /// `nt` was just reduced at `loc`. A template is tagged if it's reduced
+    /// as part of a member expression, call or optional chain, and not if
+    /// it's reduced to a PrimaryExpression by itself.
+    fn check_templates(&mut self, nt: NonterminalId, loc: SourceLocation) {
+        let tagged = match nt {
+            NonterminalId::PrimaryExpression => false,
+            NonterminalId::MemberExpression | NonterminalId::CallExpression | NonterminalId::OptionalChain => true,
+            _ => return,
+        };
+        let (inside, outside) = self.bad_templates.drain(..).partition(|t| t.start >= loc.start);
+        self.bad_templates = outside;
+        if !tagged {
+            for template in inside {
+                self.error(EarlyErrorKind::InvalidTemplateEscape, None, template);
+            }
+        }
+    }
+
+    fn check_strict_token(&mut self, token: &Token) {
+        let t = token.get_id();
+        if let Some(loc) = self.prefix_update.take() {
+            if !matches!(
+                t,
+                TerminalId::FullStop
+                    | TerminalId::LeftSquareBracket
+                    | TerminalId::LeftParenthesis
+                    | TerminalId::NoSubstitutionTemplate
+                    | TerminalId::TemplateHead
+            ) {
+                self.error(EarlyErrorKind::AssignEvalOrArguments, None, loc);
+            }
+        }
+
+        match t {
+            TerminalId::With => self.error(EarlyErrorKind::With, None, token.loc),
+            TerminalId::NumericLiteral => {
+                let text = token.value.as_deref().unwrap_or("");
+                let mut chars = text.chars();
+                if chars.next() == Some('0') && chars.next().is_some_and(|c| c.is_ascii_digit()) {
+                    if text.chars().all(|c| ('0'..='7').contains(&c)) {
+                        self.error(EarlyErrorKind::OctalLiteral, None, token.loc);
+                    } else {
+                        self.error(EarlyErrorKind::LeadingZero, None, token.loc);
+                    }
+                }
+            }
+            TerminalId::StringLiteral if has_octal_escape(token) => {
+                self.error(EarlyErrorKind::OctalEscape, None, token.loc);
+            }
+            TerminalId::Identifier if token.value.as_deref() == Some("yield") => {
+                self.error(EarlyErrorKind::ReservedWord, Some("yield"), token.loc);
+            }
+            TerminalId::Identifier
+                if self.last_was_update_operator()
+                    && token.value.as_deref().is_some_and(|name| name == "eval" || name == "arguments") =>
+            {
+                self.prefix_update = Some(token.loc);
+            }
+            _ => {}
+        }
+
+        if is_assignment_operator(t) || is_update_operator(t) {
+            let target = match &self.last_identifier {
+                Some(id) if id.is_eval_or_arguments() => id.loc,
+                _ => return,
+            };
+            let member = self.previous[1] == Some(TerminalId::FullStop);
+            // A `++` on the next line belongs to whatever comes after it.
+            let postfix = !is_update_operator(t) || target.line == token.loc.line;
+            if !member && postfix {
+                self.error(EarlyErrorKind::AssignEvalOrArguments, None, target);
+            }
+        }
+    }
+
+    fn check_regexp(&mut self, token: &Token) {
+        let text = token.value.as_deref().unwrap_or("");
+        if let Err(error) = regexp::check_literal(text) {
+            // Point at the problem inside the literal, which is all on one
+            // line.
+            let start = token.loc.start + error.offset;
+            let end = text[error.offset..].chars().next().map_or(start, |c| start + c.len_utf8());
+            let column = token.loc.column + text[..error.offset].chars().count() as u32;
+            let loc = SourceLocation::new(start, end, token.loc.line, column);
+            self.error(EarlyErrorKind::InvalidRegExp(error.message), None, loc);
+        }
+    }
+
+    fn last_was_update_operator(&self) -> bool {
+        self.previous[0].is_some_and(is_update_operator)
+    }
+
+    /// Report duplicates in a parameter list if `dups` is true, and
+    /// parameters named eval or arguments if `names` is true.
+    fn check_params(&mut self, params: &[Binding], dups: bool, names: bool) {
+        for (i, param) in params.iter().enumerate() {
+            if names && param.is_eval_or_arguments() {
+                self.error(EarlyErrorKind::BindEvalOrArguments, None, param.loc);
+            }
+            if let Some(first) = params[..i].iter().find(|p| dups && p.name == param.name) {
+                self.duplicate(EarlyErrorKind::DuplicateParameter, param, first.loc);
+            }
+        }
+    }
+
+    /// Check a name that's spelled with escapes. What the escapes stand for
+    /// has to be allowed in a name, and if it's an identifier, it can't be
+    /// a reserved word, as in `\u0076ar x;`. `yield` and `await` follow the
+    /// same rules as when they're spelled without escapes.
+    fn check_escaped_name(&mut self, token: &Token) {
+        let name = token.value.as_deref().unwrap_or("");
+        let spelled = match token.get_id() {
+            TerminalId::PrivateIdentifier => name.strip_prefix('#').unwrap_or(name),
+            _ => name,
+        };
+        if !lexer::is_identifier_name(spelled) {
+            self.error(EarlyErrorKind::InvalidIdentifierEscape, None, token.loc);
+        } else if token.get_id() == TerminalId::Identifier
+            && lexer::keyword(name).is_some_and(|t| !matches!(t, TerminalId::Yield | TerminalId::Await))
+        {
+            self.error(EarlyErrorKind::ReservedWord, Some(name), token.loc);
+        }
+    }
+
+    fn error(&mut self, kind: EarlyErrorKind, name: Option<&str>, loc: SourceLocation) {
+        self.errors.push((kind, name.map(str::to_string), loc, None));
+    }
+
+    /// Report `binding` as declaring a name that was already declared at
+    /// `first`.
+    fn duplicate(&mut self, kind: EarlyErrorKind, binding: &Binding, first: SourceLocation) {
+        self.errors.push((kind, Some(binding.name.clone()), binding.loc, Some(first)));
+    }
+}
+
+pub(crate) fn is_assignment_operator(t: TerminalId) -> bool {
+    matches!(
+        t,
+        TerminalId::EqualsSign
+            | TerminalId::AsteriskEqualsSign
+            | TerminalId::SolidusEqualsSign
+            | TerminalId::PercentSignEqualsSign
+            | TerminalId::PlusSignEqualsSign
+            | TerminalId::HyphenMinusEqualsSign
+            | TerminalId::LessThanSignLessThanSignEqualsSign
+            | TerminalId::GreaterThanSignGreaterThanSignEqualsSign
+            | TerminalId::GreaterThanSignGreaterThanSignGreaterThanSignEqualsSign
+            | TerminalId::AmpersandEqualsSign
+            | TerminalId::CircumflexAccentEqualsSign
+            | TerminalId::VerticalLineEqualsSign
+            | TerminalId::AsteriskAsteriskEqualsSign
+    )
+}
+
+fn is_update_operator(t: TerminalId) -> bool {
+    t == TerminalId::PlusSignPlusSign || t == TerminalId::HyphenMinusHyphenMinus
+}
+
+/// True if the string literal `token` has a legacy octal escape, or `\8` or
+/// `\9`.
+fn has_octal_escape(token: &Token) -> bool {
+    let mut chars = match &token.raw {
+        Some(value) => value.chars().peekable(),
+        None => return false,
+    };
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            continue;
+        }
+        match chars.next() {
+            Some('1'..='9') => return true,
+            Some('0') if chars.peek().is_some_and(|c| c.is_ascii_digit()) => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EarlyErrorKind;
+    use crate::ast_arena::Arena;
+    use crate::parser::{ParseError, Parser};
+    use crate::parser_generated::{concrete, DefaultHandler};
+    use crate::testing::{async_function_body, early_error, module_function_body};
+
+    #[test]
+    fn strict_mode() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("with (x) {}", None),
+            ("\"use strict\"; with (x) {}", Some(With)),
+            ("\"use strict\"\nwith (x) {}", Some(With)),
+            ("'a'; 'use strict'; x = 010;", Some(OctalLiteral)),
+            ("x = 010; y = 0.5 + 0x10 + 0;", None),
+            ("'use strict'; x = 08;", Some(LeadingZero)),
+            ("\"use strict\" + 1; with (x) {}", None),
+            ("x = \"use strict\"; with (x) {}", None),
+            ("'use\\x20strict'; with (x) {}", None),
+            ("'\\01'; 'use strict';", Some(OctalEscape)),
+            ("'use strict'; x = '\\0 \\8';", Some(OctalEscape)),
+            ("function f() { 'use strict'; } with (x) {}", None),
+            ("function f() { 'use strict'; with (x) {} }", Some(With)),
+            ("function f(a, a) {}", None),
+            ("function f(a, a) { 'use strict'; }", Some(DuplicateParameter)),
+            ("'use strict'; function f(a, b, a) {}", Some(DuplicateParameter)),
+            ("function f(a, a = 1) {}", Some(DuplicateParameter)),
+            ("function f(a, ...a) {}", Some(DuplicateParameter)),
+            ("function f(a, [b], b) {}", Some(DuplicateParameter)),
+            ("(a, a) => 1", Some(DuplicateParameter)),
+            ("\"use strict\"; (a, b, a) => 1", Some(DuplicateParameter)),
+            ("({a}, [b = a], ...c) => { a = b; }", None),
+            ("function f(eval) { 'use strict'; }", Some(BindEvalOrArguments)),
+            ("'use strict'; var arguments;", Some(BindEvalOrArguments)),
+            ("'use strict'; eval = 1;", Some(AssignEvalOrArguments)),
+            ("'use strict'; arguments += 1;", Some(AssignEvalOrArguments)),
+            ("'use strict'; ++eval;", Some(AssignEvalOrArguments)),
+            ("'use strict'; eval++;", Some(AssignEvalOrArguments)),
+            ("'use strict'; x = eval\n++y;", None),
+            ("'use strict'; x = eval(y);", None),
+            ("class C extends (function () { with (x) {} }) {}", Some(With)),
+            ("class C {} with (x) {}", None),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+        assert_eq!(early_error("with (x) {}", true), Some(With));
+
+        // A function with default values, a rest parameter or patterns in
+        // its parameters can't have a "use strict" directive, even in
+        // strict mode code.
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("function f(a = 1) { 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("function f(a, ...b) { \"use strict\" }", Some(UseStrictWithNonSimpleParameters)),
+            ("function f([a]) { 'a'; 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("'use strict'; function f({a}) { 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("class C { m(a = 1) { 'use strict'; } }", Some(UseStrictWithNonSimpleParameters)),
+            ("({ set x([a]) { 'use strict'; } })", Some(UseStrictWithNonSimpleParameters)),
+            ("(a, b = 1) => { 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("(...a) => { 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("({a}) => { 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("async ([a]) => { 'use strict'; }", Some(UseStrictWithNonSimpleParameters)),
+            ("function f(a, b) { 'use strict'; }", None),
+            ("function f(a = 1) { 'use\\x20strict'; x; 'use strict'; }", None),
+            ("function f(a = 1) { 'use strict' + 1; }", None),
+            ("function f(a = function () { 'use strict'; }) {}", None),
+            ("function f(a = 1) {} function g(b) { 'use strict'; }", None),
+            ("(a, b,) => { 'use strict'; }", None),
+            ("() => { 'use strict'; }", None),
+            ("a => { 'use strict'; }", None),
+            ("async (a) => { 'use strict'; }", None),
+            ("({ get x() { 'use strict'; } })", None),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+
+        // A skipped function body is checked with the strictness around it.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        let result = parser.parse_script("'use strict'; function f(a) { with (a) {} }");
+        assert!(matches!(result, Err(ParseError::EarlyError { kind: With, .. })));
+    }
+
+    #[test]
+    fn yield_and_await() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("function* g() { yield 1; yield* h(); }", None),
+            ("async function f() { await x; }", None),
+            ("async function* g() { yield await x; }", None),
+            ("var yield = 1, await = yield + await;", None),
+            ("function* g() { function f() { var yield; yield = 1; } }", None),
+            ("async function f() { function g() { await = 1; } }", None),
+            ("function* g() {} yield = 1;", None),
+            ("async function f() {} await = 1;", None),
+            ("function* yield() {} async function await() {}", None),
+            ("f = async x => await x;", None),
+            ("async function f() { g = x => { var await; }; }", None),
+            ("function* g() { var yield; }", Some(ReservedWord)),
+            ("async function f() { var await; }", Some(ReservedWord)),
+            ("function* g() { function* yield() {} }", Some(ReservedWord)),
+            ("'use strict'; var yield;", Some(ReservedWord)),
+            ("function f() { 'use strict'; yield = 1; }", Some(ReservedWord)),
+            ("function* g(a = yield) {}", Some(YieldOrAwaitInParameters)),
+            ("async function f(a = await 1) {}", Some(YieldOrAwaitInParameters)),
+            ("function* g() { function f(a = yield) {} }", None),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+        // In module code, `await` is reserved everywhere.
+        let module_cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("var await;", Some(ReservedWord)),
+            ("function await() {}", Some(ReservedWord)),
+            ("function f() { var await; }", Some(ReservedWord)),
+            ("function f() { await x; }", Some(ReservedWord)),
+            ("f = x => await x;", Some(ReservedWord)),
+            ("async function f() { await x; }", None),
+            ("await x;", None),
+        ];
+        for &(src, expected) in module_cases {
+            assert_eq!(early_error(src, true), expected, "{}", src);
+        }
+
+        // Outside async functions, `await` is an identifier, so this is a
+        // syntax error instead of an await expression.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let result = Parser::new_script(&handler).parse_script("function f() { for await (x of y); }");
+        assert!(matches!(result, Err(ParseError::SyntaxError { .. })));
+
+        // Skipped and reparsed function bodies know what kind of function
+        // they're in.
+        let src = "async function f() { await g(function* () { yield; }); }";
+        let full = Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script();
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        let script = parser.parse_script(src).ok().unwrap().into_script();
+        let lazy = match *async_function_body(script) {
+            concrete::FunctionBody::Lazy(lazy) => lazy,
+            ref body => panic!("expected a lazy function body, got {:?}", body),
+        };
+        let body = Parser::new_script(&handler).parse_lazy_function(src, &lazy).ok().unwrap().into_function_body();
+        assert_eq!(format!("{:?}", body), format!("{:?}", async_function_body(full)));
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        let result = parser.parse_script("async function f() { function* g() { var yield; } }");
+        assert!(matches!(result, Err(ParseError::EarlyError { kind: ReservedWord, .. })));
+    }
+
+    #[test]
+    fn import_calls_and_import_meta() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, bool, Option<EarlyErrorKind>)] = &[
+            ("import('a').then(f);", false, None),
+            ("x = await import(a + b);", true, None),
+            ("var meta = 1; meta.x;", false, None),
+            ("x = import.meta;", false, Some(ImportMetaOutsideModule)),
+            ("x = import.meta.url;", true, None),
+            ("function f() { return () => import.meta; }", true, None),
+        ];
+        for &(src, module, expected) in cases {
+            assert_eq!(early_error(src, module), expected, "{}", src);
+        }
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_module(&handler);
+        parser
+            .parse_script("import x from 'a'; import('b').then(y => x); export {x};")
+            .ok()
+            .unwrap();
+        let summary = parser.take_module_summary().unwrap();
+        assert_eq!(summary.specifiers().collect::<Vec<_>>(), ["a"]);
+        assert_eq!(summary.exported_names().collect::<Vec<_>>(), ["x"]);
+
+        // Skipped function bodies in a module are still module code.
+        let src = "function f() { return import.meta; }";
+        let mut parser = Parser::new_module(&handler);
+        parser.set_lazy(true);
+        let module = parser.parse_script(src).ok().unwrap().into_module();
+        let lazy = match *module_function_body(module) {
+            concrete::FunctionBody::Lazy(lazy) => lazy,
+            ref body => panic!("expected a lazy function body, got {:?}", body),
+        };
+        assert!(Parser::new_module(&handler).parse_lazy_function(src, &lazy).is_ok());
+        assert!(matches!(
+            Parser::new_script(&handler).parse_lazy_function(src, &lazy),
+            Err(ParseError::EarlyError { kind: ImportMetaOutsideModule, .. })
+        ));
+    }
+
+    #[test]
+    fn redeclarations() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("let x; let x;", Some(Redeclaration)),
+            ("let x; var x;", Some(Redeclaration)),
+            ("var x; let x;", Some(Redeclaration)),
+            ("const a = 1; const a = 2;", Some(Redeclaration)),
+            ("let [a, b] = c, {a} = e;", Some(Redeclaration)),
+            ("class C {} function C() {}", Some(Redeclaration)),
+            ("var x; var x; function x() {}", None),
+            ("let x; { let x; }", None),
+            ("{ let x; } let x;", None),
+            ("{ var x; } let x;", Some(Redeclaration)),
+            ("let x; { { var x; } }", Some(Redeclaration)),
+            ("{ function f() {} let f; }", Some(Redeclaration)),
+            ("{ function f() {} function f() {} }", None),
+            ("'use strict'; { function f() {} function f() {} }", Some(Redeclaration)),
+            ("switch (x) { case 1: let y; break; default: let y; }", Some(Redeclaration)),
+            ("function f(a) { let a; }", Some(Redeclaration)),
+            ("function f(a) { var a; { let a; } }", None),
+            ("function f() { var x; } let x;", None),
+            ("let f = function x() { let x; };", None),
+            ("let g = a => a; let a;", None),
+            ("a => { let a; }", Some(Redeclaration)),
+            ("(a, {b}) => { const b = 1; }", Some(Redeclaration)),
+            ("a => { var a; }", None),
+            ("for (let i = 0; ; ) { let i; }", None),
+            ("for (let i of x) { var i; }", Some(Redeclaration)),
+            ("for (var i in x) ; let i;", Some(Redeclaration)),
+            ("try {} catch (e) { let e; }", Some(Redeclaration)),
+            ("try {} catch (e) { var e; }", None),
+            ("a: a: ;", Some(DuplicateLabel)),
+            ("a: { b: { a: ; } }", Some(DuplicateLabel)),
+            ("a: { } a: ;", None),
+            ("a: while (x) { break a; } a: ;", None),
+            ("a: function f() { a: ; }", None),
+            ("while (1) break foo;", Some(UndefinedLabel)),
+            ("while (1) continue foo;", Some(UndefinedLabel)),
+            ("a: ; while (1) break a;", Some(UndefinedLabel)),
+            ("a: while (1) { (function () { break a; }); }", Some(UndefinedLabel)),
+            ("a: { break a; }", None),
+            ("a: { continue a; }", Some(UndefinedLabel)),
+            ("a: b: for (;;) { continue a; }", None),
+            ("a: do { b: { continue a; } } while (0);", None),
+            ("a: x\nwhile (1) continue a;", Some(UndefinedLabel)),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        let message = parser.parse_script("let x;\nlet x;").err().map(|e| e.message());
+        assert_eq!(message.as_deref(), Some("redeclaration of `x` at line 2, column 4"));
+
+        // Skipped function bodies are checked too.
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        let result = parser.parse_script("function f(a) { { var b; } let a, b; }");
+        assert!(matches!(result, Err(ParseError::EarlyError { kind: Redeclaration, .. })));
+    }
+
+    #[test]
+    fn classes() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            (
+                "class A extends B { constructor() { super(); } static m() {} get x() { return 1; } \
+                 set x(v) {} *g() { yield; } async a() { await b; } static async *h() {} }",
+                None,
+            ),
+            ("class C { a = 1; b; static c = 2; 'd' = 3; [e] = 4; 5 = f; get; set = 6; static; }", None),
+            ("class C { a = 1\n b\n m() {} static\n x }", None),
+            ("x = class { get() {} set() {} static() {} async() {} };", None),
+            (
+                "class C { #x = 1; #m() { return this.#x; } get #y() {} set #y(v) {} static #z; \
+                 f(o) { o.#x++; this.#x = C.#z; return this.#m().#x; } }",
+                None,
+            ),
+            ("class C { m() { return this.#x; } #x; }", None),
+            ("class C { #x; m() { class D { n() { return this.#x; } } } }", None),
+            ("class C { #x; m() { return class { #y; n() { return this.#x + this.#y; } }; } }", None),
+            ("class C { #x = () => this.#x; static #y = this.#y; }", None),
+            ("class C { #x; m(o) { return #x in o; } }", None),
+            ("class C { #x; m(o) { return #x in o && !(#x in this); } }", None),
+            ("this.#x;", Some(UndeclaredPrivateName)),
+            ("function f() { return this.#x; }", Some(UndeclaredPrivateName)),
+            ("class C { m() { return this.#y; } #x; }", Some(UndeclaredPrivateName)),
+            ("class C { m() { class D { #x; } return this.#x; } }", Some(UndeclaredPrivateName)),
+            ("class C { #x; m(o) { return #y in o; } }", Some(UndeclaredPrivateName)),
+            ("class C { #x; #x; }", Some(DuplicatePrivateName)),
+            ("class C { get #x() {} get #x() {} }", Some(DuplicatePrivateName)),
+            ("class C { #x; set #x(v) {} }", Some(DuplicatePrivateName)),
+            ("class C { #x; m() { class D { #x; } } }", None),
+            ("class C { #constructor() {} }", Some(PrivateConstructor)),
+            ("class C { constructor() {} constructor() {} }", Some(DuplicateConstructor)),
+            ("class C { constructor() {} 'constructor'() {} }", Some(DuplicateConstructor)),
+            ("class C { constructor() {} static constructor() {} ['constructor']() {} }", None),
+            ("class C { constructor() { class D { constructor() {} } return { constructor() {} }; } }", None),
+            ("class C { #x; m() { return { #x() {} }; } }", Some(PrivateNameOutsideClass)),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+
+        // A skipped method body hands the private names it uses to its
+        // class.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for &(src, ok) in &[
+            ("class C { #x; m() { return this.#x; } }", true),
+            ("class C { m() { return this.#x; } }", false),
+            ("class C { m() { return () => this.#x; } #x; }", true),
+        ] {
+            let mut parser = Parser::new_script(&handler);
+            parser.set_lazy(true);
+            let result = parser.parse_script(src);
+            assert_eq!(result.is_ok(), ok, "{}", src);
+        }
+    }
+
+    #[test]
+    fn optional_chaining_and_coalescing() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("a?.b; a?.[0]; a?.(); a?.b.c[d](e)?.f; f()?.g; new A()?.b;", None),
+            ("class C { #x; m(o) { return o?.#x + o?.y.#x; } }", None),
+            ("x = a?.5:1; y = a ?.5 : .5;", None),
+            ("x = a ?? b ?? c; y = (a || b) ?? c; z = a ?? (b && c); w = a ?? b ? c : d;", None),
+            ("a ?? b || c;", Some(MixedCoalesce)),
+            ("a || b ?? c;", Some(MixedCoalesce)),
+            ("a && b ?? c;", Some(MixedCoalesce)),
+            ("a ?? b && c;", Some(MixedCoalesce)),
+            ("a?.b`c`;", Some(TaggedTemplateInOptionalChain)),
+            ("a?.`b${c}d`;", Some(TaggedTemplateInOptionalChain)),
+            ("a?.b = 1;", Some(InvalidAssignmentTarget)),
+            ("[a?.b] = c;", Some(InvalidAssignmentTarget)),
+            ("for (a?.b of c);", Some(InvalidAssignmentTarget)),
+            ("o?.#x;", Some(UndeclaredPrivateName)),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+    }
+}
@@ -0,0 +1,2529 @@
+//! The concrete syntax tree as ESTree JSON.
+//!
+//! ESTree is the tree format acorn and babel's `estree` plugin produce, so
+//! printing a parse in it lets our output be diffed against theirs. It isn't
+//! a dump of the concrete tree: wrapper nodes like Block and Initializer
+//! disappear, parentheses leave no node behind, and the expressions the
+//! cover grammars parse arrow parameters and destructuring targets as become
+//! patterns again. The concrete tree doesn't keep the keywords and
+//! punctuators that carry no value, so where ESTree wants the position of
+//! one, like the `{` that starts a function body, it's found by scanning the
+//! source forward from a node whose position is known.
+//!
+//! Positions are the ones acorn gives with `locations` and `ranges` on:
+//! `start`, `end` and `range` count UTF-16 code units from the start of the
+//! source, and `loc` has 1-based lines and 0-based UTF-16 columns. Nodes
+//! that error recovery made are written as `null`.
+
+use std::fmt::Write;
+
+use crate::ast_arena::{Arena, ArenaRef, NodeLocations};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::parser::{describe_location, ParseError, Parser};
+use crate::parser_generated::concrete::*;
+use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TABLES};
+use crate::parser_runtime::{TerminalId, Token};
+
+/// Byte offsets of the start and end of some source text.
+type Span = (usize, usize);
+
+pub fn to_estree_json(script: &Script, locations: &NodeLocations, source: &str) -> String {
+    let mut writer = Writer::new(source, locations);
+    match script {
+        Script::Script(body) => {
+            let items = match body.as_deref() {
+                Some(ScriptBody::ScriptBody(list)) => statements(Some(*list)),
+                _ => vec![],
+            };
+            writer.program(items, "script");
+        }
+        Script::Error(_) => writer.null(),
+    }
+    writer.out
+}
+
+pub fn module_to_estree_json(module: &Module, locations: &NodeLocations, source: &str) -> String {
+    let mut writer = Writer::new(source, locations);
+    match module {
+        Module::Module(body) => {
+            let items = match body.as_deref() {
+                Some(ModuleBody::ModuleBody(list)) => {
+                    left_list(*list, |list| match list {
+                        ModuleItemList::ModuleItemListP0(item) => (None, Some(*item)),
+                        ModuleItemList::ModuleItemListP1(rest, item) => (Some(*rest), Some(*item)),
+                        ModuleItemList::Error(_) => (None, None),
+                    })
+                }
+                _ => vec![],
+            };
+            writer.program(items, "module");
+        }
+        Module::Error(_) => writer.null(),
+    }
+    writer.out
+}
+
+/// Parse `source` as a script or, if `module` is true, a module, and return
+/// `{"ast": ..., "diagnostics": [...]}`. `ast` is the Program, or `null` if
+/// the source doesn't parse. Each diagnostic has the error's `message`, a
+/// `severity` and `code` as a Diagnostic does, and `start`, `end` and `loc`
+/// like a node's if the error has a position. With
+/// `recover`, the syntax errors and malformed tokens recovery mode got past
+/// are diagnostics too.
+pub fn parse_to_estree_json(source: &str, module: bool, recover: bool) -> String {
+    let (ast, errors) = parse_to_estree(source, module, recover);
+    format!(
+        "{{\"ast\":{},\"diagnostics\":{}}}",
+        ast.as_deref().unwrap_or("null"),
+        diagnostics_json(&errors, source)
+    )
+}
+
+/// `errors` as a JSON array of diagnostics, as parse_to_estree_json()
+/// describes them.
+pub fn diagnostics_json(errors: &[ParseError], source: &str) -> String {
+    let locations = NodeLocations::new();
+    let mut writer = Writer::new(source, &locations);
+    writer.out.push('[');
+    for (i, error) in errors.iter().enumerate() {
+        if i > 0 {
+            writer.out.push(',');
+        }
+        writer.diagnostic(&error.message(), &Diagnostic::from(error));
+    }
+    writer.out.push(']');
+    writer.out
+}
+
+/// `warnings`, as from lint_script(), as a JSON array like
+/// diagnostics_json()'s.
+pub fn warnings_json(warnings: &Diagnostics, source: &str) -> String {
+    let locations = NodeLocations::new();
+    let mut writer = Writer::new(source, &locations);
+    writer.out.push('[');
+    for (i, warning) in warnings.iter().enumerate() {
+        if i > 0 {
+            writer.out.push(',');
+        }
+        let message = match &warning.loc {
+            Some(loc) => format!("{} at {}", warning.message, describe_location(loc)),
+            None => warning.message.clone(),
+        };
+        writer.diagnostic(&message, warning);
+    }
+    writer.out.push(']');
+    writer.out
+}
+
+/// Parse `source` as parse_to_estree_json() does, returning the Program's
+/// JSON if it parses, and the errors that stopped it or that recovery mode
+/// got past.
+pub(crate) fn parse_to_estree(source: &str, module: bool, recover: bool) -> (Option<String>, Vec<ParseError>) {
+    let arena = Arena::new();
+    let locations = NodeLocations::new();
+    let handler = DefaultHandler::with_locations(&arena, &locations);
+    let entry = if module { EntryPoint::Module } else { EntryPoint::Script };
+    let mut parser = Parser::new(&TABLES, reduce, &handler, entry.state(), recover);
+    if module {
+        parser.set_module();
+    }
+    let result = parser.parse_script(source);
+    let mut errors = parser.errors().to_vec();
+    let ast = match result {
+        Ok(value) if module => Some(module_to_estree_json(&value.into_module(), &locations, source)),
+        Ok(value) => Some(to_estree_json(&value.into_script(), &locations, source)),
+        Err(error) => {
+            errors.push(error);
+            None
+        }
+    };
+    (ast, errors)
+}
+
+/// The items of a left-recursive list, in source order. `split` takes a list
+/// node apart into the list before its last item, if any, and the item.
+fn left_list<'a, L, T>(list: ArenaRef<'a, L>, split: impl Fn(&'a L) -> (Option<ArenaRef<'a, L>>, Option<T>)) -> Vec<T> {
+    let mut items = vec![];
+    let mut next = Some(list);
+    while let Some(list) = next {
+        let (rest, item) = split(list.get());
+        items.extend(item);
+        next = rest;
+    }
+    items.reverse();
+    items
+}
+
+fn statements<'a>(list: Option<ArenaRef<'a, StatementList<'a>>>) -> Vec<ArenaRef<'a, ModuleItem<'a>>> {
+    match list {
+        Some(list) => left_list(list, |list| match list {
+            StatementList::StatementListP0(item) => (None, Some(*item)),
+            StatementList::StatementListP1(rest, item) => (Some(*rest), Some(*item)),
+            StatementList::Error(_) => (None, None),
+        }),
+        None => vec![],
+    }
+}
+
+/// The expressions `a, b, c` is a sequence of, or just the expression if
+/// it isn't one.
+fn sequence<'a>(expression: ArenaRef<'a, Expression<'a>>) -> Vec<ArenaRef<'a, Expression<'a>>> {
+    let mut expressions = vec![];
+    let mut next = expression;
+    while let Expression::ExpressionP1(rest, last) = &*next {
+        expressions.push(*last);
+        next = *rest;
+    }
+    expressions.push(next);
+    expressions.reverse();
+    expressions
+}
+
+fn object_properties<'a>(object: ArenaRef<'a, Expression<'a>>) -> Vec<ArenaRef<'a, PropertyDefinition<'a>>> {
+    match &*object {
+        Expression::ObjectLiteralP1(list) | Expression::ObjectLiteralP2(list) => left_list(*list, |list| match list {
+            PropertyDefinitionList::PropertyDefinitionListP0(property) => (None, Some(*property)),
+            PropertyDefinitionList::PropertyDefinitionListP1(rest, property) => (Some(*rest), Some(*property)),
+            PropertyDefinitionList::Error(_) => (None, None),
+        }),
+        _ => vec![],
+    }
+}
+
+/// The node for the first argument in an argument list.
+fn first_argument<'a>(list: ArenaRef<'a, ArgumentList<'a>>) -> ArenaRef<'a, ArgumentList<'a>> {
+    let mut first = list;
+    while let ArgumentList::ArgumentListP2(rest, _) | ArgumentList::ArgumentListP3(rest, _) = &*first {
+        first = *rest;
+    }
+    first
+}
+
+fn is_computed(name: ArenaRef<'_, ClassElementName<'_>>) -> bool {
+    match &*name {
+        ClassElementName::ClassElementNameP0(name) => matches!(**name, PropertyName::PropertyNameP1(_)),
+        _ => false,
+    }
+}
+
+/// Whether a method or field named `name` is named `text`, either as an
+/// identifier or a string.
+fn element_name_is(name: ArenaRef<'_, ClassElementName<'_>>, text: &str) -> bool {
+    match &*name {
+        ClassElementName::ClassElementNameP0(name) => match &**name {
+            PropertyName::PropertyNameP0(literal) => match &**literal {
+                LiteralPropertyName::LiteralPropertyNameP0(token)
+                | LiteralPropertyName::LiteralPropertyNameP1(token) => token.value.as_deref() == Some(text),
+                _ => false,
+            },
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn elision_count(elision: Option<ArenaRef<'_, Elision<'_>>>) -> usize {
+    let mut count = 0;
+    let mut next = elision;
+    while let Some(elision) = next {
+        count += 1;
+        next = match &*elision {
+            Elision::ElisionP1(rest) => Some(*rest),
+            _ => None,
+        };
+    }
+    count
+}
+
+fn function_body<'a>(body: ArenaRef<'a, FunctionBody<'a>>) -> Option<Vec<ArenaRef<'a, ModuleItem<'a>>>> {
+    match &*body {
+        FunctionBody::FunctionBody(list) => match &**list {
+            FunctionStatementList::FunctionStatementList(list) => Some(statements(*list)),
+            FunctionStatementList::Error(_) => None,
+        },
+        FunctionBody::Error(_) | FunctionBody::Lazy(_) => None,
+    }
+}
+
+fn generator_body<'a>(body: ArenaRef<'a, GeneratorBody<'a>>) -> Option<Vec<ArenaRef<'a, ModuleItem<'a>>>> {
+    match &*body {
+        GeneratorBody::GeneratorBody(body) => function_body(*body),
+        GeneratorBody::Error(_) => None,
+    }
+}
+
+fn async_generator_body<'a>(body: ArenaRef<'a, AsyncGeneratorBody<'a>>) -> Option<Vec<ArenaRef<'a, ModuleItem<'a>>>> {
+    match &*body {
+        AsyncGeneratorBody::AsyncGeneratorBody(body) => function_body(*body),
+        AsyncGeneratorBody::Error(_) => None,
+    }
+}
+
+fn async_function_body<'a>(body: ArenaRef<'a, AsyncFunctionBody<'a>>) -> Option<Vec<ArenaRef<'a, ModuleItem<'a>>>> {
+    match &*body {
+        AsyncFunctionBody::AsyncFunctionBody(body) => function_body(*body),
+        AsyncFunctionBody::Error(_) => None,
+    }
+}
+
+/// The parameter list of `async x => ...`.
+fn async_arrow_param<'a>(id: ArenaRef<'a, AsyncArrowBindingIdentifier<'a>>) -> Vec<Param<'a>> {
+    match &*id {
+        AsyncArrowBindingIdentifier::AsyncArrowBindingIdentifier(id) => vec![Param::Identifier(*id)],
+        AsyncArrowBindingIdentifier::Error(_) => vec![],
+    }
+}
+
+/// The name an identifier-like token stands for, with escapes decoded.
+fn name<'t>(token: &'t Token) -> &'t str {
+    token.value.as_deref().or(token.raw.as_deref()).unwrap_or("")
+}
+
+/// An argument, or an element of an array literal.
+#[derive(Clone, Copy)]
+enum Arg<'a> {
+    Expression(ArenaRef<'a, Expression<'a>>),
+    /// `...x`, and where the `...` starts.
+    Spread(usize, ArenaRef<'a, Expression<'a>>),
+}
+
+/// A function parameter, in whichever form the grammar left it.
+#[derive(Clone)]
+enum Param<'a> {
+    Element(ArenaRef<'a, BindingElement<'a>>),
+    Identifier(ArenaRef<'a, BindingIdentifier<'a>>),
+    Pattern(ArenaRef<'a, BindingPattern<'a>>),
+    /// An arrow function's parameter, still parsed as an expression.
+    Expression(ArenaRef<'a, Expression<'a>>),
+    /// `...` and a parameter, and where the `...` starts.
+    Rest(usize, Box<Param<'a>>),
+}
+
+/// The object of a member expression or the callee of a call.
+enum Object<'a> {
+    Expression(ArenaRef<'a, Expression<'a>>),
+    /// `super`, which starts at the given offset.
+    Super(usize),
+    /// The part of an optional chain before a link: the expression it
+    /// starts with, where that starts, and the links up to this one.
+    Chain(usize, ArenaRef<'a, Expression<'a>>, ArenaRef<'a, OptionalChain<'a>>),
+}
+
+enum Property<'a> {
+    Computed(ArenaRef<'a, Expression<'a>>),
+    Name(&'a Token<'a>),
+    Private(&'a Token<'a>),
+}
+
+/// The parts of a method that ESTree puts in its FunctionExpression.
+struct Method<'a> {
+    kind: &'static str,
+    name: ArenaRef<'a, ClassElementName<'a>>,
+    params: Vec<Param<'a>>,
+    body: Option<Vec<ArenaRef<'a, ModuleItem<'a>>>>,
+    generator: bool,
+    is_async: bool,
+}
+
+struct Writer<'s> {
+    source: &'s str,
+    locations: &'s NodeLocations,
+    /// The byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    /// The UTF-16 offset of each byte offset, unless the source is ASCII
+    /// and they're the same.
+    utf16_offsets: Option<Vec<usize>>,
+    out: String,
+}
+
+impl<'s> Writer<'s> {
+    fn new(source: &'s str, locations: &'s NodeLocations) -> Writer<'s> {
+        let mut line_starts = vec![0];
+        let mut chars = source.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\r' if chars.peek().map(|&(_, next)| next) == Some('\n') => {}
+                '\n' | '\r' | '\u{2028}' | '\u{2029}' => line_starts.push(i + c.len_utf8()),
+                _ => {}
+            }
+        }
+        let utf16_offsets = if source.is_ascii() {
+            None
+        } else {
+            let mut offsets = Vec::with_capacity(source.len() + 1);
+            let mut offset = 0;
+            for c in source.chars() {
+                offsets.extend(std::iter::repeat_n(offset, c.len_utf8()));
+                offset += c.len_utf16();
+            }
+            offsets.push(offset);
+            Some(offsets)
+        };
+        Writer {
+            source,
+            locations,
+            line_starts,
+            utf16_offsets,
+            out: String::new(),
+        }
+    }
+
+    // Positions.
+
+    fn span<T>(&self, node: ArenaRef<'_, T>) -> Span {
+        self.locations.get(&*node).map_or((0, 0), |loc| (loc.start, loc.end))
+    }
+
+    fn token_span(&self, token: &Token) -> Span {
+        (token.loc.start, token.loc.end)
+    }
+
+    fn utf16_offset(&self, offset: usize) -> usize {
+        let offset = offset.min(self.source.len());
+        self.utf16_offsets.as_ref().map_or(offset, |offsets| offsets[offset])
+    }
+
+    /// Skip any whitespace and comments at `offset`.
+    fn skip_trivia(&self, mut offset: usize) -> usize {
+        let source = self.source;
+        while offset < source.len() {
+            let rest = &source[offset..];
+            if rest.starts_with("//") {
+                offset += rest.find(['\n', '\r', '\u{2028}', '\u{2029}']).unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                offset += comment.find("*/").map_or(rest.len(), |end| end + 4);
+            } else {
+                match rest.chars().next() {
+                    Some(c) if c.is_whitespace() || c == '\u{feff}' => offset += c.len_utf8(),
+                    _ => break,
+                }
+            }
+        }
+        offset
+    }
+
+    /// The offset of the first `(` at or after `offset` that isn't in a
+    /// comment.
+    fn open_paren(&self, mut offset: usize) -> usize {
+        loop {
+            offset = self.skip_trivia(offset);
+            match self.source[offset..].chars().next() {
+                Some('(') | None => return offset,
+                Some(c) => offset += c.len_utf8(),
+            }
+        }
+    }
+
+    /// The offset just past `text`, if it's the next thing after `offset`
+    /// apart from trivia. Otherwise `offset`.
+    fn after(&self, offset: usize, text: &str) -> usize {
+        let start = self.skip_trivia(offset);
+        if self.source[start..].starts_with(text) {
+            start + text.len()
+        } else {
+            offset
+        }
+    }
+
+    // JSON.
+
+    fn begin(&mut self, ty: &str, span: Span) {
+        write!(self.out, "{{\"type\":\"{}\"", ty).unwrap();
+        self.position(span);
+    }
+
+    fn position(&mut self, (start, end): Span) {
+        let (start_line, start_column) = self.line_column(start);
+        let (end_line, end_column) = self.line_column(end);
+        let (start, end) = (self.utf16_offset(start), self.utf16_offset(end));
+        write!(
+            self.out,
+            ",\"start\":{},\"end\":{},\"loc\":{{\"start\":{{\"line\":{},\"column\":{}}},\
+             \"end\":{{\"line\":{},\"column\":{}}}}},\"range\":[{},{}]",
+            start, end, start_line, start_column, end_line, end_column, start, end
+        )
+        .unwrap();
+    }
+
+    /// One element of a diagnostics array, with `message` as its message.
+    fn diagnostic(&mut self, message: &str, diagnostic: &Diagnostic) {
+        self.out.push_str("{\"message\":");
+        self.string(message);
+        self.out.push_str(",\"severity\":");
+        self.string(&diagnostic.severity.to_string());
+        self.out.push_str(",\"code\":");
+        self.string(diagnostic.code);
+        if let Some(loc) = diagnostic.loc {
+            self.position((loc.start, loc.end));
+        }
+        self.end();
+    }
+
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        (line + 1, self.utf16_offset(offset) - self.utf16_offset(self.line_starts[line]))
+    }
+
+    fn end(&mut self) {
+        self.out.push('}');
+    }
+
+    fn field(&mut self, key: &str) {
+        write!(self.out, ",\"{}\":", key).unwrap();
+    }
+
+    fn null(&mut self) {
+        self.out.push_str("null");
+    }
+
+    fn bool_field(&mut self, key: &str, value: bool) {
+        self.field(key);
+        self.out.push_str(if value { "true" } else { "false" });
+    }
+
+    fn str_field(&mut self, key: &str, value: &str) {
+        self.field(key);
+        self.string(value);
+    }
+
+    fn null_field(&mut self, key: &str) {
+        self.field(key);
+        self.null();
+    }
+
+    fn string(&mut self, value: &str) {
+        self.out.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if c < ' ' => write!(self.out, "\\u{:04x}", c as u32).unwrap(),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn array<T>(&mut self, key: &str, items: impl IntoIterator<Item = T>, mut write_item: impl FnMut(&mut Self, T)) {
+        self.field(key);
+        self.out.push('[');
+        for (i, item) in items.into_iter().enumerate() {
+            if i > 0 {
+                self.out.push(',');
+            }
+            write_item(self, item);
+        }
+        self.out.push(']');
+    }
+
+    fn optional<T>(&mut self, key: &str, value: Option<T>, write_value: impl FnOnce(&mut Self, T)) {
+        self.field(key);
+        match value {
+            Some(value) => write_value(self, value),
+            None => self.null(),
+        }
+    }
+
+    /// A node that has only a name, like an Identifier.
+    fn named(&mut self, ty: &str, span: Span, name: &str) {
+        self.begin(ty, span);
+        self.str_field("name", name);
+        self.end();
+    }
+
+    // Statements.
+
+    fn program(&mut self, items: Vec<ArenaRef<'_, ModuleItem<'_>>>, source_type: &str) {
+        self.begin("Program", (0, self.source.len()));
+        self.body(items);
+        self.str_field("sourceType", source_type);
+        self.end();
+    }
+
+    /// The `body` of a Program or function, whose directive prologue gets
+    /// marked as such.
+    fn body(&mut self, items: Vec<ArenaRef<'_, ModuleItem<'_>>>) {
+        let mut prologue = true;
+        self.array("body", items, |w, item| {
+            let mut statement = item;
+            while let ModuleItem::CheckAsi(inner) = &*statement {
+                statement = *inner;
+            }
+            if prologue {
+                if let ModuleItem::ExpressionStatement(expression) = &*statement {
+                    if let Expression::LiteralP4(token) = &**expression {
+                        let (start, end) = w.token_span(token);
+                        let directive = &w.source[start + 1..end - 1];
+                        w.expression_statement(w.span(item), *expression, Some(directive));
+                        return;
+                    }
+                }
+                prologue = false;
+            }
+            w.statement(item);
+        });
+    }
+
+    fn block_statement(&mut self, span: Span, items: Vec<ArenaRef<'_, ModuleItem<'_>>>) {
+        self.begin("BlockStatement", span);
+        self.array("body", items, Self::statement);
+        self.end();
+    }
+
+    fn block(&mut self, block: ArenaRef<'_, Block<'_>>) {
+        match &*block {
+            Block::Block(list) => self.block_statement(self.span(block), statements(*list)),
+            Block::Error(_) => self.null(),
+        }
+    }
+
+    fn function_block(&mut self, span: Span, body: Option<Vec<ArenaRef<'_, ModuleItem<'_>>>>) {
+        match body {
+            Some(items) => {
+                self.begin("BlockStatement", span);
+                self.body(items);
+                self.end();
+            }
+            None => self.null(),
+        }
+    }
+
+    fn expression_statement(&mut self, span: Span, expression: ArenaRef<'_, Expression<'_>>, directive: Option<&str>) {
+        self.begin("ExpressionStatement", span);
+        self.field("expression");
+        self.expression(expression);
+        if let Some(directive) = directive {
+            self.str_field("directive", directive);
+        }
+        self.end();
+    }
+
+    fn statement(&mut self, item: ArenaRef<'_, ModuleItem<'_>>) {
+        let span = self.span(item);
+        match &*item {
+            ModuleItem::BlockStatement(block) => self.block(*block),
+            ModuleItem::LexicalDeclaration(kind, list) => self.lexical_declaration(span, *kind, *list),
+            ModuleItem::CheckAsi(item) => self.statement(*item),
+            ModuleItem::VariableStatement(list) => self.variable_declaration(span, *list),
+            ModuleItem::EmptyStatement() => {
+                self.begin("EmptyStatement", span);
+                self.end();
+            }
+            ModuleItem::ExpressionStatement(expression) => self.expression_statement(span, *expression, None),
+            ModuleItem::AsyncArrowStatement(id, body) => {
+                self.begin("ExpressionStatement", span);
+                self.field("expression");
+                self.async_arrow((span.0, self.span(*body).1), async_arrow_param(*id), *body);
+                self.end();
+            }
+            ModuleItem::IfStatementP0(test, consequent, alternate) => self.if_statement(span, *test, *consequent, Some(*alternate)),
+            ModuleItem::IfStatementP1(test, consequent) => self.if_statement(span, *test, *consequent, None),
+            ModuleItem::IterationStatementP0(body, test) => {
+                self.begin("DoWhileStatement", span);
+                self.field("body");
+                self.statement(*body);
+                self.field("test");
+                self.expression(*test);
+                self.end();
+            }
+            ModuleItem::IterationStatementP1(test, body) => {
+                self.begin("WhileStatement", span);
+                self.field("test");
+                self.expression(*test);
+                self.field("body");
+                self.statement(*body);
+                self.end();
+            }
+            ModuleItem::IterationStatementP2(init, test, update, body) => {
+                self.for_statement(span, |w| match init {
+                    Some(init) => w.expression(*init),
+                    None => w.null(),
+                }, *test, *update, *body);
+            }
+            ModuleItem::IterationStatementP3(list, test, update, body) => {
+                let var_start = self.for_head(span.0);
+                self.for_statement(span, |w| w.variable_declaration((var_start, w.span(*list).1), *list), *test, *update, *body);
+            }
+            ModuleItem::IterationStatementP4(declaration, test, update, body) => {
+                self.for_statement(span, |w| match &**declaration {
+                    // The declaration's span takes in the `;` after it,
+                    // which ESTree leaves out.
+                    ModuleItem::LexicalDeclaration(kind, list) => {
+                        w.lexical_declaration((w.span(*declaration).0, w.span(*list).1), *kind, *list)
+                    }
+                    _ => w.null(),
+                }, *test, *update, *body);
+            }
+            ModuleItem::IterationStatementP5(target, object, body) => {
+                self.for_in_of("ForInStatement", span, |w| w.pattern(*target), *object, *body, None)
+            }
+            ModuleItem::IterationStatementP6(binding, object, body) => {
+                self.for_in_of("ForInStatement", span, |w| w.for_var(span, *binding), *object, *body, None)
+            }
+            ModuleItem::IterationStatementP7(declaration, object, body) => {
+                self.for_in_of("ForInStatement", span, |w| w.for_declaration(*declaration), *object, *body, None)
+            }
+            ModuleItem::IterationStatementP8(target, iterable, body) => {
+                self.for_in_of("ForOfStatement", span, |w| w.pattern(*target), *iterable, *body, Some(false))
+            }
+            ModuleItem::IterationStatementP9(binding, iterable, body) => {
+                self.for_in_of("ForOfStatement", span, |w| w.for_var(span, *binding), *iterable, *body, Some(false))
+            }
+            ModuleItem::IterationStatementP10(declaration, iterable, body) => {
+                self.for_in_of("ForOfStatement", span, |w| w.for_declaration(*declaration), *iterable, *body, Some(false))
+            }
+            ModuleItem::IterationStatementP11(target, iterable, body) => {
+                self.for_in_of("ForOfStatement", span, |w| w.pattern(*target), *iterable, *body, Some(true))
+            }
+            ModuleItem::IterationStatementP12(binding, iterable, body) => {
+                self.for_in_of("ForOfStatement", span, |w| w.for_var(span, *binding), *iterable, *body, Some(true))
+            }
+            ModuleItem::IterationStatementP13(declaration, iterable, body) => {
+                self.for_in_of("ForOfStatement", span, |w| w.for_declaration(*declaration), *iterable, *body, Some(true))
+            }
+            ModuleItem::ContinueStatementP0() => self.jump("ContinueStatement", span, None),
+            ModuleItem::ContinueStatementP1(label) => self.jump("ContinueStatement", span, Some(*label)),
+            ModuleItem::BreakStatementP0() => self.jump("BreakStatement", span, None),
+            ModuleItem::BreakStatementP1(label) => self.jump("BreakStatement", span, Some(*label)),
+            ModuleItem::ReturnStatementP0() => {
+                self.begin("ReturnStatement", span);
+                self.null_field("argument");
+                self.end();
+            }
+            ModuleItem::ReturnStatementP1(argument) => {
+                self.begin("ReturnStatement", span);
+                self.field("argument");
+                self.expression(*argument);
+                self.end();
+            }
+            ModuleItem::WithStatement(object, body) => {
+                self.begin("WithStatement", span);
+                self.field("object");
+                self.expression(*object);
+                self.field("body");
+                self.statement(*body);
+                self.end();
+            }
+            ModuleItem::SwitchStatement(discriminant, cases) => self.switch_statement(span, *discriminant, *cases),
+            ModuleItem::LabelledStatement(label, item) => {
+                self.begin("LabeledStatement", span);
+                self.field("label");
+                self.label(*label);
+                self.field("body");
+                match &**item {
+                    LabelledItem::LabelledItemP0(item) | LabelledItem::LabelledItemP1(item) => self.statement(*item),
+                    LabelledItem::Error(_) => self.null(),
+                }
+                self.end();
+            }
+            ModuleItem::ThrowStatement(argument) => {
+                self.begin("ThrowStatement", span);
+                self.field("argument");
+                self.expression(*argument);
+                self.end();
+            }
+            ModuleItem::TryStatementP0(block, handler) => self.try_statement(span, *block, Some(*handler), None),
+            ModuleItem::TryStatementP1(block, finalizer) => self.try_statement(span, *block, None, Some(*finalizer)),
+            ModuleItem::TryStatementP2(block, handler, finalizer) => {
+                self.try_statement(span, *block, Some(*handler), Some(*finalizer))
+            }
+            ModuleItem::DebuggerStatement() => {
+                self.begin("DebuggerStatement", span);
+                self.end();
+            }
+            ModuleItem::FunctionDeclarationP0(id, params, body) => {
+                self.function("FunctionDeclaration", span, Some(*id), *params, function_body(*body), false, false)
+            }
+            ModuleItem::FunctionDeclarationP1(params, body) => {
+                self.function("FunctionDeclaration", span, None, *params, function_body(*body), false, false)
+            }
+            ModuleItem::GeneratorDeclarationP0(id, params, body) => {
+                self.function("FunctionDeclaration", span, Some(*id), *params, generator_body(*body), true, false)
+            }
+            ModuleItem::GeneratorDeclarationP1(params, body) => {
+                self.function("FunctionDeclaration", span, None, *params, generator_body(*body), true, false)
+            }
+            ModuleItem::AsyncGeneratorDeclarationP0(id, params, body) => {
+                self.function("FunctionDeclaration", span, Some(*id), *params, async_generator_body(*body), true, true)
+            }
+            ModuleItem::AsyncGeneratorDeclarationP1(params, body) => {
+                self.function("FunctionDeclaration", span, None, *params, async_generator_body(*body), true, true)
+            }
+            ModuleItem::AsyncFunctionDeclarationP0(id, params, body) => {
+                self.function("FunctionDeclaration", span, Some(*id), *params, async_function_body(*body), false, true)
+            }
+            ModuleItem::AsyncFunctionDeclarationP1(params, body) => {
+                self.function("FunctionDeclaration", span, None, *params, async_function_body(*body), false, true)
+            }
+            ModuleItem::ClassDeclarationP0(id, tail) => self.class("ClassDeclaration", span, Some(*id), *tail),
+            ModuleItem::ClassDeclarationP1(tail) => self.class("ClassDeclaration", span, None, *tail),
+            ModuleItem::ImportDeclarationP0(clause, from) => {
+                self.begin("ImportDeclaration", span);
+                self.import_specifiers(*clause);
+                self.field("source");
+                self.module_source(*from);
+                self.end();
+            }
+            ModuleItem::ImportDeclarationP1(specifier) => {
+                self.begin("ImportDeclaration", span);
+                self.array("specifiers", Vec::<()>::new(), |_, _| {});
+                self.field("source");
+                self.module_specifier(*specifier);
+                self.end();
+            }
+            ModuleItem::ExportDeclarationP0(from) => {
+                self.begin("ExportAllDeclaration", span);
+                self.null_field("exported");
+                self.field("source");
+                self.module_source(*from);
+                self.end();
+            }
+            ModuleItem::ExportDeclarationP1(clause, from) => self.export_named(span, None, Some(*clause), Some(*from)),
+            ModuleItem::ExportDeclarationP2(clause) => self.export_named(span, None, Some(*clause), None),
+            ModuleItem::ExportDeclarationP3(declaration) | ModuleItem::ExportDeclarationP4(declaration) => {
+                self.export_named(span, Some(*declaration), None, None)
+            }
+            ModuleItem::ExportDeclarationP5(declaration) | ModuleItem::ExportDeclarationP6(declaration) => {
+                self.begin("ExportDefaultDeclaration", span);
+                self.field("declaration");
+                self.statement(*declaration);
+                self.end();
+            }
+            ModuleItem::ExportDeclarationP7(expression) => {
+                self.begin("ExportDefaultDeclaration", span);
+                self.field("declaration");
+                self.expression(*expression);
+                self.end();
+            }
+            ModuleItem::ClassElementP0(_)
+            | ModuleItem::ClassElementP1(_)
+            | ModuleItem::ClassElementP2(_)
+            | ModuleItem::ClassElementP3(_)
+            | ModuleItem::ClassElementP4()
+            | ModuleItem::Error(_) => self.null(),
+        }
+    }
+
+    fn if_statement(
+        &mut self,
+        span: Span,
+        test: ArenaRef<'_, Expression<'_>>,
+        consequent: ArenaRef<'_, ModuleItem<'_>>,
+        alternate: Option<ArenaRef<'_, ModuleItem<'_>>>,
+    ) {
+        self.begin("IfStatement", span);
+        self.field("test");
+        self.expression(test);
+        self.field("consequent");
+        self.statement(consequent);
+        self.optional("alternate", alternate, Self::statement);
+        self.end();
+    }
+
+    /// Where the declaration in the head of a `for` statement that starts
+    /// at `start` begins.
+    fn for_head(&self, start: usize) -> usize {
+        let offset = self.after(start, "for");
+        let offset = self.after(offset, "await");
+        self.skip_trivia(self.after(offset, "("))
+    }
+
+    fn for_statement(
+        &mut self,
+        span: Span,
+        init: impl FnOnce(&mut Self),
+        test: Option<ArenaRef<'_, Expression<'_>>>,
+        update: Option<ArenaRef<'_, Expression<'_>>>,
+        body: ArenaRef<'_, ModuleItem<'_>>,
+    ) {
+        self.begin("ForStatement", span);
+        self.field("init");
+        init(self);
+        self.optional("test", test, Self::expression);
+        self.optional("update", update, Self::expression);
+        self.field("body");
+        self.statement(body);
+        self.end();
+    }
+
+    fn for_in_of(
+        &mut self,
+        ty: &str,
+        span: Span,
+        left: impl FnOnce(&mut Self),
+        right: ArenaRef<'_, Expression<'_>>,
+        body: ArenaRef<'_, ModuleItem<'_>>,
+        is_await: Option<bool>,
+    ) {
+        self.begin(ty, span);
+        if let Some(is_await) = is_await {
+            self.bool_field("await", is_await);
+        }
+        self.field("left");
+        left(self);
+        self.field("right");
+        self.expression(right);
+        self.field("body");
+        self.statement(body);
+        self.end();
+    }
+
+    /// `var x` in the head of a `for` statement with the given span.
+    fn for_var(&mut self, span: Span, binding: ArenaRef<'_, ForBinding<'_>>) {
+        let binding_span = self.span(binding);
+        self.begin("VariableDeclaration", (self.for_head(span.0), binding_span.1));
+        self.array("declarations", Some(binding), |w, binding| {
+            w.begin("VariableDeclarator", binding_span);
+            w.field("id");
+            w.for_binding(binding);
+            w.null_field("init");
+            w.end();
+        });
+        self.str_field("kind", "var");
+        self.end();
+    }
+
+    fn for_declaration(&mut self, declaration: ArenaRef<'_, ForDeclaration<'_>>) {
+        match &*declaration {
+            ForDeclaration::ForDeclaration(kind, binding) => {
+                let binding_span = self.span(*binding);
+                self.begin("VariableDeclaration", self.span(declaration));
+                self.array("declarations", Some(*binding), |w, binding| {
+                    w.begin("VariableDeclarator", binding_span);
+                    w.field("id");
+                    w.for_binding(binding);
+                    w.null_field("init");
+                    w.end();
+                });
+                self.str_field("kind", let_or_const(kind));
+                self.end();
+            }
+            ForDeclaration::Error(_) => self.null(),
+        }
+    }
+
+    fn for_binding(&mut self, binding: ArenaRef<'_, ForBinding<'_>>) {
+        match &*binding {
+            ForBinding::ForBindingP0(id) => self.binding_identifier(*id),
+            ForBinding::ForBindingP1(pattern) => self.binding_pattern(*pattern),
+            ForBinding::Error(_) => self.null(),
+        }
+    }
+
+    fn variable_declaration(&mut self, span: Span, list: ArenaRef<'_, VariableDeclarationList<'_>>) {
+        let declarations = left_list(list, |list| match list {
+            VariableDeclarationList::VariableDeclarationListP0(declaration) => (None, Some(*declaration)),
+            VariableDeclarationList::VariableDeclarationListP1(rest, declaration) => (Some(*rest), Some(*declaration)),
+            VariableDeclarationList::Error(_) => (None, None),
+        });
+        self.begin("VariableDeclaration", span);
+        self.array("declarations", declarations, |w, declaration| match &*declaration {
+            VariableDeclaration::VariableDeclarationP0(id, init) => {
+                w.declarator(w.span(declaration), |w| w.binding_identifier(*id), *init)
+            }
+            VariableDeclaration::VariableDeclarationP1(pattern, init) => {
+                w.declarator(w.span(declaration), |w| w.binding_pattern(*pattern), Some(*init))
+            }
+            VariableDeclaration::Error(_) => w.null(),
+        });
+        self.str_field("kind", "var");
+        self.end();
+    }
+
+    fn lexical_declaration(&mut self, span: Span, kind: ArenaRef<'_, LetOrConst>, list: ArenaRef<'_, BindingList<'_>>) {
+        let bindings = left_list(list, |list| match list {
+            BindingList::BindingListP0(binding) => (None, Some(*binding)),
+            BindingList::BindingListP1(rest, binding) => (Some(*rest), Some(*binding)),
+            BindingList::Error(_) => (None, None),
+        });
+        self.begin("VariableDeclaration", span);
+        self.array("declarations", bindings, |w, binding| match &*binding {
+            LexicalBinding::LexicalBindingP0(id, init) => w.declarator(w.span(binding), |w| w.binding_identifier(*id), *init),
+            LexicalBinding::LexicalBindingP1(pattern, init) => {
+                w.declarator(w.span(binding), |w| w.binding_pattern(*pattern), Some(*init))
+            }
+            LexicalBinding::Error(_) => w.null(),
+        });
+        self.str_field("kind", let_or_const(&kind));
+        self.end();
+    }
+
+    fn declarator(&mut self, span: Span, id: impl FnOnce(&mut Self), init: Option<ArenaRef<'_, Initializer<'_>>>) {
+        self.begin("VariableDeclarator", span);
+        self.field("id");
+        id(self);
+        self.optional("init", init, Self::initializer);
+        self.end();
+    }
+
+    fn initializer(&mut self, init: ArenaRef<'_, Initializer<'_>>) {
+        match &*init {
+            Initializer::Initializer(expression) => self.expression(*expression),
+            Initializer::Error(_) => self.null(),
+        }
+    }
+
+    fn jump(&mut self, ty: &str, span: Span, label: Option<ArenaRef<'_, LabelIdentifier>>) {
+        self.begin(ty, span);
+        self.optional("label", label, Self::label);
+        self.end();
+    }
+
+    fn label(&mut self, label: ArenaRef<'_, LabelIdentifier>) {
+        match &*label {
+            LabelIdentifier::LabelIdentifier(token) => self.named("Identifier", self.token_span(token), name(token)),
+            LabelIdentifier::Error(_) => self.null(),
+        }
+    }
+
+    fn switch_statement(&mut self, span: Span, discriminant: ArenaRef<'_, Expression<'_>>, cases: ArenaRef<'_, CaseBlock<'_>>) {
+        let (before, default, after) = match &*cases {
+            CaseBlock::CaseBlockP0(list) => (*list, None, None),
+            CaseBlock::CaseBlockP1(before, default, after) => (*before, Some(*default), *after),
+            CaseBlock::Error(_) => (None, None, None),
+        };
+        // Each case as its span, test and consequent.
+        let mut all = self.case_clauses(before);
+        if let Some(default) = default {
+            if let DefaultClause::DefaultClause(consequent) = &*default {
+                all.push((self.span(default), None, statements(*consequent)));
+            }
+        }
+        all.extend(self.case_clauses(after));
+        self.begin("SwitchStatement", span);
+        self.field("discriminant");
+        self.expression(discriminant);
+        self.array("cases", all, |w, (span, test, consequent)| {
+            w.begin("SwitchCase", span);
+            w.array("consequent", consequent, Self::statement);
+            w.optional("test", test, Self::expression);
+            w.end();
+        });
+        self.end();
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn case_clauses<'a>(
+        &self,
+        list: Option<ArenaRef<'a, CaseClauses<'a>>>,
+    ) -> Vec<(Span, Option<ArenaRef<'a, Expression<'a>>>, Vec<ArenaRef<'a, ModuleItem<'a>>>)> {
+        let clauses = match list {
+            Some(list) => left_list(list, |list| match list {
+                CaseClauses::CaseClausesP0(clause) => (None, Some(*clause)),
+                CaseClauses::CaseClausesP1(rest, clause) => (Some(*rest), Some(*clause)),
+                CaseClauses::Error(_) => (None, None),
+            }),
+            None => vec![],
+        };
+        clauses
+            .into_iter()
+            .filter_map(|clause| match &*clause {
+                CaseClause::CaseClause(test, consequent) => Some((self.span(clause), Some(*test), statements(*consequent))),
+                CaseClause::Error(_) => None,
+            })
+            .collect()
+    }
+
+    fn try_statement(
+        &mut self,
+        span: Span,
+        block: ArenaRef<'_, Block<'_>>,
+        handler: Option<ArenaRef<'_, Catch<'_>>>,
+        finalizer: Option<ArenaRef<'_, Finally<'_>>>,
+    ) {
+        self.begin("TryStatement", span);
+        self.field("block");
+        self.block(block);
+        self.optional("handler", handler, |w, handler| match &*handler {
+            Catch::Catch(param, body) => {
+                w.begin("CatchClause", w.span(handler));
+                w.field("param");
+                match &**param {
+                    CatchParameter::CatchParameterP0(id) => w.binding_identifier(*id),
+                    CatchParameter::CatchParameterP1(pattern) => w.binding_pattern(*pattern),
+                    CatchParameter::Error(_) => w.null(),
+                }
+                w.field("body");
+                w.block(*body);
+                w.end();
+            }
+            Catch::Error(_) => w.null(),
+        });
+        self.optional("finalizer", finalizer, |w, finalizer| match &*finalizer {
+            Finally::Finally(block) => w.block(*block),
+            Finally::Error(_) => w.null(),
+        });
+        self.end();
+    }
+
+    // Modules.
+
+    fn import_specifiers(&mut self, clause: ArenaRef<'_, ImportClause<'_>>) {
+        let (default, namespace, named) = match &*clause {
+            ImportClause::ImportClauseP0(default) => (Some(*default), None, None),
+            ImportClause::ImportClauseP1(namespace) => (None, Some(*namespace), None),
+            ImportClause::ImportClauseP2(named) => (None, None, Some(*named)),
+            ImportClause::ImportClauseP3(default, namespace) => (Some(*default), Some(*namespace), None),
+            ImportClause::ImportClauseP4(default, named) => (Some(*default), None, Some(*named)),
+            ImportClause::Error(_) => (None, None, None),
+        };
+        let named = match named.as_deref() {
+            Some(NamedImports::NamedImportsP1(list)) | Some(NamedImports::NamedImportsP2(list)) => {
+                left_list(*list, |list| match list {
+                    ImportsList::ImportsListP0(specifier) => (None, Some(*specifier)),
+                    ImportsList::ImportsListP1(rest, specifier) => (Some(*rest), Some(*specifier)),
+                    ImportsList::Error(_) => (None, None),
+                })
+            }
+            _ => vec![],
+        };
+        self.field("specifiers");
+        self.out.push('[');
+        let mut first = true;
+        let mut separate = |w: &mut Self| {
+            if !first {
+                w.out.push(',');
+            }
+            first = false;
+        };
+        if let Some(default) = default {
+            separate(self);
+            match &*default {
+                ImportedDefaultBinding::ImportedDefaultBinding(binding) => {
+                    self.begin("ImportDefaultSpecifier", self.span(default));
+                    self.field("local");
+                    self.imported_binding(*binding);
+                    self.end();
+                }
+                ImportedDefaultBinding::Error(_) => self.null(),
+            }
+        }
+        if let Some(namespace) = namespace {
+            separate(self);
+            match &*namespace {
+                NameSpaceImport::NameSpaceImport(binding) => {
+                    self.begin("ImportNamespaceSpecifier", self.span(namespace));
+                    self.field("local");
+                    self.imported_binding(*binding);
+                    self.end();
+                }
+                NameSpaceImport::Error(_) => self.null(),
+            }
+        }
+        for specifier in named {
+            separate(self);
+            self.begin("ImportSpecifier", self.span(specifier));
+            match &*specifier {
+                ImportSpecifier::ImportSpecifierP0(binding) => {
+                    self.field("imported");
+                    self.imported_binding(*binding);
+                    self.field("local");
+                    self.imported_binding(*binding);
+                }
+                ImportSpecifier::ImportSpecifierP1(token, binding) => {
+                    self.field("imported");
+                    self.module_export_name(token);
+                    self.field("local");
+                    self.imported_binding(*binding);
+                }
+                ImportSpecifier::Error(_) => {}
+            }
+            self.end();
+        }
+        self.out.push(']');
+    }
+
+    fn imported_binding(&mut self, binding: ArenaRef<'_, ImportedBinding<'_>>) {
+        match &*binding {
+            ImportedBinding::ImportedBinding(id) => self.binding_identifier(*id),
+            ImportedBinding::Error(_) => self.null(),
+        }
+    }
+
+    fn module_export_name(&mut self, token: &Token) {
+        if token.terminal_id == TerminalId::StringLiteral {
+            self.literal(token);
+        } else {
+            self.named("Identifier", self.token_span(token), name(token));
+        }
+    }
+
+    fn module_source(&mut self, from: ArenaRef<'_, FromClause<'_>>) {
+        match &*from {
+            FromClause::FromClause(specifier) => self.module_specifier(*specifier),
+            FromClause::Error(_) => self.null(),
+        }
+    }
+
+    fn module_specifier(&mut self, specifier: ArenaRef<'_, ModuleSpecifier>) {
+        match &*specifier {
+            ModuleSpecifier::ModuleSpecifier(token) => self.literal(token),
+            ModuleSpecifier::Error(_) => self.null(),
+        }
+    }
+
+    fn export_named(
+        &mut self,
+        span: Span,
+        declaration: Option<ArenaRef<'_, ModuleItem<'_>>>,
+        clause: Option<ArenaRef<'_, ExportClause<'_>>>,
+        from: Option<ArenaRef<'_, FromClause<'_>>>,
+    ) {
+        let specifiers = match clause.as_deref() {
+            Some(ExportClause::ExportClauseP1(list)) | Some(ExportClause::ExportClauseP2(list)) => {
+                left_list(*list, |list| match list {
+                    ExportsList::ExportsListP0(specifier) => (None, Some(*specifier)),
+                    ExportsList::ExportsListP1(rest, specifier) => (Some(*rest), Some(*specifier)),
+                    ExportsList::Error(_) => (None, None),
+                })
+            }
+            _ => vec![],
+        };
+        self.begin("ExportNamedDeclaration", span);
+        self.optional("declaration", declaration, Self::statement);
+        self.array("specifiers", specifiers, |w, specifier| {
+            let (local, exported) = match &*specifier {
+                ExportSpecifier::ExportSpecifierP0(token) => (token, token),
+                ExportSpecifier::ExportSpecifierP1(local, exported) => (local, exported),
+                ExportSpecifier::Error(_) => return w.null(),
+            };
+            w.begin("ExportSpecifier", w.span(specifier));
+            w.field("local");
+            w.module_export_name(local);
+            w.field("exported");
+            w.module_export_name(exported);
+            w.end();
+        });
+        self.optional("source", from, Self::module_source);
+        self.end();
+    }
+
+    // Functions and classes.
+
+    #[allow(clippy::too_many_arguments)]
+    fn function(
+        &mut self,
+        ty: &str,
+        span: Span,
+        id: Option<ArenaRef<'_, BindingIdentifier>>,
+        params: ArenaRef<'_, FormalParameters<'_>>,
+        body: Option<Vec<ArenaRef<'_, ModuleItem<'_>>>>,
+        generator: bool,
+        is_async: bool,
+    ) {
+        let anchor = id.map_or(span.0, |id| self.span(id).1);
+        self.function_parts(ty, span, id, self.formal_parameters(params), anchor, body, generator, is_async);
+    }
+
+    /// A function whose body ends where the function does. `anchor` is an
+    /// offset before the `(` and after anything else that could contain
+    /// one, which is where the parameters are looked for when there are
+    /// none of them to say where they end.
+    #[allow(clippy::too_many_arguments)]
+    fn function_parts(
+        &mut self,
+        ty: &str,
+        span: Span,
+        id: Option<ArenaRef<'_, BindingIdentifier>>,
+        params: Vec<Param<'_>>,
+        anchor: usize,
+        body: Option<Vec<ArenaRef<'_, ModuleItem<'_>>>>,
+        generator: bool,
+        is_async: bool,
+    ) {
+        // An empty parameter list is reduced along with the function around
+        // it, so it has the function's span rather than one of its own.
+        let params_end = match params.last() {
+            Some(param) => self.after(self.param_end(param), ","),
+            None => self.after(self.open_paren(anchor), "("),
+        };
+        let body_start = self.skip_trivia(self.after(params_end, ")"));
+        self.begin(ty, span);
+        self.optional("id", id, Self::binding_identifier);
+        self.bool_field("expression", false);
+        self.bool_field("generator", generator);
+        self.bool_field("async", is_async);
+        self.array("params", params, |w, param| w.param(&param));
+        self.field("body");
+        self.function_block((body_start, span.1), body);
+        self.end();
+    }
+
+    fn param(&mut self, param: &Param<'_>) {
+        match param {
+            Param::Element(element) => self.binding_element(*element),
+            Param::Identifier(id) => self.binding_identifier(*id),
+            Param::Pattern(pattern) => self.binding_pattern(*pattern),
+            Param::Expression(expression) => self.pattern(*expression),
+            Param::Rest(start, param) => {
+                self.begin("RestElement", (*start, self.param_end(param)));
+                self.field("argument");
+                self.param(param);
+                self.end();
+            }
+        }
+    }
+
+    fn param_end(&self, param: &Param<'_>) -> usize {
+        match param {
+            Param::Element(element) => self.span(*element).1,
+            Param::Identifier(id) => self.span(*id).1,
+            Param::Pattern(pattern) => self.span(*pattern).1,
+            Param::Expression(expression) => self.span(*expression).1,
+            Param::Rest(_, param) => self.param_end(param),
+        }
+    }
+
+    fn arrow_function(&mut self, span: Span, arrow: ArenaRef<'_, ArrowFunction<'_>>) {
+        match &*arrow {
+            ArrowFunction::ArrowFunction(params, body) => {
+                let params = match &**params {
+                    ArrowParameters::ArrowParametersP0(id) => vec![Param::Identifier(*id)],
+                    ArrowParameters::ArrowParametersP1(cover) => self.cover_parameters(*cover),
+                    ArrowParameters::Error(_) => vec![],
+                };
+                match &**body {
+                    ConciseBody::ConciseBodyP0(expression) => self.arrow(span, params, false, |w| w.expression(*expression), true),
+                    ConciseBody::ConciseBodyP1(function) => {
+                        let body_span = self.span(*body);
+                        self.arrow(span, params, false, |w| w.function_block(body_span, function_body(*function)), false)
+                    }
+                    ConciseBody::Error(_) => self.arrow(span, params, false, Self::null, false),
+                }
+            }
+            ArrowFunction::Error(_) => self.null(),
+        }
+    }
+
+    fn async_arrow_function(&mut self, span: Span, arrow: ArenaRef<'_, AsyncArrowFunction<'_>>) {
+        let (params, body) = match &*arrow {
+            AsyncArrowFunction::AsyncArrowFunctionP0(id, body) => (async_arrow_param(*id), *body),
+            AsyncArrowFunction::AsyncArrowFunctionP1(head, body) => match &**head {
+                CoverCallExpressionAndAsyncArrowHead::CoverCallExpressionAndAsyncArrowHead(_, args) => {
+                    let params = self
+                        .arguments(*args)
+                        .into_iter()
+                        .map(|arg| match arg {
+                            Arg::Expression(expression) => Param::Expression(expression),
+                            Arg::Spread(start, expression) => Param::Rest(start, Box::new(Param::Expression(expression))),
+                        })
+                        .collect();
+                    (params, *body)
+                }
+                CoverCallExpressionAndAsyncArrowHead::Error(_) => (vec![], *body),
+            },
+            AsyncArrowFunction::Error(_) => return self.null(),
+        };
+        self.async_arrow(span, params, body);
+    }
+
+    fn async_arrow(&mut self, span: Span, params: Vec<Param<'_>>, body: ArenaRef<'_, AsyncConciseBody<'_>>) {
+        match &*body {
+            AsyncConciseBody::AsyncConciseBodyP0(expression) => self.arrow(span, params, true, |w| w.expression(*expression), true),
+            AsyncConciseBody::AsyncConciseBodyP1(function) => {
+                let body_span = self.span(body);
+                self.arrow(span, params, true, |w| w.function_block(body_span, async_function_body(*function)), false)
+            }
+            AsyncConciseBody::Error(_) => self.arrow(span, params, true, Self::null, false),
+        }
+    }
+
+    fn arrow(&mut self, span: Span, params: Vec<Param<'_>>, is_async: bool, body: impl FnOnce(&mut Self), expression: bool) {
+        self.begin("ArrowFunctionExpression", span);
+        self.null_field("id");
+        self.bool_field("expression", expression);
+        self.bool_field("generator", false);
+        self.bool_field("async", is_async);
+        self.array("params", params, |w, param| w.param(&param));
+        self.field("body");
+        body(self);
+        self.end();
+    }
+
+    /// The parameters of an arrow function, from the parenthesized
+    /// expression the parser took them for.
+    fn cover_parameters<'a>(&self, cover: ArenaRef<'a, CoverParenthesizedExpressionAndArrowParameterList<'a>>) -> Vec<Param<'a>> {
+        use CoverParenthesizedExpressionAndArrowParameterList as Cover;
+        let sequence = |expression| sequence(expression).into_iter().map(Param::Expression).collect::<Vec<_>>();
+        let rest_after = |offset, text| self.skip_trivia(self.after(offset, text));
+        match &*cover {
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP0(expression)
+            | Cover::CoverParenthesizedExpressionAndArrowParameterListP1(expression) => sequence(*expression),
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP2() => vec![],
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP3(id) => {
+                vec![Param::Rest(rest_after(self.span(cover).0, "("), Box::new(Param::Identifier(*id)))]
+            }
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP4(pattern) => {
+                vec![Param::Rest(rest_after(self.span(cover).0, "("), Box::new(Param::Pattern(*pattern)))]
+            }
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP5(expression, id) => {
+                let mut params = sequence(*expression);
+                params.push(Param::Rest(rest_after(self.span(*expression).1, ","), Box::new(Param::Identifier(*id))));
+                params
+            }
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP6(expression, pattern) => {
+                let mut params = sequence(*expression);
+                params.push(Param::Rest(rest_after(self.span(*expression).1, ","), Box::new(Param::Pattern(*pattern))));
+                params
+            }
+            Cover::Error(_) => vec![],
+        }
+    }
+
+    fn method<'a>(&self, method: ArenaRef<'a, MethodDefinition<'a>>) -> Option<Method<'a>> {
+        let unique = |params: ArenaRef<'a, UniqueFormalParameters<'a>>| match &*params {
+            UniqueFormalParameters::UniqueFormalParameters(params) => self.formal_parameters(*params),
+            UniqueFormalParameters::Error(_) => vec![],
+        };
+        let (kind, name, params, body, generator, is_async) = match &*method {
+            MethodDefinition::MethodDefinitionP0(name, params, body) => {
+                ("method", *name, unique(*params), function_body(*body), false, false)
+            }
+            MethodDefinition::MethodDefinitionP4(name, body) => ("get", *name, vec![], function_body(*body), false, false),
+            MethodDefinition::MethodDefinitionP5(name, param, body) => {
+                let params = match &**param {
+                    PropertySetParameterList::PropertySetParameterList(param) => match &**param {
+                        FormalParameter::FormalParameter(element) => vec![Param::Element(*element)],
+                        FormalParameter::Error(_) => vec![],
+                    },
+                    PropertySetParameterList::Error(_) => vec![],
+                };
+                ("set", *name, params, function_body(*body), false, false)
+            }
+            MethodDefinition::GeneratorMethod(name, params, body) => {
+                ("method", *name, unique(*params), generator_body(*body), true, false)
+            }
+            MethodDefinition::AsyncGeneratorMethod(name, params, body) => {
+                ("method", *name, unique(*params), async_generator_body(*body), true, true)
+            }
+            MethodDefinition::AsyncMethod(name, params, body) => {
+                ("method", *name, unique(*params), async_function_body(*body), false, true)
+            }
+            MethodDefinition::Error(_) => return None,
+        };
+        Some(Method {
+            kind,
+            name,
+            params,
+            body,
+            generator,
+            is_async,
+        })
+    }
+
+    /// The FunctionExpression of a method that ends at `end`.
+    fn method_value(&mut self, method: Method<'_>, end: usize) {
+        let start = self.skip_trivia(self.span(method.name).1);
+        self.function_parts(
+            "FunctionExpression",
+            (start, end),
+            None,
+            method.params,
+            start,
+            method.body,
+            method.generator,
+            method.is_async,
+        );
+    }
+
+    fn class(&mut self, ty: &str, span: Span, id: Option<ArenaRef<'_, BindingIdentifier>>, tail: ArenaRef<'_, ClassTail<'_>>) {
+        self.begin(ty, span);
+        self.optional("id", id, Self::binding_identifier);
+        match &*tail {
+            ClassTail::ClassTail(heritage, body) => {
+                let (tail_start, tail_end) = self.span(tail);
+                let body_start = match heritage {
+                    Some(heritage) => self.skip_trivia(self.span(*heritage).1),
+                    None => tail_start,
+                };
+                self.optional("superClass", *heritage, |w, heritage| match &*heritage {
+                    ClassHeritage::ClassHeritage(expression) => w.expression(*expression),
+                    ClassHeritage::Error(_) => w.null(),
+                });
+                let elements = match body.as_deref() {
+                    Some(ClassBody::ClassBody(list)) => left_list(*list, |list| match list {
+                        ClassElementList::ClassElementListP0(element) => (None, Some(*element)),
+                        ClassElementList::ClassElementListP1(rest, element) => (Some(*rest), Some(*element)),
+                        ClassElementList::Error(_) => (None, None),
+                    }),
+                    _ => vec![],
+                };
+                self.field("body");
+                self.begin("ClassBody", (body_start, tail_end));
+                let elements = elements.into_iter().filter(|element| !matches!(**element, ModuleItem::ClassElementP4()));
+                self.array("body", elements, Self::class_element);
+                self.end();
+            }
+            ClassTail::Error(_) => {
+                self.null_field("superClass");
+                self.null_field("body");
+            }
+        }
+        self.end();
+    }
+
+    fn class_element(&mut self, element: ArenaRef<'_, ModuleItem<'_>>) {
+        let span = self.span(element);
+        match &*element {
+            ModuleItem::ClassElementP0(method) | ModuleItem::ClassElementP1(method) => {
+                let is_static = matches!(*element, ModuleItem::ClassElementP1(_));
+                let method = match self.method(*method) {
+                    Some(method) => method,
+                    None => return self.null(),
+                };
+                let computed = is_computed(method.name);
+                let kind = if !is_static && !computed && element_name_is(method.name, "constructor") {
+                    "constructor"
+                } else {
+                    method.kind
+                };
+                self.begin("MethodDefinition", span);
+                self.bool_field("static", is_static);
+                self.bool_field("computed", computed);
+                self.field("key");
+                self.class_element_name(method.name);
+                self.str_field("kind", kind);
+                self.field("value");
+                self.method_value(method, span.1);
+                self.end();
+            }
+            ModuleItem::ClassElementP2(field) | ModuleItem::ClassElementP3(field) => match &**field {
+                FieldDefinition::FieldDefinition(name, init) => {
+                    self.begin("PropertyDefinition", span);
+                    self.bool_field("static", matches!(*element, ModuleItem::ClassElementP3(_)));
+                    self.bool_field("computed", is_computed(*name));
+                    self.field("key");
+                    self.class_element_name(*name);
+                    self.optional("value", *init, Self::initializer);
+                    self.end();
+                }
+                FieldDefinition::Error(_) => self.null(),
+            },
+            _ => self.null(),
+        }
+    }
+
+    fn class_element_name(&mut self, name: ArenaRef<'_, ClassElementName<'_>>) {
+        match &*name {
+            ClassElementName::ClassElementNameP0(name) => self.property_name(*name),
+            ClassElementName::ClassElementNameP1(token) => self.private_identifier(token),
+            ClassElementName::Error(_) => self.null(),
+        }
+    }
+
+    fn property_name(&mut self, property: ArenaRef<'_, PropertyName<'_>>) {
+        match &*property {
+            PropertyName::PropertyNameP0(literal) => match &**literal {
+                LiteralPropertyName::LiteralPropertyNameP0(token) => self.named("Identifier", self.token_span(token), name(token)),
+                LiteralPropertyName::LiteralPropertyNameP1(token)
+                | LiteralPropertyName::LiteralPropertyNameP2(token)
+                | LiteralPropertyName::LiteralPropertyNameP3(token) => self.literal(token),
+                LiteralPropertyName::Error(_) => self.null(),
+            },
+            PropertyName::PropertyNameP1(computed) => match &**computed {
+                ComputedPropertyName::ComputedPropertyName(expression) => self.expression(*expression),
+                ComputedPropertyName::Error(_) => self.null(),
+            },
+            PropertyName::Error(_) => self.null(),
+        }
+    }
+
+    fn formal_parameters<'a>(&self, params: ArenaRef<'a, FormalParameters<'a>>) -> Vec<Param<'a>> {
+        let (list, rest) = match &*params {
+            FormalParameters::FormalParametersP0() | FormalParameters::Error(_) => (None, None),
+            FormalParameters::FormalParametersP1(rest) => (None, Some(*rest)),
+            FormalParameters::FormalParametersP2(list) | FormalParameters::FormalParametersP3(list) => (Some(*list), None),
+            FormalParameters::FormalParametersP4(list, rest) => (Some(*list), Some(*rest)),
+        };
+        let mut result = match list {
+            Some(list) => left_list(list, |list| match list {
+                FormalParameterList::FormalParameterListP0(param) => (None, Some(*param)),
+                FormalParameterList::FormalParameterListP1(rest, param) => (Some(*rest), Some(*param)),
+                FormalParameterList::Error(_) => (None, None),
+            })
+            .into_iter()
+            .filter_map(|param| match &*param {
+                FormalParameter::FormalParameter(element) => Some(Param::Element(*element)),
+                FormalParameter::Error(_) => None,
+            })
+            .collect(),
+            None => vec![],
+        };
+        if let Some(rest) = rest {
+            if let FunctionRestParameter::FunctionRestParameter(element) = &*rest {
+                match &**element {
+                    BindingRestElement::BindingRestElementP0(id) => {
+                        result.push(Param::Rest(self.span(*element).0, Box::new(Param::Identifier(*id))))
+                    }
+                    BindingRestElement::BindingRestElementP1(pattern) => {
+                        result.push(Param::Rest(self.span(*element).0, Box::new(Param::Pattern(*pattern))))
+                    }
+                    BindingRestElement::Error(_) => {}
+                }
+            }
+        }
+        result
+    }
+
+    // Patterns.
+
+    fn binding_identifier(&mut self, id: ArenaRef<'_, BindingIdentifier>) {
+        let span = self.span(id);
+        match &*id {
+            BindingIdentifier::BindingIdentifierP0(token) => self.named("Identifier", span, name(token)),
+            BindingIdentifier::BindingIdentifierP1() => self.named("Identifier", span, "yield"),
+            BindingIdentifier::BindingIdentifierP2() => self.named("Identifier", span, "await"),
+            BindingIdentifier::Error(_) => self.null(),
+        }
+    }
+
+    fn binding_pattern(&mut self, pattern: ArenaRef<'_, BindingPattern<'_>>) {
+        let span = self.span(pattern);
+        match &*pattern {
+            BindingPattern::BindingPatternP0(object) => {
+                let (list, rest) = match &**object {
+                    ObjectBindingPattern::ObjectBindingPatternP0() => (None, None),
+                    ObjectBindingPattern::ObjectBindingPatternP1(rest) => (None, Some(*rest)),
+                    ObjectBindingPattern::ObjectBindingPatternP2(list) => (Some(*list), None),
+                    ObjectBindingPattern::ObjectBindingPatternP3(list, rest) => (Some(*list), *rest),
+                    ObjectBindingPattern::Error(_) => return self.null(),
+                };
+                let properties = match list {
+                    Some(list) => left_list(list, |list| match list {
+                        BindingPropertyList::BindingPropertyListP0(property) => (None, Some(*property)),
+                        BindingPropertyList::BindingPropertyListP1(rest, property) => (Some(*rest), Some(*property)),
+                        BindingPropertyList::Error(_) => (None, None),
+                    }),
+                    None => vec![],
+                };
+                self.begin("ObjectPattern", span);
+                self.field("properties");
+                self.out.push('[');
+                for (i, property) in properties.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push(',');
+                    }
+                    self.binding_property(*property);
+                }
+                if let Some(rest) = rest {
+                    if !properties.is_empty() {
+                        self.out.push(',');
+                    }
+                    match &*rest {
+                        BindingRestProperty::BindingRestProperty(id) => {
+                            self.begin("RestElement", self.span(rest));
+                            self.field("argument");
+                            self.binding_identifier(*id);
+                            self.end();
+                        }
+                        BindingRestProperty::Error(_) => self.null(),
+                    }
+                }
+                self.out.push(']');
+                self.end();
+            }
+            BindingPattern::BindingPatternP1(array) => {
+                let (holes, list, more_holes, rest) = match &**array {
+                    ArrayBindingPattern::ArrayBindingPatternP0(holes, rest) => (*holes, None, None, *rest),
+                    ArrayBindingPattern::ArrayBindingPatternP1(list) => (None, Some(*list), None, None),
+                    ArrayBindingPattern::ArrayBindingPatternP2(list, holes, rest) => (None, Some(*list), *holes, *rest),
+                    ArrayBindingPattern::Error(_) => return self.null(),
+                };
+                // Each element, None for a hole.
+                let mut elements = vec![None; elision_count(holes)];
+                if let Some(list) = list {
+                    let items = left_list(list, |list| match list {
+                        BindingElementList::BindingElementListP0(element) => (None, Some(*element)),
+                        BindingElementList::BindingElementListP1(rest, element) => (Some(*rest), Some(*element)),
+                        BindingElementList::Error(_) => (None, None),
+                    });
+                    for item in items {
+                        if let BindingElisionElement::BindingElisionElement(holes, element) = &*item {
+                            elements.extend(std::iter::repeat_n(None, elision_count(*holes)));
+                            elements.push(Some(Param::Element(*element)));
+                        }
+                    }
+                }
+                elements.extend(std::iter::repeat_n(None, elision_count(more_holes)));
+                if let Some(rest) = rest {
+                    let start = self.span(rest).0;
+                    match &*rest {
+                        BindingRestElement::BindingRestElementP0(id) => {
+                            elements.push(Some(Param::Rest(start, Box::new(Param::Identifier(*id)))))
+                        }
+                        BindingRestElement::BindingRestElementP1(pattern) => {
+                            elements.push(Some(Param::Rest(start, Box::new(Param::Pattern(*pattern)))))
+                        }
+                        BindingRestElement::Error(_) => {}
+                    }
+                }
+                self.begin("ArrayPattern", span);
+                self.array("elements", elements, |w, element| match element {
+                    Some(element) => w.param(&element),
+                    None => w.null(),
+                });
+                self.end();
+            }
+            BindingPattern::Error(_) => self.null(),
+        }
+    }
+
+    fn binding_property(&mut self, property: ArenaRef<'_, BindingProperty<'_>>) {
+        let span = self.span(property);
+        match &*property {
+            BindingProperty::BindingPropertyP0(single) => {
+                let id = match &**single {
+                    SingleNameBinding::SingleNameBinding(id, _) => *id,
+                    SingleNameBinding::Error(_) => return self.null(),
+                };
+                self.property_node(span, false, true, false, |w| w.binding_identifier(id), |w| w.single_name_binding(*single), "init");
+            }
+            BindingProperty::BindingPropertyP1(name, element) => {
+                let computed = matches!(**name, PropertyName::PropertyNameP1(_));
+                self.property_node(span, false, false, computed, |w| w.property_name(*name), |w| w.binding_element(*element), "init");
+            }
+            BindingProperty::Error(_) => self.null(),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn property_node(
+        &mut self,
+        span: Span,
+        method: bool,
+        shorthand: bool,
+        computed: bool,
+        key: impl FnOnce(&mut Self),
+        value: impl FnOnce(&mut Self),
+        kind: &str,
+    ) {
+        self.begin("Property", span);
+        self.bool_field("method", method);
+        self.bool_field("shorthand", shorthand);
+        self.bool_field("computed", computed);
+        self.field("key");
+        key(self);
+        self.field("value");
+        value(self);
+        self.str_field("kind", kind);
+        self.end();
+    }
+
+    fn binding_element(&mut self, element: ArenaRef<'_, BindingElement<'_>>) {
+        match &*element {
+            BindingElement::BindingElementP0(single) => self.single_name_binding(*single),
+            BindingElement::BindingElementP1(pattern, None) => self.binding_pattern(*pattern),
+            BindingElement::BindingElementP1(pattern, Some(init)) => {
+                self.assignment_pattern(self.span(element), |w| w.binding_pattern(*pattern), *init)
+            }
+            BindingElement::Error(_) => self.null(),
+        }
+    }
+
+    fn single_name_binding(&mut self, single: ArenaRef<'_, SingleNameBinding<'_>>) {
+        match &*single {
+            SingleNameBinding::SingleNameBinding(id, None) => self.binding_identifier(*id),
+            SingleNameBinding::SingleNameBinding(id, Some(init)) => {
+                self.assignment_pattern(self.span(single), |w| w.binding_identifier(*id), *init)
+            }
+            SingleNameBinding::Error(_) => self.null(),
+        }
+    }
+
+    fn assignment_pattern(&mut self, span: Span, left: impl FnOnce(&mut Self), init: ArenaRef<'_, Initializer<'_>>) {
+        self.begin("AssignmentPattern", span);
+        self.field("left");
+        left(self);
+        self.field("right");
+        self.initializer(init);
+        self.end();
+    }
+
+    /// An expression the parser took for one, where a pattern was really
+    /// meant: the target of `=`, `for (... of`, or an arrow function's
+    /// parameter.
+    fn pattern(&mut self, expression: ArenaRef<'_, Expression<'_>>) {
+        let span = self.span(expression);
+        match &*expression {
+            Expression::ArrayLiteralP0(_) | Expression::ArrayLiteralP1(_) | Expression::ArrayLiteralP2(..) => {
+                let elements = self.array_elements(expression);
+                self.begin("ArrayPattern", span);
+                self.array("elements", elements, |w, element| match element {
+                    Some(Arg::Expression(element)) => w.pattern(element),
+                    Some(Arg::Spread(start, element)) => w.rest_pattern((start, w.span(element).1), element),
+                    None => w.null(),
+                });
+                self.end();
+            }
+            Expression::ObjectLiteralP0() | Expression::ObjectLiteralP1(_) | Expression::ObjectLiteralP2(_) => {
+                self.begin("ObjectPattern", span);
+                self.array("properties", object_properties(expression), |w, property| w.property(property, true));
+                self.end();
+            }
+            Expression::AssignmentExpressionP4(target, value) => {
+                self.begin("AssignmentPattern", span);
+                self.field("left");
+                self.pattern(*target);
+                self.field("right");
+                self.expression(*value);
+                self.end();
+            }
+            Expression::PrimaryExpressionP12(cover) => match &**cover {
+                CoverParenthesizedExpressionAndArrowParameterList::CoverParenthesizedExpressionAndArrowParameterListP0(
+                    expression,
+                ) => self.pattern(*expression),
+                _ => self.expression(expression),
+            },
+            _ => self.expression(expression),
+        }
+    }
+
+    fn rest_pattern(&mut self, span: Span, argument: ArenaRef<'_, Expression<'_>>) {
+        self.begin("RestElement", span);
+        self.field("argument");
+        self.pattern(argument);
+        self.end();
+    }
+
+    // Expressions.
+
+    fn expression(&mut self, expression: ArenaRef<'_, Expression<'_>>) {
+        let span = self.span(expression);
+        match &*expression {
+            Expression::PrimaryExpressionP0() => {
+                self.begin("ThisExpression", span);
+                self.end();
+            }
+            Expression::PrimaryExpressionP1(id) => self.identifier_reference(*id),
+            Expression::PrimaryExpressionP10(token) => {
+                let text = name(token);
+                let pattern_end = text.rfind('/').unwrap_or(0);
+                self.begin("Literal", self.token_span(token));
+                self.null_field("value");
+                self.str_field("raw", &self.source[token.loc.start..token.loc.end]);
+                self.field("regex");
+                self.out.push_str("{\"pattern\":");
+                self.string(text.get(1..pattern_end).unwrap_or(""));
+                self.out.push_str(",\"flags\":");
+                self.string(&text[pattern_end + 1..]);
+                self.out.push('}');
+                self.end();
+            }
+            Expression::PrimaryExpressionP11(template) => self.template_literal(*template),
+            Expression::PrimaryExpressionP12(cover) => match &**cover {
+                CoverParenthesizedExpressionAndArrowParameterList::CoverParenthesizedExpressionAndArrowParameterListP0(
+                    expression,
+                )
+                | CoverParenthesizedExpressionAndArrowParameterList::CoverParenthesizedExpressionAndArrowParameterListP1(
+                    expression,
+                ) => self.expression(*expression),
+                _ => self.null(),
+            },
+            Expression::LiteralP0() => {
+                self.begin("Literal", span);
+                self.null_field("value");
+                self.str_field("raw", "null");
+                self.end();
+            }
+            Expression::LiteralP1(token)
+            | Expression::LiteralP2(token)
+            | Expression::LiteralP3(token)
+            | Expression::LiteralP4(token) => self.literal(token),
+            Expression::ArrayLiteralP0(_) | Expression::ArrayLiteralP1(_) | Expression::ArrayLiteralP2(..) => {
+                let elements = self.array_elements(expression);
+                self.begin("ArrayExpression", span);
+                self.array("elements", elements, |w, element| match element {
+                    Some(arg) => w.argument(arg),
+                    None => w.null(),
+                });
+                self.end();
+            }
+            Expression::ObjectLiteralP0() | Expression::ObjectLiteralP1(_) | Expression::ObjectLiteralP2(_) => {
+                self.begin("ObjectExpression", span);
+                self.array("properties", object_properties(expression), |w, property| w.property(property, false));
+                self.end();
+            }
+            Expression::MemberExpressionP1(object, property) | Expression::CallExpressionP4(object, property) => {
+                self.member(span, Object::Expression(*object), Property::Computed(*property), false)
+            }
+            Expression::MemberExpressionP2(object, token) | Expression::CallExpressionP5(object, token) => {
+                self.member(span, Object::Expression(*object), Property::Name(token), false)
+            }
+            Expression::MemberExpressionP3(object, token) | Expression::CallExpressionP6(object, token) => {
+                self.member(span, Object::Expression(*object), Property::Private(token), false)
+            }
+            Expression::MemberExpressionP4(tag, template) | Expression::CallExpressionP7(tag, template) => {
+                self.tagged_template(span, Object::Expression(*tag), *template)
+            }
+            Expression::MemberExpressionP5(property) => match &**property {
+                SuperProperty::SuperPropertyP0(property) => {
+                    self.member(span, Object::Super(span.0), Property::Computed(*property), false)
+                }
+                SuperProperty::SuperPropertyP1(token) => self.member(span, Object::Super(span.0), Property::Name(token), false),
+                SuperProperty::Error(_) => self.null(),
+            },
+            Expression::MemberExpressionP6(meta) => {
+                let (meta_name, property_name) = match &**meta {
+                    MetaProperty::MetaPropertyP0(_) => ("new", "target"),
+                    MetaProperty::MetaPropertyP1(_) => ("import", "meta"),
+                    MetaProperty::Error(_) => return self.null(),
+                };
+                self.begin("MetaProperty", span);
+                self.field("meta");
+                self.named("Identifier", (span.0, span.0 + meta_name.len()), meta_name);
+                self.field("property");
+                self.named("Identifier", (span.1 - property_name.len(), span.1), property_name);
+                self.end();
+            }
+            Expression::MemberExpressionP7(callee, args) => self.new_expression(span, *callee, Some(*args)),
+            Expression::NewExpressionP1(callee) => self.new_expression(span, *callee, None),
+            Expression::CallExpressionP0(cover) => match &**cover {
+                CoverCallExpressionAndAsyncArrowHead::CoverCallExpressionAndAsyncArrowHead(callee, args) => {
+                    self.call(span, Object::Expression(*callee), *args, false)
+                }
+                CoverCallExpressionAndAsyncArrowHead::Error(_) => self.null(),
+            },
+            Expression::CallExpressionP1(call) => match &**call {
+                SuperCall::SuperCall(args) => self.call(span, Object::Super(span.0), *args, false),
+                SuperCall::Error(_) => self.null(),
+            },
+            Expression::CallExpressionP2(call) => match &**call {
+                ImportCall::ImportCall(source) => {
+                    self.begin("ImportExpression", span);
+                    self.field("source");
+                    self.expression(*source);
+                    self.end();
+                }
+                ImportCall::Error(_) => self.null(),
+            },
+            Expression::CallExpressionP3(callee, args) => self.call(span, Object::Expression(*callee), *args, false),
+            Expression::OptionalExpressionP0(..) | Expression::OptionalExpressionP1(..) | Expression::OptionalExpressionP2(..) => {
+                self.begin("ChainExpression", span);
+                self.field("expression");
+                self.optional_expression(expression);
+                self.end();
+            }
+            Expression::UpdateExpressionP1(argument) => self.update(span, "++", false, *argument),
+            Expression::UpdateExpressionP2(argument) => self.update(span, "--", false, *argument),
+            Expression::UpdateExpressionP3(argument) => self.update(span, "++", true, *argument),
+            Expression::UpdateExpressionP4(argument) => self.update(span, "--", true, *argument),
+            Expression::UnaryExpressionP1(argument) => self.unary(span, "delete", *argument),
+            Expression::UnaryExpressionP2(argument) => self.unary(span, "void", *argument),
+            Expression::UnaryExpressionP3(argument) => self.unary(span, "typeof", *argument),
+            Expression::UnaryExpressionP4(argument) => self.unary(span, "+", *argument),
+            Expression::UnaryExpressionP5(argument) => self.unary(span, "-", *argument),
+            Expression::UnaryExpressionP6(argument) => self.unary(span, "~", *argument),
+            Expression::UnaryExpressionP7(argument) => self.unary(span, "!", *argument),
+            Expression::ExponentiationExpressionP1(left, right) => self.binary(span, "**", *left, *right),
+            Expression::MultiplicativeExpressionP1(left, operator, right) => {
+                let operator = match **operator {
+                    MultiplicativeOperator::MultiplicativeOperatorP0() => "*",
+                    MultiplicativeOperator::MultiplicativeOperatorP1() => "/",
+                    MultiplicativeOperator::MultiplicativeOperatorP2() => "%",
+                    MultiplicativeOperator::Error(_) => return self.null(),
+                };
+                self.binary(span, operator, *left, *right)
+            }
+            Expression::AdditiveExpressionP1(left, right) => self.binary(span, "+", *left, *right),
+            Expression::AdditiveExpressionP2(left, right) => self.binary(span, "-", *left, *right),
+            Expression::ShiftExpressionP1(left, right) => self.binary(span, "<<", *left, *right),
+            Expression::ShiftExpressionP2(left, right) => self.binary(span, ">>", *left, *right),
+            Expression::ShiftExpressionP3(left, right) => self.binary(span, ">>>", *left, *right),
+            Expression::RelationalExpressionP1(left, right) => self.binary(span, "<", *left, *right),
+            Expression::RelationalExpressionP2(left, right) => self.binary(span, ">", *left, *right),
+            Expression::RelationalExpressionP3(left, right) => self.binary(span, "<=", *left, *right),
+            Expression::RelationalExpressionP4(left, right) => self.binary(span, ">=", *left, *right),
+            Expression::RelationalExpressionP5(left, right) => self.binary(span, "instanceof", *left, *right),
+            Expression::RelationalExpressionP6(left, right) => self.binary(span, "in", *left, *right),
+            Expression::RelationalExpressionP7(token, right) => {
+                self.begin("BinaryExpression", span);
+                self.field("left");
+                self.private_identifier(token);
+                self.str_field("operator", "in");
+                self.field("right");
+                self.expression(*right);
+                self.end();
+            }
+            Expression::EqualityExpressionP1(left, right) => self.binary(span, "==", *left, *right),
+            Expression::EqualityExpressionP2(left, right) => self.binary(span, "!=", *left, *right),
+            Expression::EqualityExpressionP3(left, right) => self.binary(span, "===", *left, *right),
+            Expression::EqualityExpressionP4(left, right) => self.binary(span, "!==", *left, *right),
+            Expression::BitwiseAndExpressionP1(left, right) => self.binary(span, "&", *left, *right),
+            Expression::BitwiseXorExpressionP1(left, right) => self.binary(span, "^", *left, *right),
+            Expression::BitwiseOrExpressionP1(left, right) => self.binary(span, "|", *left, *right),
+            Expression::LogicalAndExpressionP1(left, right) => self.logical(span, "&&", *left, *right),
+            Expression::LogicalOrExpressionP1(left, right) => self.logical(span, "||", *left, *right),
+            Expression::CoalesceExpression(head, right) => match &**head {
+                CoalesceExpressionHead::CoalesceExpressionHeadP0(left)
+                | CoalesceExpressionHead::CoalesceExpressionHeadP1(left) => self.logical(span, "??", *left, *right),
+                CoalesceExpressionHead::Error(_) => self.null(),
+            },
+            Expression::ConditionalExpressionP1(test, consequent, alternate) => {
+                self.begin("ConditionalExpression", span);
+                self.field("test");
+                self.expression(*test);
+                self.field("consequent");
+                self.expression(*consequent);
+                self.field("alternate");
+                self.expression(*alternate);
+                self.end();
+            }
+            Expression::AssignmentExpressionP2(arrow) => self.arrow_function(span, *arrow),
+            Expression::AssignmentExpressionP3(arrow) => self.async_arrow_function(span, *arrow),
+            Expression::AssignmentExpressionP4(left, right) => self.assignment(span, "=", *left, *right),
+            Expression::AssignmentExpressionP5(left, operator, right) => {
+                let operator = match **operator {
+                    AssignmentOperator::AssignmentOperatorP0() => "*=",
+                    AssignmentOperator::AssignmentOperatorP1() => "/=",
+                    AssignmentOperator::AssignmentOperatorP2() => "%=",
+                    AssignmentOperator::AssignmentOperatorP3() => "+=",
+                    AssignmentOperator::AssignmentOperatorP4() => "-=",
+                    AssignmentOperator::AssignmentOperatorP5() => "<<=",
+                    AssignmentOperator::AssignmentOperatorP6() => ">>=",
+                    AssignmentOperator::AssignmentOperatorP7() => ">>>=",
+                    AssignmentOperator::AssignmentOperatorP8() => "&=",
+                    AssignmentOperator::AssignmentOperatorP9() => "^=",
+                    AssignmentOperator::AssignmentOperatorP10() => "|=",
+                    AssignmentOperator::AssignmentOperatorP11() => "**=",
+                    AssignmentOperator::Error(_) => return self.null(),
+                };
+                self.assignment(span, operator, *left, *right)
+            }
+            Expression::ExpressionP1(..) => {
+                self.begin("SequenceExpression", span);
+                self.array("expressions", sequence(expression), Self::expression);
+                self.end();
+            }
+            Expression::FunctionExpression(id, params, body) => {
+                self.function("FunctionExpression", span, *id, *params, function_body(*body), false, false)
+            }
+            Expression::GeneratorExpression(id, params, body) => {
+                self.function("FunctionExpression", span, *id, *params, generator_body(*body), true, false)
+            }
+            Expression::AsyncGeneratorExpression(id, params, body) => {
+                self.function("FunctionExpression", span, *id, *params, async_generator_body(*body), true, true)
+            }
+            Expression::AsyncFunctionExpressionP0(params, body) => {
+                self.function("FunctionExpression", span, None, *params, async_function_body(*body), false, true)
+            }
+            Expression::AsyncFunctionExpressionP1(id, params, body) => {
+                self.function("FunctionExpression", span, Some(*id), *params, async_function_body(*body), false, true)
+            }
+            Expression::YieldExpressionP0() => self.yield_expression(span, None, false),
+            Expression::YieldExpressionP1(argument) => self.yield_expression(span, Some(*argument), false),
+            Expression::YieldExpressionP2(argument) => self.yield_expression(span, Some(*argument), true),
+            Expression::ClassExpression(id, tail) => self.class("ClassExpression", span, *id, *tail),
+            Expression::AwaitExpression(argument) => {
+                self.begin("AwaitExpression", span);
+                self.field("argument");
+                self.expression(*argument);
+                self.end();
+            }
+            Expression::Error(_) => self.null(),
+        }
+    }
+
+    fn identifier_reference(&mut self, id: ArenaRef<'_, IdentifierReference>) {
+        match &*id {
+            IdentifierReference::IdentifierReference(token) => self.named("Identifier", self.token_span(token), name(token)),
+            IdentifierReference::Error(_) => self.null(),
+        }
+    }
+
+    fn private_identifier(&mut self, token: &Token) {
+        let name = name(token);
+        self.named("PrivateIdentifier", self.token_span(token), name.strip_prefix('#').unwrap_or(name));
+    }
+
+    fn literal(&mut self, token: &Token) {
+        let raw = &self.source[token.loc.start..token.loc.end];
+        self.begin("Literal", self.token_span(token));
+        self.field("value");
+        match token.terminal_id {
+            TerminalId::StringLiteral => self.string(token.value.as_deref().unwrap_or("")),
+            TerminalId::NumericLiteral => match token.number {
+                Some(number) if number.is_finite() => write!(self.out, "{}", number).unwrap(),
+                _ => self.null(),
+            },
+            TerminalId::BooleanLiteral => self.out.push_str(if raw == "true" { "true" } else { "false" }),
+            _ => self.null(),
+        }
+        self.str_field("raw", raw);
+        if token.terminal_id == TerminalId::BigIntLiteral {
+            self.str_field("bigint", &name(token).replace('_', ""));
+        }
+        self.end();
+    }
+
+    fn template_literal(&mut self, template: ArenaRef<'_, TemplateLiteral<'_>>) {
+        let mut quasis = vec![];
+        let mut expressions = vec![];
+        match &*template {
+            TemplateLiteral::TemplateLiteralP0(token) => quasis.push(token),
+            TemplateLiteral::TemplateLiteralP1(substitution) => match &**substitution {
+                SubstitutionTemplate::SubstitutionTemplate(head, expression, spans) => {
+                    quasis.push(head);
+                    expressions.push(*expression);
+                    let tail = match &**spans {
+                        TemplateSpans::TemplateSpansP0(tail) => Some(tail),
+                        TemplateSpans::TemplateSpansP1(list, tail) => {
+                            let middles = left_list(*list, |list| match list {
+                                TemplateMiddleList::TemplateMiddleListP0(middle, expression) => (None, Some((middle, *expression))),
+                                TemplateMiddleList::TemplateMiddleListP1(rest, middle, expression) => {
+                                    (Some(*rest), Some((middle, *expression)))
+                                }
+                                TemplateMiddleList::Error(_) => (None, None),
+                            });
+                            for (middle, expression) in middles {
+                                quasis.push(middle);
+                                expressions.push(expression);
+                            }
+                            Some(tail)
+                        }
+                        TemplateSpans::Error(_) => None,
+                    };
+                    quasis.extend(tail);
+                }
+                SubstitutionTemplate::Error(_) => return self.null(),
+            },
+            TemplateLiteral::Error(_) => return self.null(),
+        }
+        self.begin("TemplateLiteral", self.span(template));
+        self.array("expressions", expressions, Self::expression);
+        self.array("quasis", quasis, |w, token| {
+            // The element leaves out the `` ` ``, `${` or `}` around it.
+            let (start, end) = w.token_span(token);
+            let tail = matches!(token.terminal_id, TerminalId::NoSubstitutionTemplate | TerminalId::TemplateTail);
+            let end = if tail { end - 1 } else { end - 2 };
+            w.begin("TemplateElement", (start + 1, end));
+            w.field("value");
+            w.out.push_str("{\"raw\":");
+            w.string(token.raw.as_deref().unwrap_or(""));
+            w.out.push_str(",\"cooked\":");
+            match &token.value {
+                Some(cooked) => w.string(cooked),
+                None => w.null(),
+            }
+            w.out.push('}');
+            w.bool_field("tail", tail);
+            w.end();
+        });
+        self.end();
+    }
+
+    /// The elements of an array literal, None for a hole.
+    fn array_elements<'a>(&self, array: ArenaRef<'a, Expression<'a>>) -> Vec<Option<Arg<'a>>> {
+        let (list, trailing_holes) = match &*array {
+            Expression::ArrayLiteralP0(holes) => (None, *holes),
+            Expression::ArrayLiteralP1(list) => (Some(*list), None),
+            Expression::ArrayLiteralP2(list, holes) => (Some(*list), *holes),
+            _ => (None, None),
+        };
+        let mut elements = vec![];
+        if let Some(list) = list {
+            let items = left_list(list, |list| match list {
+                ElementList::ElementListP0(holes, expression) => (None, Some((*holes, Ok(*expression)))),
+                ElementList::ElementListP1(holes, spread) => (None, Some((*holes, Err(*spread)))),
+                ElementList::ElementListP2(rest, holes, expression) => (Some(*rest), Some((*holes, Ok(*expression)))),
+                ElementList::ElementListP3(rest, holes, spread) => (Some(*rest), Some((*holes, Err(*spread)))),
+                ElementList::Error(_) => (None, None),
+            });
+            for (holes, element) in items {
+                elements.extend(std::iter::repeat_n(None, elision_count(holes)));
+                match element {
+                    Ok(expression) => elements.push(Some(Arg::Expression(expression))),
+                    Err(spread) => {
+                        if let SpreadElement::SpreadElement(expression) = &*spread {
+                            elements.push(Some(Arg::Spread(self.span(spread).0, *expression)));
+                        }
+                    }
+                }
+            }
+        }
+        elements.extend(std::iter::repeat_n(None, elision_count(trailing_holes)));
+        elements
+    }
+
+    fn argument(&mut self, arg: Arg<'_>) {
+        match arg {
+            Arg::Expression(expression) => self.expression(expression),
+            Arg::Spread(start, expression) => {
+                self.begin("SpreadElement", (start, self.span(expression).1));
+                self.field("argument");
+                self.expression(expression);
+                self.end();
+            }
+        }
+    }
+
+    fn arguments<'a>(&self, args: ArenaRef<'a, Arguments<'a>>) -> Vec<Arg<'a>> {
+        let list = match &*args {
+            Arguments::ArgumentsP1(list) | Arguments::ArgumentsP2(list) => *list,
+            _ => return vec![],
+        };
+        // A spread after the first argument has no node of its own, so its
+        // `...` is found after the `,` that ends the arguments before it.
+        left_list(list, |list| match list {
+            ArgumentList::ArgumentListP0(expression) => (None, Some(Ok(*expression))),
+            ArgumentList::ArgumentListP1(expression) => (None, Some(Err((None, *expression)))),
+            ArgumentList::ArgumentListP2(rest, expression) => (Some(*rest), Some(Ok(*expression))),
+            ArgumentList::ArgumentListP3(rest, expression) => (Some(*rest), Some(Err((Some(*rest), *expression)))),
+            ArgumentList::Error(_) => (None, None),
+        })
+        .into_iter()
+        .map(|arg| match arg {
+            Ok(expression) => Arg::Expression(expression),
+            Err((None, expression)) => Arg::Spread(self.span(first_argument(list)).0, expression),
+            Err((Some(before), expression)) => Arg::Spread(self.skip_trivia(self.after(self.span(before).1, ",")), expression),
+        })
+        .collect()
+    }
+
+    fn property(&mut self, property: ArenaRef<'_, PropertyDefinition<'_>>, as_pattern: bool) {
+        let span = self.span(property);
+        match &*property {
+            PropertyDefinition::PropertyDefinitionP0(id) => {
+                self.property_node(span, false, true, false, |w| w.identifier_reference(*id), |w| w.identifier_reference(*id), "init")
+            }
+            PropertyDefinition::PropertyDefinitionP1(cover) => match &**cover {
+                CoverInitializedName::CoverInitializedName(id, init) => {
+                    let cover_span = self.span(*cover);
+                    self.property_node(
+                        span,
+                        false,
+                        true,
+                        false,
+                        |w| w.identifier_reference(*id),
+                        |w| w.assignment_pattern(cover_span, |w| w.identifier_reference(*id), *init),
+                        "init",
+                    )
+                }
+                CoverInitializedName::Error(_) => self.null(),
+            },
+            PropertyDefinition::PropertyDefinitionP2(name, value) => {
+                let computed = matches!(**name, PropertyName::PropertyNameP1(_));
+                self.property_node(
+                    span,
+                    false,
+                    false,
+                    computed,
+                    |w| w.property_name(*name),
+                    |w| if as_pattern { w.pattern(*value) } else { w.expression(*value) },
+                    "init",
+                )
+            }
+            PropertyDefinition::PropertyDefinitionP3(method) => {
+                let method = match self.method(*method) {
+                    Some(method) => method,
+                    None => return self.null(),
+                };
+                let kind = if method.kind == "method" { "init" } else { method.kind };
+                let computed = is_computed(method.name);
+                let name = method.name;
+                self.property_node(
+                    span,
+                    kind == "init",
+                    false,
+                    computed,
+                    |w| w.class_element_name(name),
+                    |w| w.method_value(method, span.1),
+                    kind,
+                )
+            }
+            PropertyDefinition::PropertyDefinitionP4(expression) => {
+                if as_pattern {
+                    self.rest_pattern(span, *expression);
+                } else {
+                    self.begin("SpreadElement", span);
+                    self.field("argument");
+                    self.expression(*expression);
+                    self.end();
+                }
+            }
+            PropertyDefinition::Error(_) => self.null(),
+        }
+    }
+
+    fn object(&mut self, object: Object<'_>) {
+        match object {
+            Object::Expression(expression) => self.expression(expression),
+            Object::Super(start) => {
+                self.begin("Super", (start, start + "super".len()));
+                self.end();
+            }
+            Object::Chain(start, expression, chain) => self.chain(start, expression, chain),
+        }
+    }
+
+    fn member(&mut self, span: Span, object: Object<'_>, property: Property<'_>, optional: bool) {
+        self.begin("MemberExpression", span);
+        self.field("object");
+        self.object(object);
+        self.field("property");
+        let computed = match property {
+            Property::Computed(expression) => {
+                self.expression(expression);
+                true
+            }
+            Property::Name(token) => {
+                self.named("Identifier", self.token_span(token), name(token));
+                false
+            }
+            Property::Private(token) => {
+                self.private_identifier(token);
+                false
+            }
+        };
+        self.bool_field("computed", computed);
+        self.bool_field("optional", optional);
+        self.end();
+    }
+
+    fn call(&mut self, span: Span, callee: Object<'_>, args: ArenaRef<'_, Arguments<'_>>, optional: bool) {
+        let args = self.arguments(args);
+        self.begin("CallExpression", span);
+        self.field("callee");
+        self.object(callee);
+        self.array("arguments", args, Self::argument);
+        self.bool_field("optional", optional);
+        self.end();
+    }
+
+    fn new_expression(&mut self, span: Span, callee: ArenaRef<'_, Expression<'_>>, args: Option<ArenaRef<'_, Arguments<'_>>>) {
+        let args = args.map_or(vec![], |args| self.arguments(args));
+        self.begin("NewExpression", span);
+        self.field("callee");
+        self.expression(callee);
+        self.array("arguments", args, Self::argument);
+        self.end();
+    }
+
+    fn tagged_template(&mut self, span: Span, tag: Object<'_>, template: ArenaRef<'_, TemplateLiteral<'_>>) {
+        self.begin("TaggedTemplateExpression", span);
+        self.field("tag");
+        self.object(tag);
+        self.field("quasi");
+        self.template_literal(template);
+        self.end();
+    }
+
+    /// An optional expression, inside the ChainExpression ESTree wraps
+    /// around the whole chain.
+    fn optional_expression(&mut self, expression: ArenaRef<'_, Expression<'_>>) {
+        match &*expression {
+            Expression::OptionalExpressionP0(object, chain)
+            | Expression::OptionalExpressionP1(object, chain)
+            | Expression::OptionalExpressionP2(object, chain) => self.chain(self.span(expression).0, *object, *chain),
+            _ => self.expression(expression),
+        }
+    }
+
+    /// `object` followed by the links in `chain`. The whole thing starts at
+    /// `start`.
+    fn chain(&mut self, start: usize, object: ArenaRef<'_, Expression<'_>>, chain: ArenaRef<'_, OptionalChain<'_>>) {
+        let span = (start, self.span(chain).1);
+        // A chain that's continued after another `?.` has the chain before
+        // it as its object, which is part of this ChainExpression rather
+        // than one of its own.
+        let first = match &*object {
+            Expression::OptionalExpressionP0(inner, before)
+            | Expression::OptionalExpressionP1(inner, before)
+            | Expression::OptionalExpressionP2(inner, before) => Object::Chain(self.span(object).0, *inner, *before),
+            _ => Object::Expression(object),
+        };
+        match &*chain {
+            OptionalChain::OptionalChainP0(args) => self.call(span, first, *args, true),
+            OptionalChain::OptionalChainP1(property) => self.member(span, first, Property::Computed(*property), true),
+            OptionalChain::OptionalChainP2(token) => self.member(span, first, Property::Name(token), true),
+            OptionalChain::OptionalChainP3(token) => self.member(span, first, Property::Private(token), true),
+            OptionalChain::OptionalChainP4(template) => self.tagged_template(span, first, *template),
+            OptionalChain::OptionalChainP5(rest, args) => self.call(span, Object::Chain(start, object, *rest), *args, false),
+            OptionalChain::OptionalChainP6(rest, property) => {
+                self.member(span, Object::Chain(start, object, *rest), Property::Computed(*property), false)
+            }
+            OptionalChain::OptionalChainP7(rest, token) => {
+                self.member(span, Object::Chain(start, object, *rest), Property::Name(token), false)
+            }
+            OptionalChain::OptionalChainP8(rest, token) => {
+                self.member(span, Object::Chain(start, object, *rest), Property::Private(token), false)
+            }
+            OptionalChain::OptionalChainP9(rest, template) => {
+                self.tagged_template(span, Object::Chain(start, object, *rest), *template)
+            }
+            OptionalChain::Error(_) => self.null(),
+        }
+    }
+
+    fn update(&mut self, span: Span, operator: &str, prefix: bool, argument: ArenaRef<'_, Expression<'_>>) {
+        self.begin("UpdateExpression", span);
+        self.str_field("operator", operator);
+        self.bool_field("prefix", prefix);
+        self.field("argument");
+        self.expression(argument);
+        self.end();
+    }
+
+    fn unary(&mut self, span: Span, operator: &str, argument: ArenaRef<'_, Expression<'_>>) {
+        self.begin("UnaryExpression", span);
+        self.str_field("operator", operator);
+        self.bool_field("prefix", true);
+        self.field("argument");
+        self.expression(argument);
+        self.end();
+    }
+
+    fn binary(&mut self, span: Span, operator: &str, left: ArenaRef<'_, Expression<'_>>, right: ArenaRef<'_, Expression<'_>>) {
+        self.operation("BinaryExpression", span, operator, left, right);
+    }
+
+    fn logical(&mut self, span: Span, operator: &str, left: ArenaRef<'_, Expression<'_>>, right: ArenaRef<'_, Expression<'_>>) {
+        self.operation("LogicalExpression", span, operator, left, right);
+    }
+
+    fn operation(
+        &mut self,
+        ty: &str,
+        span: Span,
+        operator: &str,
+        left: ArenaRef<'_, Expression<'_>>,
+        right: ArenaRef<'_, Expression<'_>>,
+    ) {
+        self.begin(ty, span);
+        self.field("left");
+        self.expression(left);
+        self.str_field("operator", operator);
+        self.field("right");
+        self.expression(right);
+        self.end();
+    }
+
+    fn assignment(&mut self, span: Span, operator: &str, left: ArenaRef<'_, Expression<'_>>, right: ArenaRef<'_, Expression<'_>>) {
+        self.begin("AssignmentExpression", span);
+        self.str_field("operator", operator);
+        self.field("left");
+        self.pattern(left);
+        self.field("right");
+        self.expression(right);
+        self.end();
+    }
+
+    fn yield_expression(&mut self, span: Span, argument: Option<ArenaRef<'_, Expression<'_>>>, delegate: bool) {
+        self.begin("YieldExpression", span);
+        self.bool_field("delegate", delegate);
+        self.optional("argument", argument, Self::expression);
+        self.end();
+    }
+}
+
+fn let_or_const(kind: &LetOrConst) -> &'static str {
+    match kind {
+        LetOrConst::LetOrConstP1() => "const",
+        _ => "let",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TABLES};
+
+    #[test]
+    fn estree() {
+        use crate::ast_arena::NodeLocations;
+        use super::{module_to_estree_json, to_estree_json};
+        let arena = Arena::new();
+        let locations = NodeLocations::new();
+        let handler = DefaultHandler::with_locations(&arena, &locations);
+        let json = |src: &str| {
+            let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), false);
+            match parser.parse_script(src) {
+                Ok(script) => to_estree_json(&script.into_script(), &locations, src),
+                Err(error) => panic!("{}: {}", src, error.message()),
+            }
+        };
+        let position = |start, end, line, column, end_column| {
+            format!(
+                "\"start\":{},\"end\":{},\"loc\":{{\"start\":{{\"line\":{},\"column\":{}}},\
+                 \"end\":{{\"line\":{},\"column\":{}}}}},\"range\":[{},{}]",
+                start, end, line, column, line, end_column, start, end
+            )
+        };
+        assert_eq!(
+            json("a = 1;"),
+            format!(
+                "{{\"type\":\"Program\",{},\"body\":[{{\"type\":\"ExpressionStatement\",{},\
+                 \"expression\":{{\"type\":\"AssignmentExpression\",{},\"operator\":\"=\",\
+                 \"left\":{{\"type\":\"Identifier\",{},\"name\":\"a\"}},\
+                 \"right\":{{\"type\":\"Literal\",{},\"value\":1,\"raw\":\"1\"}}}}}}],\"sourceType\":\"script\"}}",
+                position(0, 6, 1, 0, 6),
+                position(0, 6, 1, 0, 6),
+                position(0, 5, 1, 0, 5),
+                position(0, 1, 1, 0, 1),
+                position(4, 5, 1, 4, 5)
+            )
+        );
+
+        // Positions count UTF-16 code units, and keywords and punctuation
+        // the tree doesn't keep are found in the source.
+        let tree = json("\"use strict\";\n/* \u{1f600} */ f = (a, ...b) => { return {m() {}}; };");
+        assert!(tree.contains("\"directive\":\"use strict\""));
+        assert!(tree.contains(&format!("\"type\":\"RestElement\",{}", position(31, 35, 2, 17, 21))));
+        assert!(tree.contains(&format!("\"type\":\"BlockStatement\",{}", position(40, 60, 2, 26, 46))));
+        assert!(tree.contains(&format!("\"type\":\"FunctionExpression\",{}", position(51, 56, 2, 37, 42))));
+        assert!(tree.contains("\"type\":\"ArrowFunctionExpression\""));
+        assert!(!tree.contains("Cover"));
+
+        // `async x => x;` has a production of its own, but it's an
+        // expression statement like any other.
+        let tree = json("async x => x;");
+        assert!(tree.contains(&format!("\"type\":\"ExpressionStatement\",{}", position(0, 13, 1, 0, 13))));
+        assert!(tree.contains(&format!("\"type\":\"ArrowFunctionExpression\",{}", position(0, 12, 1, 0, 12))));
+        assert!(tree.contains("\"async\":true"));
+
+        let tree = json("class C { #x; m(o) { return #x in o; } }");
+        let left = format!("\"left\":{{\"type\":\"PrivateIdentifier\",{}", position(28, 30, 1, 28, 30));
+        assert!(tree.contains(&format!("\"type\":\"BinaryExpression\",{},{}", position(28, 35, 1, 28, 35), left)));
+        assert!(tree.contains("\"operator\":\"in\""));
+
+        let src = "import a, {b as c} from 'm'; export default [a, c];";
+        let mut parser = Parser::new_module(&handler);
+        let module = match parser.parse_script(src) {
+            Ok(module) => module.into_module(),
+            Err(error) => panic!("{}", error.message()),
+        };
+        let tree = module_to_estree_json(&module, &locations, src);
+        assert!(tree.contains("\"type\":\"ImportDefaultSpecifier\""));
+        assert!(tree.contains("\"imported\":{\"type\":\"Identifier\""));
+        assert!(tree.contains("\"type\":\"ExportDefaultDeclaration\""));
+        assert!(tree.ends_with("\"sourceType\":\"module\"}"));
+    }
+
+    #[test]
+    fn estree_diagnostics() {
+        use super::parse_to_estree_json;
+        let json = parse_to_estree_json("a;", false, false);
+        assert!(json.starts_with("{\"ast\":{\"type\":\"Program\""));
+        assert!(json.ends_with("\"sourceType\":\"script\"},\"diagnostics\":[]}"));
+
+        let json = parse_to_estree_json("a = ;", false, false);
+        assert!(json.starts_with("{\"ast\":null,\"diagnostics\":[{\"message\":\"expected one of"));
+        assert!(json.ends_with(
+            "but found `;` at line 1, column 4\",\"severity\":\"error\",\"code\":\"syntax-error\",\"start\":4,\
+             \"end\":5,\"loc\":{\"start\":{\"line\":1,\"column\":4},\"end\":{\"line\":1,\"column\":5}},\"range\":[4,5]}]}"
+        ));
+
+        // Modules are strict, and recovery mode goes on past a syntax error.
+        let json = parse_to_estree_json("with (a) {}", true, false);
+        assert!(json.contains("\"message\":\"with statements are not allowed in strict mode code"));
+        let json = parse_to_estree_json("a = ;\nc;", false, true);
+        assert!(json.contains("\"name\":\"c\""));
+        assert_eq!(json.matches("\"message\"").count(), 1);
+    }
+}
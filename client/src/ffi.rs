@@ -0,0 +1,237 @@
+//! The parser as a C library, for embedders like SpiderMonkey.
+//! `include/jsparagus.h` declares these functions; cbindgen regenerates it
+//! from this file, using `cbindgen.toml`.
+//!
+//! Ownership is explicit and simple. jsparagus_parse() returns a result the
+//! caller owns, which must be passed to jsparagus_free_result() exactly
+//! once. Every pointer an accessor returns points into the result, and is
+//! valid until the result is freed. Nothing the caller passes in is kept:
+//! the source and options may be freed as soon as jsparagus_parse()
+//! returns.
+
+use crate::estree::parse_to_estree;
+use crate::parser_runtime::SourceLocation;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str;
+
+/// How jsparagus_parse() parses.
+#[repr(C)]
+pub struct JsparagusParseOptions {
+    /// Parse a module instead of a script.
+    pub module: bool,
+    /// Keep going after syntax errors and malformed tokens, reporting each
+    /// one.
+    pub recover: bool,
+    /// Keep the tree, as ESTree JSON, for jsparagus_result_estree_json().
+    pub estree_json: bool,
+}
+
+/// One error in a result. `message` is a NUL-terminated UTF-8 string.
+/// `start` and `end` are byte offsets into the source, and `line` (1-based)
+/// and `column` (0-based, in characters) give the position of `start`. If
+/// the error has no position, `has_location` is false and the rest are 0.
+#[repr(C)]
+pub struct JsparagusDiagnostic {
+    pub message: *const c_char,
+    pub has_location: bool,
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// What jsparagus_parse() returns. Opaque to C.
+pub struct JsparagusResult {
+    ok: bool,
+    messages: Vec<CString>,
+    diagnostics: Vec<JsparagusDiagnostic>,
+    estree_json: Option<CString>,
+}
+
+impl JsparagusResult {
+    fn new() -> JsparagusResult {
+        JsparagusResult {
+            ok: false,
+            messages: vec![],
+            diagnostics: vec![],
+            estree_json: None,
+        }
+    }
+
+    fn add_diagnostic(&mut self, message: String, loc: Option<SourceLocation>) {
+        // An interior NUL can only come from the source, by way of a name
+        // in the message; cut the message off there.
+        let mut bytes = message.into_bytes();
+        if let Some(nul) = bytes.iter().position(|&b| b == 0) {
+            bytes.truncate(nul);
+        }
+        let message = CString::new(bytes).unwrap();
+        let SourceLocation { start, end, line, column } = loc.unwrap_or_default();
+        // The CString's buffer doesn't move when the CString does.
+        self.diagnostics.push(JsparagusDiagnostic {
+            message: message.as_ptr(),
+            has_location: loc.is_some(),
+            start,
+            end,
+            line,
+            column,
+        });
+        self.messages.push(message);
+    }
+}
+
+fn parse(source: &[u8], options: &JsparagusParseOptions) -> JsparagusResult {
+    let mut result = JsparagusResult::new();
+    let source = match str::from_utf8(source) {
+        Ok(source) => source,
+        Err(error) => {
+            let message = format!("source is not valid UTF-8 at byte {}", error.valid_up_to());
+            result.add_diagnostic(message, None);
+            return result;
+        }
+    };
+    let (ast, errors) = parse_to_estree(source, options.module, options.recover);
+    result.ok = ast.is_some();
+    if options.estree_json {
+        result.estree_json = ast.map(|json| CString::new(json).unwrap());
+    }
+    for error in &errors {
+        result.add_diagnostic(error.message(), error.loc());
+    }
+    result
+}
+
+/// Parse the `length` bytes of UTF-8 at `source`. `options` may be null,
+/// for all options off. Never returns null.
+///
+/// # Safety
+///
+/// `source` must point to `length` readable bytes, and `options` must be
+/// null or point to a JsparagusParseOptions.
+#[no_mangle]
+pub unsafe extern "C" fn jsparagus_parse(
+    source: *const u8,
+    length: usize,
+    options: *const JsparagusParseOptions,
+) -> *mut JsparagusResult {
+    let source = if length == 0 { &[] } else { slice::from_raw_parts(source, length) };
+    let default_options = JsparagusParseOptions {
+        module: false,
+        recover: false,
+        estree_json: false,
+    };
+    let options = options.as_ref().unwrap_or(&default_options);
+    // Unwinding into C is undefined behavior, so a panic becomes an error.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| parse(source, options))).unwrap_or_else(|_| {
+        let mut result = JsparagusResult::new();
+        result.add_diagnostic("internal error: the parser panicked".to_string(), None);
+        result
+    });
+    Box::into_raw(Box::new(result))
+}
+
+/// Free a result from jsparagus_parse(). Does nothing if `result` is null.
+///
+/// # Safety
+///
+/// `result` must be null or a result from jsparagus_parse() that hasn't
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn jsparagus_free_result(result: *mut JsparagusResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// True if the source parsed, possibly with errors that recovery mode got
+/// past.
+///
+/// # Safety
+///
+/// `result` must be a live result from jsparagus_parse().
+#[no_mangle]
+pub unsafe extern "C" fn jsparagus_result_ok(result: *const JsparagusResult) -> bool {
+    let result = &*result;
+    result.ok
+}
+
+/// The number of diagnostics in `result`.
+///
+/// # Safety
+///
+/// `result` must be a live result from jsparagus_parse().
+#[no_mangle]
+pub unsafe extern "C" fn jsparagus_diagnostic_count(result: *const JsparagusResult) -> usize {
+    let result = &*result;
+    result.diagnostics.len()
+}
+
+/// The diagnostic at `index`, or null if `index` is out of range.
+///
+/// # Safety
+///
+/// `result` must be a live result from jsparagus_parse().
+#[no_mangle]
+pub unsafe extern "C" fn jsparagus_diagnostic(
+    result: *const JsparagusResult,
+    index: usize,
+) -> *const JsparagusDiagnostic {
+    let result = &*result;
+    result.diagnostics.get(index).map_or(ptr::null(), |diagnostic| diagnostic)
+}
+
+/// The tree as a NUL-terminated ESTree JSON string, or null if the source
+/// didn't parse or the `estree_json` option was off.
+///
+/// # Safety
+///
+/// `result` must be a live result from jsparagus_parse().
+#[no_mangle]
+pub unsafe extern "C" fn jsparagus_result_estree_json(result: *const JsparagusResult) -> *const c_char {
+    let result = &*result;
+    result.estree_json.as_ref().map_or(ptr::null(), |json| json.as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn ffi() {
+        use super::*;
+        use std::ffi::CStr;
+        unsafe {
+            let src = "a = ;\nb;";
+            let options = JsparagusParseOptions {
+                module: false,
+                recover: true,
+                estree_json: true,
+            };
+            let result = jsparagus_parse(src.as_ptr(), src.len(), &options);
+            assert!(jsparagus_result_ok(result));
+            assert_eq!(jsparagus_diagnostic_count(result), 1);
+            let diagnostic = &*jsparagus_diagnostic(result, 0);
+            assert!(CStr::from_ptr(diagnostic.message).to_str().unwrap().ends_with("found `;` at line 1, column 4"));
+            assert!(diagnostic.has_location);
+            assert_eq!((diagnostic.start, diagnostic.end, diagnostic.line, diagnostic.column), (4, 5, 1, 4));
+            assert!(jsparagus_diagnostic(result, 1).is_null());
+            let json = CStr::from_ptr(jsparagus_result_estree_json(result)).to_str().unwrap();
+            assert!(json.contains("\"name\":\"b\""));
+            jsparagus_free_result(result);
+
+            // Without options, errors stop the parse and no JSON is kept.
+            let result = jsparagus_parse(src.as_ptr(), src.len(), std::ptr::null());
+            assert!(!jsparagus_result_ok(result));
+            assert!(jsparagus_result_estree_json(result).is_null());
+            jsparagus_free_result(result);
+
+            let result = jsparagus_parse(b"\xff".as_ptr(), 1, std::ptr::null());
+            assert!(!jsparagus_result_ok(result));
+            assert!(!(*jsparagus_diagnostic(result, 0)).has_location);
+            jsparagus_free_result(result);
+            jsparagus_free_result(std::ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! What the fuzz targets in `fuzz/` run. Each function takes the fuzzer's
+//! bytes and panics if it finds a bug.
+
+use crate::ast_arena::Arena;
+use crate::parser::{ParseError, Parser};
+use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TerminalId, TABLES};
+use crate::parser_runtime::{SourceLocation, Token};
+use std::borrow::Cow;
+
+/// Parse `data` as a script, as a module, and in recovery mode. Any input
+/// may fail to parse; what mustn't happen is a panic or an internal error.
+pub fn parse_bytes(data: &[u8]) {
+    let src = String::from_utf8_lossy(data);
+    let arena = Arena::new();
+    let handler = DefaultHandler::new(&arena);
+    let results = [
+        Parser::new_script(&handler).parse_script(&src).map(|_| ()),
+        Parser::new_module(&handler).parse_script(&src).map(|_| ()),
+        Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true)
+            .parse_script(&src)
+            .map(|_| ()),
+    ];
+    for result in results {
+        if let Err(error @ ParseError::InternalError(_)) = result {
+            panic!("{}", error.message());
+        }
+    }
+}
+
+/// The most tokens parse_tokens() writes after its input runs out, trying
+/// to get to a point where the parser can be closed.
+const MAX_CLOSING_TOKENS: usize = 200;
+
+/// Terminals that end what's open, in the order parse_tokens() tries them
+/// once its input runs out, before any others.
+const CLOSING: &[TerminalId] = &[
+    TerminalId::RightParenthesis,
+    TerminalId::RightSquareBracket,
+    TerminalId::TemplateTail,
+    TerminalId::RightCurlyBracket,
+    TerminalId::Semicolon,
+    TerminalId::Identifier,
+    TerminalId::IdentifierName,
+];
+
+/// Write a script or a module, as the first byte of `data` says, to the
+/// parser a token at a time, letting each other byte pick the next token
+/// out of the ones the parser can accept. Then write tokens that close
+/// brackets until the parser can be closed, and close it. Unlike text, this reaches deep into the grammar, since it
+/// never makes a syntax error for the parser to stop at.
+pub fn parse_tokens(data: &[u8]) {
+    let arena = Arena::new();
+    let handler = DefaultHandler::new(&arena);
+    let (first, data) = match data.split_first() {
+        Some((&first, rest)) => (first, rest),
+        None => return,
+    };
+    let mut parser = if first % 2 == 0 {
+        Parser::new_script(&handler)
+    } else {
+        Parser::new_module(&handler)
+    };
+    // `yield` and `await` are left out: the parser decides by itself which
+    // functions they're keywords in, and almost everywhere else the tables
+    // take them as identifiers that are then early errors.
+    let terminals: Vec<TerminalId> = (0..TABLES.action_width)
+        .filter_map(TerminalId::from_usize)
+        .filter(|t| !matches!(t, TerminalId::End | TerminalId::ErrorToken | TerminalId::Yield | TerminalId::Await))
+        .collect();
+    let mut offset = 0;
+    let mut closing = 0;
+    loop {
+        let acceptable: Vec<TerminalId> = terminals.iter().copied().filter(|&t| parser.can_accept_terminal(t)).collect();
+        let pick = |choice: usize| acceptable.get(choice % acceptable.len().max(1)).copied();
+        let t = if offset / 2 < data.len() {
+            pick(data[offset / 2] as usize)
+        } else if closing < MAX_CLOSING_TOKENS && !parser.can_close() {
+            closing += 1;
+            // Where nothing closes, go on a different way each time, so as
+            // not to keep going around the same loop.
+            CLOSING
+                .iter()
+                .copied()
+                .find(|t| acceptable.contains(t))
+                .or_else(|| pick(closing))
+        } else {
+            None
+        };
+        let t = match t {
+            Some(t) => t,
+            None => break,
+        };
+        match parser.write_token(sample_token(t, offset)) {
+            Ok(()) => {}
+            // The input can still break rules that the grammar doesn't
+            // express.
+            Err(ParseError::EarlyError { .. }) => return,
+            Err(error) => panic!("the parser could accept {} but then: {}", t, error.message()),
+        }
+        offset += 2;
+    }
+    if parser.can_close() {
+        match parser.close(SourceLocation::new(offset, offset, 1, offset as u32)) {
+            Ok(_) | Err(ParseError::EarlyError { .. }) => {}
+            Err(error) => panic!("the parser could close but then: {}", error.message()),
+        }
+    }
+}
+
+/// A token with terminal id `t` at `offset`, with whatever value the lexer
+/// would give one.
+fn sample_token(t: TerminalId, offset: usize) -> Token<'static> {
+    let mut token = Token::new(t, SourceLocation::new(offset, offset + 1, 1, offset as u32));
+    let text = |s: &'static str| Some(Cow::Borrowed(s));
+    match t {
+        TerminalId::Identifier | TerminalId::IdentifierName => token.value = text("x"),
+        TerminalId::PrivateIdentifier => token.value = text("#x"),
+        TerminalId::NumericLiteral | TerminalId::BigIntLiteral => {
+            token.value = text("1");
+            token.number = Some(1.0);
+        }
+        TerminalId::StringLiteral => {
+            token.value = text("s");
+            token.raw = text("s");
+        }
+        TerminalId::NoSubstitutionTemplate
+        | TerminalId::TemplateHead
+        | TerminalId::TemplateMiddle
+        | TerminalId::TemplateTail => {
+            token.value = text("");
+            token.raw = text("");
+        }
+        TerminalId::RegularExpressionLiteral => token.value = text("/x/"),
+        TerminalId::BooleanLiteral => token.raw = text("true"),
+        _ => {
+            // Reserved words and contextual keywords keep the word.
+            let name = t.to_string();
+            let word = name.trim_matches('`');
+            if word.chars().all(|c| c.is_ascii_lowercase()) {
+                token.raw = Some(Cow::Owned(word.to_string()));
+            }
+        }
+    }
+    token
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{assert_no_leaks, INVALID};
+
+    #[test]
+    fn fuzzing() {
+        use super::{parse_bytes, parse_tokens};
+        for src in INVALID.iter().chain(&["a = `${b}` + /c/g;", "\u{0}\u{ffff}", "/*", "(((((", "class { #"]) {
+            assert_no_leaks(|| parse_bytes(src.as_bytes()));
+        }
+        parse_bytes(&[0xff, 0xfe, b'a', 0x80]);
+
+        // Token choices from a fixed pseudo-random sequence.
+        let mut seed: u32 = 1;
+        for _ in 0..300 {
+            let data: Vec<u8> = (0..80)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    (seed >> 16) as u8
+                })
+                .collect();
+            assert_no_leaks(|| parse_tokens(&data));
+        }
+    }
+}
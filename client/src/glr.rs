@@ -0,0 +1,219 @@
+//! GLR parsing.
+//!
+//! Where the generator resolved a conflict instead of reporting it, the
+//! actions that lost are kept in the tables as alternatives. With GLR
+//! parsing turned on (see `Parser::set_glr`), a parser that reaches one of
+//! those entries doesn't commit to an action. It forks: each `Branch` is a
+//! copy of the state stack that takes one of the actions. The tokens that
+//! follow are buffered and run through every branch, without calling into
+//! the handler, and branches that hit a syntax error are dropped. Once only
+//! one branch is left, the parser replays the buffered tokens for real,
+//! making the choices that branch made.
+//!
+//! Branches that end up with the same stack are merged. If several survive
+//! to the end of the input, the one that took the table's own actions
+//! earliest wins, which is what an LR parse of the same tables would do.
+
+use crate::parser_runtime::{ParserTables, TerminalId, ACCEPT, ERROR};
+
+/// One way of parsing the tokens since the parser forked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub(crate) stack: Vec<usize>,
+    /// For each conflict the branch met, in order, the action it took: 0
+    /// for the one in the action table, `i` for the `i`th alternative.
+    pub(crate) choices: Vec<usize>,
+    /// True once the branch has accepted the input.
+    pub(crate) accepted: bool,
+}
+
+impl Branch {
+    pub fn new(stack: Vec<usize>) -> Branch {
+        Branch {
+            stack,
+            choices: vec![],
+            accepted: false,
+        }
+    }
+
+    fn state(&self) -> usize {
+        *self.stack.last().unwrap()
+    }
+
+    fn reduce(&mut self, tables: &ParserTables, action: i16) {
+        let (pop_count, nt) = tables.reduce_simulator[(-action - 1) as usize];
+        self.stack.truncate(self.stack.len() - pop_count);
+        let state_after = tables.goto_table[self.state() * tables.goto_width + nt as usize];
+        self.stack.push(state_after);
+    }
+
+    /// Mirror Parser::try_error_handling: if the state can take an
+    /// ErrorToken, move to the state after it and return true.
+    fn handle_error(&mut self, tables: &ParserTables) -> bool {
+        let mut branch = self.clone();
+        loop {
+            let action = tables.action(branch.state(), TerminalId::ErrorToken as usize);
+            if action >= 0 {
+                branch.stack.pop();
+                branch.stack.push(action as usize);
+                *self = branch;
+                return true;
+            }
+            if action == ERROR || action == ACCEPT {
+                return false;
+            }
+            branch.reduce(tables, action);
+        }
+    }
+
+    /// Every way the branch can go on after reading `t`: shifting it, or
+    /// for the End terminal, accepting. Empty if `t` is a syntax error.
+    pub fn advance(self, tables: &ParserTables, t: TerminalId) -> Vec<Branch> {
+        let terminal = t as usize;
+        let mut done = vec![];
+        let mut todo = vec![(self, None)];
+        while let Some((mut branch, mut forced)) = todo.pop() {
+            loop {
+                let action = match forced.take() {
+                    Some(action) => action,
+                    None => {
+                        let state = branch.state();
+                        let mut alternatives = tables.alternatives(state, terminal).peekable();
+                        if alternatives.peek().is_some() {
+                            for (i, action) in alternatives.enumerate() {
+                                let mut fork = branch.clone();
+                                fork.choices.push(i + 1);
+                                todo.push((fork, Some(action)));
+                            }
+                            branch.choices.push(0);
+                        }
+                        tables.action(state, terminal)
+                    }
+                };
+                if action >= 0 {
+                    branch.stack.push(action as usize);
+                    done.push(branch);
+                    break;
+                } else if action == ACCEPT {
+                    branch.accepted = true;
+                    done.push(branch);
+                    break;
+                } else if action == ERROR {
+                    if !branch.handle_error(tables) {
+                        break;
+                    }
+                } else {
+                    branch.reduce(tables, action);
+                }
+            }
+        }
+        done
+    }
+}
+
+/// Advance each of `branches` past `t`. Return the branches that survive,
+/// the preferred one first, merging any that end up with the same stack.
+pub fn advance_all(tables: &ParserTables, branches: &[Branch], t: TerminalId) -> Vec<Branch> {
+    let mut next: Vec<Branch> = branches
+        .iter()
+        .flat_map(|branch| branch.clone().advance(tables, t))
+        .collect();
+    next.sort_by(|a, b| a.choices.cmp(&b.choices));
+    let mut merged: Vec<Branch> = vec![];
+    for branch in next {
+        if !merged.iter().any(|b| b.stack == branch.stack) {
+            merged.push(branch);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TerminalId, TABLES};
+    use crate::parser_runtime::{ActionTable, ParserTables, TableProblem, TokenStream};
+
+    /// Parse `src` as a script with `tables`, returning the tree's Debug
+    /// output or the error message.
+    fn parse_with(tables: &ParserTables, src: &str, glr: bool) -> std::result::Result<String, String> {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new(tables, reduce, &handler, EntryPoint::Script.state(), false);
+        parser.set_glr(glr);
+        match parser.parse_script(src) {
+            Ok(script) => Ok(format!("{:?}", script.into_script())),
+            Err(error) => Err(error.message()),
+        }
+    }
+
+    #[test]
+    fn glr() {
+        // The only conflict in TABLES is the dangling else, which the
+        // generator settles by shifting. A GLR parse reduces as well.
+        assert_eq!(TABLES.alternatives.len(), 1);
+        let (state, t, reduce_action) = TABLES.alternatives[0];
+        assert_eq!(t, TerminalId::Else as usize);
+        for src in [
+            "if (a) if (b) x; else y;",
+            "if (a) if (b) x; else y; else z;",
+            "if (a) { if (b) x; } else if (c) y; else z; w;",
+            "if (a) if (b) x; else",
+            "if (a) if (b) x; else y; else z; else w;",
+            "x = 1 +",
+        ] {
+            assert_eq!(parse_with(&TABLES, src, true), parse_with(&TABLES, src, false), "{}", src);
+        }
+
+        // The branch that gives the first `else` to the outer `if` fails at
+        // the second one, and the parser goes with the other right away.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.set_glr(true);
+        let mut lexer = Lexer::new("if (a) if (b) x; else y; else z;");
+        let mut undecided = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            parser.write_token(TokenStream::take(&mut lexer)).ok().unwrap();
+            undecided.push(parser.undecided());
+        }
+        assert_eq!(undecided.iter().filter(|&&u| u).count(), 3); // else y ;
+        assert!(!undecided.last().unwrap());
+
+        // With the conflict settled the other way, an LR parse can't take an
+        // `else` after a nested `if` at all: it reduces the outer `if` too.
+        // A GLR parse falls back on shifting.
+        let shift = TABLES.action(state, t);
+        let mut actions: Vec<i16> = (0..TABLES.state_count)
+            .flat_map(|s| (0..TABLES.action_width).map(move |t| TABLES.action(s, t)))
+            .collect();
+        actions[state * TABLES.action_width + t] = reduce_action;
+        let alternatives = [(state, t, shift)];
+        let swapped = ParserTables {
+            action_table: ActionTable::Dense(&actions),
+            alternatives: &alternatives,
+            ..TABLES
+        };
+        assert_eq!(swapped.validate(), Ok(()));
+        let src = "if (a) if (b) x; else y; else z;";
+        assert!(parse_with(&swapped, src, false).is_err());
+        assert!(parse_with(&TABLES, src, false).is_ok());
+        assert_eq!(parse_with(&swapped, src, true), parse_with(&TABLES, src, false));
+        // Where both ways work, the table's own action wins, so this time
+        // the `else` goes with the outer `if`.
+        let src = "if (a) if (b) x; else y;";
+        assert!(parse_with(&swapped, src, false).is_err());
+        let outer = parse_with(&swapped, src, true).unwrap();
+        assert!(outer.starts_with("Script(Some(ScriptBody(StatementListP0(IfStatementP0("));
+        assert_ne!(Ok(outer), parse_with(&TABLES, src, false));
+
+        let misplaced = [(state, t, shift), (0, 0, shift)];
+        let broken = ParserTables {
+            alternatives: &misplaced,
+            ..TABLES
+        };
+        assert_eq!(broken.validate(), Err(vec![TableProblem::MisplacedAlternative { index: 1 }]));
+    }
+}
@@ -0,0 +1,160 @@
+// THIS FILE IS AUTOGENERATED by js_parser/generate_keyword_table.py.
+
+use crate::parser_runtime::TerminalId;
+
+/// The lexer returns the word as a keyword.
+pub(crate) const RESERVED: u8 = 1;
+
+/// The word is a keyword only in some places; see Parser::contextual_word().
+pub(crate) const CONTEXTUAL: u8 = 2;
+
+/// The keywords, each in the slot keyword_slot() picks for it, with the
+/// terminal it is as a keyword and its flags. Empty slots have "".
+const SLOTS: [(&str, TerminalId, u8); 128] = [
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("switch", TerminalId::Switch, RESERVED),
+    ("true", TerminalId::BooleanLiteral, RESERVED),
+    ("function", TerminalId::Function, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("typeof", TerminalId::Typeof, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("target", TerminalId::Target, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("async", TerminalId::Async, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("static", TerminalId::Static, CONTEXTUAL),
+    ("throw", TerminalId::Throw, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("while", TerminalId::While, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("if", TerminalId::If, RESERVED),
+    ("continue", TerminalId::Continue, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("yield", TerminalId::Yield, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("set", TerminalId::Set, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("class", TerminalId::Class, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("try", TerminalId::Try, RESERVED),
+    ("from", TerminalId::From, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("new", TerminalId::New, RESERVED),
+    ("case", TerminalId::Case, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("of", TerminalId::Of, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("for", TerminalId::For, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("await", TerminalId::Await, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("with", TerminalId::With, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("this", TerminalId::This, RESERVED),
+    ("false", TerminalId::BooleanLiteral, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("instanceof", TerminalId::Instanceof, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("debugger", TerminalId::Debugger, RESERVED),
+    ("else", TerminalId::Else, RESERVED),
+    ("void", TerminalId::Void, RESERVED),
+    ("get", TerminalId::Get, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("return", TerminalId::Return, RESERVED),
+    ("var", TerminalId::Var, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("const", TerminalId::Const, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("as", TerminalId::As, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("catch", TerminalId::Catch, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("meta", TerminalId::Meta, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("default", TerminalId::Default, RESERVED),
+    ("extends", TerminalId::Extends, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("import", TerminalId::Import, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("export", TerminalId::Export, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("null", TerminalId::NullLiteral, RESERVED),
+    ("super", TerminalId::Super, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("let", TerminalId::Let, CONTEXTUAL),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("finally", TerminalId::Finally, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("break", TerminalId::Break, RESERVED),
+    ("", TerminalId::Identifier, 0),
+    ("", TerminalId::Identifier, 0),
+    ("do", TerminalId::Do, RESERVED),
+    ("in", TerminalId::In, RESERVED),
+    ("delete", TerminalId::Delete, RESERVED),
+];
+
+/// The slot `word` would be in, if it's a keyword.
+fn keyword_slot(word: &[u8]) -> usize {
+    (word.len() * 9 + word[0] as usize * 6 + word[1] as usize * 14 + word[word.len() - 1] as usize * 15) & 127
+}
+
+/// The terminal `word` is as a keyword, and its flags, if it's one.
+pub(crate) fn lookup_keyword(word: &str) -> Option<(TerminalId, u8)> {
+    if !(2..=10).contains(&word.len()) {
+        return None;
+    }
+    let (keyword, terminal_id, flags) = SLOTS[keyword_slot(word.as_bytes())];
+    if keyword == word {
+        Some((terminal_id, flags))
+    } else {
+        None
+    }
+}
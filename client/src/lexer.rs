@@ -1,385 +1,1671 @@
-use crate::parser_runtime::{Token, TokenStream};
+use crate::keywords_generated::{lookup_keyword, RESERVED};
+use crate::parser_runtime::{SourceLocation, TerminalId, Token, TokenStream};
+use crate::scan;
+use crate::unicode_generated::{ID_CONTINUE, ID_START};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::str::Chars;
 
-pub struct Lexer<Iter: Iterator<Item = char>> {
-    chars: std::iter::Peekable<Iter>,
-    current: Option<Token>,
+/// Source text that the lexer couldn't turn into a token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LexError {
+    pub message: &'static str,
+    pub loc: SourceLocation,
 }
 
-impl<Iter> Lexer<Iter>
-where
-    Iter: Iterator<Item = char>,
-{
-    pub fn new(chars: Iter) -> Lexer<Iter> {
-        let mut result = Lexer {
-            chars: chars.into_iter().peekable(),
-            current: None,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriviaKind {
+    /// A run of whitespace and line terminators.
+    Whitespace,
+    /// A `//` comment, not including the line terminator after it.
+    LineComment,
+    /// A `/* */` comment.
+    BlockComment,
+    /// Text a lexer in recovery mode couldn't make a token of, like an
+    /// unexpected character. See Lexer::recover_errors().
+    Skipped,
+}
+
+/// Source text between tokens. Together with the tokens, a lexer's trivia
+/// covers the whole source text, so tools that need to reproduce it, like
+/// formatters, can.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub loc: SourceLocation,
+    /// Where the token after the trivia starts. Trivia belongs to the token
+    /// after it; at the end of the source, that's End.
+    pub token_start: usize,
+}
+
+/// The trivia just before the token that starts at `token_start`. `trivia`
+/// is in source order, as take_trivia() returns it.
+pub fn trivia_before(trivia: &[Trivia], token_start: usize) -> &[Trivia] {
+    let start = trivia.partition_point(|t| t.token_start < token_start);
+    let end = trivia.partition_point(|t| t.token_start <= token_start);
+    &trivia[start..end]
+}
+
+pub struct Lexer<'src> {
+    /// The source text from `base` onward. Token values borrow from it.
+    source: &'src str,
+    base: usize,
+    chars: std::iter::Peekable<Chars<'src>>,
+    /// The tokens peek_token() has scanned ahead, starting with the one
+    /// peek() returns. Tokens are scanned lazily, so that after take()
+    /// returns a `/` the characters after it are still unread; see
+    /// rescan_as_regexp().
+    lookahead: VecDeque<Token<'src>>,
+    /// The value of the token being scanned, for tokens that have one.
+    token_value: Option<Cow<'src, str>>,
+    token_raw: Option<Cow<'src, str>>,
+    token_number: Option<f64>,
+    /// Set when lexing fails. After that, the lexer only produces End.
+    error: Option<LexError>,
+    /// The errors got past so far, if recover_errors() turned recovery on.
+    recovered: Option<Vec<LexError>>,
+    /// The kind of literal being scanned, if a malformed one can still be
+    /// returned as a token of this kind.
+    guess: Option<TerminalId>,
+    /// Byte offset, line, and column of the next character.
+    offset: usize,
+    line: u32,
+    column: u32,
+    /// The offsets where the lines that start in the text read so far
+    /// start; see line_starts().
+    line_starts: Vec<usize>,
+    /// Where the token being scanned starts.
+    token_start: SourceLocation,
+    /// The line the last token scanned ends on.
+    last_token_line: u32,
+    /// A `.` that was read after a `?` to see if the two were `?.`, but
+    /// that starts a number instead, as in `a?.5:b`.
+    full_stop: Option<SourceLocation>,
+    /// The trivia scanned so far, if record_trivia() turned that on, and
+    /// how many pieces of it have been attached to a token.
+    trivia: Option<Vec<Trivia>>,
+    attached_trivia: usize,
+}
+
+impl<'src> Lexer<'src> {
+    pub fn new(source: &'src str) -> Lexer<'src> {
+        Lexer::new_at(source, SourceLocation::new(0, 0, 1, 0))
+    }
+
+    /// Start lexing in the middle of a source text. `source` is the text
+    /// from `start.start` onward, and `start` gives that point's line and
+    /// column, so that token locations are relative to the whole text.
+    pub fn new_at(source: &'src str, start: SourceLocation) -> Lexer<'src> {
+        Lexer {
+            source,
+            base: start.start,
+            chars: source.chars().peekable(),
+            lookahead: VecDeque::new(),
+            token_value: None,
+            token_raw: None,
+            token_number: None,
+            error: None,
+            recovered: None,
+            guess: None,
+            offset: start.start,
+            line: start.line,
+            column: start.column,
+            line_starts: if start.column == 0 { vec![start.start] } else { vec![] },
+            token_start: start.start_point(),
+            last_token_line: start.line,
+            full_stop: None,
+            trivia: None,
+            attached_trivia: 0,
+        }
+    }
+
+    /// Record the whitespace and comments between tokens, for
+    /// take_trivia(). Call this before the first token is scanned.
+    pub fn record_trivia(&mut self) {
+        self.trivia = Some(vec![]);
+    }
+
+    /// Take the trivia recorded so far, in source order.
+    pub fn take_trivia(&mut self) -> Vec<Trivia> {
+        self.attached_trivia = 0;
+        self.trivia.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Record everything scanned since self.token_start as trivia.
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        let trivia = match &mut self.trivia {
+            Some(trivia) => trivia,
+            None => return,
         };
-        result.current = result.advance();
-        result
+        let loc = SourceLocation {
+            end: self.offset,
+            ..self.token_start
+        };
+        match trivia.last_mut() {
+            Some(last) if kind == TriviaKind::Whitespace && last.kind == kind && last.loc.end == loc.start => {
+                last.loc.end = loc.end;
+            }
+            _ => trivia.push(Trivia {
+                kind,
+                loc,
+                token_start: loc.end,
+            }),
+        }
+    }
+
+    /// Return the token `n` tokens after the one peek() returns, so that
+    /// peek_token(0) is the same as peek(). Past the end, this is End.
+    ///
+    /// The tokens are scanned without help from the parser, so a `/` or `}`
+    /// among them is always scanned as itself. Look past one only if it
+    /// can't start a regular expression or continue a template there,
+    /// because rescanning it will no longer be possible.
+    pub fn peek_token(&mut self, n: usize) -> &Token<'src> {
+        while self.lookahead.len() <= n {
+            let token = self.advance();
+            self.lookahead.push_back(token);
+        }
+        &self.lookahead[n]
+    }
+
+    /// The byte offsets where lines start, in order, for the lines that
+    /// start in the text the lexer has read so far. For a lexer made with
+    /// new(), that's every line so far, starting with 0. See SourceMapper.
+    pub fn line_starts(&self) -> &[usize] {
+        &self.line_starts
+    }
+
+    /// Return the error that stopped the lexer, if any. The token stream
+    /// ends where the error happened.
+    pub fn take_error(&mut self) -> Option<LexError> {
+        self.error.take()
+    }
+
+    /// Keep going after malformed tokens, for take_errors(). A malformed
+    /// string or numeric literal, like `'abc` at the end of a line or
+    /// `0x`, is still returned as a literal, with a best guess at its
+    /// value. Anything else the lexer can't make a token of, like an
+    /// unexpected character, is skipped, and recorded as Skipped trivia.
+    /// Call this before the first token is scanned.
+    pub fn recover_errors(&mut self) {
+        self.recovered = Some(vec![]);
+    }
+
+    /// Take the errors recovery mode has got past so far, in the order they
+    /// were found. Tokens are scanned ahead by peek(), so these can include
+    /// errors in tokens that haven't been taken yet.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        self.recovered.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        match c {
+            // A CR LF pair counts as a single line terminator.
+            '\u{d}' if self.chars.peek() == Some(&'\u{a}') => self.column += 1,
+            '\u{a}' | '\u{d}' | '\u{2028}' | '\u{2029}' => {
+                self.line += 1;
+                self.column = 0;
+                self.line_starts.push(self.offset);
+            }
+            _ => self.column += 1,
+        }
+        Some(c)
+    }
+
+    /// The source text from the byte offset `start` to the next character.
+    fn text_from(&self, start: usize) -> &'src str {
+        &self.source[start - self.base..self.offset - self.base]
+    }
+
+    /// The source text from the next character on.
+    fn rest(&self) -> &'src [u8] {
+        &self.source.as_bytes()[self.offset - self.base..]
+    }
+
+    /// Read the next `len` bytes at once. They must be a run one of the
+    /// functions in crate::scan found, all ASCII and no line terminators.
+    fn skip_run(&mut self, len: usize) {
+        if len > 0 {
+            self.offset += len;
+            self.column += len as u32;
+            self.chars = self.source[self.offset - self.base..].chars().peekable();
+        }
+    }
+
+    fn mark_token_start(&mut self) {
+        self.token_start = SourceLocation::new(self.offset, self.offset, self.line, self.column);
+    }
+
+    fn advance(&mut self) -> Token<'src> {
+        if self.error.is_some() {
+            return Token::new(TerminalId::End, self.token_start.start_point());
+        }
+        let result = loop {
+            self.guess = None;
+            let result = self.scan();
+            match (result, &mut self.recovered) {
+                (Err(message), Some(recovered)) => {
+                    let loc = SourceLocation {
+                        end: self.offset,
+                        ..self.token_start
+                    };
+                    recovered.push(LexError { message, loc });
+                    match self.guess {
+                        Some(terminal_id) => {
+                            self.skip_literal_rest();
+                            break Ok(terminal_id);
+                        }
+                        None => {
+                            self.token_value = None;
+                            self.token_raw = None;
+                            self.token_number = None;
+                            self.add_trivia(TriviaKind::Skipped);
+                        }
+                    }
+                }
+                (result, _) => break result,
+            }
+        };
+        match self.finish_token(result) {
+            Ok(token) => token,
+            Err(error) => {
+                let end = Token::new(TerminalId::End, error.loc.start_point());
+                self.error = Some(error);
+                end
+            }
+        }
+    }
+
+    /// After an error in a numeric literal, read the rest of what looks like
+    /// part of it, like the `g` in `0xg`, so that it isn't scanned as a
+    /// token of its own. Its value gets the characters read, and isn't a
+    /// number.
+    fn skip_literal_rest(&mut self) {
+        if !matches!(self.guess, Some(TerminalId::NumericLiteral) | Some(TerminalId::BigIntLiteral)) {
+            return;
+        }
+        let mut value = self.token_value.take().map(Cow::into_owned).unwrap_or_default();
+        while let Some(&ch) = self.chars.peek() {
+            if !is_identifier_part(ch) {
+                break;
+            }
+            value.push(ch);
+            self.next_char();
+        }
+        self.token_value = Some(Cow::Owned(value));
+        if self.guess == Some(TerminalId::NumericLiteral) {
+            self.token_number = Some(f64::NAN);
+        }
     }
 
-    fn advance(&mut self) -> Option<Token> {
-        while let Some(c) = self.chars.next() {
+    /// Make a token out of everything scanned since self.token_start.
+    fn finish_token(
+        &mut self,
+        result: Result<TerminalId, &'static str>,
+    ) -> Result<Token<'src>, LexError> {
+        let loc = SourceLocation {
+            end: self.full_stop.map_or(self.offset, |dot| dot.start),
+            ..self.token_start
+        };
+        if let Some(trivia) = &mut self.trivia {
+            for t in &mut trivia[self.attached_trivia..] {
+                t.token_start = loc.start;
+            }
+            self.attached_trivia = trivia.len();
+        }
+        let value = self.token_value.take();
+        let raw = self.token_raw.take();
+        let number = self.token_number.take();
+        let newline_before = loc.line > self.last_token_line;
+        self.last_token_line = self.line;
+        match result {
+            Ok(terminal_id) => Ok(Token {
+                terminal_id,
+                loc,
+                value,
+                raw,
+                number,
+                newline_before,
+            }),
+            Err(message) => Err(LexError { message, loc }),
+        }
+    }
+
+    fn scan(&mut self) -> Result<TerminalId, &'static str> {
+        if let Some(dot) = self.full_stop.take() {
+            self.token_start = dot;
+            return self.scan_number();
+        }
+        loop {
+            self.mark_token_start();
+            let c = match self.next_char() {
+                Some(c) => c,
+                None => break,
+            };
             match c {
                 // WhiteSpace
                 '\u{9}' | '\u{b}' | '\u{c}' | '\u{20}' | '\u{a0}' | '\u{feff}' => {
+                    self.skip_run(scan::spaces(self.rest()));
+                    self.add_trivia(TriviaKind::Whitespace);
+                    continue;
+                }
+                c if is_space_separator(c) => {
+                    self.add_trivia(TriviaKind::Whitespace);
                     continue;
                 }
                 // LineTerminator
                 '\u{a}' | '\u{d}' | '\u{2028}' | '\u{2029}' => {
+                    // Along with the next line's indentation.
+                    self.skip_run(scan::spaces(self.rest()));
+                    self.add_trivia(TriviaKind::Whitespace);
                     continue;
                 }
                 // Idents
-                '$' | '_' | 'a'..='z' | 'A'..='Z' => {
-                    let mut var = String::new();
-                    var.push(c);
-                    while let Some(&ch) = self.chars.peek() {
-                        match ch {
-                            '$' | '_' | 'a'..='z' | 'A'..='Z' | '0'..='9' => {
-                                self.chars.next();
-                                var.push(ch);
-                            }
-                            _ => break,
-                        }
+                c if is_identifier_start(c) || c == '\\' => {
+                    let start = self.token_start.start;
+                    let escaped = self.scan_identifier_name(c)?;
+                    let text = self.text_from(start);
+                    // A word spelled with escapes is never a keyword. Its raw
+                    // text is kept to say so, and for the early errors about
+                    // what the escapes stand for.
+                    if let Some(var) = escaped {
+                        self.token_value = Some(Cow::Owned(var));
+                        self.token_raw = Some(Cow::Borrowed(text));
+                        return Ok(TerminalId::Identifier);
                     }
-                    match &var as &str {
-                        "as" => return Some(Token::As),
-                        "async" => return Some(Token::Async),
-                        "await" => return Some(Token::Await),
-                        "break" => return Some(Token::Break),
-                        "case" => return Some(Token::Case),
-                        "catch" => return Some(Token::Catch),
-                        "class" => return Some(Token::Class),
-                        "const" => return Some(Token::Const),
-                        "continue" => return Some(Token::Continue),
-                        "debugger" => return Some(Token::Debugger),
-                        "default" => return Some(Token::Default),
-                        "delete" => return Some(Token::Delete),
-                        "do" => return Some(Token::Do),
-                        "else" => return Some(Token::Else),
-                        "export" => return Some(Token::Export),
-                        "extends" => return Some(Token::Extends),
-                        "finally" => return Some(Token::Finally),
-                        "for" => return Some(Token::For),
-                        "from" => return Some(Token::From),
-                        "function" => return Some(Token::Function),
-                        "get" => return Some(Token::Get),
-                        "if" => return Some(Token::If),
-                        "import" => return Some(Token::Import),
-                        "in" => return Some(Token::In),
-                        "instanceof" => return Some(Token::Instanceof),
-                        "let" => return Some(Token::Let),
-                        "new" => return Some(Token::New),
-                        "of" => return Some(Token::Of),
-                        "return" => return Some(Token::Return),
-                        "set" => return Some(Token::Set),
-                        "static" => return Some(Token::Static),
-                        "super" => return Some(Token::Super),
-                        "switch" => return Some(Token::Switch),
-                        "target" => return Some(Token::Target),
-                        "this" => return Some(Token::This),
-                        "throw" => return Some(Token::Throw),
-                        "try" => return Some(Token::Try),
-                        "typeof" => return Some(Token::Typeof),
-                        "var" => return Some(Token::Var),
-                        "void" => return Some(Token::Void),
-                        "while" => return Some(Token::While),
-                        "with" => return Some(Token::With),
-                        "yield" => return Some(Token::Yield),
-                        "null" => return Some(Token::NullLiteral),
-                        "true" => return Some(Token::BooleanLiteral),
-                        "false" => return Some(Token::BooleanLiteral),
-                        _ => return Some(Token::Identifier),
+                    let t = match keyword(text) {
+                        Some(t) => t,
+                        None => {
+                            self.token_value = Some(Cow::Borrowed(text));
+                            return Ok(TerminalId::Identifier);
+                        }
+                    };
+                    // Keep the word, in case the parser wants an
+                    // IdentifierName here.
+                    self.token_raw = Some(Cow::Borrowed(text));
+                    return Ok(t);
+                }
+                // PrivateIdentifier, like `#x`. Its value keeps the `#`, as
+                // the spec's StringValue does.
+                '#' => {
+                    let first = match self.chars.peek() {
+                        Some(&ch) if is_identifier_start(ch) || ch == '\\' => ch,
+                        _ => return Err("expected a name after #"),
+                    };
+                    self.next_char();
+                    let escaped = self.scan_identifier_name(first)?;
+                    let text = self.text_from(self.token_start.start);
+                    match escaped {
+                        Some(var) => {
+                            self.token_value = Some(Cow::Owned(format!("#{}", var)));
+                            self.token_raw = Some(Cow::Borrowed(text));
+                        }
+                        None => self.token_value = Some(Cow::Borrowed(text)),
                     }
+                    return Ok(TerminalId::PrivateIdentifier);
                 }
                 // Numbers
                 '0'..='9' => {
-                    let mut var = String::new();
-                    var.push(c);
-                    while let Some(next) = self.chars.peek() {
-                        match next {
-                            '0'..='9' => {
-                                self.chars.next();
-                                var.push(c);
-                            }
-                            _ => break,
-                        }
-                    }
-                    let _ = var;
-                    return Some(Token::NumericLiteral);
+                    return self.scan_number();
                 }
                 // Strings
                 '"' | '\'' => {
-                    // include quotes?
-                    let mut var = String::new();
-                    self.chars.next();
-                    while let Some(ch) = self.chars.next() {
-                        if ch == c {
-                            break;
-                        }
-                        var.push(ch)
-                    }
-                    let _ = var;
-                    return Some(Token::StringLiteral);
+                    return self.scan_string(c);
                 }
 
                 '`' => {
-                    // include quotes?
-                    let mut var = String::new();
-                    self.chars.next();
-                    while let Some(ch) = self.chars.next() {
-                        if ch == '$' && self.chars.peek() == Some(&'{') {
-                            self.chars.next();
-                            return Some(Token::TemplateHead);
-                        }
-                        if ch == '`' {
-                            return Some(Token::NoSubstitutionTemplate);
-                        }
-                        var.push(ch)
-                    }
-                    return Some(Token::StringLiteral);
+                    return self.scan_template(true);
                 }
 
                 '!' => match self.chars.peek() {
                     Some('=') => {
-                        self.chars.next();
+                        self.next_char();
                         match self.chars.peek() {
                             Some('=') => {
-                                self.chars.next();
-                                return Some(Token::ExclamationMarkEqualsSignEqualsSign);
+                                self.next_char();
+                                return Ok(TerminalId::ExclamationMarkEqualsSignEqualsSign);
                             }
-                            _ => return Some(Token::ExclamationMarkEqualsSign),
+                            _ => return Ok(TerminalId::ExclamationMarkEqualsSign),
                         }
                     }
-                    _ => return Some(Token::ExclamationMark),
+                    _ => return Ok(TerminalId::ExclamationMark),
                 },
 
                 '%' => match self.chars.peek() {
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::PercentSignEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::PercentSignEqualsSign);
                     }
-                    _ => return Some(Token::PercentSign),
+                    _ => return Ok(TerminalId::PercentSign),
                 },
 
                 '&' => match self.chars.peek() {
                     Some('&') => {
-                        self.chars.next();
-                        return Some(Token::AmpersandAmpersand);
+                        self.next_char();
+                        return Ok(TerminalId::AmpersandAmpersand);
                     }
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::AmpersandEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::AmpersandEqualsSign);
                     }
-                    _ => return Some(Token::Ampersand),
+                    _ => return Ok(TerminalId::Ampersand),
                 },
 
                 '*' => match self.chars.peek() {
                     Some('*') => {
-                        self.chars.next();
+                        self.next_char();
                         match self.chars.peek() {
                             Some('=') => {
-                                self.chars.next();
-                                return Some(Token::AsteriskAsteriskEqualsSign);
+                                self.next_char();
+                                return Ok(TerminalId::AsteriskAsteriskEqualsSign);
                             }
-                            _ => return Some(Token::AsteriskAsterisk),
+                            _ => return Ok(TerminalId::AsteriskAsterisk),
                         }
                     }
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::AsteriskEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::AsteriskEqualsSign);
                     }
-                    _ => return Some(Token::Asterisk),
+                    _ => return Ok(TerminalId::Asterisk),
                 },
 
                 '+' => match self.chars.peek() {
                     Some('+') => {
-                        self.chars.next();
-                        return Some(Token::PlusSignPlusSign);
+                        self.next_char();
+                        return Ok(TerminalId::PlusSignPlusSign);
                     }
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::PlusSignEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::PlusSignEqualsSign);
                     }
-                    _ => return Some(Token::PlusSign),
+                    _ => return Ok(TerminalId::PlusSign),
                 },
 
                 '-' => match self.chars.peek() {
                     Some('-') => {
-                        self.chars.next();
-                        return Some(Token::HyphenMinusHyphenMinus);
+                        self.next_char();
+                        return Ok(TerminalId::HyphenMinusHyphenMinus);
                     }
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::HyphenMinusEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::HyphenMinusEqualsSign);
                     }
-                    _ => return Some(Token::HyphenMinus),
+                    _ => return Ok(TerminalId::HyphenMinus),
                 },
 
                 '.' => match self.chars.peek() {
+                    Some('0'..='9') => {
+                        return self.scan_number();
+                    }
                     Some('.') => {
-                        self.chars.next();
+                        self.next_char();
                         match self.chars.peek() {
                             Some('.') => {
-                                self.chars.next();
-                                return Some(Token::FullStopFullStopFullStop);
+                                self.next_char();
+                                return Ok(TerminalId::FullStopFullStopFullStop);
                             }
-                            _ => return None,
+                            _ => return Err("unexpected `..`"),
                         }
                     }
-                    _ => return Some(Token::FullStop),
+                    _ => return Ok(TerminalId::FullStop),
                 },
 
                 '/' => match self.chars.peek() {
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::SolidusEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::SolidusEqualsSign);
                     }
                     // Single-line comment
                     Some('/') => {
-                        self.chars.next();
-                        while let Some(ch) = self.chars.next() {
-                            match ch {
-                                '\u{a}' | '\u{d}' | '\u{2028}' | '\u{2029}' => break,
-                                _ => continue,
+                        self.next_char();
+                        self.skip_run(scan::line_comment(self.rest()));
+                        while let Some(&ch) = self.chars.peek() {
+                            if matches!(ch, '\u{a}' | '\u{d}' | '\u{2028}' | '\u{2029}') {
+                                break;
                             }
+                            self.next_char();
+                            self.skip_run(scan::line_comment(self.rest()));
                         }
+                        self.add_trivia(TriviaKind::LineComment);
                         continue;
                     }
                     // Multiline comment
                     Some('*') => {
-                        self.chars.next();
-                        while let Some(ch) = self.chars.next() {
-                            if ch == '*' && self.chars.peek() == Some(&'/') {
-                                self.chars.next();
-                                break;
+                        self.next_char();
+                        loop {
+                            self.skip_run(scan::block_comment(self.rest()));
+                            match self.next_char() {
+                                Some('*') if self.chars.peek() == Some(&'/') => {
+                                    self.next_char();
+                                    break;
+                                }
+                                Some(_) => {}
+                                None => return Err("unterminated comment"),
                             }
                         }
+                        self.add_trivia(TriviaKind::BlockComment);
                         continue;
                     }
-                    _ => return Some(Token::Solidus),
+                    _ => return Ok(TerminalId::Solidus),
                 },
 
                 '<' => match self.chars.peek() {
                     Some('<') => {
-                        self.chars.next();
+                        self.next_char();
                         match self.chars.peek() {
                             Some('=') => {
-                                self.chars.next();
-                                return Some(Token::LessThanSignLessThanSignEqualsSign);
+                                self.next_char();
+                                return Ok(TerminalId::LessThanSignLessThanSignEqualsSign);
                             }
-                            _ => return Some(Token::LessThanSignLessThanSign),
+                            _ => return Ok(TerminalId::LessThanSignLessThanSign),
                         }
                     }
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::LessThanSignEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::LessThanSignEqualsSign);
                     }
-                    _ => return Some(Token::LessThanSign),
+                    _ => return Ok(TerminalId::LessThanSign),
                 },
 
                 '=' => match self.chars.peek() {
                     Some('=') => {
-                        self.chars.next();
+                        self.next_char();
                         match self.chars.peek() {
                             Some('=') => {
-                                self.chars.next();
-                                return Some(Token::EqualsSignEqualsSignEqualsSign);
+                                self.next_char();
+                                return Ok(TerminalId::EqualsSignEqualsSignEqualsSign);
                             }
-                            _ => return Some(Token::EqualsSignEqualsSign),
+                            _ => return Ok(TerminalId::EqualsSignEqualsSign),
                         }
                     }
                     Some('>') => {
-                        self.chars.next();
-                        return Some(Token::Arrow);
+                        self.next_char();
+                        return Ok(TerminalId::Arrow);
                     }
-                    _ => return Some(Token::EqualsSign),
+                    _ => return Ok(TerminalId::EqualsSign),
                 },
 
                 '>' => {
                     match self.chars.peek() {
                         Some('>') => {
-                            self.chars.next();
+                            self.next_char();
                             match self.chars.peek() {
                                 Some('>') => {
-                                    self.chars.next();
+                                    self.next_char();
                                     match self.chars.peek() {
                                         Some('=') => {
-                                            self.chars.next();
-                                            return Some(Token::GreaterThanSignGreaterThanSignGreaterThanSignEqualsSign);
+                                            self.next_char();
+                                            return Ok(TerminalId::GreaterThanSignGreaterThanSignGreaterThanSignEqualsSign);
                                         }
-                                        _ => return Some(
-                                            Token::GreaterThanSignGreaterThanSignGreaterThanSign,
+                                        _ => return Ok(
+                                            TerminalId::GreaterThanSignGreaterThanSignGreaterThanSign,
                                         ),
                                     }
                                 }
                                 Some('=') => {
-                                    self.chars.next();
-                                    return Some(Token::GreaterThanSignGreaterThanSignEqualsSign);
+                                    self.next_char();
+                                    return Ok(TerminalId::GreaterThanSignGreaterThanSignEqualsSign);
                                 }
-                                _ => return Some(Token::GreaterThanSignGreaterThanSign),
+                                _ => return Ok(TerminalId::GreaterThanSignGreaterThanSign),
                             }
                         }
                         Some('=') => {
-                            self.chars.next();
-                            return Some(Token::GreaterThanSignEqualsSign);
+                            self.next_char();
+                            return Ok(TerminalId::GreaterThanSignEqualsSign);
                         }
-                        _ => return Some(Token::GreaterThanSign),
+                        _ => return Ok(TerminalId::GreaterThanSign),
                     }
                 }
 
                 '^' => match self.chars.peek() {
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::CircumflexAccentEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::CircumflexAccentEqualsSign);
                     }
-                    _ => return Some(Token::CircumflexAccent),
+                    _ => return Ok(TerminalId::CircumflexAccent),
                 },
 
                 '|' => match self.chars.peek() {
                     Some('|') => {
-                        self.chars.next();
-                        return Some(Token::VerticalLineVerticalLine);
+                        self.next_char();
+                        return Ok(TerminalId::VerticalLineVerticalLine);
                     }
                     Some('=') => {
-                        self.chars.next();
-                        return Some(Token::VerticalLineEqualsSign);
+                        self.next_char();
+                        return Ok(TerminalId::VerticalLineEqualsSign);
                     }
-                    _ => return Some(Token::VerticalLine),
+                    _ => return Ok(TerminalId::VerticalLine),
                 },
 
-                '(' => return Some(Token::LeftParenthesis),
-                ')' => return Some(Token::RightParenthesis),
-                ',' => return Some(Token::Comma),
-                ':' => return Some(Token::Colon),
-                ';' => return Some(Token::Semicolon),
-                '?' => return Some(Token::QuestionMark),
-                '[' => return Some(Token::LeftSquareBracket),
-                ']' => return Some(Token::RightSquareBracket),
-                '{' => return Some(Token::LeftCurlyBracket),
-                '}' => return Some(Token::RightCurlyBracket),
-                '~' => return Some(Token::Tilde),
-                _ => return None,
+                '(' => return Ok(TerminalId::LeftParenthesis),
+                ')' => return Ok(TerminalId::RightParenthesis),
+                ',' => return Ok(TerminalId::Comma),
+                ':' => return Ok(TerminalId::Colon),
+                ';' => return Ok(TerminalId::Semicolon),
+                '?' => match self.chars.peek() {
+                    Some('?') => {
+                        self.next_char();
+                        return Ok(TerminalId::QuestionMarkQuestionMark);
+                    }
+                    Some('.') => {
+                        let dot = SourceLocation::new(self.offset, self.offset, self.line, self.column);
+                        self.next_char();
+                        match self.chars.peek() {
+                            Some('0'..='9') => {
+                                self.full_stop = Some(dot);
+                                return Ok(TerminalId::QuestionMark);
+                            }
+                            _ => return Ok(TerminalId::QuestionMarkFullStop),
+                        }
+                    }
+                    _ => return Ok(TerminalId::QuestionMark),
+                },
+                '[' => return Ok(TerminalId::LeftSquareBracket),
+                ']' => return Ok(TerminalId::RightSquareBracket),
+                '{' => return Ok(TerminalId::LeftCurlyBracket),
+                '}' => return Ok(TerminalId::RightCurlyBracket),
+                '~' => return Ok(TerminalId::Tilde),
+                _ => return Err("unexpected character"),
+            }
+        }
+        Ok(TerminalId::End)
+    }
+
+    /// `token` is a `/` or `/=` just returned by take(), in a place where
+    /// the syntactic grammar allows a regular expression. Scan it again as
+    /// the start of a RegularExpressionLiteral.
+    ///
+    /// The lexer can't tell the two apart by itself; only the parser knows
+    /// whether it's expecting an operator or an operand.
+    pub fn rescan_as_regexp(&mut self, token: Token<'src>) -> Result<Token<'src>, LexError> {
+        debug_assert!(self.lookahead.is_empty());
+        assert!(
+            matches!(token.get_id(), TerminalId::Solidus | TerminalId::SolidusEqualsSign),
+            "rescan_as_regexp called on a token that isn't `/` or `/=`"
+        );
+        self.token_start = token.loc;
+        let mut in_class = false;
+        loop {
+            let ch = match self.next_char() {
+                None | Some('\u{a}') | Some('\u{d}') | Some('\u{2028}') | Some('\u{2029}') => {
+                    return Err(LexError {
+                        message: "unterminated regular expression literal",
+                        loc: SourceLocation {
+                            end: self.offset,
+                            ..token.loc
+                        },
+                    });
+                }
+                Some(ch) => ch,
+            };
+            match ch {
+                '\\' => match self.chars.peek() {
+                    Some('\u{a}') | Some('\u{d}') | Some('\u{2028}') | Some('\u{2029}') | None => {}
+                    Some(_) => {
+                        self.next_char();
+                    }
+                },
+                '[' => in_class = true,
+                ']' => in_class = false,
+                '/' if !in_class => break,
+                _ => {}
+            }
+        }
+        // RegularExpressionFlags
+        while let Some(&ch) = self.chars.peek() {
+            if !is_identifier_part(ch) {
+                break;
+            }
+            self.next_char();
+        }
+        self.token_value = Some(Cow::Borrowed(self.text_from(token.loc.start)));
+        let regexp = self.finish_token(Ok(TerminalId::RegularExpressionLiteral))?;
+        Ok(Token {
+            newline_before: token.newline_before,
+            ..regexp
+        })
+    }
+
+    /// `token` is a `}` just returned by take(), in a place where the
+    /// syntactic grammar allows the rest of a template. Scan it again as the
+    /// start of a TemplateMiddle or TemplateTail.
+    ///
+    /// Templates can nest inside substitutions, and substitutions can
+    /// contain braces, so only the parser knows which `}` ends one.
+    pub fn rescan_as_template(&mut self, token: Token<'src>) -> Result<Token<'src>, LexError> {
+        debug_assert!(self.lookahead.is_empty());
+        debug_assert_eq!(token.get_id(), TerminalId::RightCurlyBracket);
+        self.token_start = token.loc;
+        let result = self.scan_template(false);
+        let template = self.finish_token(result)?;
+        Ok(Token {
+            newline_before: token.newline_before,
+            ..template
+        })
+    }
+
+    /// Scan the rest of an IdentifierName that starts with `first`, which
+    /// has been read. If it has any `\u` escapes, return its value; if not,
+    /// its value is its text. An escape stands for its code point even if
+    /// that's not allowed where it is, or U+FFFD if it's a surrogate; see
+    /// is_identifier_name().
+    fn scan_identifier_name(&mut self, first: char) -> Result<Option<String>, &'static str> {
+        let start = self.offset - first.len_utf8();
+        let mut value: Option<String> = None;
+        let mut c = first;
+        loop {
+            if c == '\\' {
+                let mut var = value.take().unwrap_or_else(|| {
+                    // The text before this `\`, the first escape.
+                    let text = self.text_from(start);
+                    text[..text.len() - 1].to_string()
+                });
+                var.push(self.scan_identifier_escape()?);
+                value = Some(var);
+            } else if let Some(var) = &mut value {
+                var.push(c);
+            }
+            let run = scan::identifier_part(self.rest());
+            if let Some(var) = &mut value {
+                var.push_str(&self.source[self.offset - self.base..][..run]);
+            }
+            self.skip_run(run);
+            c = match self.chars.peek() {
+                Some(&ch) if is_identifier_part(ch) || ch == '\\' => ch,
+                _ => break,
+            };
+            self.next_char();
+        }
+        Ok(value)
+    }
+
+    /// Scan the rest of a `\u` escape in an identifier, after the `\`.
+    fn scan_identifier_escape(&mut self) -> Result<char, &'static str> {
+        const MESSAGE: &str = "invalid Unicode escape sequence in identifier";
+        if self.chars.peek() != Some(&'u') {
+            return Err(MESSAGE);
+        }
+        self.next_char();
+        let braced = self.chars.peek() == Some(&'{');
+        if braced {
+            self.next_char();
+        }
+        let mut code: u32 = 0;
+        let mut count = 0;
+        loop {
+            match self.chars.peek() {
+                Some('}') if braced && count > 0 => {
+                    self.next_char();
+                    break;
+                }
+                Some(&ch) if ch.is_ascii_hexdigit() && (braced || count < 4) => {
+                    code = code * 16 + ch.to_digit(16).unwrap();
+                    if code > 0x10ffff {
+                        return Err("Unicode escape sequence out of range");
+                    }
+                    self.next_char();
+                    count += 1;
+                    if !braced && count == 4 {
+                        break;
+                    }
+                }
+                _ => return Err(MESSAGE),
             }
         }
-        Some(Token::End)
+        Ok(std::char::from_u32(code).unwrap_or('\u{fffd}'))
+    }
+
+    /// Scan the rest of a NumericLiteral, whose first character, a digit
+    /// or a `.` that's followed by one, has been read. The token's value is
+    /// the text, even if it's malformed.
+    fn scan_number(&mut self) -> Result<TerminalId, &'static str> {
+        self.guess = Some(TerminalId::NumericLiteral);
+        let start = self.token_start.start;
+        let result = self.scan_number_rest(start);
+        let text = self.text_from(start);
+        // A BigInt's value is its digits, with the prefix but without the
+        // `n`.
+        let text = match self.guess {
+            Some(TerminalId::BigIntLiteral) => &text[..text.len() - 1],
+            _ => text,
+        };
+        self.token_value = Some(Cow::Borrowed(text));
+        result
     }
+
+    /// Scan the rest of the NumericLiteral that starts at `start`.
+    fn scan_number_rest(&mut self, start: usize) -> Result<TerminalId, &'static str> {
+        let text = self.text_from(start);
+        let radix = match (text, self.chars.peek()) {
+            ("0", Some('x')) | ("0", Some('X')) => Some(16),
+            ("0", Some('o')) | ("0", Some('O')) => Some(8),
+            ("0", Some('b')) | ("0", Some('B')) => Some(2),
+            _ => None,
+        };
+
+        let mut is_integer = !text.starts_with('.');
+        let number;
+        if let Some(radix) = radix {
+            self.next_char();
+            if self.scan_digits(start, radix, true)? == 0 {
+                return Err("missing digits after numeric literal prefix");
+            }
+            number = radix_value(&self.text_from(start)[2..], radix);
+        } else {
+            // A legacy octal literal like `010`, or a decimal one with a
+            // leading zero like `08`. Neither can have separators.
+            let leading_zero = text == "0" && self.chars.peek().is_some_and(|c| c.is_ascii_digit() || *c == '_');
+            self.scan_digits(start, 10, !leading_zero)?;
+            let legacy_octal = leading_zero && self.text_from(start).chars().all(|c| ('0'..='7').contains(&c));
+            if is_integer && !legacy_octal && self.chars.peek() == Some(&'.') {
+                self.next_char();
+                self.scan_digits(start, 10, true)?;
+                is_integer = false;
+            }
+            if !legacy_octal {
+                if let Some(&e) = self.chars.peek() {
+                    if e == 'e' || e == 'E' {
+                        self.next_char();
+                        if let Some(&sign) = self.chars.peek() {
+                            if sign == '+' || sign == '-' {
+                                self.next_char();
+                            }
+                        }
+                        if self.scan_digits(start, 10, true)? == 0 {
+                            return Err("missing digits in numeric literal exponent");
+                        }
+                        is_integer = false;
+                    }
+                }
+            }
+            if !is_integer && self.chars.peek() == Some(&'n') {
+                return Err("BigInt literals must be integers");
+            }
+            if leading_zero && self.chars.peek() == Some(&'n') {
+                return Err("BigInt literals can't have a leading zero");
+            }
+            let text = self.text_from(start);
+            number = if legacy_octal {
+                radix_value(text, 8)
+            } else if text.contains('_') {
+                text.replace('_', "").parse().unwrap_or(f64::NAN)
+            } else {
+                text.parse().unwrap_or(f64::NAN)
+            };
+        }
+
+        let terminal_id = if self.chars.peek() == Some(&'n') {
+            self.next_char();
+            self.guess = Some(TerminalId::BigIntLiteral);
+            TerminalId::BigIntLiteral
+        } else {
+            self.token_number = Some(number);
+            TerminalId::NumericLiteral
+        };
+
+        // "The SourceCharacter immediately following a NumericLiteral must
+        // not be an IdentifierStart or DecimalDigit."
+        if let Some(&ch) = self.chars.peek() {
+            if is_identifier_start(ch) || ch == '\\' || ch.is_ascii_digit() {
+                return Err("unexpected character after numeric literal");
+            }
+        }
+        Ok(terminal_id)
+    }
+
+    /// Scan any digits in the given radix, along with the separators between
+    /// them if `separators` is true, continuing the literal that starts at
+    /// `start`. Return how many digits there were.
+    fn scan_digits(&mut self, start: usize, radix: u32, separators: bool) -> Result<usize, &'static str> {
+        let mut count = 0;
+        let mut after_digit = self.text_from(start).chars().last().is_some_and(|c| c.is_digit(radix));
+        while let Some(&ch) = self.chars.peek() {
+            if ch == '_' {
+                if !separators {
+                    return Err("numeric separators aren't allowed after a leading 0");
+                }
+                self.next_char();
+                if !after_digit || !self.chars.peek().is_some_and(|c| c.is_digit(radix)) {
+                    return Err("numeric separators are only allowed between digits");
+                }
+                after_digit = false;
+                continue;
+            }
+            if !ch.is_digit(radix) {
+                break;
+            }
+            self.next_char();
+            count += 1;
+            after_digit = true;
+        }
+        Ok(count)
+    }
+
+    /// Scan the characters of a template up to and including the next `` ` ``
+    /// or `${`, after the `` ` `` (if `head`) or `}` that starts them.
+    fn scan_template(&mut self, head: bool) -> Result<TerminalId, &'static str> {
+        let start = self.offset;
+        // In raw text, CR LF and CR are both normalized to LF.
+        let mut has_cr = false;
+        let (terminal_id, end) = loop {
+            self.skip_run(scan::template(self.rest()));
+            match self.next_char() {
+                None => return Err("unterminated template literal"),
+                Some('`') if head => break (TerminalId::NoSubstitutionTemplate, self.offset - 1),
+                Some('`') => break (TerminalId::TemplateTail, self.offset - 1),
+                Some('$') if self.chars.peek() == Some(&'{') => {
+                    self.next_char();
+                    if head {
+                        break (TerminalId::TemplateHead, self.offset - 2);
+                    }
+                    break (TerminalId::TemplateMiddle, self.offset - 2);
+                }
+                Some('\\') => match self.next_char() {
+                    None => return Err("unterminated template literal"),
+                    Some('\u{d}') => has_cr = true,
+                    Some(_) => {}
+                },
+                Some('\u{d}') => has_cr = true,
+                Some(_) => {}
+            }
+        };
+        let text = &self.source[start - self.base..end - self.base];
+        let raw = if has_cr {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+        } else {
+            Cow::Borrowed(text)
+        };
+        self.token_value = cook(&raw, false).ok();
+        self.token_raw = Some(raw);
+        Ok(terminal_id)
+    }
+
+    /// Scan the rest of a StringLiteral delimited by `quote`. The token's
+    /// value is the string with its escape sequences and line continuations
+    /// replaced, and its raw text is the source text between the quotes.
+    ///
+    /// An unterminated string ends before the line terminator, and its value
+    /// is what there is of it. If an escape sequence doesn't stand for
+    /// anything, the value is the raw text.
+    fn scan_string(&mut self, quote: char) -> Result<TerminalId, &'static str> {
+        self.guess = Some(TerminalId::StringLiteral);
+        let start = self.offset;
+        let mut result = Ok(TerminalId::StringLiteral);
+        let end = loop {
+            self.skip_run(scan::string(self.rest(), quote as u8));
+            let ch = match self.chars.peek() {
+                None | Some('\u{a}') | Some('\u{d}') => {
+                    result = Err("unterminated string literal");
+                    break self.offset;
+                }
+                Some(&ch) => ch,
+            };
+            self.next_char();
+            if ch == quote {
+                break self.offset - 1;
+            }
+            if ch == '\\' && self.next_char().is_none() {
+                result = Err("unterminated string literal");
+                break self.offset;
+            }
+        };
+        let raw = Cow::Borrowed(&self.source[start - self.base..end - self.base]);
+        match cook(&raw, true) {
+            Ok(value) => self.token_value = Some(value),
+            Err(message) => {
+                self.token_value = Some(raw.clone());
+                if result.is_ok() {
+                    result = Err(message);
+                }
+            }
+        }
+        self.token_raw = Some(raw);
+        result
+    }
+}
+
+/// cook_escapes(), but without copying `raw` if it has no escapes.
+fn cook<'src>(raw: &Cow<'src, str>, legacy_octal: bool) -> Result<Cow<'src, str>, &'static str> {
+    if raw.contains('\\') {
+        cook_escapes(raw, legacy_octal).map(Cow::Owned)
+    } else {
+        Ok(raw.clone())
+    }
+}
+
+/// Replace the escape sequences in `raw` with the characters they stand for,
+/// and drop its line continuations. Return an error if there's an escape
+/// sequence that doesn't stand for anything, like `\u{110000}`. Legacy octal
+/// escapes like `\01`, and `\8` and `\9`, are only allowed if
+/// `legacy_octal`, as they are in string literals but not in templates.
+/// Unpaired surrogates, which a Rust string can't hold, become U+FFFD.
+pub fn cook_escapes(raw: &str, legacy_octal: bool) -> Result<String, &'static str> {
+    let mut cooked = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    // A high surrogate from a \u escape, waiting for its low half.
+    let mut pending_high: Option<u32> = None;
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            if pending_high.take().is_some() {
+                cooked.push('\u{fffd}');
+            }
+            cooked.push(ch);
+            continue;
+        }
+        let escaped = chars.next().ok_or("unterminated escape sequence")?;
+        let code = match escaped {
+            'b' => 0x8,
+            't' => 0x9,
+            'n' => 0xa,
+            'v' => 0xb,
+            'f' => 0xc,
+            'r' => 0xd,
+            '0' if !chars.peek().is_some_and(|c| c.is_ascii_digit()) => 0,
+            '0'..='9' if !legacy_octal => return Err("octal escape sequences are not allowed here"),
+            '8' | '9' => escaped as u32,
+            // LegacyOctalEscapeSequence: up to three octal digits, with a
+            // value of at most 0o377.
+            '0'..='7' => {
+                let mut code = escaped.to_digit(8).unwrap();
+                let max_digits = if code < 4 { 3 } else { 2 };
+                for _ in 1..max_digits {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            code = code * 8 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                code
+            }
+            'x' => hex_digits(&mut chars, 2).ok_or("invalid hexadecimal escape sequence")?,
+            'u' => {
+                if chars.peek() == Some(&'{') {
+                    chars.next();
+                    let mut code: u32 = 0;
+                    let mut count = 0;
+                    loop {
+                        match chars.next() {
+                            Some('}') if count > 0 => break,
+                            Some(c) if c.is_ascii_hexdigit() => {
+                                code = code * 16 + c.to_digit(16).unwrap();
+                                if code > 0x10ffff {
+                                    return Err("Unicode escape sequence out of range");
+                                }
+                                count += 1;
+                            }
+                            _ => return Err("invalid Unicode escape sequence"),
+                        }
+                    }
+                    code
+                } else {
+                    hex_digits(&mut chars, 4).ok_or("invalid Unicode escape sequence")?
+                }
+            }
+            // LineContinuation
+            '\u{a}' | '\u{d}' | '\u{2028}' | '\u{2029}' => {
+                if escaped == '\u{d}' && chars.peek() == Some(&'\u{a}') {
+                    chars.next();
+                }
+                continue;
+            }
+            other => other as u32,
+        };
+
+        match (pending_high.take(), code) {
+            (Some(high), 0xdc00..=0xdfff) => {
+                let pair = 0x10000 + ((high - 0xd800) << 10) + (code - 0xdc00);
+                cooked.push(std::char::from_u32(pair).unwrap());
+            }
+            (high, _) => {
+                if high.is_some() {
+                    cooked.push('\u{fffd}');
+                }
+                match code {
+                    0xd800..=0xdbff => pending_high = Some(code),
+                    _ => cooked.push(std::char::from_u32(code).unwrap_or('\u{fffd}')),
+                }
+            }
+        }
+    }
+    if pending_high.is_some() {
+        cooked.push('\u{fffd}');
+    }
+    Ok(cooked)
 }
 
-impl<Iter> TokenStream for Lexer<Iter>
+/// Read exactly `count` hex digits from `chars`.
+fn hex_digits<I>(chars: &mut std::iter::Peekable<I>, count: usize) -> Option<u32>
 where
-    Iter: Iterator<Item = char>,
+    I: Iterator<Item = char>,
 {
-    type Token = Token;
+    let mut code = 0;
+    for _ in 0..count {
+        code = code * 16 + chars.next()?.to_digit(16)?;
+    }
+    Some(code)
+}
+
+/// Whitespace in Unicode category Zs, other than U+0020 and U+00A0.
+fn is_space_separator(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1680}' | '\u{2000}'..='\u{200a}' | '\u{202f}' | '\u{205f}' | '\u{3000}'
+    )
+}
+
+/// The value of `digits`, which are in the given radix, a power of two,
+/// and may have separators, rounded to the nearest double.
+fn radix_value(digits: &str, radix: u32) -> f64 {
+    let bits_per_digit = radix.trailing_zeros();
+    let mut bits = vec![];
+    for d in digits.chars().filter_map(|c| c.to_digit(radix)) {
+        for i in (0..bits_per_digit).rev() {
+            let bit = (d >> i) & 1 == 1;
+            // Leave out leading zeros.
+            if bit || !bits.is_empty() {
+                bits.push(bit);
+            }
+        }
+    }
+    // A double has 53 bits of precision. Round the rest off to the nearest
+    // value, ties to even.
+    let precise = bits.len().min(53);
+    let mut mantissa = bits[..precise].iter().fold(0u64, |m, &bit| (m << 1) | bit as u64);
+    if bits.len() > 53 {
+        let half = bits[53];
+        let rest = bits[54..].iter().any(|&bit| bit);
+        if half && (rest || mantissa & 1 == 1) {
+            mantissa += 1;
+        }
+    }
+    let exponent = (bits.len() - precise) as i32;
+    mantissa as f64 * 2f64.powi(exponent)
+}
+
+/// The terminal for `word` if it's a reserved word. Contextual keywords,
+/// like `async` and `of`, aren't: the lexer returns them as identifiers, and
+/// the parser decides which ones are keywords where they are.
+pub(crate) fn keyword(word: &str) -> Option<TerminalId> {
+    match lookup_keyword(word) {
+        Some((terminal_id, flags)) if flags & RESERVED != 0 => Some(terminal_id),
+        _ => None,
+    }
+}
+
+/// IdentifierStart, other than an escape: ID_Start, `$` or `_`.
+pub(crate) fn is_identifier_start(c: char) -> bool {
+    match c {
+        '$' | '_' | 'a'..='z' | 'A'..='Z' => true,
+        _ => !c.is_ascii() && in_table(c, ID_START),
+    }
+}
+
+/// IdentifierPart, other than an escape: ID_Continue, `$`, ZWNJ or ZWJ.
+pub(crate) fn is_identifier_part(c: char) -> bool {
+    match c {
+        '$' | '_' | 'a'..='z' | 'A'..='Z' | '0'..='9' | '\u{200c}' | '\u{200d}' => true,
+        _ => !c.is_ascii() && in_table(c, ID_CONTINUE),
+    }
+}
+
+/// Return true if `name`, the value of an identifier, is one that could be
+/// written without escapes. It isn't if an escape in it stands for a code
+/// point that isn't allowed where it is, like the `\u0031` in `\u0031x`
+/// or `\u002e` anywhere.
+pub(crate) fn is_identifier_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_identifier_start) && chars.all(is_identifier_part)
+}
+
+/// Return true if `c` is in one of the ranges in `table`, which are in order.
+fn in_table(c: char, table: &[(char, char)]) -> bool {
+    table
+        .binary_search_by(|&(first, last)| {
+            if last < c {
+                Ordering::Less
+            } else if first > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+impl<'src> TokenStream for Lexer<'src> {
+    type Token = Token<'src>;
     fn peek(&mut self) -> &Self::Token {
-        self.current.as_ref().unwrap()
+        self.peek_token(0)
     }
     fn take(&mut self) -> Self::Token {
-        let result = self.current.take().unwrap();
-        self.current = self.advance();
-        result
+        match self.lookahead.pop_front() {
+            Some(token) => token,
+            None => self.advance(),
+        }
     }
     fn token_as_index(t: &Self::Token) -> usize {
         t.get_id() as usize
     }
 }
+
+/// Iterate over the tokens, stopping before the End token.
+impl<'src> Iterator for Lexer<'src> {
+    type Item = Token<'src>;
+    fn next(&mut self) -> Option<Token<'src>> {
+        if self.peek().get_id() == TerminalId::End {
+            None
+        } else {
+            Some(TokenStream::take(self))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lexer;
+    use crate::ast_arena::Arena;
+    use crate::early_errors::EarlyErrorKind;
+    use crate::parser::{ParseError, Parser};
+    use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TerminalId, TABLES};
+    use crate::parser_runtime::TokenStream;
+    use crate::testing::early_error;
+
+    #[test]
+    fn numeric_literals() {
+        let src = "1_000_000 0x1F 0Xf_f 0o17 0b1_01 010 08 08.5 .5 1. 1e3 1.5E-3 2_5e1_0 \
+                   0x20000000000001 0x20000000000003 0b11111111111111111111111111111111111111111111111111111111";
+        let mut lexer = Lexer::new(src);
+        let mut numbers = vec![];
+        loop {
+            let token = TokenStream::take(&mut lexer);
+            if token.get_id() == TerminalId::End {
+                break;
+            }
+            assert_eq!(token.get_id(), TerminalId::NumericLiteral);
+            numbers.push(token.number.unwrap());
+        }
+        let exact = |s: &str| s.parse::<f64>().unwrap();
+        assert_eq!(
+            numbers,
+            vec![
+                1e6,
+                31.0,
+                255.0,
+                15.0,
+                5.0,
+                8.0,
+                8.0,
+                8.5,
+                0.5,
+                1.0,
+                1000.0,
+                0.0015,
+                2.5e11,
+                exact("9007199254740993"),
+                exact("9007199254740995"),
+                exact("72057594037927935"),
+            ]
+        );
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for src in &["1__0;", "1_;", "1_.5;", "1._5;", "1e_5;", "0x_1;", "0x1_;", "0_1;", "01_1;", "08_1;", "1_n;"] {
+            let result = Parser::new_script(&handler).parse_script(src);
+            assert!(matches!(result, Err(ParseError::LexError(_))), "{}", src);
+        }
+    }
+
+    #[test]
+    fn token_forms() {
+        // U+00A0 and U+FEFF are whitespace, and U+2028 and U+2029 end lines,
+        // even escaped in a string or inside a comment.
+        let src = "a\u{a0}=\u{feff}0x1F+.5e1;\u{2028}b >>>= 'c\\\u{2028}d' /* e\n */ \
+                   ?.5 : f?.g // h\u{2029}\"i\"\u{3000}0b10n";
+        let mut lexer = Lexer::new(src);
+        let mut tokens = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            let token = TokenStream::take(&mut lexer);
+            let value = token.value.as_deref().map(str::to_string);
+            tokens.push((token.get_id(), value, token.loc.line, token.loc.column));
+        }
+        let token = |id, value: Option<&str>, line, column| (id, value.map(str::to_string), line, column);
+        use TerminalId::*;
+        assert_eq!(
+            tokens,
+            [
+                token(Identifier, Some("a"), 1, 0),
+                token(EqualsSign, None, 1, 2),
+                token(NumericLiteral, Some("0x1F"), 1, 4),
+                token(PlusSign, None, 1, 8),
+                token(NumericLiteral, Some(".5e1"), 1, 9),
+                token(Semicolon, None, 1, 13),
+                token(Identifier, Some("b"), 2, 0),
+                token(GreaterThanSignGreaterThanSignGreaterThanSignEqualsSign, None, 2, 2),
+                token(StringLiteral, Some("cd"), 2, 7),
+                // `?.` followed by a digit is `?` and a number.
+                token(QuestionMark, None, 4, 4),
+                token(NumericLiteral, Some(".5"), 4, 5),
+                token(Colon, None, 4, 8),
+                token(Identifier, Some("f"), 4, 10),
+                token(QuestionMarkFullStop, None, 4, 11),
+                token(Identifier, Some("g"), 4, 13),
+                token(StringLiteral, Some("i"), 5, 0),
+                token(BigIntLiteral, Some("0b10"), 5, 4),
+            ]
+        );
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for src in &["x /* a", "x = 'a\nb';", "x = \"a", "x = 0b2;", "x = 3in y;", "x = #;"] {
+            let result = Parser::new_script(&handler).parse_script(src);
+            assert!(matches!(result, Err(ParseError::LexError(_))), "{}", src);
+        }
+    }
+
+    #[test]
+    fn newline_before() {
+        // A string with a line continuation ends on a later line than it
+        // starts on.
+        let mut lexer = Lexer::new("a /* \n */ b 'c\\\nd' e // f\ng\u{2028}h i");
+        let mut newlines = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            newlines.push(TokenStream::take(&mut lexer).newline_before);
+        }
+        assert_eq!(newlines, [false, true, false, false, true, true, false]);
+    }
+
+    #[test]
+    fn bigint_literals() {
+        let mut lexer = Lexer::new("1n 0x1Fn 0o7n 0b10n 0n 12");
+        let mut tokens = vec![];
+        loop {
+            let token = TokenStream::take(&mut lexer);
+            if token.get_id() == TerminalId::End {
+                break;
+            }
+            tokens.push((token.get_id(), token.value.unwrap_or_default().into_owned()));
+        }
+        let big = |digits: &str| (TerminalId::BigIntLiteral, digits.to_string());
+        assert_eq!(
+            tokens,
+            vec![
+                big("1"),
+                big("0x1F"),
+                big("0o7"),
+                big("0b10"),
+                big("0"),
+                (TerminalId::NumericLiteral, "12".to_string())
+            ]
+        );
+
+        assert_eq!(early_error("x = 1n + {2n: 3n}[2n]; 'use strict'; y = 0n;", false), None);
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for src in &["01n;", "09n;", "1.5n;", ".5n;", "1e3n;", "1nn;"] {
+            let result = Parser::new_script(&handler).parse_script(src);
+            assert!(matches!(result, Err(ParseError::LexError(_))), "{}", src);
+        }
+    }
+
+    #[test]
+    fn string_escapes() {
+        let src = r#"'a\nb' "\x41B\u{43}\u{1F600}" '\0\101\08\8\377\400' "a\
+b" '\'\"\\\q' '😀\uD83D'"#;
+        let mut lexer = Lexer::new(src);
+        let mut values = vec![];
+        loop {
+            let token = TokenStream::take(&mut lexer);
+            if token.get_id() == TerminalId::End {
+                break;
+            }
+            assert_eq!(token.get_id(), TerminalId::StringLiteral);
+            values.push(token.value.unwrap());
+        }
+        assert_eq!(
+            values,
+            vec!["a\nb", "ABC\u{1F600}", "\0A\088\u{ff} 0", "ab", "'\"\\q", "\u{1F600}\u{fffd}"]
+        );
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for src in &[r"'\x4';", r"'\u12';", r"'\u{}';", r"'\u{110000}';", r"'\u{12';"] {
+            let result = Parser::new_script(&handler).parse_script(src);
+            assert!(matches!(result, Err(ParseError::LexError(_))), "{}", src);
+        }
+
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            (r"'use\x20strict'; with (x) {}", None),
+            ("'use \\\nstrict'; with (x) {}", None),
+            (r"x = `\x41${1}\u{42}`;", None),
+            (r"x = `\x`;", Some(InvalidTemplateEscape)),
+            (r"x = `a${1}\01`;", Some(InvalidTemplateEscape)),
+            (r"x = `${1}${2}\u{110000}`;", Some(InvalidTemplateEscape)),
+            (r"f`\x\01\u{110000}${1}\u`;", None),
+            (r"a.b`\x`; f()`\x`; new f`\x`;", None),
+            (r"f`${`\x`}`;", Some(InvalidTemplateEscape)),
+            (r"`${f`\x`}\x`;", Some(InvalidTemplateEscape)),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+    }
+
+    #[test]
+    fn trivia() {
+        use super::{trivia_before, TriviaKind::*};
+        let src = "/* a */ x = 1; // b\r\n\t// c\n  y /**/\u{2028}\u{3000}";
+        let mut lexer = Lexer::new(src);
+        lexer.record_trivia();
+        let mut spans = vec![];
+        loop {
+            let token = TokenStream::take(&mut lexer);
+            spans.push(token.loc);
+            if token.get_id() == TerminalId::End {
+                break;
+            }
+        }
+        let trivia = lexer.take_trivia();
+        let pieces: Vec<_> = trivia
+            .iter()
+            .map(|t| (t.kind, &src[t.loc.start..t.loc.end], t.token_start))
+            .collect();
+        assert_eq!(
+            pieces,
+            vec![
+                (BlockComment, "/* a */", 8),
+                (Whitespace, " ", 8),
+                (Whitespace, " ", 10),
+                (Whitespace, " ", 12),
+                (Whitespace, " ", 29),
+                (LineComment, "// b", 29),
+                (Whitespace, "\r\n\t", 29),
+                (LineComment, "// c", 29),
+                (Whitespace, "\n  ", 29),
+                (Whitespace, " ", src.len()),
+                (BlockComment, "/**/", src.len()),
+                (Whitespace, "\u{2028}\u{3000}", src.len()),
+            ]
+        );
+        assert_eq!(trivia_before(&trivia, 29).len(), 5);
+        assert_eq!(trivia_before(&trivia, 30).len(), 0);
+
+        // Tokens and trivia tile the source.
+        spans.extend(trivia.iter().map(|t| t.loc));
+        spans.sort_by_key(|loc| loc.start);
+        let mut offset = 0;
+        for loc in spans {
+            assert_eq!(loc.start, offset);
+            offset = loc.end;
+        }
+        assert_eq!(offset, src.len());
+
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.set_record_trivia(true);
+        let src = "x = /a/g /* c */ + `${a /* d */}`;";
+        assert!(parser.parse_script(src).is_ok());
+        let comments: Vec<_> = parser
+            .take_trivia()
+            .unwrap()
+            .iter()
+            .filter(|t| t.kind == BlockComment)
+            .map(|t| &src[t.loc.start..t.loc.end])
+            .collect();
+        assert_eq!(comments, vec!["/* c */", "/* d */"]);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        use EarlyErrorKind::*;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let src = r"let café = 1; var \u{1D49C} = caf\u00e9, a\u200d = 𝒜; o.\u0076ar; l\u0065t = 1;";
+        assert!(Parser::new_script(&handler).parse_script(src).is_ok());
+        let mut lexer = Lexer::new(r"\u{1D49C}x");
+        let token = TokenStream::take(&mut lexer);
+        assert_eq!(token.value.as_deref(), Some("𝒜x"));
+        assert_eq!(token.raw.as_deref(), Some(r"\u{1D49C}x"));
+
+        for (src, kind) in [
+            (r"var \u0031x;", InvalidIdentifierEscape),
+            (r"var a\u002eb;", InvalidIdentifierEscape),
+            (r"var a\uD800;", InvalidIdentifierEscape),
+            (r"class C { #\u0031 = 1; }", InvalidIdentifierEscape),
+            (r"var \u0076ar;", ReservedWord),
+            (r"\u0074his.x;", ReservedWord),
+        ] {
+            match Parser::new_script(&handler).parse_script(src) {
+                Err(ParseError::EarlyError { kind: k, .. }) => assert_eq!(k, kind, "{}", src),
+                _ => panic!("expected an early error: {}", src),
+            }
+        }
+        assert!(matches!(Parser::new_script(&handler).parse_script(r"var a\x;"), Err(ParseError::LexError(_))));
+    }
+
+    #[test]
+    fn tokens_borrow_source() {
+        use std::borrow::Cow;
+        let src = r"abc 'def' 'd\x65f' 12n `g\n` \u0061b";
+        let mut lexer = Lexer::new(src);
+        let mut values = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            let token = TokenStream::take(&mut lexer);
+            values.push(token.value.map(|value| (value.to_string(), matches!(value, Cow::Borrowed(_)))));
+        }
+        // Only the text whose escapes had to be cooked is copied.
+        let value = |value: &str, borrowed| Some((value.to_string(), borrowed));
+        assert_eq!(
+            values,
+            [
+                value("abc", true),
+                value("def", true),
+                value("def", false),
+                value("12", true),
+                value("g\n", false),
+                value("ab", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_table() {
+        use crate::keywords_generated::{lookup_keyword, CONTEXTUAL, RESERVED};
+        assert_eq!(lookup_keyword("instanceof"), Some((TerminalId::Instanceof, RESERVED)));
+        assert_eq!(lookup_keyword("true"), Some((TerminalId::BooleanLiteral, RESERVED)));
+        assert_eq!(lookup_keyword("of"), Some((TerminalId::Of, CONTEXTUAL)));
+        // Words like keywords, and words in a keyword's slot, like `foo` in
+        // `while`'s.
+        for word in &["", "x", "Do", "lets", "instanceofs", "\u{e9}t", "foo", "nul", "ofs"] {
+            assert_eq!(lookup_keyword(word), None, "{}", word);
+        }
+    }
+
+    #[test]
+    fn lexer_runs() {
+        // Runs longer than a SIMD chunk, ended by non-ASCII characters and
+        // escapes, which the lexer reads one at a time.
+        let src = "/* 0123456789abcdefghij \u{e9} */ 'abcdefghijklmnopq\\x41 \u{e9}' \
+                   // 0123456789abcdefghij \u{e9}\n   \
+                   `abcdefghijklmnopq$rstu` abcdefghijklmnopqrstuvwxyz0123456789_$ end";
+        let mut lexer = Lexer::new(src);
+        let mut tokens = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            let token = TokenStream::take(&mut lexer);
+            tokens.push((token.value.unwrap().to_string(), token.loc.line, token.loc.column));
+        }
+        let token = |value: &str, line, column| (value.to_string(), line, column);
+        assert_eq!(
+            tokens,
+            [
+                token("abcdefghijklmnopqA \u{e9}", 1, 29),
+                token("abcdefghijklmnopq$rstu", 2, 3),
+                token("abcdefghijklmnopqrstuvwxyz0123456789_$", 2, 28),
+                token("end", 2, 67),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_recovery() {
+        use super::TriviaKind;
+        use std::borrow::Cow;
+        let src = "a = 'abc\nb = 0x;\nc = 1 @ 2nx;";
+        let mut lexer = Lexer::new(src);
+        lexer.record_trivia();
+        lexer.recover_errors();
+        let mut tokens = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            let token = TokenStream::take(&mut lexer);
+            tokens.push((token.get_id(), token.value.clone()));
+        }
+        assert!(lexer.take_error().is_none());
+        let string = |s| Some(Cow::Borrowed(s));
+        assert_eq!(
+            tokens,
+            [
+                (TerminalId::Identifier, string("a")),
+                (TerminalId::EqualsSign, None),
+                (TerminalId::StringLiteral, string("abc")),
+                (TerminalId::Identifier, string("b")),
+                (TerminalId::EqualsSign, None),
+                (TerminalId::NumericLiteral, string("0x")),
+                (TerminalId::Semicolon, None),
+                (TerminalId::Identifier, string("c")),
+                (TerminalId::EqualsSign, None),
+                (TerminalId::NumericLiteral, string("1")),
+                (TerminalId::BigIntLiteral, string("2x")),
+                (TerminalId::Semicolon, None),
+            ]
+        );
+        let errors: Vec<_> = lexer.take_errors().iter().map(|e| (e.message, e.loc.start)).collect();
+        assert_eq!(
+            errors,
+            [
+                ("unterminated string literal", 4),
+                ("missing digits after numeric literal prefix", 13),
+                ("unexpected character", 23),
+                ("unexpected character after numeric literal", 25),
+            ]
+        );
+        let trivia = lexer.take_trivia();
+        let skipped: Vec<_> = trivia.iter().filter(|t| t.kind == TriviaKind::Skipped).map(|t| t.loc.start).collect();
+        assert_eq!(skipped, [23]);
+
+        // A parser in recovery mode reports them, and keeps going.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+        assert!(parser.parse_script("x = '\\u{zz}';\ny = 08n;\nz = 'ok';").is_ok());
+        let errors: Vec<_> = parser.diagnostics().iter().map(|d| (d.code, d.loc.unwrap().line)).collect();
+        assert_eq!(errors, [("lex-error", 1), ("lex-error", 2)]);
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), false);
+        assert!(matches!(parser.parse_script("x = 0x;"), Err(ParseError::LexError(_))));
+    }
+}
@@ -0,0 +1,33 @@
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+pub mod ast_arena;
+pub mod automaton;
+pub mod checkpoint;
+pub mod codegen;
+pub mod coverage;
+pub mod cst;
+pub mod diagnostics;
+mod early_errors;
+pub mod estree;
+pub mod ffi;
+pub mod fuzzing;
+mod glr;
+mod keywords_generated;
+pub mod lexer;
+pub mod lint;
+mod modules;
+pub mod parallel;
+pub mod parser;
+pub mod parser_generated;
+pub mod parser_runtime;
+mod patterns;
+pub mod refine;
+mod regexp;
+mod scan;
+mod scope;
+pub mod source_mapper;
+mod stack;
+pub mod trace;
+#[cfg(test)]
+mod testing;
+mod unicode_generated;
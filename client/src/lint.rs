@@ -0,0 +1,510 @@
+//! Warnings about code that parses but is probably a mistake.
+//!
+//! This is a pass over the tree after a successful parse, and only runs if
+//! asked for: lint_script() or lint_module(), or `jsparagus check --lint`.
+//! It reports what's cheap to see from the tree alone, without scopes or
+//! types:
+//!
+//! - `useless-expression`: an expression statement that can't do anything,
+//!   like `a + 1;` or `x === y;`. Directives like `"use strict";` are
+//!   skipped.
+//! - `unreachable-code`: a statement after a `return` or `throw` in the same
+//!   list of statements. Function declarations are hoisted, so they don't
+//!   count.
+//! - `duplicate-key`: a key an object literal already has, like the second
+//!   `a` in `{a: 1, a: 2}`. A getter and a setter for the same key are
+//!   fine, and destructuring targets aren't object literals, so their keys
+//!   aren't checked.
+//!
+//! Locations come from the NodeLocations the parse filled in. Without them,
+//! only duplicate keys, which are tokens, have a location.
+
+use std::collections::HashMap;
+use std::mem;
+
+use crate::ast_arena::NodeLocations;
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::parser_generated::concrete::*;
+use crate::parser_generated::{
+    walk_arrow_parameters, walk_async_arrow_function, walk_expression, walk_function_statement_list, walk_initializer,
+    walk_module_body, walk_module_item, walk_script_body, Visit,
+};
+use crate::parser_runtime::{SourceLocation, TerminalId};
+
+/// Lint a script, returning its warnings in source order.
+pub fn lint_script<'alloc>(script: &'alloc Script<'alloc>, locations: &NodeLocations) -> Diagnostics {
+    let mut linter = Linter::new(locations);
+    linter.visit_script(script);
+    linter.finish()
+}
+
+/// Lint a module, returning its warnings in source order.
+pub fn lint_module<'alloc>(module: &'alloc Module<'alloc>, locations: &NodeLocations) -> Diagnostics {
+    let mut linter = Linter::new(locations);
+    linter.visit_module(module);
+    linter.finish()
+}
+
+fn warning(code: &'static str, message: String, loc: Option<SourceLocation>) -> Diagnostic {
+    Diagnostic::new(Severity::Warning, code, message, loc)
+}
+
+struct Linter<'l> {
+    locations: &'l NodeLocations,
+    diagnostics: Diagnostics,
+    /// True from the start of a script, module or function body until its
+    /// statements are visited, since they start with a directive prologue.
+    prologue: bool,
+    /// True while visiting an expression that's really a destructuring
+    /// target or arrow parameters.
+    pattern: bool,
+}
+
+impl<'l> Linter<'l> {
+    fn new(locations: &'l NodeLocations) -> Linter<'l> {
+        Linter {
+            locations,
+            diagnostics: Diagnostics::new(),
+            prologue: false,
+            pattern: false,
+        }
+    }
+
+    fn finish(mut self) -> Diagnostics {
+        self.diagnostics.sort();
+        self.diagnostics
+    }
+
+    /// Run `f` with `pattern` set to `pattern`.
+    fn in_pattern(&mut self, pattern: bool, f: impl FnOnce(&mut Self)) {
+        let outer = mem::replace(&mut self.pattern, pattern);
+        f(self);
+        self.pattern = outer;
+    }
+
+    /// Check and visit a list of statements, first to last.
+    fn statements<'alloc>(&mut self, items: Vec<&'alloc ModuleItem<'alloc>>) {
+        let mut prologue = mem::take(&mut self.prologue);
+        // The `return` or `throw` the statements so far end with, if any,
+        // and whether what follows it was reported yet.
+        let mut exit = None;
+        let mut reported = false;
+        for item in items {
+            let statement = without_asi(item);
+            if prologue && is_directive(statement) {
+                continue;
+            }
+            prologue = false;
+            match exit {
+                Some((keyword, loc)) => {
+                    if !reported && !is_hoisted(statement) {
+                        let mut diagnostic = warning(
+                            "unreachable-code",
+                            "unreachable code".to_string(),
+                            self.locations.get(item),
+                        );
+                        if let Some(loc) = loc {
+                            diagnostic = diagnostic.with_label(loc, &format!("after this `{}`", keyword));
+                        }
+                        self.diagnostics.push(diagnostic);
+                        reported = true;
+                    }
+                }
+                None => exit = exit_keyword(statement).map(|keyword| (keyword, self.locations.get(item))),
+            }
+            self.visit_module_item(item);
+        }
+    }
+
+    /// Warn about each key `list` has more than once.
+    fn check_keys(&mut self, list: &PropertyDefinitionList<'_>) {
+        let mut seen: HashMap<String, (SourceLocation, Vec<KeyKind>)> = HashMap::new();
+        for property in properties(list) {
+            let ((name, loc), kind) = match property_key(property) {
+                Some(key) => key,
+                None => continue,
+            };
+            if name == "__proto__" {
+                // `__proto__: x` sets the prototype instead of defining a
+                // key. Having it twice is a SyntaxError, which is for the
+                // early errors to report, not a lint.
+                continue;
+            }
+            match seen.get_mut(&name) {
+                Some((first, kinds)) => {
+                    if kind == KeyKind::Value || kinds.iter().any(|&k| k == KeyKind::Value || k == kind) {
+                        let message = format!("duplicate key `{}` in object literal", name);
+                        let first = *first;
+                        self.diagnostics
+                            .push(warning("duplicate-key", message, Some(loc)).with_label(first, "first defined here"));
+                    }
+                    kinds.push(kind);
+                }
+                None => {
+                    seen.insert(name, (loc, vec![kind]));
+                }
+            }
+        }
+    }
+}
+
+impl<'alloc, 'l> Visit<'alloc> for Linter<'l> {
+    fn visit_script_body(&mut self, node: &'alloc ScriptBody<'alloc>) {
+        self.prologue = true;
+        walk_script_body(self, node);
+        self.prologue = false;
+    }
+
+    fn visit_module_body(&mut self, node: &'alloc ModuleBody<'alloc>) {
+        self.prologue = true;
+        walk_module_body(self, node);
+        self.prologue = false;
+    }
+
+    fn visit_function_statement_list(&mut self, node: &'alloc FunctionStatementList<'alloc>) {
+        self.prologue = true;
+        walk_function_statement_list(self, node);
+        self.prologue = false;
+    }
+
+    fn visit_statement_list(&mut self, node: &'alloc StatementList<'alloc>) {
+        let mut items = vec![];
+        let mut next = Some(node);
+        while let Some(list) = next {
+            next = match list {
+                StatementList::StatementListP0(item) => {
+                    items.push(item.get());
+                    None
+                }
+                StatementList::StatementListP1(rest, item) => {
+                    items.push(item.get());
+                    Some(rest.get())
+                }
+                StatementList::Error(_) => None,
+            };
+        }
+        items.reverse();
+        self.statements(items);
+    }
+
+    fn visit_module_item_list(&mut self, node: &'alloc ModuleItemList<'alloc>) {
+        let mut items = vec![];
+        let mut next = Some(node);
+        while let Some(list) = next {
+            next = match list {
+                ModuleItemList::ModuleItemListP0(item) => {
+                    items.push(item.get());
+                    None
+                }
+                ModuleItemList::ModuleItemListP1(rest, item) => {
+                    items.push(item.get());
+                    Some(rest.get())
+                }
+                ModuleItemList::Error(_) => None,
+            };
+        }
+        items.reverse();
+        self.statements(items);
+    }
+
+    fn visit_module_item(&mut self, node: &'alloc ModuleItem<'alloc>) {
+        match node {
+            ModuleItem::ExpressionStatement(expression) if has_no_effect(expression) => {
+                let message = "expression statement has no effect".to_string();
+                self.diagnostics
+                    .push(warning("useless-expression", message, self.locations.get(node)));
+            }
+            ModuleItem::IterationStatementP5(target, object, body)
+            | ModuleItem::IterationStatementP8(target, object, body)
+            | ModuleItem::IterationStatementP11(target, object, body) => {
+                self.in_pattern(true, |v| v.visit_expression(target.get()));
+                self.visit_expression(object.get());
+                self.visit_module_item(body.get());
+                return;
+            }
+            _ => {}
+        }
+        walk_module_item(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &'alloc Expression<'alloc>) {
+        match node {
+            Expression::ObjectLiteralP1(list) | Expression::ObjectLiteralP2(list) if !self.pattern => {
+                self.check_keys(list);
+            }
+            Expression::AssignmentExpressionP4(target, value) => {
+                self.in_pattern(true, |v| v.visit_expression(target.get()));
+                self.in_pattern(false, |v| v.visit_expression(value.get()));
+                return;
+            }
+            _ => {}
+        }
+        walk_expression(self, node);
+    }
+
+    fn visit_initializer(&mut self, node: &'alloc Initializer<'alloc>) {
+        self.in_pattern(false, |v| walk_initializer(v, node));
+    }
+
+    fn visit_arrow_parameters(&mut self, node: &'alloc ArrowParameters<'alloc>) {
+        self.in_pattern(true, |v| walk_arrow_parameters(v, node));
+    }
+
+    fn visit_async_arrow_function(&mut self, node: &'alloc AsyncArrowFunction<'alloc>) {
+        match node {
+            AsyncArrowFunction::AsyncArrowFunctionP1(head, body) => {
+                self.in_pattern(true, |v| v.visit_cover_call_expression_and_async_arrow_head(head.get()));
+                self.visit_async_concise_body(body.get());
+            }
+            _ => walk_async_arrow_function(self, node),
+        }
+    }
+}
+
+/// What an object literal's property defines under its key.
+#[derive(Clone, Copy, PartialEq)]
+enum KeyKind {
+    Value,
+    Getter,
+    Setter,
+}
+
+/// `list`'s properties, first to last.
+fn properties<'a>(list: &'a PropertyDefinitionList<'a>) -> Vec<&'a PropertyDefinition<'a>> {
+    let mut properties = vec![];
+    let mut next = Some(list);
+    while let Some(list) = next {
+        next = match list {
+            PropertyDefinitionList::PropertyDefinitionListP0(property) => {
+                properties.push(property.get());
+                None
+            }
+            PropertyDefinitionList::PropertyDefinitionListP1(rest, property) => {
+                properties.push(property.get());
+                Some(rest.get())
+            }
+            PropertyDefinitionList::Error(_) => None,
+        };
+    }
+    properties.reverse();
+    properties
+}
+
+/// The key a property defines, with where it's written, and what it defines
+/// there. None for spread and computed properties, and keys this pass
+/// doesn't compare, like BigInts.
+fn property_key(property: &PropertyDefinition<'_>) -> Option<((String, SourceLocation), KeyKind)> {
+    match property {
+        PropertyDefinition::PropertyDefinitionP0(id) => match &**id {
+            IdentifierReference::IdentifierReference(token) => {
+                Some(((token.value.as_deref()?.to_string(), token.loc), KeyKind::Value))
+            }
+            IdentifierReference::Error(_) => None,
+        },
+        PropertyDefinition::PropertyDefinitionP2(name, _) => Some((literal_key(name)?, KeyKind::Value)),
+        PropertyDefinition::PropertyDefinitionP3(method) => {
+            let (name, kind) = match &**method {
+                MethodDefinition::MethodDefinitionP4(name, _) => (name, KeyKind::Getter),
+                MethodDefinition::MethodDefinitionP5(name, ..) => (name, KeyKind::Setter),
+                MethodDefinition::MethodDefinitionP0(name, ..)
+                | MethodDefinition::GeneratorMethod(name, ..)
+                | MethodDefinition::AsyncGeneratorMethod(name, ..)
+                | MethodDefinition::AsyncMethod(name, ..) => (name, KeyKind::Value),
+                MethodDefinition::Error(_) => return None,
+            };
+            match &**name {
+                ClassElementName::ClassElementNameP0(name) => Some((literal_key(name)?, kind)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The key a property name that isn't computed stands for, as a string:
+/// `a`, `"a"` and `'a'` are all `a`, and `1`, `1.0` and `"1"` are all `1`.
+fn literal_key(name: &PropertyName<'_>) -> Option<(String, SourceLocation)> {
+    let token = match name {
+        PropertyName::PropertyNameP0(literal) => match &**literal {
+            LiteralPropertyName::LiteralPropertyNameP0(token)
+            | LiteralPropertyName::LiteralPropertyNameP1(token)
+            | LiteralPropertyName::LiteralPropertyNameP2(token)
+            | LiteralPropertyName::LiteralPropertyNameP3(token) => token,
+            LiteralPropertyName::Error(_) => return None,
+        },
+        _ => return None,
+    };
+    let key = match token.terminal_id {
+        TerminalId::NumericLiteral => token.number.filter(|n| n.is_finite())?.to_string(),
+        TerminalId::BigIntLiteral => return None,
+        _ => token.value.as_deref().or(token.raw.as_deref())?.to_string(),
+    };
+    Some((key, token.loc))
+}
+
+fn without_asi<'a>(item: &'a ModuleItem<'a>) -> &'a ModuleItem<'a> {
+    let mut statement = item;
+    while let ModuleItem::CheckAsi(inner) = statement {
+        statement = inner.get();
+    }
+    statement
+}
+
+fn is_directive(statement: &ModuleItem<'_>) -> bool {
+    match statement {
+        ModuleItem::ExpressionStatement(expression) => matches!(**expression, Expression::LiteralP4(_)),
+        _ => false,
+    }
+}
+
+/// True for statements that do their work before the statements before
+/// them run, so they aren't unreachable after a `return`.
+fn is_hoisted(statement: &ModuleItem<'_>) -> bool {
+    matches!(
+        statement,
+        ModuleItem::EmptyStatement()
+            | ModuleItem::FunctionDeclarationP0(..)
+            | ModuleItem::FunctionDeclarationP1(..)
+            | ModuleItem::GeneratorDeclarationP0(..)
+            | ModuleItem::GeneratorDeclarationP1(..)
+            | ModuleItem::AsyncGeneratorDeclarationP0(..)
+            | ModuleItem::AsyncGeneratorDeclarationP1(..)
+            | ModuleItem::AsyncFunctionDeclarationP0(..)
+            | ModuleItem::AsyncFunctionDeclarationP1(..)
+    )
+}
+
+fn exit_keyword(statement: &ModuleItem<'_>) -> Option<&'static str> {
+    match statement {
+        ModuleItem::ReturnStatementP0() | ModuleItem::ReturnStatementP1(_) => Some("return"),
+        ModuleItem::ThrowStatement(_) => Some("throw"),
+        _ => None,
+    }
+}
+
+/// True if evaluating `expression` can't do anything but make a value: it
+/// calls nothing, assigns nothing and creates nothing anyone can see. This
+/// ignores conversions, so `a + b` has no effect even though `a` might
+/// have a valueOf() method.
+fn has_no_effect(expression: &Expression<'_>) -> bool {
+    match expression {
+        Expression::PrimaryExpressionP0()
+        | Expression::PrimaryExpressionP1(_)
+        | Expression::PrimaryExpressionP10(_)
+        | Expression::LiteralP0()
+        | Expression::LiteralP1(_)
+        | Expression::LiteralP2(_)
+        | Expression::LiteralP3(_)
+        | Expression::LiteralP4(_)
+        | Expression::FunctionExpression(..)
+        | Expression::GeneratorExpression(..)
+        | Expression::AsyncGeneratorExpression(..)
+        | Expression::AsyncFunctionExpressionP0(..)
+        | Expression::AsyncFunctionExpressionP1(..)
+        | Expression::AssignmentExpressionP2(_)
+        | Expression::AssignmentExpressionP3(_) => true,
+        Expression::PrimaryExpressionP11(template) => matches!(**template, TemplateLiteral::TemplateLiteralP0(_)),
+        Expression::PrimaryExpressionP12(cover) => match &**cover {
+            CoverParenthesizedExpressionAndArrowParameterList::CoverParenthesizedExpressionAndArrowParameterListP0(
+                inner,
+            ) => has_no_effect(inner),
+            _ => false,
+        },
+        // Every unary operator but `delete`.
+        Expression::UnaryExpressionP2(operand)
+        | Expression::UnaryExpressionP3(operand)
+        | Expression::UnaryExpressionP4(operand)
+        | Expression::UnaryExpressionP5(operand)
+        | Expression::UnaryExpressionP6(operand)
+        | Expression::UnaryExpressionP7(operand) => has_no_effect(operand),
+        Expression::ExponentiationExpressionP1(left, right)
+        | Expression::MultiplicativeExpressionP1(left, _, right)
+        | Expression::AdditiveExpressionP1(left, right)
+        | Expression::AdditiveExpressionP2(left, right)
+        | Expression::ShiftExpressionP1(left, right)
+        | Expression::ShiftExpressionP2(left, right)
+        | Expression::ShiftExpressionP3(left, right)
+        | Expression::RelationalExpressionP1(left, right)
+        | Expression::RelationalExpressionP2(left, right)
+        | Expression::RelationalExpressionP3(left, right)
+        | Expression::RelationalExpressionP4(left, right)
+        | Expression::RelationalExpressionP5(left, right)
+        | Expression::RelationalExpressionP6(left, right)
+        | Expression::EqualityExpressionP1(left, right)
+        | Expression::EqualityExpressionP2(left, right)
+        | Expression::EqualityExpressionP3(left, right)
+        | Expression::EqualityExpressionP4(left, right)
+        | Expression::BitwiseAndExpressionP1(left, right)
+        | Expression::BitwiseXorExpressionP1(left, right)
+        | Expression::BitwiseOrExpressionP1(left, right)
+        | Expression::LogicalAndExpressionP1(left, right)
+        | Expression::LogicalOrExpressionP1(left, right)
+        | Expression::ExpressionP1(left, right) => has_no_effect(left) && has_no_effect(right),
+        Expression::CoalesceExpression(head, right) => match &**head {
+            CoalesceExpressionHead::CoalesceExpressionHeadP0(left)
+            | CoalesceExpressionHead::CoalesceExpressionHeadP1(left) => has_no_effect(left) && has_no_effect(right),
+            CoalesceExpressionHead::Error(_) => false,
+        },
+        Expression::ConditionalExpressionP1(test, consequent, alternate) => {
+            has_no_effect(test) && has_no_effect(consequent) && has_no_effect(alternate)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::DefaultHandler;
+
+    #[test]
+    fn lint() {
+        use crate::ast_arena::NodeLocations;
+        use crate::diagnostics::Severity;
+        use super::{lint_module, lint_script};
+        let arena = Arena::new();
+        let locations = NodeLocations::new();
+        let handler = DefaultHandler::with_locations(&arena, &locations);
+        let codes = |src: &str| match Parser::new_script(&handler).parse_script(src) {
+            Ok(script) => {
+                let warnings = lint_script(script.into_script().get(), &locations);
+                assert!(!warnings.has_errors());
+                warnings.iter().map(|d| (d.code, d.loc.map(|loc| loc.start))).collect::<Vec<_>>()
+            }
+            Err(error) => panic!("{}", error.message()),
+        };
+
+        assert_eq!(codes("a + 1; x === y; (a); !a; a ? b : c;").len(), 5);
+        assert_eq!(codes("a(); a = 1; a++; delete a.b; a && b(); new A; void f();"), []);
+        assert_eq!(codes("'use strict'; function f() { 'use strict'; `a`; }").len(), 1);
+        assert_eq!(codes("f(); 'not a directive';"), [("useless-expression", Some(5))]);
+
+        assert_eq!(codes("function f() { return; g(); h(); }"), [("unreachable-code", Some(23))]);
+        assert_eq!(codes("function f() { return g(); function g() {} }"), []);
+        assert_eq!(codes("function f() { if (a) { throw e; } g(); }"), []);
+        assert_eq!(codes("switch (a) { case 1: throw e; b(); }"), [("unreachable-code", Some(30))]);
+
+        assert_eq!(codes("o = {a: 1, 'a': 2};"), [("duplicate-key", Some(11))]);
+        assert_eq!(codes("o = {1: a, 1.0: b, '1': c};").len(), 2);
+        assert_eq!(codes("o = {get a() {}, set a(v) {}, [a]: 1, b, ...c};"), []);
+        assert_eq!(codes("o = {get a() {}, a: 1};"), [("duplicate-key", Some(17))]);
+        assert_eq!(codes("({a: x, a: y} = o); [{b: x, b: y}] = o; f = ({c: x, c: y}) => 0;"), []);
+        assert_eq!(codes("[x = {a: 1, a: 2}] = o;"), [("duplicate-key", Some(12))]);
+
+        let src = "function f() {\n  return;\n  g();\n}";
+        let script = Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script();
+        let warnings = lint_script(script.get(), &locations);
+        let warning = warnings.iter().next().unwrap();
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(
+            warning.to_string(),
+            "warning[unreachable-code]: unreachable code at line 3, column 2\n    \
+             note: after this `return` at line 2, column 2"
+        );
+
+        let module = Parser::new_module(&handler).parse_script("export {}; a; b = {c, c};").ok().unwrap();
+        let codes: Vec<_> = lint_module(module.into_module().get(), &locations).iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["useless-expression", "duplicate-key"]);
+    }
+}
@@ -1,49 +1,64 @@
-#![cfg_attr(feature = "unstable", feature(test))]
-
-mod lexer;
-mod parser;
-mod parser_generated;
-mod parser_runtime;
-
-use crate::lexer::Lexer;
+use client::ast_arena::Arena;
+use client::lexer::LexError;
+use client::parser::{ParseError, Parser};
+use client::parser_generated;
 use std::error::Error;
 use std::io;
 use std::io::prelude::*;
 
-#[cfg(all(feature = "unstable", test))]
-mod tests {
-    extern crate test;
-
-    use crate::lexer::Lexer;
-    use std::fs::File;
-    use std::io::Read;
-    use test::Bencher;
+/// What became of the source entered so far.
+enum Input {
+    /// It's the start of a program, but needs more lines.
+    Incomplete,
+    Done(Result<String, String>),
+}
 
-    #[bench]
-    fn bench_parse_grammar(b: &mut Bencher) {
-        let mut file = File::open("../mtg/mtg.pgen").expect("opening test file");
-        let mut buffer = String::new();
-        file.read_to_string(&mut buffer)
-            .expect("reading from test file");
-        b.iter(|| {
-            let lexer = Lexer::new(buffer.chars());
-            crate::parser_generated::parse_Script(lexer).unwrap();
-        });
+/// Parse `buffer`. Unless `force` is true, a buffer that could be the start
+/// of a longer program is left for more lines to finish.
+fn parse(buffer: &str, force: bool) -> Input {
+    let arena = Arena::new();
+    let handler = parser_generated::DefaultHandler::new(&arena);
+    let mut parser = Parser::new_script(&handler);
+    let end = match parser.write_source(buffer) {
+        Ok(end) => end,
+        // The lexer only gets to the end of the input in a template or a
+        // comment if it's unterminated.
+        Err(ParseError::LexError(LexError { message, .. }))
+            if !force && (message == "unterminated template literal" || message == "unterminated comment") =>
+        {
+            return Input::Incomplete
+        }
+        Err(err) => return Input::Done(Err(err.message())),
+    };
+    if !force && !parser.can_close() {
+        return Input::Incomplete;
     }
+    Input::Done(match parser.close(end) {
+        Ok(ast) => Ok(format!("{:?}", ast.into_script())),
+        Err(err) => Err(err.message()),
+    })
 }
 
+/// Read a script a line at a time, and print its tree. While what's been
+/// entered is unfinished, like a block that hasn't been closed, prompt for
+/// more; an empty line parses it as it is.
 fn main() {
-    while let Ok(buffer) = get_input("> ") {
-        let lexer = Lexer::new(buffer.chars());
-        let parse_result =
-            parser_generated::parse_Script(
-                &mut parser_generated::DefaultHandler {},
-                lexer,
-            );
-        match parse_result {
-            Ok(ast) => println!("{:?}", ast),
-            Err(err) => println!("{}", err.message()),
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+        let line = match get_input(prompt) {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let force = !buffer.is_empty() && line.trim().is_empty();
+        buffer.push_str(&line);
+        buffer.push('\n');
+        match parse(&buffer, force) {
+            Input::Incomplete => continue,
+            Input::Done(Ok(tree)) => println!("{}", tree),
+            Input::Done(Err(message)) => println!("{}", message),
         }
+        buffer.clear();
     }
 }
 
@@ -54,7 +69,7 @@ pub fn get_input(prompt: &str) -> Result<String, Box<dyn Error>> {
     if let 0 = io::stdin().read_line(&mut input)? {
         Err("EOF".into())
     } else {
-        Ok(input.trim().to_string())
+        Ok(input.trim_end_matches(&['\n', '\r'][..]).to_string())
     }
 }
 
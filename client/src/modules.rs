@@ -0,0 +1,403 @@
+//! What a module imports and exports.
+//!
+//! While a module is parsed, `EarlyErrors` passes the names in its import
+//! and export declarations to a `ModuleLog`, which collects them into a
+//! `ModuleSummary`: the modules it requests, and its import and export
+//! entries, as in the spec's Source Text Module Records. A bundler can use it
+//! to find a module's dependencies without walking the AST, and the
+//! emitter to set up the module's environment.
+
+use crate::parser_runtime::{SourceLocation, TerminalId, Token};
+
+/// The local name the spec gives an anonymous default export, like
+/// `export default 1`.
+pub const DEFAULT_LOCAL_NAME: &str = "*default*";
+
+/// A module specifier, like the `"./a.js"` in `import x from "./a.js"`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleRequest {
+    /// The specifier as written, without the quotes.
+    pub specifier: String,
+    /// Where the specifier first appears.
+    pub loc: SourceLocation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportName {
+    Name(String),
+    /// The module namespace object, as in `import * as ns from "a"`.
+    Namespace,
+    /// Every export but the default, as in `export * from "a"`.
+    AllButDefault,
+}
+
+/// A binding that an import declaration creates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportEntry {
+    /// An index into ModuleSummary::requests.
+    pub request: usize,
+    pub import_name: ImportName,
+    pub local_name: String,
+    /// Where the local name is bound.
+    pub loc: SourceLocation,
+}
+
+/// One name an export declaration exports, or for `export * from "a"`,
+/// all of another module's names.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportEntry {
+    /// None for `export *`.
+    pub export_name: Option<String>,
+    /// For re-exports, an index into ModuleSummary::requests.
+    pub request: Option<usize>,
+    /// For re-exports, what the other module exports.
+    pub import_name: Option<ImportName>,
+    /// For local exports, the binding that's exported.
+    pub local_name: Option<String>,
+    /// Where the name is: its specifier or declaration, or for `export *`
+    /// and anonymous default exports, the `export`.
+    pub loc: SourceLocation,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleSummary {
+    /// The modules that this one imports from or re-exports, in source
+    /// order, once each.
+    pub requests: Vec<ModuleRequest>,
+    pub imports: Vec<ImportEntry>,
+    pub exports: Vec<ExportEntry>,
+}
+
+impl ModuleSummary {
+    pub fn specifiers(&self) -> impl Iterator<Item = &str> {
+        self.requests.iter().map(|r| r.specifier.as_str())
+    }
+
+    /// The names that the module binds to imports.
+    pub fn imported_names(&self) -> impl Iterator<Item = &str> {
+        self.imports.iter().map(|e| e.local_name.as_str())
+    }
+
+    /// The names that the module exports, not counting those from
+    /// `export *`.
+    pub fn exported_names(&self) -> impl Iterator<Item = &str> {
+        self.exports.iter().filter_map(|e| e.export_name.as_deref())
+    }
+
+    /// The index of the request for `specifier`, added if it's new.
+    fn request(&mut self, specifier: String, loc: SourceLocation) -> usize {
+        match self.requests.iter().position(|r| r.specifier == specifier) {
+            Some(i) => i,
+            None => {
+                self.requests.push(ModuleRequest { specifier, loc });
+                self.requests.len() - 1
+            }
+        }
+    }
+}
+
+/// A name in an import or export declaration, and where it is.
+type Name = (String, SourceLocation);
+
+/// Collects a ModuleSummary. The parser reduces the pieces of each import
+/// or export declaration before the declaration itself, so the log holds on
+/// to them until then.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleLog {
+    summary: ModuleSummary,
+    /// True between an `import` or `export` and the end of its declaration.
+    open: bool,
+    /// The last terminal shifted.
+    previous: Option<TerminalId>,
+    /// The `export` that starts the declaration being parsed, if any, and
+    /// the token after it.
+    export: Option<(SourceLocation, Option<TerminalId>)>,
+    /// The IdentifierNames in the import or export specifier being parsed.
+    names: Vec<Name>,
+    /// The last string literal shifted in an import or export declaration.
+    string: Option<Name>,
+    specifier: Option<Name>,
+    /// The binding for the import specifier being parsed.
+    binding: Option<Name>,
+    imports: Vec<(ImportName, Name)>,
+    /// Local names and export names from an export clause.
+    export_specifiers: Vec<(Name, Name)>,
+    /// Names declared by an exported declaration, like `export var x`.
+    declared: Vec<Name>,
+}
+
+impl ModuleLog {
+    pub fn new() -> ModuleLog {
+        ModuleLog::default()
+    }
+
+    /// `token` was just shifted.
+    pub fn shift(&mut self, token: &Token) {
+        let previous = self.previous.replace(token.get_id());
+        match token.get_id() {
+            TerminalId::Import => {
+                self.open = true;
+                self.names.clear();
+            }
+            TerminalId::Export => {
+                self.open = true;
+                self.names.clear();
+                self.export = Some((token.loc, None));
+            }
+            // Not a declaration, but `import(...)` or `import.meta`.
+            TerminalId::LeftParenthesis | TerminalId::FullStop if previous == Some(TerminalId::Import) => {
+                self.open = false;
+            }
+            t => {
+                if let Some((_, after @ None)) = &mut self.export {
+                    *after = Some(t);
+                }
+                let value = || token.value.as_deref().unwrap_or_default().to_string();
+                match t {
+                    TerminalId::IdentifierName if self.open => self.names.push((value(), token.loc)),
+                    TerminalId::StringLiteral => self.string = Some((value(), token.loc)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// `name` is the ImportedBinding just reduced.
+    pub fn imported_binding(&mut self, name: &str, loc: SourceLocation) {
+        self.binding = Some((name.to_string(), loc));
+    }
+
+    /// `name` was declared at the top level of the module.
+    pub fn declared(&mut self, name: &str, loc: SourceLocation) {
+        if let Some((export, _)) = self.export {
+            if export.start < loc.start {
+                self.declared.push((name.to_string(), loc));
+            }
+        }
+    }
+
+    /// An import specifier was reduced: the default import, a namespace
+    /// import, or `x` or `x as y` in braces.
+    pub fn import_specifier(&mut self, default: bool, namespace: bool) {
+        let binding = match self.binding.take() {
+            Some(binding) => binding,
+            None => return,
+        };
+        let name = if default {
+            ImportName::Name("default".to_string())
+        } else if namespace {
+            ImportName::Namespace
+        } else {
+            match self.names.pop() {
+                Some((name, _)) => ImportName::Name(name),
+                None => ImportName::Name(binding.0.clone()),
+            }
+        };
+        self.names.clear();
+        self.imports.push((name, binding));
+    }
+
+    /// `x` or `x as y` in an export clause was reduced.
+    pub fn export_specifier(&mut self) {
+        let mut names = std::mem::take(&mut self.names).into_iter();
+        if let Some(local) = names.next() {
+            let export = names.next().unwrap_or_else(|| local.clone());
+            self.export_specifiers.push((local, export));
+        }
+    }
+
+    /// The string literal just shifted is a module specifier.
+    pub fn module_specifier(&mut self) {
+        self.specifier = self.string.take();
+    }
+
+    /// An import declaration was reduced.
+    pub fn import_declaration(&mut self) {
+        self.open = false;
+        let (specifier, loc) = match self.specifier.take() {
+            Some(specifier) => specifier,
+            None => return,
+        };
+        let request = self.summary.request(specifier, loc);
+        for (import_name, (local_name, loc)) in self.imports.drain(..) {
+            self.summary.imports.push(ImportEntry {
+                request,
+                import_name,
+                local_name,
+                loc,
+            });
+        }
+    }
+
+    /// An export declaration was reduced. Return the names it exports that
+    /// were already exported, and where.
+    pub fn export_declaration(&mut self) -> Vec<Name> {
+        self.open = false;
+        let (export, after) = match self.export.take() {
+            Some(export) => export,
+            None => return vec![],
+        };
+        let request = self
+            .specifier
+            .take()
+            .map(|(specifier, loc)| self.summary.request(specifier, loc));
+        let declared = std::mem::take(&mut self.declared);
+        let mut entries = vec![];
+        match after {
+            Some(TerminalId::Asterisk) => entries.push(ExportEntry {
+                export_name: None,
+                request,
+                import_name: Some(ImportName::AllButDefault),
+                local_name: None,
+                loc: export,
+            }),
+            Some(TerminalId::LeftCurlyBracket) => {
+                for ((local, start), (name, end)) in self.export_specifiers.drain(..) {
+                    let loc = start.to(&end);
+                    entries.push(match request {
+                        Some(_) => ExportEntry {
+                            export_name: Some(name),
+                            request,
+                            import_name: Some(ImportName::Name(local)),
+                            local_name: None,
+                            loc,
+                        },
+                        None => ExportEntry {
+                            export_name: Some(name),
+                            request: None,
+                            import_name: None,
+                            local_name: Some(local),
+                            loc,
+                        },
+                    });
+                }
+            }
+            Some(TerminalId::Default) => {
+                let (local, loc) = declared
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| (DEFAULT_LOCAL_NAME.to_string(), export));
+                entries.push(ExportEntry {
+                    export_name: Some("default".to_string()),
+                    request: None,
+                    import_name: None,
+                    local_name: Some(local),
+                    loc,
+                });
+            }
+            _ => {
+                for (name, loc) in declared {
+                    entries.push(ExportEntry {
+                        export_name: Some(name.clone()),
+                        request: None,
+                        import_name: None,
+                        local_name: Some(name),
+                        loc,
+                    });
+                }
+            }
+        }
+        self.names.clear();
+        let mut duplicates = vec![];
+        for entry in entries {
+            if let Some(name) = &entry.export_name {
+                if self.summary.exported_names().any(|n| n == name) {
+                    duplicates.push((name.clone(), entry.loc));
+                }
+            }
+            self.summary.exports.push(entry);
+        }
+        duplicates
+    }
+
+    /// The local names of the module's local exports, and where they're
+    /// exported.
+    pub fn local_exports(&self) -> impl Iterator<Item = (&str, SourceLocation)> {
+        self.summary
+            .exports
+            .iter()
+            .filter(|e| e.request.is_none())
+            .filter_map(|e| e.local_name.as_deref().map(|name| (name, e.loc)))
+    }
+
+    pub fn finish(self) -> ModuleSummary {
+        self.summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImportName, DEFAULT_LOCAL_NAME};
+    use crate::ast_arena::Arena;
+    use crate::early_errors::EarlyErrorKind;
+    use crate::parser::Parser;
+    use crate::parser_generated::DefaultHandler;
+    use crate::testing::early_error;
+
+    #[test]
+    fn modules() {
+        use EarlyErrorKind::*;
+        let src = "import a, {b as c, default as d} from './x.js';
+            import * as ns from './y.js';
+            import './x.js';
+            export {a as e, c};
+            export {f as g} from './z.js';
+            export * from './y.js';
+            export var h = 1, [i] = j;
+            export function k() { var l; }
+            export default class {}";
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_module(&handler);
+        parser.parse_script(src).ok().unwrap();
+        let summary = parser.take_module_summary().unwrap();
+        assert_eq!(summary.specifiers().collect::<Vec<_>>(), ["./x.js", "./y.js", "./z.js"]);
+        let imports: Vec<_> = summary
+            .imports
+            .iter()
+            .map(|e| (e.request, e.import_name.clone(), e.local_name.as_str()))
+            .collect();
+        assert_eq!(
+            imports,
+            [
+                (0, ImportName::Name("default".to_string()), "a"),
+                (0, ImportName::Name("b".to_string()), "c"),
+                (0, ImportName::Name("default".to_string()), "d"),
+                (1, ImportName::Namespace, "ns"),
+            ]
+        );
+        let exports: Vec<_> = summary
+            .exports
+            .iter()
+            .map(|e| (e.export_name.as_deref(), e.request, e.import_name.clone(), e.local_name.as_deref()))
+            .collect();
+        assert_eq!(
+            exports,
+            [
+                (Some("e"), None, None, Some("a")),
+                (Some("c"), None, None, Some("c")),
+                (Some("g"), Some(2), Some(ImportName::Name("f".to_string())), None),
+                (None, Some(1), Some(ImportName::AllButDefault), None),
+                (Some("h"), None, None, Some("h")),
+                (Some("i"), None, None, Some("i")),
+                (Some("k"), None, None, Some("k")),
+                (Some("default"), None, None, Some(DEFAULT_LOCAL_NAME)),
+            ]
+        );
+
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("export {x}; let x;", None),
+            ("export {x as default}; function x() {}", None),
+            ("export {x};", Some(UndeclaredExport)),
+            ("export {x} from 'a';", None),
+            ("function f() { var x; } export {x};", Some(UndeclaredExport)),
+            ("export var x; export {x};", Some(DuplicateExport)),
+            ("export default 1; export default function () {}", Some(DuplicateExport)),
+            ("export {x as y, x as y} from 'a';", Some(DuplicateExport)),
+            ("import {x} from 'a'; import {x} from 'b';", Some(Redeclaration)),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, true), expected, "{}", src);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Parsing a script's function bodies on several threads at once.
+//!
+//! A lazy parse of the script checks every function body but builds only the
+//! tree around them. Then the bodies it skipped are parsed in full on worker
+//! threads, each into an arena of its own, and put back in the tree in place
+//! of the lazy ones. Most of a big script, like a bundle, is in function
+//! bodies, so most of the building is spread out; the checking isn't, so
+//! this only wins with enough threads to make up for doing it twice.
+
+use crate::ast_arena::{Arena, ArenaRef};
+use crate::parser::{LazyFunction, ParseError, Parser, Result};
+use crate::parser_generated::{concrete, walk_function_body, walk_function_body_mut, DefaultHandler, Visit, VisitMut};
+use std::cmp::Reverse;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Parse `src` as a script, with the function bodies parsed on a thread for
+/// each arena in `workers`, which the bodies are allocated in. The tree is
+/// the one Parser::parse_script() builds, apart from which arenas its
+/// nodes are in.
+pub fn parse_script_parallel<'a>(
+    handler: &'a DefaultHandler<'a>,
+    workers: &'a mut [Arena],
+    src: &'a str,
+) -> Result<ArenaRef<'a, concrete::Script<'a>>> {
+    let mut parser = Parser::new_script(handler);
+    parser.set_lazy(true);
+    let mut script = parser.parse_script(src)?.into_script();
+    let lazy = lazy_functions(&script);
+    let bodies = parse_lazy_functions(src, &lazy, workers)?;
+    Stitch {
+        arena: handler.arena(),
+        bodies,
+    }
+    .visit_script_mut(&mut script);
+    Ok(script)
+}
+
+/// The function bodies in `script` that a lazy parse skipped, in source order.
+pub fn lazy_functions(script: &concrete::Script) -> Vec<LazyFunction> {
+    let mut finder = LazyFunctions(vec![]);
+    finder.visit_script(script);
+    finder.0
+}
+
+struct LazyFunctions(Vec<LazyFunction>);
+
+impl<'alloc> Visit<'alloc> for LazyFunctions {
+    fn visit_function_body(&mut self, node: &'alloc concrete::FunctionBody<'alloc>) {
+        match node {
+            concrete::FunctionBody::Lazy(lazy) => self.0.push(*lazy),
+            _ => walk_function_body(self, node),
+        }
+    }
+}
+
+/// Fully parse the function bodies `lazy`, which a lazy parse of the script
+/// `src` skipped, on a thread for each arena in `workers`. Return each
+/// body's start and node, in source order, or the first error, though the
+/// lazy parse has already checked them.
+pub fn parse_lazy_functions<'a>(
+    src: &'a str,
+    lazy: &[LazyFunction],
+    workers: &'a mut [Arena],
+) -> Result<Vec<(usize, ArenaRef<'a, concrete::FunctionBody<'a>>)>> {
+    if workers.is_empty() && !lazy.is_empty() {
+        return Err(ParseError::InternalError("no arenas to parse function bodies in"));
+    }
+    // The threads take the biggest bodies first, so that none is left to
+    // parse on its own at the end.
+    let mut queue: Vec<&LazyFunction> = lazy.iter().collect();
+    queue.sort_by_key(|lazy| Reverse(lazy.loc.end - lazy.loc.start));
+    let next = AtomicUsize::new(0);
+    let workers = IntoIterator::into_iter(workers).take(queue.len());
+    let mut bodies = thread::scope(|scope| {
+        let threads: Vec<_> = workers
+            .map(|arena| {
+                let (queue, next) = (&queue, &next);
+                scope.spawn(move || {
+                    let arena: &'a Arena = arena;
+                    let handler = DefaultHandler::new(arena);
+                    let mut parser = Parser::new_script(&handler);
+                    let mut bodies = vec![];
+                    while let Some(lazy) = queue.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let body = parser.parse_lazy_function(src, lazy).map(|body| body.into_function_body());
+                        bodies.push((lazy.loc.start, body));
+                    }
+                    bodies
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            .collect::<Vec<_>>()
+    });
+    bodies.sort_by_key(|&(start, _)| start);
+    bodies.into_iter().map(|(start, body)| body.map(|body| (start, body))).collect()
+}
+
+/// Puts fully parsed bodies in place of the lazy ones that start where they
+/// do.
+struct Stitch<'alloc> {
+    arena: &'alloc Arena,
+    bodies: Vec<(usize, ArenaRef<'alloc, concrete::FunctionBody<'alloc>>)>,
+}
+
+impl<'alloc> VisitMut<'alloc> for Stitch<'alloc> {
+    fn arena(&self) -> &'alloc Arena {
+        self.arena
+    }
+
+    fn visit_function_body_mut(&mut self, node: &mut ArenaRef<'alloc, concrete::FunctionBody<'alloc>>) {
+        match **node {
+            concrete::FunctionBody::Lazy(lazy) => {
+                if let Ok(i) = self.bodies.binary_search_by_key(&lazy.loc.start, |&(start, _)| start) {
+                    *node = self.bodies[i].1;
+                }
+            }
+            _ => walk_function_body_mut(self, node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::DefaultHandler;
+
+    #[test]
+    fn parallel_function_bodies() {
+        use super::{lazy_functions, parse_script_parallel};
+        let src = "function f(a) { return function () { return a; }; }\n\
+                   var g = function* () { yield [1, 2]; };\n\
+                   class C { m() { if (x) { return `${ () => 1 }`; } } static n() {} }\n\
+                   h(function () { var i; });";
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let full = Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script();
+
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        assert_eq!(lazy_functions(&parser.parse_script(src).ok().unwrap().into_script()).len(), 4);
+
+        // With fewer threads than bodies, and more.
+        for &threads in &[1, 2, 8] {
+            let mut workers: Vec<Arena> = (0..threads).map(|_| Arena::new()).collect();
+            let script = parse_script_parallel(&handler, &mut workers, src).ok().unwrap();
+            assert_eq!(format!("{:?}", script), format!("{:?}", full));
+            assert!(lazy_functions(&script).is_empty());
+        }
+    }
+
+    #[test]
+    fn no_workers() {
+        use super::{lazy_functions, parse_lazy_functions, parse_script_parallel};
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let src = "function f() { return 1; }";
+        let result = parse_script_parallel(&handler, &mut [], src);
+        assert_eq!(
+            result.err().map(|error| error.message()).as_deref(),
+            Some("internal error: no arenas to parse function bodies in")
+        );
+
+        // With no bodies to parse, no arenas are needed.
+        let script = parse_script_parallel(&handler, &mut [], "x = 1;").ok().unwrap();
+        assert!(lazy_functions(&script).is_empty());
+        assert!(parse_lazy_functions(src, &[], &mut []).ok().unwrap().is_empty());
+
+        // A syntax error in a body is reported by the lazy parse, before
+        // any thread is started.
+        let mut workers = [Arena::new()];
+        let result = parse_script_parallel(&handler, &mut workers, "function f() { return (1; }");
+        assert!(result.err().unwrap().message().ends_with("found `;` at line 1, column 24"));
+    }
+}
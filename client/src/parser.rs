@@ -1,11 +1,24 @@
-pub use crate::parser_generated::{Handler, NonterminalId, TerminalId, Token};
-use crate::parser_runtime::ParserTables;
-
-const ACCEPT: i64 = -0x7fff_ffff_ffff_ffff;
-const ERROR: i64 = ACCEPT - 1;
+pub use crate::parser_generated::{Handler, NonterminalId, TerminalId};
+use crate::checkpoint::{Checkpoint, CheckpointLog, TextEdit, CHECKPOINT_INTERVAL};
+use crate::coverage::Coverage;
+use crate::diagnostics::Diagnostics;
+use crate::early_errors::{EarlyErrorKind, EarlyErrors, FunctionKind};
+use crate::glr::{advance_all, Branch};
+use crate::keywords_generated::{lookup_keyword, CONTEXTUAL};
+use crate::lexer::{LexError, Lexer, Trivia};
+use crate::modules::ModuleSummary;
+use crate::parser_generated::{reduce, SyntaxOnlyHandler};
+use crate::parser_runtime::{ParserTables, Reducer, SourceLocation, Token, TokenStream, ACCEPT, ERROR};
+use crate::scope::{ScopeLog, ScopeTree};
+use crate::stack::{Stack, States};
+use crate::trace::TraceEvent;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::Rc;
 
 #[derive(Clone, Copy)]
-struct Action(i64);
+struct Action(i16);
 
 impl Action {
     fn is_shift(self) -> bool {
@@ -35,166 +48,2210 @@ impl Action {
     }
 }
 
+#[derive(Clone)]
 pub enum ParseError {
-    SyntaxError,
-    UnexpectedEnd
+    /// `token` can't appear here. `expected` lists the terminals that could.
+    SyntaxError {
+        token: Token<'static>,
+        expected: Vec<TerminalId>,
+    },
+    UnexpectedEnd {
+        loc: SourceLocation,
+        expected: Vec<TerminalId>,
+    },
+    LexError(LexError),
+    /// The input matches the grammar, but breaks one of the extra rules,
+    /// like the ones for strict mode code. `name` is the name the error is
+    /// about, for kinds that have one, and `first`, for a name declared
+    /// twice, is where it was declared first.
+    EarlyError {
+        kind: EarlyErrorKind,
+        name: Option<String>,
+        loc: SourceLocation,
+        first: Option<SourceLocation>,
+    },
+    /// The parser tables or the reduce callback are inconsistent with the
+    /// parser's stacks, or the parser was driven incorrectly. This is a bug,
+    /// not a problem with the input.
+    InternalError(&'static str),
 }
 
 impl ParseError {
     pub fn message(&self) -> String {
-        match *self {
-            ParseError::SyntaxError => format!("syntax error, lol"),
-            ParseError::UnexpectedEnd => format!("unexpected end of input"),
+        match self {
+            ParseError::SyntaxError { token, expected } => format!(
+                "{} but found {} at {}",
+                describe_expected(expected),
+                token.get_id(),
+                describe_location(&token.loc)
+            ),
+            ParseError::UnexpectedEnd { loc, expected } => format!(
+                "unexpected end of input at {} ({})",
+                describe_location(loc),
+                describe_expected(expected)
+            ),
+            ParseError::LexError(LexError { message, loc }) => {
+                format!("{} at {}", message, describe_location(loc))
+            }
+            ParseError::EarlyError {
+                kind,
+                name: Some(name),
+                loc,
+                ..
+            } => format!("{} `{}` at {}", kind, name, describe_location(loc)),
+            ParseError::EarlyError {
+                kind,
+                name: None,
+                loc,
+                ..
+            } => format!("{} at {}", kind, describe_location(loc)),
+            ParseError::InternalError(msg) => format!("internal error: {}", msg),
         }
     }
+
+    /// Where in the source the error is, if anywhere.
+    pub fn loc(&self) -> Option<SourceLocation> {
+        match self {
+            ParseError::SyntaxError { token, .. } => Some(token.loc),
+            ParseError::UnexpectedEnd { loc, .. }
+            | ParseError::LexError(LexError { loc, .. })
+            | ParseError::EarlyError { loc, .. } => Some(*loc),
+            ParseError::InternalError(_) => None,
+        }
+    }
+}
+
+pub(crate) fn describe_location(loc: &SourceLocation) -> String {
+    format!("line {}, column {}", loc.line, loc.column)
+}
+
+pub(crate) fn describe_expected(expected: &[TerminalId]) -> String {
+    let names: Vec<String> = expected.iter().map(|t| t.to_string()).collect();
+    match names.len() {
+        0 => "expected nothing".to_string(),
+        1 => format!("expected {}", names[0]),
+        _ => format!("expected one of {}", names.join(", ")),
+    }
 }
 
 pub type Result<T> = std::result::Result<T, ParseError>;
 
-pub type Node = *mut ();
+/// In recovery mode, the nonterminals that a syntax error can be turned into,
+/// in order of preference.
+const RECOVERY_NONTERMINALS: [NonterminalId; 2] =
+    [NonterminalId::Statement, NonterminalId::ClassElement];
 
-pub struct Parser<'a, Out, Reduce>
+/// How far recovery mode goes to get past syntax errors, for
+/// Parser::set_recovery_policy(). The default goes as far as it can, which
+/// suits a compiler; an editor may rather give up sooner, or skip ahead to
+/// somewhere it's likely to get back on track.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryPolicy {
+    /// Fail with the error, rather than recover from it, after this many
+    /// recoveries in a row, with no token shifted in between.
+    pub max_consecutive: usize,
+    /// When the parser can only get past an error by discarding the token,
+    /// discard the tokens after it too, up to one of these, and go on from
+    /// there. If this is empty, each token after the error is tried.
+    pub sync_terminals: Vec<TerminalId>,
+    /// If false, fail where recovery would discard a token, so that every
+    /// token of a parse that succeeds is in the tree.
+    pub skip_tokens: bool,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> RecoveryPolicy {
+        RecoveryPolicy {
+            max_consecutive: usize::MAX,
+            sync_terminals: vec![],
+            skip_tokens: true,
+        }
+    }
+}
+
+/// Turns a token from a lexer whose source lives for `'s` into one that a
+/// parser whose tokens live for `'a` can keep.
+type Own<'s, 'a> = fn(Token<'s>) -> Token<'a>;
+
+/// A function body that a lazy parse checked for syntax errors but didn't
+/// build. `loc` spans the text between the braces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LazyFunction {
+    pub loc: SourceLocation,
+    /// The parser state just after the body's `{`.
+    state: usize,
+    /// True if the function is in strict mode code, not counting any
+    /// "use strict" directive in the body.
+    strict: bool,
+}
+
+/// Every value the parser holds is owned: dropping a Parser, whether after an
+/// error or partway through the input, drops the tokens and nodes on its
+/// stacks along with it.
+pub struct Parser<'a, 'h, Out, Reduce>
 where
-    Out: Handler,
-    Reduce: Fn(&Out, usize, &mut Vec<Node>) -> NonterminalId,
+    Out: Handler<'a>,
+    Reduce: Reducer<'a, Out>,
 {
     tables: &'a ParserTables<'a>,
-    state_stack: Vec<usize>,
-    node_stack: Vec<Node>,
+    stack: Stack<Out::Value>,
     reduce: Reduce,
-    handler: &'a Out,
+    /// The handler only has to outlive the parser, not the nodes it makes,
+    /// so a thread can parse with a handler of its own into a shared arena.
+    handler: &'h Out,
+    /// Set by parse_script_incremental(), for use by reparse().
+    log: Option<CheckpointLog<'a, Out::Value>>,
+    /// If true, syntax errors are recorded in `errors` and parsing continues.
+    recover: bool,
+    /// True if an error has been recorded since the last token was shifted.
+    /// Errors are not recorded again until the parser gets back on track.
+    recovering: bool,
+    errors: Vec<ParseError>,
+    recovery_policy: RecoveryPolicy,
+    /// How many times the parser has recovered since the last token was
+    /// shifted.
+    recoveries: usize,
+    /// True while tokens are being discarded up to one of the policy's
+    /// sync_terminals.
+    skipping: bool,
+    /// If true, function bodies are only checked for syntax errors, and the
+    /// handler's lazy_function_body() stands in for each one.
+    lazy: bool,
+    early: EarlyErrors,
+    /// If true, conflicts the generator resolved are parsed both ways, as
+    /// described in the `glr` module.
+    glr: bool,
+    /// While a GLR parse is undecided, the live branches and the tokens
+    /// written since it forked.
+    branches: Vec<Branch>,
+    pending: Vec<Token<'a>>,
+    /// While replaying `pending`, the choices still to be made at conflicts.
+    replay: VecDeque<usize>,
+    /// The whitespace and comments parse_script() found, if
+    /// set_record_trivia() turned that on.
+    trivia: Option<Vec<Trivia>>,
+    /// The productions reduced so far, if set_record_coverage() turned
+    /// that on.
+    coverage: Option<Coverage>,
+    /// The shifts, reduces and gotos so far, if set_trace() turned that on.
+    trace: Option<Vec<TraceEvent<'a>>>,
 }
 
-impl<'a, Out, Reduce> Parser<'a, Out, Reduce>
+impl<'a, 'h, Out, Reduce> Parser<'a, 'h, Out, Reduce>
 where
-    Out: Handler,
-    Reduce: Fn(&Out, usize, &mut Vec<Node>) -> NonterminalId,
+    Out: Handler<'a>,
+    Reduce: Reducer<'a, Out>,
 {
     pub fn new(
         tables: &'a ParserTables<'a>,
         reduce: Reduce,
-        handler: &'a Out,
+        handler: &'h Out,
         entry_state: usize,
-    ) -> Parser<'a, Out, Reduce> {
+        recover: bool,
+    ) -> Parser<'a, 'h, Out, Reduce> {
         tables.check();
         assert!(entry_state < tables.state_count);
 
         Parser {
             tables,
-            state_stack: vec![entry_state],
-            node_stack: vec![],
+            stack: Stack::new(entry_state),
             reduce,
             handler,
+            log: None,
+            recover,
+            recovering: false,
+            errors: vec![],
+            recovery_policy: RecoveryPolicy::default(),
+            recoveries: 0,
+            skipping: false,
+            lazy: false,
+            early: EarlyErrors::new(false),
+            glr: false,
+            branches: vec![],
+            pending: vec![],
+            replay: VecDeque::new(),
+            trivia: None,
+            coverage: None,
+            trace: None,
+        }
+    }
+
+    /// Treat the input as strict mode code from the start, as for a module.
+    /// Call this before writing any tokens.
+    pub fn set_strict(&mut self, strict: bool) {
+        let scopes = self.early.is_recording_scopes();
+        let module = self.early.is_module();
+        self.early = EarlyErrors::new(strict);
+        if scopes {
+            self.early.record_scopes();
+        }
+        if module {
+            self.early.record_module();
+        }
+    }
+
+    /// Treat the input as a module: strict mode code, where `import.meta`
+    /// is allowed, and whose imports and exports take_module_summary()
+    /// returns. Call this before writing any tokens.
+    pub fn set_module(&mut self) {
+        self.set_strict(true);
+        self.early.record_module();
+    }
+
+    /// Record what scope analysis needs while parsing, for take_scopes().
+    /// Call this before writing any tokens.
+    pub fn set_analyze_scopes(&mut self, analyze: bool) {
+        if analyze {
+            self.early.record_scopes();
+        } else {
+            self.early.take_scopes();
         }
     }
 
+    /// After a successful parse with scope analysis turned on, return the
+    /// scopes of the input.
+    pub fn take_scopes(&mut self) -> Option<ScopeTree> {
+        self.early.take_scopes().map(ScopeLog::finish)
+    }
+
+    /// After a successful parse of a module, return what it imports and
+    /// exports.
+    pub fn take_module_summary(&mut self) -> Option<ModuleSummary> {
+        self.early.take_module()
+    }
+
+    /// Record the whitespace and comments between tokens in the source that
+    /// parse_script() parses, for take_trivia().
+    pub fn set_record_trivia(&mut self, record: bool) {
+        self.trivia = if record { Some(vec![]) } else { None };
+    }
+
+    /// After a successful parse_script() with trivia recording turned on,
+    /// return the whitespace and comments, in source order.
+    pub fn take_trivia(&mut self) -> Option<Vec<Trivia>> {
+        self.trivia.take()
+    }
+
+    /// Count the productions reduced while parsing, for take_coverage().
+    pub fn set_record_coverage(&mut self, record: bool) {
+        self.coverage = if record { Some(Coverage::new()) } else { None };
+    }
+
+    /// Return the counts of the productions reduced so far, if coverage
+    /// recording is turned on, and start counting again from zero.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.as_mut().map(mem::take)
+    }
+
+    /// Log each shift, reduce and goto, for take_trace().
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = if trace { Some(vec![]) } else { None };
+    }
+
+    /// Return the events logged since the last call, if tracing is turned
+    /// on, in the order they happened. Function bodies that a lazy parse
+    /// skips are traced too.
+    pub fn take_trace(&mut self) -> Option<Vec<TraceEvent<'a>>> {
+        self.trace.as_mut().map(mem::take)
+    }
+
+    fn trace(&mut self, event: TraceEvent<'a>) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(event);
+        }
+    }
+
+    pub fn set_lazy(&mut self, lazy: bool) {
+        self.lazy = lazy;
+    }
+
+    /// Parse the alternatives the tables keep at conflicts, and go with
+    /// whichever parse doesn't run into a syntax error. Call this before
+    /// writing any tokens. Function bodies that a lazy parse skips are
+    /// checked without it.
+    pub fn set_glr(&mut self, glr: bool) {
+        self.glr = glr;
+    }
+
+    /// True if a GLR parse has forked and tokens are waiting to be replayed.
+    pub(crate) fn undecided(&self) -> bool {
+        !self.branches.is_empty()
+    }
+
+    /// Set how far recovery mode goes to get past syntax errors. This does
+    /// nothing unless the parser was made with recovery on.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.recovery_policy = policy;
+    }
+
+    /// The syntax errors and malformed tokens that recovery mode has
+    /// recovered from so far.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
+    /// The errors that recovery mode has recovered from so far, as
+    /// diagnostics. Report the error that stopped the parse, if any, to
+    /// the result to have everything the parse found.
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics::from_errors(&self.errors)
+    }
+
     fn state(&self) -> usize {
-        *self.state_stack.last().unwrap()
+        self.stack.state()
     }
 
     fn action(&self, t: TerminalId) -> Action {
         let t = t as usize;
         debug_assert!(t < self.tables.action_width);
-        Action(self.tables.action_table[
-            self.state() * self.tables.action_width + t
-        ])
+        Action(self.tables.action(self.state(), t))
     }
 
-    fn reduce_all(&mut self, t: TerminalId) -> Action {
+    /// Like self.action(), but while replaying GLR choices, make the next
+    /// one if `t` is at a conflict. Error handling doesn't fork, so
+    /// ErrorToken never is.
+    fn replay_action(&mut self, t: TerminalId) -> Action {
+        if self.replay.is_empty()
+            || t == TerminalId::ErrorToken
+            || !self.tables.has_alternatives(self.state(), t as usize)
+        {
+            return self.action(t);
+        }
+        match self.replay.pop_front() {
+            Some(choice) if choice > 0 => match self.tables.alternatives(self.state(), t as usize).nth(choice - 1) {
+                Some(action) => Action(action),
+                None => self.action(t),
+            },
+            _ => self.action(t),
+        }
+    }
+
+    /// Perform all reductions triggered by the lookahead terminal `t`, whose
+    /// location is `lookahead`.
+    fn reduce_all(&mut self, t: TerminalId, lookahead: &SourceLocation) -> Result<Action> {
+        self.reduce_until(t, lookahead, None)
+    }
+
+    /// Like reduce_all, but stop early if the states on the stack become
+    /// `stop`.
+    fn reduce_until(&mut self, t: TerminalId, lookahead: &SourceLocation, stop: Option<&[usize]>) -> Result<Action> {
         let tables = self.tables;
-        let mut action = self.action(t);
-        while action.is_reduce() {
+        let mut action = self.replay_action(t);
+        while action.is_reduce() && !stop.is_some_and(|stop| self.stack.has_states(stop)) {
             let prod_index = action.reduce_prod_index();
-            let nt = (self.reduce)(self.handler, prod_index, &mut self.node_stack);
-            debug_assert!((nt as usize) < tables.goto_width);
-            debug_assert!(self.state_stack.len() >= self.node_stack.len());
-            self.state_stack.truncate(self.node_stack.len());
-            let prev_state = *self.state_stack.last().unwrap();
+            let (pop_count, _) = *tables
+                .reduce_simulator
+                .get(prod_index)
+                .ok_or(ParseError::InternalError("reduce action names an unknown production"))?;
+            if pop_count > self.stack.len() {
+                return Err(ParseError::InternalError("production is longer than the stack"));
+            }
+            let base = self.stack.len() - pop_count;
+            let loc = match (self.stack.loc(base), self.stack.last_loc()) {
+                (Some(first), Some(last)) => first.to(&last),
+                // Empty production: it sits right before the lookahead.
+                _ => lookahead.start_point(),
+            };
+            if self.trace.is_some() {
+                let production = tables.production_names.get(prod_index).copied().unwrap_or("");
+                self.trace(TraceEvent::Reduce {
+                    state: self.state(),
+                    prod_index,
+                    production,
+                });
+            }
+            let prev_state = self.stack.state_at(base).unwrap();
+            let nt = self.reduce.reduce(self.handler, prod_index, loc, &mut self.stack.reducing(prev_state, loc));
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(prod_index);
+            }
+            if nt as usize >= tables.goto_width {
+                return Err(ParseError::InternalError("reduce returned an unknown nonterminal"));
+            }
+            if self.stack.len() != base + 1 {
+                return Err(ParseError::InternalError("reduce left the stack at the wrong depth"));
+            }
             let state_after = tables.goto_table[prev_state * tables.goto_width + nt as usize];
-            debug_assert!(state_after < tables.state_count);
-            self.state_stack.push(state_after);
-            action = self.action(t);
+            if state_after >= tables.state_count {
+                return Err(ParseError::InternalError("goto table names an unknown state"));
+            }
+            self.stack.set_state(state_after);
+            self.trace(TraceEvent::Goto {
+                state: prev_state,
+                nonterminal: nt,
+                next: state_after,
+            });
+            self.early.reduced(nt, loc, &self.stack);
+            action = self.replay_action(t);
         }
+        Ok(action)
+    }
 
-        debug_assert_eq!(self.state_stack.len(), self.node_stack.len() + 1);
-        action
+    pub fn write_token(&mut self, token: Token<'a>) -> Result<()> {
+        let token = self.contextual_keyword(token);
+        if self.skipping {
+            if !self.recovery_policy.sync_terminals.contains(&token.get_id()) {
+                return Ok(());
+            }
+            self.skipping = false;
+        }
+        if self.glr {
+            self.write_token_glr(token)
+        } else {
+            self.write_token_lr(token)
+        }
+    }
+
+    /// Write `token` to each live GLR branch, forking at conflicts, and
+    /// commit to a branch as soon as the others have failed.
+    fn write_token_glr(&mut self, token: Token<'a>) -> Result<()> {
+        let t = token.get_id();
+        if !self.undecided() && !self.simulator().meets_conflict(t) {
+            return self.write_token_lr(token);
+        }
+        let branches = if self.undecided() {
+            mem::take(&mut self.branches)
+        } else {
+            vec![Branch::new(self.stack.states())]
+        };
+        let next = advance_all(self.tables, &branches, t);
+        if self.pending.is_empty() && next.iter().all(|b| b.choices.is_empty()) {
+            // The conflict was only on the way to an error.
+            return self.write_token_lr(token);
+        }
+        match next.len() {
+            0 => {
+                // Every branch fails here. Go with the best one so far, and
+                // let the LR parser report the error.
+                self.commit(&branches[0].choices)?;
+                self.write_token_lr(token)
+            }
+            1 => {
+                self.pending.push(token);
+                self.commit(&next[0].choices)
+            }
+            _ => {
+                self.pending.push(token);
+                self.branches = next;
+                Ok(())
+            }
+        }
+    }
+
+    /// Replay the tokens written since the GLR parse forked, making
+    /// `choices` at the conflicts along the way.
+    fn commit(&mut self, choices: &[usize]) -> Result<()> {
+        self.branches.clear();
+        self.replay = choices.iter().copied().collect();
+        for token in mem::take(&mut self.pending) {
+            self.write_token_lr(token)?;
+        }
+        Ok(())
     }
 
-    pub fn write_token(&mut self, token: Token) -> Result<()> {
+    fn write_token_lr(&mut self, token: Token<'a>) -> Result<()> {
         // Loop for error-handling. The normal path through this code reaches
         // the `return` statement.
+        let t = token.get_id();
+        let mut asi = false;
         loop {
-            let t = token.get_id();
-            let action = self.reduce_all(t);
+            let action = self.reduce_all(t, &token.loc)?;
             if action.is_shift() {
-                self.node_stack.push(
-                    Box::into_raw(Box::new(token)) as *mut _
-                );
-                self.state_stack.push(action.shift_state());
-                return Ok(());
+                self.trace(TraceEvent::Shift {
+                    state: self.state(),
+                    terminal: t,
+                    next: action.shift_state(),
+                });
+                self.early.shift(&token, &self.stack, asi);
+                let loc = token.loc;
+                self.stack.push(Out::Value::from(token), loc, action.shift_state());
+                self.recovering = false;
+                self.recoveries = 0;
+                self.enter_frames(t);
+                return self.report_early_errors();
+            } else if action.is_error() {
+                match self.try_error_handling(&token) {
+                    Ok(()) => asi = true,
+                    Err(error) => {
+                        if !self.recover_from(&token, error)? {
+                            return Ok(());
+                        }
+                    }
+                }
             } else {
-                assert!(action.is_error());
-                self.try_error_handling(t)?;
+                return Err(ParseError::InternalError("accept action before end of input"));
             }
         }
     }
 
-    pub fn close(&mut self) -> Result<Node> {
+    /// The lexer always returns `yield` and `await` as keywords. Turn
+    /// `token` into an identifier if it's one of them and the function it's
+    /// in isn't a generator or async function, respectively. In module code,
+    /// `await` is always a keyword.
+    fn contextual_keyword(&self, token: Token<'a>) -> Token<'a> {
+        let (name, keyword): (&str, fn(FunctionKind) -> bool) = match token.get_id() {
+            TerminalId::Yield => ("yield", |kind| kind.generator),
+            TerminalId::Await if self.early.is_module() => return token,
+            TerminalId::Await => ("await", |kind| kind.is_async),
+            _ => return token,
+        };
+        // The reductions before the token may end the function it seems to
+        // be in, as when it follows a function declaration.
+        let mut sim = self.simulator();
+        sim.reduce_all(TerminalId::Identifier);
+        let mut stack: Vec<usize> = (0..sim.sp).filter_map(|depth| sim.stack.state_at(depth)).collect();
+        stack.extend_from_slice(&sim.sim_state_stack);
+        if keyword(self.early.function_kind(&stack)) {
+            return token;
+        }
+        Token {
+            terminal_id: TerminalId::Identifier,
+            value: Some(Cow::Borrowed(name)),
+            ..token
+        }
+    }
+
+    /// The kind of function whose body starts in `state`, the state after
+    /// its `{`.
+    fn body_kind(&self, state: usize) -> FunctionKind {
+        let async_generator = self.goto(state, NonterminalId::AsyncGeneratorBody) != 0;
+        FunctionKind {
+            generator: async_generator || self.goto(state, NonterminalId::GeneratorBody) != 0,
+            is_async: async_generator || self.goto(state, NonterminalId::AsyncFunctionBody) != 0,
+        }
+    }
+
+    /// The kind of function whose parameter list starts in `state`, the
+    /// state after its `(`. Look ahead to its body to find out.
+    fn params_kind(&self, state: usize) -> FunctionKind {
+        let params = match self.goto(state, NonterminalId::UniqueFormalParameters) {
+            0 => self.goto(state, NonterminalId::FormalParameters),
+            params => params,
+        };
+        let close = Action(self.tables.action(params, TerminalId::RightParenthesis as usize));
+        if !close.is_shift() {
+            return FunctionKind::default();
+        }
+        let open = Action(self.tables.action(close.shift_state(), TerminalId::LeftCurlyBracket as usize));
+        if !open.is_shift() {
+            return FunctionKind::default();
+        }
+        self.body_kind(open.shift_state())
+    }
+
+    /// Tell self.early about the construct, if any, that starts with the
+    /// terminal `t` that was just shifted.
+    fn enter_frames(&mut self, t: TerminalId) {
+        let state = self.state();
+        match t {
+            TerminalId::LeftCurlyBracket if self.goto(state, NonterminalId::FunctionBody) != 0 => {
+                let kind = self.body_kind(state);
+                self.early.enter_function(&self.stack, kind)
+            }
+            TerminalId::LeftCurlyBracket
+                if self.goto(state, NonterminalId::StatementList) != 0
+                    || self.goto(state, NonterminalId::CaseClauses) != 0 =>
+            {
+                self.early.enter_block(&self.stack, false)
+            }
+            TerminalId::Class => self.early.enter_class(&self.stack),
+            TerminalId::LeftParenthesis
+                if self.goto(state, NonterminalId::FormalParameters) != 0
+                    || self.goto(state, NonterminalId::UniqueFormalParameters) != 0
+                    || self.goto(state, NonterminalId::PropertySetParameterList) != 0 =>
+            {
+                let kind = self.params_kind(state);
+                self.early.enter_params(&self.stack, kind)
+            }
+            TerminalId::LeftParenthesis
+                if self.goto(state, NonterminalId::ForDeclaration) != 0
+                    || self.goto(state, NonterminalId::LexicalDeclaration) != 0 =>
+            {
+                self.early.enter_block(&self.stack, false)
+            }
+            TerminalId::LeftParenthesis if self.goto(state, NonterminalId::CatchParameter) != 0 => {
+                self.early.enter_block(&self.stack, true)
+            }
+            TerminalId::Colon => self.early.enter_label(&self.stack),
+            TerminalId::Arrow => {
+                let kind = FunctionKind {
+                    generator: false,
+                    is_async: self.goto(state, NonterminalId::AsyncConciseBody) != 0,
+                };
+                self.early.enter_arrow_body(&self.stack, kind)
+            }
+            _ => {}
+        }
+    }
+
+    /// Move the early errors found so far into self.errors, or outside of
+    /// recovery mode, return the first one.
+    fn report_early_errors(&mut self) -> Result<()> {
+        for (kind, name, loc, first) in self.early.take_errors() {
+            let error = ParseError::EarlyError { kind, name, loc, first };
+            if !self.recover {
+                return Err(error);
+            }
+            self.errors.push(error);
+        }
+        Ok(())
+    }
+
+    /// Write each token produced by `tokens`, stopping at the first error.
+    /// The iterator should not produce the End token; call self.close() for
+    /// that.
+    pub fn extend_from_tokens<I>(&mut self, tokens: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Token<'a>>,
+    {
+        for token in tokens {
+            self.write_token(token)?;
+        }
+        Ok(())
+    }
+
+    /// Lex and parse all of `src`, then close the parser.
+    pub fn parse_script(&mut self, src: &'a str) -> Result<Out::Value> {
+        let end = self.write_source(src)?;
+        self.close(end)
+    }
+
+    /// Lex all of `src` and write its tokens to the parser, but don't close
+    /// it. Return the location of the end of `src`, to pass to close(). If
+    /// this succeeds but can_close() is false, `src` is the start of a
+    /// program, but not a whole one.
+    pub fn write_source(&mut self, src: &'a str) -> Result<SourceLocation> {
+        let mut lexer = Lexer::new(src);
+        if self.trivia.is_some() {
+            lexer.record_trivia();
+        }
+        if self.recover {
+            lexer.recover_errors();
+        }
+        while lexer.peek().get_id() != TerminalId::End {
+            let token = self.next_token(&mut lexer)?;
+            self.write_lazily(token, &mut lexer, |token| token)?;
+        }
+        if let Some(error) = lexer.take_error() {
+            return Err(ParseError::LexError(error));
+        }
+        if self.trivia.is_some() {
+            self.trivia = Some(lexer.take_trivia());
+        }
+        Ok(lexer.peek().loc)
+    }
+
+    /// Take the next token from `lexer`. A `/` or `/=` where the parser can
+    /// accept a regular expression is scanned again as one, and so is a `}`
+    /// where the parser can accept the rest of a template. Contextual
+    /// keywords and IdentifierNames are told apart from identifiers here too.
+    /// In recovery mode, the errors the lexer got past are reported.
+    fn next_token<'s>(&mut self, lexer: &mut Lexer<'s>) -> Result<Token<'s>> {
+        let token = TokenStream::take(lexer);
+        let result = match token.get_id() {
+            TerminalId::Solidus | TerminalId::SolidusEqualsSign
+                if self.can_accept_terminal(TerminalId::RegularExpressionLiteral) =>
+            {
+                lexer.rescan_as_regexp(token).map_err(ParseError::LexError)
+            }
+            TerminalId::RightCurlyBracket
+                if self.can_accept_terminal(TerminalId::TemplateTail) =>
+            {
+                lexer.rescan_as_template(token).map_err(ParseError::LexError)
+            }
+            TerminalId::Identifier => {
+                let next = Next::of(lexer.peek());
+                let token = self.contextual_word(token, next);
+                Ok(self.identifier_name(token, next))
+            }
+            _ => Ok(self.identifier_name(token, Next::of(lexer.peek()))),
+        };
+        self.errors.extend(lexer.take_errors().into_iter().map(ParseError::LexError));
+        result
+    }
+
+    /// If `token` is an identifier spelled like a contextual keyword, decide
+    /// which it is here, from what the tables can accept: the keyword if it
+    /// and `next`, the token after it, can come next, or else an identifier
+    /// if that can, or else the keyword if it can come next by itself. So
+    /// `async function` starts an async function but `async = 1` assigns to
+    /// a variable, and in `for (of of x)`, only the second `of` is a keyword.
+    fn contextual_word<'s>(&self, token: Token<'s>, next: Next) -> Token<'s> {
+        let keyword = match contextual_keyword_id(&token) {
+            Some(keyword) => keyword,
+            None => return token,
+        };
+        // No line terminator is allowed between `async` and what it makes
+        // async, so `async` at the end of a line is an identifier.
+        if keyword == TerminalId::Async && next.newline_before {
+            return token;
+        }
+        // In strict mode code, `let` is reserved, so it's always a keyword.
+        let is_keyword = (keyword == TerminalId::Let && self.early.is_strict())
+            || self.can_accept_before(keyword, next)
+            || (!self.can_accept_before(TerminalId::Identifier, next) && self.can_accept_terminal(keyword));
+        if !is_keyword {
+            return token;
+        }
+        Token {
+            terminal_id: keyword,
+            raw: token.value,
+            value: None,
+            ..token
+        }
+    }
+
+    /// Turn `token` into an IdentifierName if it's a word that should be
+    /// read as one here. See wants_identifier_name().
+    fn identifier_name<'s>(&self, token: Token<'s>, next: Next) -> Token<'s> {
+        if word(&token).is_none() || !self.wants_identifier_name(token.get_id(), next) {
+            return token;
+        }
+        // The value is the word, as word() finds it.
+        let (value, raw) = match token.get_id() {
+            // Keep the escapes, if it has any.
+            TerminalId::Identifier => (token.value, token.raw),
+            _ => (token.raw, None),
+        };
+        Token {
+            terminal_id: TerminalId::IdentifierName,
+            value,
+            raw,
+            ..token
+        }
+    }
+
+    /// Return true if the word `t` should be read as an IdentifierName,
+    /// like the `x` in `export {x}`: if IdentifierName is acceptable here
+    /// and `t` isn't, or if `next`, the token after it, can only follow an
+    /// IdentifierName, as in `import {x as y}`. If neither is acceptable,
+    /// the same goes for after an inserted semicolon, as for the field
+    /// `b` in `class C { a = 1 \n b }`.
+    fn wants_identifier_name(&self, t: TerminalId, next: Next) -> bool {
+        if !self.can_accept_terminal(TerminalId::IdentifierName) {
+            return !self.can_accept_terminal(t)
+                && self.can_accept_after_semicolon(TerminalId::IdentifierName)
+                && !self.can_accept_after_semicolon(t);
+        }
+        !self.can_accept_terminal(t)
+            || (!self.can_accept_before(t, next) && self.can_accept_before(TerminalId::IdentifierName, next))
+    }
+
+    /// Write `token`. In lazy mode, if it's the `{` of a function body, go
+    /// on to skip the body and write its `}` as well, turning the tokens
+    /// `lexer` returns into ones the parser can keep with `own`. Empty bodies
+    /// aren't skipped, since there's nothing to skip, and the grammar has
+    /// productions for some functions with them that have no FunctionBody,
+    /// as in `m() {}`.
+    fn write_lazily<'s>(&mut self, token: Token<'a>, lexer: &mut Lexer<'s>, own: Own<'s, 'a>) -> Result<()> {
+        let open_brace = token.get_id() == TerminalId::LeftCurlyBracket;
+        let brace_loc = token.loc;
+        self.write_token(token)?;
+        if self.lazy
+            && open_brace
+            && !self.undecided()
+            && self.goto(self.state(), NonterminalId::FunctionBody) != 0
+            && lexer.peek().get_id() != TerminalId::RightCurlyBracket
+        {
+            self.skip_function_body(brace_loc, lexer, own)?;
+        }
+        Ok(())
+    }
+
+    /// Check the function body after the `{` at `brace_loc` using a parser
+    /// that builds nothing, then push the handler's stand-in for it.
+    fn skip_function_body<'s>(
+        &mut self,
+        brace_loc: SourceLocation,
+        lexer: &mut Lexer<'s>,
+        own: Own<'s, 'a>,
+    ) -> Result<()> {
+        let state = self.state();
+        let strict = self.early.is_strict();
+        let mut body = Parser::new(self.tables, reduce, &SyntaxOnlyHandler, state, self.recover);
+        body.early = self.early.split_off_function();
+        body.recovery_policy = self.recovery_policy.clone();
+        body.coverage = self.coverage.take();
+        body.trace = self.trace.take();
+        let result = body.parse_function_body(lexer, own);
+        self.coverage = body.coverage.take();
+        self.trace = body.trace.take();
+        self.errors.append(&mut body.errors);
+        self.early.join_function(body.early);
+        let (_, close) = result?;
+        let lazy = LazyFunction {
+            loc: SourceLocation::new(brace_loc.end, close.loc.start, brace_loc.line, brace_loc.column + 1),
+            state,
+            strict,
+        };
+        let next = self.goto(state, NonterminalId::FunctionBody);
+        self.stack.push(self.handler.lazy_function_body(lazy), lazy.loc, next);
+        self.trace(TraceEvent::Goto {
+            state,
+            nonterminal: NonterminalId::FunctionBody,
+            next,
+        });
+        self.write_token(close)
+    }
+
+    /// Parse a function body, starting in the state after its `{`. Return
+    /// the body and the `}` that closes it, which is left unwritten.
+    fn parse_function_body<'s>(&mut self, lexer: &mut Lexer<'s>, own: Own<'s, 'a>) -> Result<(Out::Value, Token<'a>)> {
+        let close = loop {
+            if lexer.peek().get_id() == TerminalId::End {
+                if let Some(error) = lexer.take_error() {
+                    return Err(ParseError::LexError(error));
+                }
+                let end = lexer.peek().loc;
+                self.close(end)?;
+                return Err(ParseError::InternalError("function body accepted at end of input"));
+            }
+            let token = own(self.next_token(lexer)?);
+            if token.get_id() == TerminalId::RightCurlyBracket && self.closes_function_body() {
+                break token;
+            }
+            self.write_lazily(token, lexer, own)?;
+        };
+        // Stop at the FunctionBody, before it's wrapped in, say, a
+        // GeneratorBody.
+        let start = self.stack.state_at(0).unwrap();
+        let stop = [start, self.goto(start, NonterminalId::FunctionBody)];
+        if self.reduce_until(TerminalId::RightCurlyBracket, &close.loc, Some(&stop))?.is_error() {
+            // closes_function_body() checked that error handling gets past
+            // this.
+            self.try_error_handling(&close)?;
+            self.reduce_until(TerminalId::RightCurlyBracket, &close.loc, Some(&stop))?;
+        }
+        // The `}` isn't shifted here, so report what those reductions found.
+        self.report_early_errors()?;
+        if self.stack.len() != 1 {
+            return Err(ParseError::InternalError("function body didn't end at its closing brace"));
+        }
+        let body = self.stack.pop().ok_or(ParseError::InternalError("stack underflow"))?;
+        Ok((body, close))
+    }
+
+    /// Return true if a `}` would close the function body that this parser
+    /// started in, rather than something nested in it.
+    fn closes_function_body(&self) -> bool {
+        // The reductions on `}` go on past the FunctionBody if it's wrapped
+        // in, say, a GeneratorBody.
+        let start = self.stack.state_at(0).unwrap();
+        let body_states: Vec<usize> = [
+            NonterminalId::FunctionBody,
+            NonterminalId::GeneratorBody,
+            NonterminalId::AsyncFunctionBody,
+            NonterminalId::AsyncGeneratorBody,
+        ]
+        .iter()
+        .map(|&nt| self.goto(start, nt))
+        .filter(|&state| state != 0)
+        .collect();
+        let mut sim = self.simulator();
+        let mut action = sim.reduce_all(TerminalId::RightCurlyBracket);
+        if action.is_error() {
+            // Maybe error-handling would get past it, as with a missing
+            // semicolon before the `}`. Mirror try_error_handling.
+            let error_action = sim.reduce_all(TerminalId::ErrorToken);
+            if !error_action.is_shift() {
+                return false;
+            }
+            sim.replace(error_action.shift_state());
+            action = sim.reduce_all(TerminalId::RightCurlyBracket);
+        }
+        action.is_shift()
+            && sim.sp == 1
+            && sim.sim_state_stack.len() == 1
+            && body_states.contains(&sim.sim_state_stack[0])
+    }
+
+    /// Fully parse a function body that a lazy parse of `src` skipped, and
+    /// return the node for it. This discards whatever the parser was doing.
+    /// If `src` is a module, the parser should be a module parser.
+    pub fn parse_lazy_function(&mut self, src: &'a str, lazy: &LazyFunction) -> Result<Out::Value> {
+        if lazy.state >= self.tables.state_count {
+            return Err(ParseError::InternalError("lazy function is from different tables"));
+        }
+        let rest = src
+            .get(lazy.loc.start..)
+            .ok_or(ParseError::InternalError("lazy function is out of range for the source text"))?;
+        self.stack = Stack::new(lazy.state);
+        self.recovering = false;
+        self.recoveries = 0;
+        self.skipping = false;
+        let module = self.early.is_module();
+        self.early = EarlyErrors::new_function(lazy.strict, self.body_kind(lazy.state));
+        if module {
+            self.early.record_module();
+        }
+        let mut lexer = Lexer::new_at(rest, lazy.loc.start_point());
+        if self.recover {
+            lexer.recover_errors();
+        }
+        let (body, _) = self.parse_function_body(&mut lexer, |token| token)?;
+        Ok(body)
+    }
+
+    /// Finish parsing. `end` is the location of the end of the input.
+    pub fn close(&mut self, end: SourceLocation) -> Result<Out::Value> {
+        let end = Token::new(TerminalId::End, end);
+        if self.glr {
+            self.decide_at_end()?;
+        }
         // Loop for error-handling.
         loop {
-            let action = self.reduce_all(TerminalId::End);
+            let action = self.reduce_all(TerminalId::End, &end.loc)?;
             if action.is_accept() {
-                assert_eq!(self.node_stack.len(), 1);
-                return Ok(self.node_stack.pop().unwrap());
+                self.report_early_errors()?;
+                if self.stack.len() != 1 {
+                    return Err(ParseError::InternalError("accepted with the wrong number of nodes"));
+                }
+                return self.stack.pop().ok_or(ParseError::InternalError("stack underflow"));
+            } else if action.is_error() {
+                if let Err(error) = self.try_error_handling(&end) {
+                    self.recover_from(&end, error)?;
+                }
             } else {
-                assert!(action.is_error());
-                self.try_error_handling(TerminalId::End)?;
+                return Err(ParseError::InternalError("shift action on end of input"));
             }
         }
     }
 
-    fn try_error_handling(&mut self, t: TerminalId) -> Result<()> {
+    /// At the end of a GLR parse, commit to the preferred branch that
+    /// accepts, or if none does, to the preferred one that's left.
+    fn decide_at_end(&mut self) -> Result<()> {
+        let branches = if self.undecided() {
+            mem::take(&mut self.branches)
+        } else {
+            vec![Branch::new(self.stack.states())]
+        };
+        let accepted = advance_all(self.tables, &branches, TerminalId::End);
+        match accepted.iter().find(|b| b.accepted) {
+            Some(branch) => self.commit(&branch.choices),
+            None => self.commit(&branches[0].choices),
+        }
+    }
+
+    fn try_error_handling(&mut self, token: &Token) -> Result<()> {
         // Error recovery version of the code in write_terminal. Differences
         // between this and write_terminal are commented below.
-        assert!(t != TerminalId::ErrorToken);
-
-        let action = self.reduce_all(TerminalId::ErrorToken);
-        if action.is_shift() {
-            // Don't actually push an ErrorToken onto the stack here. Treat the
-            // ErrorToken as having been consumed and move to the recovered
-            // state.
-            *self.state_stack.last_mut().unwrap() = action.shift_state();
-            Ok(())
-        } else {
+        let t = token.get_id();
+        if t == TerminalId::ErrorToken {
+            return Err(ParseError::InternalError("ErrorToken written as input"));
+        }
+        if self.mixes_coalesce(t) {
+            return Err(ParseError::EarlyError {
+                kind: EarlyErrorKind::MixedCoalesce,
+                name: None,
+                loc: token.loc,
+                first: None,
+            });
+        }
+
+        // Check before reducing anything, so that on failure the stack still
+        // reflects the state where the error happened.
+        if !self.can_accept_terminal(TerminalId::ErrorToken) {
             // On error, don't attempt error handling again.
-            assert!(action.is_error());
-            Err(
-                if t == TerminalId::End {
-                    ParseError::UnexpectedEnd
-                } else {
-                    ParseError::SyntaxError
+            let expected = self.expected_terminals();
+            let error = if t == TerminalId::End {
+                ParseError::UnexpectedEnd {
+                    loc: token.loc,
+                    expected,
+                }
+            } else {
+                ParseError::SyntaxError {
+                    token: token.clone().into_owned(),
+                    expected,
+                }
+            };
+            // In recovery mode, do as yacc does: pop back to a state that
+            // can take an ErrorToken, and go on from there. That keeps what
+            // came before the error in the statement it's in, where
+            // recover_from() would make an error node of the statement.
+            let point = if self.recover { self.find_error_token_point(Some(t)) } else { None };
+            let depth = match point {
+                Some(depth) => depth,
+                None => return Err(error),
+            };
+            if !self.count_recovery() {
+                return Err(error);
+            }
+            self.discard_to(depth);
+            if !self.recovering {
+                self.errors.push(error);
+                self.recovering = true;
+            }
+        }
+
+        self.shift_error_token(&token.loc)
+    }
+
+    /// Do the reductions on an ErrorToken at `loc` and shift it, which the
+    /// caller has checked the parser can.
+    fn shift_error_token(&mut self, loc: &SourceLocation) -> Result<()> {
+        let action = self.reduce_all(TerminalId::ErrorToken, loc)?;
+        if !action.is_shift() {
+            return Err(ParseError::InternalError("ErrorToken was accepted but not shifted"));
+        }
+        // Don't actually push an ErrorToken onto the stack here. Treat the
+        // ErrorToken as having been consumed and move to the recovered
+        // state.
+        self.stack.set_state(action.shift_state());
+        Ok(())
+    }
+
+    /// True if `t`, which the parser can't accept, is a `??` after an
+    /// operand of `&&` or `||`, or the other way around, as in `a ?? b || c`.
+    /// The grammar doesn't allow these without parentheses, so that nobody
+    /// has to remember which goes first.
+    fn mixes_coalesce(&self, t: TerminalId) -> bool {
+        match t {
+            TerminalId::QuestionMarkQuestionMark => {
+                self.can_accept_terminal(TerminalId::AmpersandAmpersand)
+                    || self.can_accept_terminal(TerminalId::VerticalLineVerticalLine)
+            }
+            TerminalId::AmpersandAmpersand | TerminalId::VerticalLineVerticalLine => {
+                self.can_accept_terminal(TerminalId::QuestionMarkQuestionMark)
+            }
+            _ => false,
+        }
+    }
+
+    /// In recovery mode, get past `error`, which happened at `token`: pop
+    /// back to the nearest state where one of RECOVERY_NONTERMINALS can be
+    /// followed by `token`, and replace what was popped with an error node.
+    /// If there's no such state, discard `token`, after popping back to the
+    /// nearest state that can take an ErrorToken, or failing that, one that
+    /// can take an error node.
+    ///
+    /// Return true if `token` should be written again, false if it was
+    /// discarded. The recovery policy can rule out either.
+    fn recover_from(&mut self, token: &Token, error: ParseError) -> Result<bool> {
+        if !self.recover {
+            return Err(error);
+        }
+        if let ParseError::InternalError(_) = error {
+            return Err(error);
+        }
+        if !self.count_recovery() {
+            return Err(error);
+        }
+
+        let t = token.get_id();
+        let (point, retry) = match self.find_recovery_point(Some(t)) {
+            Some(point) => (Some(point), true),
+            None if t == TerminalId::End => return Err(error),
+            None if !self.recovery_policy.skip_tokens => return Err(error),
+            // This token is garbage too. The error node is already there.
+            None if self.recovering => (None, false),
+            None => match self.find_error_token_point(None) {
+                Some(depth) => {
+                    self.discard_to(depth);
+                    self.shift_error_token(&token.loc)?;
+                    (None, false)
+                }
+                None => match self.find_recovery_point(None) {
+                    Some(point) => (Some(point), false),
+                    None => return Err(error),
+                },
+            },
+        };
+
+        if let Some((depth, nt)) = point {
+            // The state at `depth` stays; the nodes above it are replaced.
+            let start = match self.stack.loc(depth) {
+                Some(loc) => loc,
+                None => token.loc.start_point(),
+            };
+            let end = if retry {
+                self.stack.last_loc().unwrap_or(start)
+            } else {
+                token.loc
+            };
+            let loc = start.to(&end);
+            let node = match self.handler.error_node(nt, loc) {
+                Some(node) => node,
+                None => return Err(error),
+            };
+            let state = self.stack.state_at(depth).unwrap();
+            let next = self.goto(state, nt);
+            self.trace(TraceEvent::Goto {
+                state,
+                nonterminal: nt,
+                next,
+            });
+            self.discard_to(depth);
+            self.stack.push(node, loc, next);
+        }
+
+        if !self.recovering {
+            self.errors.push(error);
+            self.recovering = true;
+        }
+        if !retry && !self.recovery_policy.sync_terminals.is_empty() {
+            self.skipping = true;
+        }
+        Ok(retry)
+    }
+
+    /// Count a recovery, or return false if it would be more in a row than
+    /// the recovery policy allows.
+    fn count_recovery(&mut self) -> bool {
+        if self.recoveries >= self.recovery_policy.max_consecutive {
+            return false;
+        }
+        self.recoveries += 1;
+        true
+    }
+
+    /// Find the topmost state on the stack that has a goto for one of
+    /// RECOVERY_NONTERMINALS, such that after the goto the terminal `t` would
+    /// be accepted. If `t` is None, any such state will do. Return the
+    /// state's depth and the nonterminal.
+    fn find_recovery_point(&self, t: Option<TerminalId>) -> Option<(usize, NonterminalId)> {
+        for depth in (0..self.stack.depth()).rev() {
+            let state = self.stack.state_at(depth).unwrap();
+            for &nt in RECOVERY_NONTERMINALS.iter() {
+                let next = self.goto(state, nt);
+                // Entry states are never the target of a goto, so 0 means
+                // there's no entry in the table.
+                if next == 0 {
+                    continue;
                 }
-            )
+                let accepted = match t {
+                    None => true,
+                    Some(t) => {
+                        let mut sim = Simulator {
+                            tables: self.tables,
+                            stack: &self.stack,
+                            sp: depth + 1,
+                            sim_state_stack: vec![next],
+                        };
+                        !sim.reduce_all(t).is_error()
+                    }
+                };
+                if accepted {
+                    return Some((depth, nt));
+                }
+            }
+        }
+        None
+    }
+
+    /// Find the topmost state below the current one that can take an
+    /// ErrorToken, such that the terminal `t` would be accepted after it.
+    /// If `t` is None, any such state will do. Return the state's depth.
+    fn find_error_token_point(&self, t: Option<TerminalId>) -> Option<usize> {
+        (0..self.stack.len()).rev().find(|&depth| {
+            let mut sim = Simulator {
+                tables: self.tables,
+                stack: &self.stack,
+                sp: depth + 1,
+                sim_state_stack: vec![],
+            };
+            // Mirror try_error_handling.
+            let action = sim.reduce_all(TerminalId::ErrorToken);
+            if !action.is_shift() {
+                return false;
+            }
+            sim.replace(action.shift_state());
+            t.is_none_or(|t| !sim.reduce_all(t).is_error())
+        })
+    }
+
+    /// Pop nodes, giving them back to the handler, until there are `depth`
+    /// left.
+    fn discard_to(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            if let Some(node) = self.stack.pop() {
+                self.handler.discard(node);
+            }
         }
     }
 
-    fn can_accept_terminal(&self, t: TerminalId) -> bool {
-        // BUG: This is wrong. Because this parser may be LALR, if we see a
-        // reduce action, we need to simulate the reduce before we know if t is
-        // really acceptable.
-        !self.action(t).is_error()
+    fn goto(&self, state: usize, nt: NonterminalId) -> usize {
+        self.tables.goto_table[state * self.tables.goto_width + nt as usize]
     }
 
+    /// List the terminals that self.write_token() would accept in the
+    /// current state (not counting ErrorToken).
+    fn expected_terminals(&self) -> Vec<TerminalId> {
+        (0..self.tables.action_width)
+            .filter_map(TerminalId::from_usize)
+            .filter(|&t| t != TerminalId::ErrorToken && self.can_accept_terminal(t))
+            .collect()
+    }
+
+    fn simulator(&self) -> Simulator<'_, Out::Value> {
+        Simulator {
+            tables: self.tables,
+            stack: &self.stack,
+            sp: self.stack.depth(),
+            sim_state_stack: vec![],
+        }
+    }
+
+    /// Return true if self.write_token() would accept a token with the given
+    /// terminal id without going through error handling.
+    pub fn can_accept_terminal(&self, t: TerminalId) -> bool {
+        if self.undecided() {
+            return !advance_all(self.tables, &self.branches, t).is_empty();
+        }
+        // Because this parser may be LALR, a reduce action doesn't mean `t`
+        // is acceptable; simulate the reductions to find out.
+        !self.simulator().reduce_all(t).is_error()
+    }
+
+    /// Return true if self.write_token() would accept a token with terminal
+    /// id `first` and then one with `second`, without going through error
+    /// handling.
+    fn can_accept_pair(&self, first: TerminalId, second: TerminalId) -> bool {
+        if self.undecided() {
+            let branches = advance_all(self.tables, &self.branches, first);
+            return !advance_all(self.tables, &branches, second).is_empty();
+        }
+        let mut sim = self.simulator();
+        let action = sim.reduce_all(first);
+        if !action.is_shift() {
+            return false;
+        }
+        sim.sim_state_stack.push(action.shift_state());
+        !sim.reduce_all(second).is_error()
+    }
+
+    /// Return true if self.write_token() would accept a token with terminal
+    /// id `first` and then `next`, read either way it might be.
+    fn can_accept_before(&self, first: TerminalId, next: Next) -> bool {
+        self.can_accept_pair(first, next.id) || next.keyword.is_some_and(|keyword| self.can_accept_pair(first, keyword))
+    }
+
+    /// Return true if self.write_token() would accept a token with terminal
+    /// id `t` once error handling inserts a semicolon.
+    fn can_accept_after_semicolon(&self, t: TerminalId) -> bool {
+        if self.undecided() {
+            return false;
+        }
+        // Mirror try_error_handling.
+        let mut sim = self.simulator();
+        let action = sim.reduce_all(TerminalId::ErrorToken);
+        if !action.is_shift() {
+            return false;
+        }
+        sim.replace(action.shift_state());
+        !sim.reduce_all(t).is_error()
+    }
 
     /// Return true if self.close() would succeed.
-    fn can_close(&self) -> bool {
-        // Easy case: no error, parsing just succeeds.
-        if self.can_accept_terminal(TerminalId::End) {
-            true
-        } else {
-            // Hard case: maybe error-handling would succeed?  BUG: Need
-            // simulator to simulate reduce_all; for now just give up
-            false
+    pub fn can_close(&self) -> bool {
+        if self.undecided() {
+            return advance_all(self.tables, &self.branches, TerminalId::End)
+                .iter()
+                .any(|b| b.accepted);
+        }
+        let mut sim = self.simulator();
+        loop {
+            let action = sim.reduce_all(TerminalId::End);
+            if action.is_accept() {
+                return true;
+            }
+            if !action.is_error() {
+                // close() would report an InternalError.
+                return false;
+            }
+            // Maybe error-handling would succeed? Mirror
+            // try_error_handling.
+            let action = sim.reduce_all(TerminalId::ErrorToken);
+            if action.is_shift() {
+                sim.replace(action.shift_state());
+            } else {
+                return false;
+            }
+        }
+    }
+}
+
+impl<'a, 'h, Out, Reduce> Parser<'a, 'h, Out, Reduce>
+where
+    Out: Handler<'a>,
+    Out::Value: Clone,
+    Reduce: Reducer<'a, Out>,
+{
+    /// Like parse_script(), but take checkpoints along the way so that
+    /// self.reparse() can later skip the part of `src` before an edit.
+    pub fn parse_script_incremental(&mut self, src: &str) -> Result<Out::Value> {
+        let initial = self.checkpoint_at(SourceLocation::new(0, 0, 1, 0));
+        self.log = Some(CheckpointLog::new(src, initial));
+        self.parse_from(Rc::from(src), SourceLocation::new(0, 0, 1, 0))
+    }
+
+    /// Apply `edit` to the source text of the last incremental parse and
+    /// parse the result, resuming from the last checkpoint that the edit
+    /// doesn't invalidate.
+    pub fn reparse(&mut self, edit: &TextEdit) -> Result<Out::Value> {
+        let log = self.log.as_mut().ok_or(ParseError::InternalError(
+            "reparse() called before parse_script_incremental()",
+        ))?;
+        let source: Rc<str> = Rc::from(
+            edit.apply(&log.source)
+                .ok_or(ParseError::InternalError("edit is out of range for the source text"))?,
+        );
+        log.source = Rc::clone(&source);
+        let checkpoint = log.rewind_for(edit).clone();
+        let resume = checkpoint.resume;
+        self.rewind(checkpoint);
+        self.parse_from(source, resume)
+    }
+
+    /// Save the parser's state, so that rewind() can go back to it after
+    /// writing more tokens: to try parsing one way and back off if it
+    /// doesn't work out, say. The nodes on the stack are cloned, which is
+    /// cheap for the default handler's arena references, and any nodes the
+    /// handler makes after this are left for it to clean up once it's
+    /// done; in an arena, they're dropped along with it.
+    pub fn checkpoint(&self) -> Checkpoint<'a, Out::Value> {
+        self.checkpoint_at(SourceLocation::default())
+    }
+
+    /// Go back to the state `checkpoint` saved. The nodes the parser holds
+    /// now that aren't in the checkpoint are dropped, and so are the errors
+    /// recovery mode recorded since.
+    pub fn rewind(&mut self, checkpoint: Checkpoint<'a, Out::Value>) {
+        self.stack = checkpoint.stack;
+        self.errors.truncate(checkpoint.error_count);
+        self.recovering = checkpoint.recovering;
+        self.recoveries = checkpoint.recoveries;
+        self.skipping = checkpoint.skipping;
+        self.early = checkpoint.early;
+        self.branches = checkpoint.branches;
+        self.pending = checkpoint.pending;
+        self.replay = checkpoint.replay;
+    }
+
+    fn checkpoint_at(&self, resume: SourceLocation) -> Checkpoint<'a, Out::Value> {
+        Checkpoint {
+            stack: self.stack.clone(),
+            error_count: self.errors.len(),
+            recovering: self.recovering,
+            recoveries: self.recoveries,
+            skipping: self.skipping,
+            early: self.early.clone(),
+            branches: self.branches.clone(),
+            pending: self.pending.clone(),
+            replay: self.replay.clone(),
+            resume,
+        }
+    }
+
+    /// Lex and parse `source` from `start` onward, taking a checkpoint every
+    /// CHECKPOINT_INTERVAL tokens, then close the parser. The tokens are
+    /// copied out of `source`, since the checkpoints outlive it: each edit
+    /// makes a new one.
+    fn parse_from(&mut self, source: Rc<str>, start: SourceLocation) -> Result<Out::Value> {
+        let mut lexer = Lexer::new_at(&source[start.start..], start);
+        if self.recover {
+            lexer.recover_errors();
         }
+        let mut count = 0;
+        while lexer.peek().get_id() != TerminalId::End {
+            count += 1;
+            // An undecided GLR parse has tokens the checkpoint wouldn't
+            // include.
+            if count % CHECKPOINT_INTERVAL == 0 && !self.undecided() {
+                let checkpoint = self.checkpoint_at(lexer.peek().loc.start_point());
+                if let Some(log) = self.log.as_mut() {
+                    log.checkpoints.push(checkpoint);
+                }
+            }
+            let token = self.next_token(&mut lexer)?.into_owned();
+            self.write_lazily(token, &mut lexer, Token::into_owned)?;
+        }
+        if let Some(error) = lexer.take_error() {
+            return Err(ParseError::LexError(error));
+        }
+        let end = lexer.peek().loc;
+        self.close(end)
+    }
+}
+
+/// The keyword `token` may be, if it's an identifier spelled like a
+/// contextual keyword, without escapes. Contextual keywords are the words
+/// that are keywords only in some places. The lexer returns them as
+/// identifiers, and Parser::contextual_word() decides. `yield` and `await`
+/// depend on the function they're in instead, so they're left to
+/// contextual_keyword().
+fn contextual_keyword_id(token: &Token) -> Option<TerminalId> {
+    if token.get_id() != TerminalId::Identifier || token.raw.is_some() {
+        return None;
+    }
+    match lookup_keyword(token.value.as_deref()?) {
+        Some((keyword, flags)) if flags & CONTEXTUAL != 0 => Some(keyword),
+        _ => None,
+    }
+}
+
+/// What the token after the one being decided could be: its terminal id,
+/// and the keyword it may be instead, since which it is hasn't been decided
+/// yet. In `import {x as y}`, it's `as` as a keyword that says `x` is an
+/// IdentifierName; in `for (let of of x)`, it's the first `of` as an
+/// identifier that says `let` is a keyword.
+#[derive(Clone, Copy)]
+struct Next {
+    id: TerminalId,
+    keyword: Option<TerminalId>,
+    newline_before: bool,
+}
+
+impl Next {
+    fn of(token: &Token) -> Next {
+        Next {
+            id: token.get_id(),
+            keyword: contextual_keyword_id(token),
+            newline_before: token.newline_before,
+        }
+    }
+}
+
+/// The word `token` is spelled with, if it's an identifier, a reserved
+/// word, or a contextual keyword.
+fn word<'t>(token: &'t Token) -> Option<&'t str> {
+    match token.get_id() {
+        TerminalId::Identifier => token.value.as_deref(),
+        TerminalId::StringLiteral
+        | TerminalId::NoSubstitutionTemplate
+        | TerminalId::TemplateHead
+        | TerminalId::TemplateMiddle
+        | TerminalId::TemplateTail => None,
+        _ => token.raw.as_deref(),
+    }
+}
+
+/// A non-destructive copy of a parser's state stack, for answering "what if"
+/// questions. The simulator applies reduce and goto actions using the
+/// `reduce_simulator` table, without calling into the handler.
+///
+/// To avoid copying the whole stack, the simulator only copies the states it
+/// pushes. The states of `stack` below depth `sp` are the part of the
+/// parser's stack that has not been popped yet, and `sim_state_stack` holds
+/// the states pushed on top of it.
+struct Simulator<'a, V> {
+    tables: &'a ParserTables<'a>,
+    stack: &'a Stack<V>,
+    sp: usize,
+    sim_state_stack: Vec<usize>,
+}
+
+impl<'a, V> Simulator<'a, V> {
+    fn state(&self) -> usize {
+        match self.sim_state_stack.last() {
+            Some(&state) => state,
+            None => self.stack.state_at(self.sp - 1).unwrap(),
+        }
+    }
+
+    fn action(&self, t: TerminalId) -> Action {
+        let t = t as usize;
+        debug_assert!(t < self.tables.action_width);
+        Action(self.tables.action(self.state(), t))
+    }
+
+    fn pop(&mut self, mut n: usize) {
+        let from_sim = n.min(self.sim_state_stack.len());
+        self.sim_state_stack.truncate(self.sim_state_stack.len() - from_sim);
+        n -= from_sim;
+        debug_assert!(n < self.sp);
+        self.sp -= n;
+    }
+
+    fn replace(&mut self, state: usize) {
+        self.pop(1);
+        self.sim_state_stack.push(state);
+    }
+
+    fn reduce(&mut self, action: Action) {
+        let tables = self.tables;
+        let (pop_count, nt) = tables.reduce_simulator[action.reduce_prod_index()];
+        debug_assert!((nt as usize) < tables.goto_width);
+        self.pop(pop_count);
+        let prev_state = self.state();
+        let state_after = tables.goto_table[prev_state * tables.goto_width + nt as usize];
+        debug_assert!(state_after < tables.state_count);
+        self.sim_state_stack.push(state_after);
+    }
+
+    fn reduce_all(&mut self, t: TerminalId) -> Action {
+        let mut action = self.action(t);
+        while action.is_reduce() {
+            self.reduce(action);
+            action = self.action(t);
+        }
+        action
+    }
+
+    /// Return true if writing `t` might run into a conflict: if one of the
+    /// actions on the way has alternatives, or if `t` is an error, since
+    /// error handling goes on from there.
+    fn meets_conflict(&mut self, t: TerminalId) -> bool {
+        loop {
+            if self.tables.has_alternatives(self.state(), t as usize) {
+                return true;
+            }
+            let action = self.action(t);
+            if !action.is_reduce() {
+                return action.is_error();
+            }
+            self.reduce(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Parser, RecoveryPolicy};
+    use crate::ast_arena::{Arena, ArenaRef};
+    use crate::lexer::Lexer;
+    use crate::modules::ImportName;
+    use crate::parser_generated::{concrete, reduce, DefaultHandler, EntryPoint, TerminalId, TABLES};
+    use crate::parser_runtime::{SourceLocation, TokenStream};
+    use crate::testing::{assert_no_leaks, function_body, INVALID};
+
+    #[test]
+    fn no_leaks() {
+        // Errors.
+        for src in INVALID {
+            assert_no_leaks(|| {
+                let arena = Arena::new();
+                let handler = DefaultHandler::new(&arena);
+                assert!(Parser::new_script(&handler).parse_script(src).is_err());
+            });
+        }
+
+        // Partial trees from recovery mode.
+        for src in INVALID {
+            assert_no_leaks(|| {
+                let arena = Arena::new();
+                let handler = DefaultHandler::new(&arena);
+                let mut parser =
+                    Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+                let _ = parser.parse_script(src);
+            });
+        }
+
+        // Dropping the parser before the end of the input.
+        assert_no_leaks(|| {
+            let arena = Arena::new();
+            let handler = DefaultHandler::new(&arena);
+            let mut parser = Parser::new_script(&handler);
+            let mut lexer = Lexer::new("a = f(b, c) + d; if (e) { g");
+            for _ in 0..12 {
+                parser.write_token(TokenStream::take(&mut lexer)).ok().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn lazy_function_bodies() {
+        let src = "function f(a) { if (a) { return g(`${ function () { return 1; } }`); } }";
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let full = Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script();
+
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        let script = parser.parse_script(src).ok().unwrap().into_script();
+        let lazy = match *function_body(script) {
+            concrete::FunctionBody::Lazy(lazy) => lazy,
+            ref body => panic!("expected a lazy function body, got {:?}", body),
+        };
+        assert_eq!(
+            &src[lazy.loc.start..lazy.loc.end],
+            " if (a) { return g(`${ function () { return 1; } }`); } "
+        );
+
+        let body = Parser::new_script(&handler)
+            .parse_lazy_function(src, &lazy)
+            .ok()
+            .unwrap()
+            .into_function_body();
+        assert_eq!(format!("{:?}", body), format!("{:?}", function_body(full)));
+
+        // Empty bodies are left alone; some aren't FunctionBodies at all.
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(true);
+        assert!(parser.parse_script("function f() {} class C { m() {} }").is_ok());
+
+        // Skipped bodies are still checked.
+        for src in &["function f() { x = ; }", "function f() { if (x) {"] {
+            let mut parser = Parser::new_script(&handler);
+            parser.set_lazy(true);
+            assert!(parser.parse_script(src).is_err());
+        }
+    }
+
+    #[test]
+    fn reducers() {
+        use crate::parser_runtime::ReduceStack;
+        use std::cell::Cell;
+        let src = "var x = 1; function f(a, b) { return a ? b : x; }";
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let direct = Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script();
+
+        // A closure is a Reducer too, and builds the same tree.
+        let reductions = Cell::new(0);
+        let counting = |handler: &_, prod_index, loc, stack: &mut ReduceStack<_>| {
+            reductions.set(reductions.get() + 1);
+            reduce(handler, prod_index, loc, stack)
+        };
+        let mut parser = Parser::new(&TABLES, counting, &handler, EntryPoint::Script.state(), false);
+        let script = parser.parse_script(src).ok().unwrap().into_script();
+        assert_eq!(format!("{:?}", script), format!("{:?}", direct));
+        assert!(reductions.get() > 0);
+    }
+
+    #[test]
+    fn identifier_names() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for src in [
+            "a.b.if = c.default;",
+            "x = {a: 1, if: 2, b, c() {}, get d() {}};",
+            "x = {a, b: c} = d;",
+        ] {
+            if let Err(error) = Parser::new_script(&handler).parse_script(src) {
+                panic!("{}: {}", src, error.message());
+            }
+        }
+        // `as` after a name in braces means it's an IdentifierName.
+        let mut parser = Parser::new_module(&handler);
+        parser.parse_script("import {if as a, b} from 'c';").ok().unwrap();
+        let imports: Vec<_> = parser.take_module_summary().unwrap().imports.into_iter().map(|e| e.import_name).collect();
+        assert_eq!(imports, [ImportName::Name("if".to_string()), ImportName::Name("b".to_string())]);
+    }
+
+    /// The tokens of `src`, as the parser sees them: lexed with feedback
+    /// from the parser about what it can accept next.
+    fn parser_tokens(src: &str) -> Vec<(TerminalId, Option<String>)> {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        let mut lexer = Lexer::new(src);
+        let mut tokens = vec![];
+        while lexer.peek().get_id() != TerminalId::End {
+            let token = parser.next_token(&mut lexer).ok().unwrap();
+            tokens.push((token.get_id(), token.value.as_deref().map(str::to_string)));
+            parser.write_token(token).ok().unwrap();
+        }
+        tokens
+    }
+
+    #[test]
+    fn regexp_or_division() {
+        use TerminalId::*;
+        let div = (Solidus, None);
+        let regexp = |text: &str| (RegularExpressionLiteral, Some(text.to_string()));
+
+        // The same characters are one or the other depending on what the
+        // parser expects, not on the token before them.
+        let cases = [
+            ("a / b / c;", vec![div.clone(), div.clone()]),
+            ("x = /b/g;", vec![regexp("/b/g")]),
+            ("a /= /=/;", vec![(SolidusEqualsSign, None), regexp("/=/")]),
+            ("a\n/b/g;", vec![div.clone(), div.clone()]),
+            ("(a) / 2;", vec![div.clone()]),
+            ("if (a) /b/.test(c);", vec![regexp("/b/")]),
+            ("x = {} / 1;", vec![div.clone()]),
+            ("{} /a/g;", vec![regexp("/a/g")]),
+            ("a++ / 2;", vec![div]),
+        ];
+        for (src, expected) in cases {
+            let slashes: Vec<_> = parser_tokens(src)
+                .into_iter()
+                .filter(|(id, _)| matches!(id, Solidus | SolidusEqualsSign | RegularExpressionLiteral))
+                .collect();
+            assert_eq!(slashes, expected, "{}", src);
+        }
+
+        // By itself, the lexer always reads an operator.
+        let ids: Vec<_> = Lexer::new("x = /b/g;").map(|token| token.get_id()).collect();
+        assert_eq!(ids, [Identifier, EqualsSign, Solidus, Identifier, Solidus, Identifier, Semicolon]);
+    }
+
+    #[test]
+    fn template_substitutions() {
+        use TerminalId::*;
+        let token = |id, value: Option<&str>| (id, value.map(str::to_string));
+        assert_eq!(
+            parser_tokens("`a${b}c${d}e`;"),
+            [
+                token(TemplateHead, Some("a")),
+                token(Identifier, Some("b")),
+                token(TemplateMiddle, Some("c")),
+                token(Identifier, Some("d")),
+                token(TemplateTail, Some("e")),
+                token(Semicolon, None),
+            ]
+        );
+
+        // A `}` that closes a brace opened in the substitution stays a `}`,
+        // and a template in a substitution has substitutions of its own.
+        assert_eq!(
+            parser_tokens("`${ {a: 1}.a }${`x${y}`}z`;"),
+            [
+                token(TemplateHead, Some("")),
+                token(LeftCurlyBracket, None),
+                token(IdentifierName, Some("a")),
+                token(Colon, None),
+                token(NumericLiteral, Some("1")),
+                token(RightCurlyBracket, None),
+                token(FullStop, None),
+                token(IdentifierName, Some("a")),
+                token(TemplateMiddle, Some("")),
+                token(TemplateHead, Some("x")),
+                token(Identifier, Some("y")),
+                token(TemplateTail, Some("")),
+                token(TemplateTail, Some("z")),
+                token(Semicolon, None),
+            ]
+        );
+        let ids: Vec<_> = parser_tokens("`${function () { return {}; }}`;").into_iter().map(|(id, _)| id).collect();
+        assert_eq!(
+            ids,
+            [
+                TemplateHead,
+                Function,
+                LeftParenthesis,
+                RightParenthesis,
+                LeftCurlyBracket,
+                Return,
+                LeftCurlyBracket,
+                RightCurlyBracket,
+                Semicolon,
+                RightCurlyBracket,
+                TemplateTail,
+                Semicolon,
+            ]
+        );
+
+        // By itself, the lexer reads a `}` that ends a substitution as a `}`.
+        let ids: Vec<_> = Lexer::new("`a${b}c`d`").map(|token| token.get_id()).collect();
+        assert_eq!(ids, [TemplateHead, Identifier, RightCurlyBracket, Identifier, NoSubstitutionTemplate]);
+    }
+
+    #[test]
+    fn visitors() {
+        use crate::codegen::{print_script, Options};
+        use crate::parser_generated::{walk_expression, Visit, VisitMut};
+        use crate::parser_runtime::Token;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), false);
+        let script = match parser.parse_script("f(a); function g(a) { return a + b; } c = [a, b];") {
+            Ok(script) => script.into_script(),
+            Err(error) => panic!("{}", error.message()),
+        };
+
+        // Counts the identifiers used as expressions, skipping those in
+        // call arguments.
+        #[derive(Default)]
+        struct References(Vec<String>);
+        impl<'alloc> Visit<'alloc> for References {
+            fn visit_identifier_reference(&mut self, node: &'alloc concrete::IdentifierReference<'alloc>) {
+                if let concrete::IdentifierReference::IdentifierReference(token) = node {
+                    self.0.extend(token.value.as_deref().map(str::to_string));
+                }
+            }
+            fn visit_expression(&mut self, node: &'alloc concrete::Expression<'alloc>) {
+                if let concrete::Expression::CallExpressionP0(..) = node {
+                    return;
+                }
+                walk_expression(self, node);
+            }
+        }
+        let mut references = References::default();
+        references.visit_script(script.get());
+        assert_eq!(references.0, ["a", "b", "c", "a", "b"]);
+
+        // Renames every `a`, leaving any subtree without one as it was.
+        struct Rename<'alloc>(&'alloc Arena);
+        impl<'alloc> VisitMut<'alloc> for Rename<'alloc> {
+            fn arena(&self) -> &'alloc Arena {
+                self.0
+            }
+            fn visit_token_mut(&mut self, token: &mut Token<'alloc>) {
+                if token.value.as_deref() == Some("a") {
+                    token.value = Some("z".into());
+                }
+            }
+        }
+        let mut renamed = script;
+        Rename(&arena).visit_script_mut(&mut renamed);
+        let options = Options::default();
+        assert_eq!(
+            print_script(&renamed, &options).unwrap(),
+            "f(z);\nfunction g(z) {\n    return z + b;\n}\nc = [z, b];\n"
+        );
+        assert_eq!(
+            print_script(&script, &options).unwrap(),
+            "f(a);\nfunction g(a) {\n    return a + b;\n}\nc = [a, b];\n"
+        );
+        let mut unchanged = script;
+        struct Nothing<'alloc>(&'alloc Arena);
+        impl<'alloc> VisitMut<'alloc> for Nothing<'alloc> {
+            fn arena(&self) -> &'alloc Arena {
+                self.0
+            }
+        }
+        Nothing(&arena).visit_script_mut(&mut unchanged);
+        assert!(ArenaRef::ptr_eq(script, unchanged));
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let complete = |src: &str| {
+            let mut parser = Parser::new_script(&handler);
+            match parser.write_source(src) {
+                Ok(_) => parser.can_close(),
+                Err(error) => panic!("{}: {}", src, error.message()),
+            }
+        };
+        for src in &["a", "a = 1\n", "if (x) {}", "function f() { return }", "x = y\n+ z"] {
+            assert!(complete(src), "{}", src);
+        }
+        for src in &["if (x)", "function f() {", "a = [1,", "f(", "x +", "class C { m() {} "] {
+            assert!(!complete(src), "{}", src);
+        }
+
+        // Closing after write_source() is the same as parse_script().
+        let mut parser = Parser::new_script(&handler);
+        let end = match parser.write_source("a;\n") {
+            Ok(end) => end,
+            Err(error) => panic!("{}", error.message()),
+        };
+        assert_eq!((end.start, end.line), (3, 2));
+        assert!(parser.close(end).is_ok());
+    }
+
+    #[test]
+    fn entry_points() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let sum = Parser::new_expression(&handler).parse_script("a + b").ok().unwrap().into_expression();
+        assert!(matches!(&*sum, concrete::Expression::AdditiveExpressionP1(..)), "{:?}", sum);
+        // An expression goal takes one expression, not a statement.
+        for src in ["var x;", "a;"] {
+            assert!(Parser::new_expression(&handler).parse_script(src).is_err(), "{}", src);
+        }
+
+        // Only a module can have imports.
+        let src = "import x from 'y';";
+        assert!(Parser::new_script(&handler).parse_script(src).is_err());
+        assert!(Parser::new_module(&handler).parse_script(src).is_ok());
+        assert_ne!(EntryPoint::Script.state(), EntryPoint::Module.state());
+    }
+
+    #[test]
+    fn node_locations() {
+        use crate::ast_arena::NodeLocations;
+        use crate::testing::expression;
+        let arena = Arena::new();
+        let locations = NodeLocations::new();
+        let handler = DefaultHandler::with_locations(&arena, &locations);
+        let src = "/* x */\n  [a,\n b] + c;";
+        let sum = expression(Parser::new_script(&handler).parse_script(src).ok().unwrap().into_script());
+
+        // A node spans its first token to its last, and starts on the line
+        // and column of its first token.
+        assert_eq!(locations.get(&*sum), Some(SourceLocation::new(10, 21, 2, 2)));
+        let (left, right) = match &*sum {
+            concrete::Expression::AdditiveExpressionP1(left, right) => (*left, *right),
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(locations.get(&*left), Some(SourceLocation::new(10, 17, 2, 2)));
+        assert_eq!(locations.get(&*right), Some(SourceLocation::new(20, 21, 3, 6)));
+    }
+
+    #[test]
+    fn syntax_error_messages() {
+        use super::ParseError;
+        use TerminalId::*;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let error = match Parser::new_script(&handler).parse_script("var = 1;") {
+            Err(error) => error,
+            Ok(_) => panic!("var = 1;"),
+        };
+        match &error {
+            ParseError::SyntaxError { token, expected } => {
+                assert_eq!((token.get_id(), token.loc.start), (EqualsSign, 4));
+                assert_eq!(expected, &[LeftCurlyBracket, Identifier, Yield, Await, LeftSquareBracket]);
+            }
+            _ => panic!("{}", error.message()),
+        }
+        assert_eq!(
+            error.message(),
+            "expected one of `{`, Identifier, `yield`, `await`, `[` but found `=` at line 1, column 4"
+        );
+
+        // At the end of the input, there's no token to blame.
+        let error = match Parser::new_script(&handler).parse_script("f(") {
+            Err(error) => error,
+            Ok(_) => panic!("f("),
+        };
+        assert!(matches!(error, ParseError::UnexpectedEnd { .. }), "{}", error.message());
+        assert!(error.message().starts_with("unexpected end of input at line 1, column 2 (expected one of "));
+    }
+
+    #[test]
+    fn internal_errors() {
+        use super::ParseError;
+        use crate::parser_runtime::Token;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        // ErrorToken is only for error handling to insert; a caller writing
+        // one is driving the parser wrong.
+        let mut parser = Parser::new_script(&handler);
+        let error = match parser.write_token(Token::new(TerminalId::ErrorToken, SourceLocation::new(0, 0, 1, 0))) {
+            Err(error) => error,
+            Ok(()) => panic!("ErrorToken was accepted"),
+        };
+        assert!(matches!(error, ParseError::InternalError(_)));
+        assert_eq!(error.message(), "internal error: ErrorToken written as input");
+        assert!(error.loc().is_none());
+    }
+
+    #[test]
+    fn acceptable_terminals() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.write_source("x = a").ok().unwrap();
+        for t in [TerminalId::Semicolon, TerminalId::PlusSign, TerminalId::LeftParenthesis] {
+            assert!(parser.can_accept_terminal(t), "{:?}", t);
+        }
+        assert!(!parser.can_accept_terminal(TerminalId::Colon));
+        assert!(parser.can_close());
+
+        // Asking doesn't change the parser's state.
+        let end = parser.write_source(" + b;").ok().unwrap();
+        assert!(!parser.can_accept_terminal(TerminalId::RightParenthesis));
+        assert!(parser.can_close());
+        assert!(parser.close(end).is_ok());
+    }
+
+    #[test]
+    fn lookahead() {
+        use crate::parser_runtime::TokenStream;
+        let mut lexer = Lexer::new("let [a] = b");
+        assert_eq!(lexer.peek_token(2).get_id(), TerminalId::Identifier);
+        assert_eq!(lexer.peek_token(1).get_id(), TerminalId::LeftSquareBracket);
+        assert_eq!(lexer.peek().get_id(), TerminalId::Identifier);
+        assert_eq!(TokenStream::take(&mut lexer).value.as_deref(), Some("let"));
+        assert_eq!(lexer.peek_token(5).get_id(), TerminalId::End);
+        let ids: Vec<TerminalId> = lexer.map(|token| token.get_id()).collect();
+        assert_eq!(
+            ids,
+            [
+                TerminalId::LeftSquareBracket,
+                TerminalId::Identifier,
+                TerminalId::RightSquareBracket,
+                TerminalId::EqualsSign,
+                TerminalId::Identifier
+            ]
+        );
+
+        // `let` is a keyword only where a binding comes next, and in strict
+        // mode code.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let sloppy = ["let = 1;", "let.x;", "let;", "var let;", "for (let in o);", "let\n/x/g;", "let(1);"];
+        for src in sloppy.iter().chain(&["let [a] = b;", "let\nx;"]) {
+            assert!(Parser::new_script(&handler).parse_script(src).is_ok(), "{}", src);
+        }
+        let strict = ["'use strict'; let = 1;", "function f() { 'use strict'; let.x; }"];
+        for src in strict.iter().chain(&["let let = 1;", "const [let] = x;"]) {
+            assert!(Parser::new_script(&handler).parse_script(src).is_err(), "{}", src);
+        }
+        assert!(Parser::new_module(&handler).parse_script("let = 1;").is_err());
+    }
+
+    #[test]
+    fn contextual_keywords() {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let scripts = [
+            "async = 1; async(); var async; async function f() {}",
+            "var of = 1; for (of of x); for (let of of x);",
+            "get = 1; var set, static, as, from, target;",
+            "x = {get a() {}, set a(v) {}, get: 1, set, async: 2, async f() {}};",
+            "class C { static x() {} static = 1; get; static static() {} static async *y() {} }",
+            "o.async; o.let; o.of; let async = 1;",
+            "function f() { new.target; var meta; }",
+            "async x => x; async y => await y;",
+            "async function f() { for await (x of y); } x = async function () { for await (x of y); };",
+        ];
+        for src in scripts {
+            assert!(Parser::new_script(&handler).parse_script(src).is_ok(), "{}", src);
+        }
+        // `async` at the end of a line is an identifier, so these aren't
+        // async functions, and `for await` isn't allowed in them.
+        for src in ["async\nfunction f() { for await (x of y); }", "x = async\nfunction () { for await (x of y); };"] {
+            assert!(Parser::new_script(&handler).parse_script(src).is_err(), "{}", src);
+        }
+        let src = "import {as} from 'm'; import from from 'm'; import * as of from 'm'; import.meta;";
+        assert!(Parser::new_module(&handler).parse_script(src).is_ok());
+        assert!(Parser::new_script(&handler).parse_script("for (of x);").is_err());
+    }
+
+    #[test]
+    fn error_nodes() {
+        /// The statements in `script`, in order.
+        fn statements<'alloc>(
+            script: ArenaRef<'alloc, concrete::Script<'alloc>>,
+        ) -> Vec<ArenaRef<'alloc, concrete::ModuleItem<'alloc>>> {
+            let mut items = vec![];
+            if let concrete::Script::Script(Some(body)) = &*script {
+                if let concrete::ScriptBody::ScriptBody(list) = &**body {
+                    let mut list = *list;
+                    loop {
+                        match &*list {
+                            concrete::StatementList::StatementListP0(item) => {
+                                items.push(*item);
+                                break;
+                            }
+                            concrete::StatementList::StatementListP1(rest, item) => {
+                                items.push(*item);
+                                list = *rest;
+                            }
+                            other => panic!("unexpected statement list {:?}", other),
+                        }
+                    }
+                }
+            }
+            items.reverse();
+            items
+        }
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let parse = |src| {
+            let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+            let items = statements(parser.parse_script(src).ok().unwrap().into_script());
+            assert_eq!(parser.errors().len(), 1);
+            items
+        };
+        let span = |loc: &SourceLocation| (loc.start, loc.end);
+
+        // The statement that failed to parse is replaced, and the ones after
+        // it are parsed as usual.
+        let items = parse("x;\nvar = 1;\ny();");
+        assert_eq!(items.len(), 4);
+        assert!(matches!(&*items[0], concrete::ModuleItem::ExpressionStatement(_)));
+        match &*items[1] {
+            concrete::ModuleItem::Error(loc) => assert_eq!(span(loc), (3, 8)),
+            other => panic!("expected an error node, got {:?}", other),
+        }
+        assert!(matches!(&*items[2], concrete::ModuleItem::ExpressionStatement(_)));
+        assert!(matches!(&*items[3], concrete::ModuleItem::ExpressionStatement(_)));
+
+        // An error in a nested statement only replaces that statement.
+        let items = parse("a; if (x) { y = [1, 2");
+        assert_eq!(items.len(), 2);
+        match &*items[1] {
+            concrete::ModuleItem::IfStatementP1(_, body) => match &**body {
+                concrete::ModuleItem::Error(loc) => assert_eq!(span(loc), (10, 21)),
+                other => panic!("expected an error node, got {:?}", other),
+            },
+            other => panic!("expected an if statement, got {:?}", other),
+        }
+
+        // At the end of the input, the error node runs to the last token.
+        let items = parse("{ a b");
+        match &items[..] {
+            [item] => match &**item {
+                concrete::ModuleItem::Error(loc) => assert_eq!(span(loc), (0, 5)),
+                other => panic!("expected an error node, got {:?}", other),
+            },
+            other => panic!("expected one statement, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_token_recovery() {
+        use crate::cst::{CstElement, CstNode};
+        use crate::parser_generated::CstHandler;
+        fn statements(node: &CstNode, out: &mut Vec<String>) {
+            for child in &node.children {
+                if let CstElement::Node(child) = child {
+                    match child.kind {
+                        "script_body" | "statement_list_p0" | "statement_list_p1" | "check_asi" => {
+                            statements(child, out)
+                        }
+                        kind => out.push(kind.to_string()),
+                    }
+                }
+            }
+        }
+        let parse = |src| {
+            let mut parser = Parser::new(&TABLES, reduce, &CstHandler, EntryPoint::Script.state(), true);
+            let mut kinds = vec![];
+            statements(&parser.parse_script(src).ok().unwrap().into_script(), &mut kinds);
+            (kinds, parser.errors().len())
+        };
+
+        // An error deep in a statement pops back to where a semicolon could
+        // have gone, and the statement up to there is kept.
+        let (kinds, errors) = parse("var x = 1 + * 2; y();");
+        assert_eq!(kinds, ["variable_statement", "expression_statement", "expression_statement"]);
+        assert_eq!(errors, 1);
+        assert_eq!(parse("var x = 1 +"), (vec!["variable_statement".to_string()], 1));
+        let (kinds, errors) = parse("a = ;\nb = ;");
+        assert_eq!(kinds, ["expression_statement", "empty_statement", "expression_statement", "empty_statement"]);
+        assert_eq!(errors, 2);
+
+        // Without recovery mode, an error is an error.
+        assert!(Parser::new_script(&CstHandler).parse_script("var x = 1 + * 2;").is_err());
+    }
+
+    #[test]
+    fn recovery_policy() {
+        use crate::parser_generated::CstHandler;
+        let parse = |src, policy: RecoveryPolicy| {
+            let mut parser = Parser::new(&TABLES, reduce, &CstHandler, EntryPoint::Script.state(), true);
+            parser.set_recovery_policy(policy);
+            parser.parse_script(src).ok().map(|_| parser.errors().len())
+        };
+
+        // By default, the parser gets past anything.
+        assert_eq!(parse("a = ) ) ) ;\nb = 1;", RecoveryPolicy::default()), Some(1));
+        assert_eq!(parse("a = ) b ) c; d;", RecoveryPolicy::default()), Some(2));
+
+        // Each `)` is a recovery in a row: one to get past the error, and
+        // one for each token discarded after it.
+        let budget = |max_consecutive| RecoveryPolicy {
+            max_consecutive,
+            ..RecoveryPolicy::default()
+        };
+        assert_eq!(parse("a = ) ) ) ;\nb = 1;", budget(2)), None);
+        assert_eq!(parse("a = ) ) ) ;\nb = 1;", budget(3)), Some(1));
+        // Shifting a token starts the count again.
+        assert_eq!(parse("a = ) ;\nb = ) ;\nc = ) ;", budget(1)), Some(3));
+
+        // Skipping to a semicolon doesn't make a statement of `b`, so the
+        // second `)` isn't another error.
+        let sync = RecoveryPolicy {
+            sync_terminals: vec![TerminalId::Semicolon],
+            ..RecoveryPolicy::default()
+        };
+        assert_eq!(parse("a = ) b ) c; d;", sync), Some(1));
+
+        // Popping nodes to get past an error is fine; discarding the token
+        // isn't.
+        let strict = RecoveryPolicy {
+            skip_tokens: false,
+            ..RecoveryPolicy::default()
+        };
+        assert_eq!(parse("a = ;", strict.clone()), Some(1));
+        assert_eq!(parse("a = ) ;", strict), None);
     }
 }
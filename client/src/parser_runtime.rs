@@ -1,5 +1,103 @@
-pub use crate::parser_generated::{Handler, NonterminalId, Token};
+pub use crate::parser_generated::{Handler, NonterminalId, TerminalId};
 use crate::parser::{Parser, Result};
+pub use crate::stack::ReduceStack;
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+
+/// The action table entry for accepting the input. Shift actions are state
+/// numbers, and reduce actions are negative: `-1 - production_index`.
+/// Entries are i16, which leaves room for 32767 states and 32766
+/// productions and keeps the table small enough to stay in cache.
+pub const ACCEPT: i16 = -0x7fff;
+/// The action table entry for a syntax error.
+pub const ERROR: i16 = ACCEPT - 1;
+
+/// A span of source text. `start` and `end` are byte offsets; `line` (1-based)
+/// and `column` (0-based, in characters) give the position of `start`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SourceLocation {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl SourceLocation {
+    pub fn new(start: usize, end: usize, line: u32, column: u32) -> SourceLocation {
+        SourceLocation {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// The empty span at the start of this one.
+    pub fn start_point(&self) -> SourceLocation {
+        SourceLocation::new(self.start, self.start, self.line, self.column)
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(&self, other: &SourceLocation) -> SourceLocation {
+        SourceLocation::new(self.start, other.end, self.line, self.column)
+    }
+}
+
+/// A token, with its text borrowed from the source where it can be: only
+/// text that had to be changed, like a string literal with escapes or a
+/// template with CR LF line terminators, is copied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token<'src> {
+    pub terminal_id: TerminalId,
+    pub loc: SourceLocation,
+    /// The text of the token, for terminals that carry a value. For string
+    /// literals and template pieces this is the cooked text; a template
+    /// piece's is None if it contains an escape sequence that has no cooked
+    /// value.
+    pub value: Option<Cow<'src, str>>,
+    /// For string literals, the source text between the quotes, and for
+    /// template pieces, the raw text. For reserved words and contextual
+    /// keywords, the word, and for names spelled with escapes, like
+    /// `\u0061`, the name as written.
+    pub raw: Option<Cow<'src, str>>,
+    /// For NumericLiterals, the value. Their `value` is the text as
+    /// written.
+    pub number: Option<f64>,
+    /// True if a line terminator comes between the token before this one
+    /// and this one, in whitespace or a comment.
+    pub newline_before: bool,
+}
+
+impl<'src> Token<'src> {
+    pub fn new(terminal_id: TerminalId, loc: SourceLocation) -> Token<'src> {
+        Token {
+            terminal_id,
+            loc,
+            value: None,
+            raw: None,
+            number: None,
+            newline_before: false,
+        }
+    }
+
+    pub fn get_id(&self) -> TerminalId {
+        self.terminal_id
+    }
+
+    /// A copy of the token that doesn't borrow from the source, for keeping
+    /// after the source is gone, as errors are.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            terminal_id: self.terminal_id,
+            loc: self.loc,
+            value: self.value.map(|value| Cow::Owned(value.into_owned())),
+            raw: self.raw.map(|raw| Cow::Owned(raw.into_owned())),
+            number: self.number,
+            newline_before: self.newline_before,
+        }
+    }
+}
 
 pub trait TokenStream {
     type Token;
@@ -8,47 +106,602 @@ pub trait TokenStream {
     fn token_as_index(t: &Self::Token) -> usize;
 }
 
+/// The action table, in one of the layouts the generator can write.
+#[derive(Clone, Copy)]
+pub enum ActionTable<'a> {
+    /// `state_count` rows of `action_width` entries.
+    Dense(&'a [i16]),
+    /// Rows packed into one array by row displacement, so that the error
+    /// entries of one row can hold the entries of others. The action for
+    /// `state` and `terminal` is `actions[base[state] + terminal]` if
+    /// `check` has `state` at that index, and ERROR otherwise.
+    Displaced {
+        base: &'a [usize],
+        actions: &'a [i16],
+        check: &'a [usize],
+    },
+}
+
 #[derive(Clone, Copy)]
 pub struct ParserTables<'a> {
     pub state_count: usize,
-    pub action_table: &'a [i64],
+    pub action_table: ActionTable<'a>,
     pub action_width: usize,
+    /// How each terminal is shown in messages, by index, like `` `;` ``.
+    pub terminal_names: &'a [&'a str],
     pub goto_table: &'a [usize],
     pub goto_width: usize,
+    /// The name of each nonterminal, by index.
+    pub nonterminal_names: &'a [&'a str],
+    /// For each production, the number of stack entries it pops and the
+    /// nonterminal it produces.
+    pub reduce_simulator: &'a [(usize, NonterminalId)],
+    /// The name of each production, like `Script ::= ScriptBody`.
+    pub production_names: &'a [&'a str],
+    /// How many conflicts the generator settled by picking an action, like
+    /// shifting the `else` of a nested `if`, rather than reporting them.
+    pub conflicts_resolved: usize,
+    /// The actions that lost those conflicts, as `(state, terminal, action)`,
+    /// sorted by state and terminal. GLR parsing tries them after the action
+    /// in the action table, in order.
+    pub alternatives: &'a [(usize, usize, i16)],
+}
+
+/// Numbers describing a set of parser tables.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TableStats {
+    pub state_count: usize,
+    pub terminal_count: usize,
+    pub nonterminal_count: usize,
+    pub production_count: usize,
+    pub action_table_bytes: usize,
+    pub goto_table_bytes: usize,
+    pub reduce_simulator_bytes: usize,
+    /// The fraction of action table entries that aren't errors.
+    pub action_density: f64,
+    /// The fraction of goto table entries that name a state.
+    pub goto_density: f64,
+    pub conflicts_resolved: usize,
+}
+
+impl TableStats {
+    pub fn total_bytes(&self) -> usize {
+        self.action_table_bytes + self.goto_table_bytes + self.reduce_simulator_bytes
+    }
+}
+
+/// Something wrong with a set of parser tables, found by
+/// ParserTables::validate().
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableProblem {
+    /// The action table isn't `state_count` rows of `action_width` entries,
+    /// or for a displaced table, there isn't a base for every state.
+    ActionTableSize { expected: usize, actual: usize },
+    /// A displaced action table's `check` array isn't as long as its
+    /// `actions`.
+    ActionCheckSize { expected: usize, actual: usize },
+    /// The goto table isn't `state_count` rows of `goto_width` entries.
+    GotoTableSize { expected: usize, actual: usize },
+    /// A shift action names a state that doesn't exist.
+    ShiftOutOfRange { state: usize, terminal: usize, target: usize },
+    /// A reduce action names a production that doesn't exist.
+    ReduceOutOfRange { state: usize, terminal: usize, production: usize },
+    /// A goto table entry names a state that doesn't exist.
+    GotoOutOfRange { state: usize, nonterminal: usize, target: usize },
+    /// A production produces a nonterminal that has no goto table column.
+    UnknownNonterminal { production: usize, nonterminal: usize },
+    /// There isn't a name for every terminal, nonterminal or production,
+    /// or there are extra names. `kind` says which.
+    NamesSize { kind: &'static str, expected: usize, actual: usize },
+    /// An entry in `alternatives` is out of order, or for a state or
+    /// terminal that doesn't exist.
+    MisplacedAlternative { index: usize },
+}
+
+impl fmt::Display for TableProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TableProblem::ActionTableSize { expected, actual } => {
+                write!(f, "action table has {} entries, expected {}", actual, expected)
+            }
+            TableProblem::ActionCheckSize { expected, actual } => {
+                write!(f, "action check array has {} entries, expected {}", actual, expected)
+            }
+            TableProblem::GotoTableSize { expected, actual } => {
+                write!(f, "goto table has {} entries, expected {}", actual, expected)
+            }
+            TableProblem::ShiftOutOfRange { state, terminal, target } => write!(
+                f,
+                "state {} shifts terminal {} to nonexistent state {}",
+                state, terminal, target
+            ),
+            TableProblem::ReduceOutOfRange { state, terminal, production } => write!(
+                f,
+                "state {} reduces nonexistent production {} on terminal {}",
+                state, production, terminal
+            ),
+            TableProblem::GotoOutOfRange { state, nonterminal, target } => write!(
+                f,
+                "state {} goes to nonexistent state {} after nonterminal {}",
+                state, target, nonterminal
+            ),
+            TableProblem::UnknownNonterminal { production, nonterminal } => write!(
+                f,
+                "production {} produces nonterminal {}, which has no goto column",
+                production, nonterminal
+            ),
+            TableProblem::NamesSize { kind, expected, actual } => {
+                write!(f, "there are {} {} names, expected {}", actual, kind, expected)
+            }
+            TableProblem::MisplacedAlternative { index } => {
+                write!(f, "alternative action {} is out of order or out of range", index)
+            }
+        }
+    }
+}
+
+impl ParserTables<'static> {
+    /// Tables that borrow arrays compiled into the binary, like the
+    /// generated TABLES, so nothing is copied or allocated at startup. The
+    /// shape of the goto table gives the number of states and nonterminals.
+    /// In a static's initializer this runs at compile time, so an action
+    /// table of the wrong size is a build error instead of a panic in
+    /// check().
+    #[allow(clippy::too_many_arguments)]
+    pub const fn from_static<const STATES: usize, const NONTERMINALS: usize>(
+        action_table: ActionTable<'static>,
+        action_width: usize,
+        terminal_names: &'static [&'static str],
+        goto_table: &'static [[usize; NONTERMINALS]; STATES],
+        nonterminal_names: &'static [&'static str; NONTERMINALS],
+        reduce_simulator: &'static [(usize, NonterminalId)],
+        production_names: &'static [&'static str],
+        alternatives: &'static [(usize, usize, i16)],
+        conflicts_resolved: usize,
+    ) -> ParserTables<'static> {
+        match action_table {
+            ActionTable::Dense(actions) => {
+                assert!(actions.len() == STATES * action_width);
+            }
+            ActionTable::Displaced { base, actions, check } => {
+                assert!(base.len() == STATES);
+                assert!(check.len() == actions.len());
+            }
+        }
+        assert!(terminal_names.len() == action_width);
+        assert!(production_names.len() == reduce_simulator.len());
+        ParserTables {
+            state_count: STATES,
+            action_table,
+            action_width,
+            terminal_names,
+            goto_table: goto_table.as_flattened(),
+            goto_width: NONTERMINALS,
+            nonterminal_names,
+            reduce_simulator,
+            production_names,
+            conflicts_resolved,
+            alternatives,
+        }
+    }
 }
 
 impl<'a> ParserTables<'a> {
+    /// The action to take in `state` when the next terminal is `terminal`.
+    pub fn action(&self, state: usize, terminal: usize) -> i16 {
+        match self.action_table {
+            ActionTable::Dense(actions) => actions[state * self.action_width + terminal],
+            ActionTable::Displaced { base, actions, check } => {
+                let i = base[state] + terminal;
+                match check.get(i) {
+                    Some(&owner) if owner == state => actions[i],
+                    _ => ERROR,
+                }
+            }
+        }
+    }
+
+    /// The actions besides action(state, terminal) that GLR parsing tries.
+    pub fn alternatives(&self, state: usize, terminal: usize) -> impl Iterator<Item = i16> + 'a {
+        let alternatives = self.alternatives;
+        let start = alternatives.partition_point(|&(s, t, _)| (s, t) < (state, terminal));
+        alternatives[start..]
+            .iter()
+            .take_while(move |&&(s, t, _)| (s, t) == (state, terminal))
+            .map(|&(_, _, action)| action)
+    }
+
+    /// Describe `problem` the way its Display does, but naming the
+    /// terminals, nonterminals and productions it's about instead of
+    /// giving their indices, where these tables have names for them.
+    pub fn describe(&self, problem: &TableProblem) -> String {
+        let name = |names: &[&str], index: usize, kind: &str| match names.get(index) {
+            Some(name) => name.to_string(),
+            None => format!("{} {}", kind, index),
+        };
+        match *problem {
+            TableProblem::ShiftOutOfRange { state, terminal, target } => format!(
+                "state {} shifts {} to nonexistent state {}",
+                state,
+                name(self.terminal_names, terminal, "terminal"),
+                target
+            ),
+            TableProblem::ReduceOutOfRange { state, terminal, production } => format!(
+                "state {} reduces nonexistent production {} on {}",
+                state,
+                production,
+                name(self.terminal_names, terminal, "terminal")
+            ),
+            TableProblem::GotoOutOfRange { state, nonterminal, target } => format!(
+                "state {} goes to nonexistent state {} after {}",
+                state,
+                target,
+                name(self.nonterminal_names, nonterminal, "nonterminal")
+            ),
+            TableProblem::UnknownNonterminal { production, nonterminal } => format!(
+                "`{}` produces nonterminal {}, which has no goto column",
+                name(self.production_names, production, "production"),
+                nonterminal
+            ),
+            _ => problem.to_string(),
+        }
+    }
+
+    /// True if there are any alternatives for `state` and `terminal`.
+    pub fn has_alternatives(&self, state: usize, terminal: usize) -> bool {
+        self.alternatives(state, terminal).next().is_some()
+    }
+
     pub fn check(&self) {
-        assert_eq!(
-            self.action_table.len(),
-            self.state_count * self.action_width
-        );
+        match self.action_table {
+            ActionTable::Dense(actions) => {
+                assert_eq!(actions.len(), self.state_count * self.action_width);
+            }
+            ActionTable::Displaced { base, actions, check } => {
+                assert_eq!(base.len(), self.state_count);
+                assert_eq!(check.len(), actions.len());
+            }
+        }
         assert_eq!(
             self.goto_table.len(),
             self.state_count * self.goto_width
         );
     }
+
+    /// The size of the action table as laid out, in bytes.
+    fn action_table_bytes(&self) -> usize {
+        match self.action_table {
+            ActionTable::Dense(actions) => mem::size_of_val(actions),
+            ActionTable::Displaced { base, actions, check } => {
+                mem::size_of_val(base) + mem::size_of_val(actions) + mem::size_of_val(check)
+            }
+        }
+    }
+
+    /// Whether the action table's arrays are the sizes `state_count` and
+    /// `action_width` call for. Reports what's wrong to `problems`.
+    fn check_action_table_size(&self, problems: &mut Vec<TableProblem>) -> bool {
+        let count = problems.len();
+        match self.action_table {
+            ActionTable::Dense(actions) => {
+                let expected = self.state_count * self.action_width;
+                if actions.len() != expected {
+                    problems.push(TableProblem::ActionTableSize {
+                        expected,
+                        actual: actions.len(),
+                    });
+                }
+            }
+            ActionTable::Displaced { base, actions, check } => {
+                if base.len() != self.state_count {
+                    problems.push(TableProblem::ActionTableSize {
+                        expected: self.state_count,
+                        actual: base.len(),
+                    });
+                }
+                if check.len() != actions.len() {
+                    problems.push(TableProblem::ActionCheckSize {
+                        expected: actions.len(),
+                        actual: check.len(),
+                    });
+                }
+            }
+        }
+        problems.len() == count
+    }
+
+    pub fn stats(&self) -> TableStats {
+        let actions = (0..self.state_count)
+            .map(|state| {
+                (0..self.action_width)
+                    .filter(|&t| self.action(state, t) != ERROR)
+                    .count()
+            })
+            .sum();
+        let gotos = self.goto_table.iter().filter(|&&g| g != 0).count();
+        let density = |used: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                used as f64 / total as f64
+            }
+        };
+        TableStats {
+            state_count: self.state_count,
+            terminal_count: self.action_width,
+            nonterminal_count: self.goto_width,
+            production_count: self.reduce_simulator.len(),
+            action_table_bytes: self.action_table_bytes(),
+            goto_table_bytes: mem::size_of_val(self.goto_table),
+            reduce_simulator_bytes: mem::size_of_val(self.reduce_simulator),
+            action_density: density(actions, self.state_count * self.action_width),
+            goto_density: density(gotos, self.goto_table.len()),
+            conflicts_resolved: self.conflicts_resolved,
+        }
+    }
+
+    /// Check that the tables are the sizes they claim to be, and that
+    /// every state and production they name exists. Unlike check(), this
+    /// looks at every entry, and it reports what's wrong instead of
+    /// panicking. Action table entries are only looked at if the action
+    /// table is the right size.
+    pub fn validate(&self) -> std::result::Result<(), Vec<TableProblem>> {
+        let mut problems = vec![];
+        let actions_ok = self.check_action_table_size(&mut problems);
+        let expected = self.state_count * self.goto_width;
+        if self.goto_table.len() != expected {
+            problems.push(TableProblem::GotoTableSize {
+                expected,
+                actual: self.goto_table.len(),
+            });
+        }
+
+        let check_action = |state, terminal, action: i16, problems: &mut Vec<TableProblem>| {
+            if action >= 0 {
+                let target = action as usize;
+                if target >= self.state_count {
+                    problems.push(TableProblem::ShiftOutOfRange { state, terminal, target });
+                }
+            } else if action != ACCEPT && action != ERROR {
+                let production = (-action - 1) as usize;
+                if production >= self.reduce_simulator.len() {
+                    problems.push(TableProblem::ReduceOutOfRange { state, terminal, production });
+                }
+            }
+        };
+        let states = if actions_ok { self.state_count } else { 0 };
+        for state in 0..states {
+            for terminal in 0..self.action_width {
+                check_action(state, terminal, self.action(state, terminal), &mut problems);
+            }
+        }
+        for (index, &(state, terminal, action)) in self.alternatives.iter().enumerate() {
+            let in_order = index == 0 || {
+                let (s, t, _) = self.alternatives[index - 1];
+                (s, t) <= (state, terminal)
+            };
+            if !in_order || state >= self.state_count || terminal >= self.action_width {
+                problems.push(TableProblem::MisplacedAlternative { index });
+            } else {
+                check_action(state, terminal, action, &mut problems);
+            }
+        }
+        // max(1) keeps chunks() from panicking. A table with no columns has
+        // no entries to check anyway, or its size is already wrong.
+        for (state, row) in self.goto_table.chunks(self.goto_width.max(1)).enumerate() {
+            for (nonterminal, &target) in row.iter().enumerate() {
+                if target >= self.state_count {
+                    problems.push(TableProblem::GotoOutOfRange { state, nonterminal, target });
+                }
+            }
+        }
+        for (production, &(_, nt)) in self.reduce_simulator.iter().enumerate() {
+            if nt as usize >= self.goto_width {
+                problems.push(TableProblem::UnknownNonterminal {
+                    production,
+                    nonterminal: nt as usize,
+                });
+            }
+        }
+        let names = [
+            ("terminal", self.terminal_names, self.action_width),
+            ("nonterminal", self.nonterminal_names, self.goto_width),
+            ("production", self.production_names, self.reduce_simulator.len()),
+        ];
+        for (kind, names, expected) in names {
+            if names.len() != expected {
+                problems.push(TableProblem::NamesSize {
+                    kind,
+                    expected,
+                    actual: names.len(),
+                });
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// What a parser calls to run a reduce action: pop the values of production
+/// `prod_index`'s right-hand side off `stack`, push the value `handler`
+/// builds from them, and return the production's nonterminal.
+///
+/// Any closure of that shape is a Reducer. The generated parser is built
+/// with parser_generated::Reduce, which calls the generated reduce() directly
+/// rather than through a function pointer.
+pub trait Reducer<'a, H: Handler<'a>> {
+    fn reduce(
+        &self,
+        handler: &H,
+        prod_index: usize,
+        loc: SourceLocation,
+        stack: &mut ReduceStack<H::Value>,
+    ) -> NonterminalId;
 }
 
-pub fn parse<H: Handler, In, Out>(
-    handler: &H,
+impl<'a, H, F> Reducer<'a, H> for F
+where
+    H: Handler<'a>,
+    F: Fn(&H, usize, SourceLocation, &mut ReduceStack<H::Value>) -> NonterminalId,
+{
+    #[inline(always)]
+    fn reduce(
+        &self,
+        handler: &H,
+        prod_index: usize,
+        loc: SourceLocation,
+        stack: &mut ReduceStack<H::Value>,
+    ) -> NonterminalId {
+        self(handler, prod_index, loc, stack)
+    }
+}
+
+pub fn parse<'a, H: Handler<'a>, In, Out>(
+    handler: &'a H,
     mut tokens: In,
     start_state: usize,
-    tables: &ParserTables,
+    tables: &'a ParserTables<'a>,
     reduce: Out,
-) -> Result<*mut ()>
+) -> Result<H::Value>
 where
-    In: TokenStream<Token = Token>,
-    Out: Fn(&H, usize, &mut Vec<*mut ()>) -> NonterminalId,
+    In: TokenStream<Token = Token<'a>>,
+    Out: Reducer<'a, H>,
 {
     tables.check();
 
-    let mut parser = Parser::new(tables, reduce, handler, start_state);
+    let mut parser = Parser::new(tables, reduce, handler, start_state, false);
 
-    let mut t = tokens.peek();
-    while *t != Token::End {
+    while tokens.peek().get_id() != TerminalId::End {
         parser.write_token(tokens.take())?;
-        t = tokens.peek();
     }
-    parser.close()
+    parser.close(tokens.peek().loc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ActionTable, ParserTables, TableProblem, TableStats, ERROR};
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::{reduce, DefaultHandler, EntryPoint, TerminalId, TABLES};
+
+    #[test]
+    fn table_validation() {
+        use crate::parser_generated::{NONTERMINAL_NAMES, TERMINAL_NAMES};
+        assert_eq!(TABLES.validate(), Ok(()));
+        let stats = TABLES.stats();
+        assert_eq!(stats.state_count, TABLES.state_count);
+        assert!(stats.action_table_bytes > 0);
+        assert!(0.0 < stats.action_density && stats.action_density < 1.0);
+        assert!(0.0 < stats.goto_density && stats.goto_density < 1.0);
+        assert_eq!(stats.conflicts_resolved, 1);
+
+        let mut goto_table = TABLES.goto_table.to_vec();
+        goto_table[3] = TABLES.state_count;
+        goto_table.pop();
+        let broken = ParserTables {
+            goto_table: &goto_table,
+            ..TABLES
+        };
+        let problems = broken.validate().unwrap_err();
+        assert_eq!(
+            problems,
+            [
+                TableProblem::GotoTableSize {
+                    expected: TABLES.goto_table.len(),
+                    actual: TABLES.goto_table.len() - 1,
+                },
+                TableProblem::GotoOutOfRange {
+                    state: 0,
+                    nonterminal: 3,
+                    target: TABLES.state_count,
+                },
+            ]
+        );
+        assert_eq!(
+            broken.describe(&problems[1]),
+            format!(
+                "state 0 goes to nonexistent state {} after {}",
+                TABLES.state_count, NONTERMINAL_NAMES[3]
+            )
+        );
+        let problem = TableProblem::ShiftOutOfRange {
+            state: 1,
+            terminal: TerminalId::Semicolon as usize,
+            target: 9999,
+        };
+        assert_eq!(TABLES.describe(&problem), "state 1 shifts `;` to nonexistent state 9999");
+        assert_eq!(TERMINAL_NAMES.len(), TABLES.action_width);
+        assert_eq!(TerminalId::Semicolon.to_string(), TERMINAL_NAMES[TerminalId::Semicolon as usize]);
+    }
+
+    #[test]
+    fn displaced_action_table() {
+        // Lay TABLES out with rows side by side. Nothing overlaps, but it
+        // goes through the same lookups as a packed table.
+        let (states, width) = (TABLES.state_count, TABLES.action_width);
+        let mut actions = vec![ERROR; states * width];
+        let mut check = vec![states; states * width];
+        for state in 0..states {
+            for t in 0..width {
+                actions[state * width + t] = TABLES.action(state, t);
+                check[state * width + t] = state;
+            }
+        }
+        let base: Vec<usize> = (0..states).map(|state| state * width).collect();
+        let tables = ParserTables {
+            action_table: ActionTable::Displaced {
+                base: &base,
+                actions: &actions,
+                check: &check,
+            },
+            ..TABLES
+        };
+        assert_eq!(tables.validate(), Ok(()));
+        assert_eq!(
+            tables.stats(),
+            TableStats {
+                action_table_bytes: states * 8 + states * width * (2 + 8),
+                ..TABLES.stats()
+            }
+        );
+        for state in 0..states {
+            for t in 0..width {
+                assert_eq!(tables.action(state, t), TABLES.action(state, t));
+            }
+        }
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let src = "function f(a, b) { for (let i = 0; i < a; i++) b += i; return b; }";
+        let mut parser = Parser::new(&tables, reduce, &handler, EntryPoint::Script.state(), false);
+        assert!(parser.parse_script(src).is_ok());
+        let mut parser = Parser::new(&tables, reduce, &handler, EntryPoint::Script.state(), false);
+        assert!(parser.parse_script("a +;").is_err());
+
+        check.pop();
+        let broken = ParserTables {
+            action_table: ActionTable::Displaced {
+                base: &base[1..],
+                actions: &actions,
+                check: &check,
+            },
+            ..TABLES
+        };
+        assert_eq!(
+            broken.validate(),
+            Err(vec![
+                TableProblem::ActionTableSize {
+                    expected: states,
+                    actual: states - 1,
+                },
+                TableProblem::ActionCheckSize {
+                    expected: states * width,
+                    actual: states * width - 1,
+                },
+            ])
+        );
+    }
 }
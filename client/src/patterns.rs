@@ -0,0 +1,648 @@
+//! Early errors for destructuring.
+//!
+//! The grammar parses `[a, b] = c` and `({a = 1}) => a` with cover grammars,
+//! so a pattern is an array or object literal until the parser sees the `=`
+//! or `=>` after it. refine.rs turns such literals into patterns in the
+//! concrete AST; this finds the errors in the ones that can't be, like
+//! `[f()] = x` and `[...a, b] = c`, without needing an AST.
+//!
+//! `EarlyErrors` passes every token and reduction to a `PatternLog`, which
+//! keeps a small tree of the expressions it has seen: just enough to know
+//! what each element of a literal is. Once an expression is part of one
+//! that can't be a pattern, its subtree is dropped.
+
+use crate::early_errors::{is_assignment_operator, EarlyError, EarlyErrorKind};
+use crate::parser_generated::{NonterminalId, TerminalId};
+use crate::parser_runtime::{SourceLocation, Token};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// An identifier reference. The flag is true for eval and arguments.
+    Name(bool),
+    /// A property access, like `a.b` or `f()[0]`.
+    Member,
+    Array,
+    Object,
+    /// `k: v` or `...v` in an object literal.
+    Property,
+    /// `a = 1` in an object literal, which is only allowed in a pattern.
+    /// The flag is true for eval and arguments.
+    CoverInit(bool),
+    /// The parentheses before an arrow function's `=>`, or around an
+    /// expression.
+    Parens,
+    /// `async(a, b)`, which is a call unless `=>` comes next. Its children
+    /// are the arguments.
+    AsyncHead,
+    /// `a, b`.
+    Sequence,
+    /// `a = b`.
+    Assign,
+    /// A binding identifier or binding pattern, as in `(...a) => a`.
+    Binding,
+    ArrowFunction,
+    /// Anything else; none of these can be a pattern or a target.
+    Other,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    loc: SourceLocation,
+    kind: Kind,
+    /// Kept only for the kinds that a pattern can be made of.
+    children: Vec<Node>,
+    /// True if the node comes right after a `...`.
+    spread: bool,
+    /// True if a `,` comes just before the closing bracket.
+    trailing_comma: bool,
+    /// The first error in this subtree that only applies if it turns out to
+    /// be an expression: an `a = 1` in an object literal that isn't part of
+    /// a pattern yet, or parentheses with a rest parameter that aren't an
+    /// arrow function's yet.
+    pending: Option<(EarlyErrorKind, SourceLocation)>,
+    /// For the left-hand side of an assignment, whether its operator is
+    /// `=` rather than, say, `+=`.
+    plain_assignment: Option<bool>,
+    /// For a name, or `a = 1` in an object literal, the name and where it
+    /// is.
+    name: Option<(String, SourceLocation)>,
+    /// For `k: v` in an object literal, true if `k` is `__proto__`, which
+    /// sets the prototype instead of a property.
+    proto: bool,
+}
+
+impl Node {
+    /// True for parentheses around a single expression.
+    fn is_parenthesized(&self) -> bool {
+        self.kind == Kind::Parens
+            && !self.trailing_comma
+            && matches!(&self.children[..], [inner] if !matches!(inner.kind, Kind::Sequence | Kind::Binding))
+    }
+
+    /// True if the node is what the spec calls a simple assignment target.
+    fn is_simple_target(&self) -> bool {
+        match self.kind {
+            Kind::Name(_) | Kind::Member => true,
+            _ => self.is_parenthesized() && self.children[0].is_simple_target(),
+        }
+    }
+
+    fn is_literal(&self) -> bool {
+        matches!(self.kind, Kind::Array | Kind::Object)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PatternLog {
+    /// Expressions that aren't part of a bigger one yet, innermost last.
+    nodes: Vec<Node>,
+    /// The last two terminals shifted, newest first.
+    previous: [Option<TerminalId>; 2],
+    /// The last token shifted, if it was an identifier: where it is and
+    /// the name.
+    identifier: Option<(SourceLocation, String)>,
+    /// Where each token just after a `...` starts, for the expressions
+    /// that aren't complete yet.
+    spreads: Vec<usize>,
+    /// Where the first token in the head of each `for` statement starts.
+    for_heads: Vec<usize>,
+    /// Where each `__proto__` before a `:` starts, for the expressions that
+    /// aren't complete yet, and where the last token starts if it's one.
+    proto_keys: Vec<usize>,
+    proto: Option<usize>,
+    errors: Vec<EarlyError>,
+}
+
+impl PatternLog {
+    pub fn new() -> PatternLog {
+        PatternLog::default()
+    }
+
+    /// `token` is about to be shifted, in strict mode code if `strict` is
+    /// true.
+    pub fn shift(&mut self, token: &Token, strict: bool) {
+        let t = token.get_id();
+        match self.previous {
+            [Some(TerminalId::FullStopFullStopFullStop), _] => self.spreads.push(token.loc.start),
+            [Some(TerminalId::LeftParenthesis), Some(TerminalId::For)]
+            | [Some(TerminalId::LeftParenthesis), Some(TerminalId::Await)] => self.for_heads.push(token.loc.start),
+            _ => {}
+        }
+        if is_assignment_operator(t) {
+            if let Some(lhs) = self.nodes.last_mut() {
+                lhs.plain_assignment = Some(t == TerminalId::EqualsSign);
+            }
+        }
+        if matches!(t, TerminalId::Of | TerminalId::In) {
+            // The left-hand side of a for-in or for-of statement.
+            let is_head = match self.nodes.last() {
+                Some(node) => self.for_heads.last() == Some(&node.loc.start) && node.kind != Kind::Binding,
+                None => false,
+            };
+            if is_head {
+                if let Some(mut node) = self.nodes.pop() {
+                    self.target(&node, strict, false);
+                    node.pending = None;
+                    self.nodes.push(node);
+                }
+            }
+        }
+        if let (TerminalId::Colon, Some(start)) = (t, self.proto) {
+            self.proto_keys.push(start);
+        }
+        self.proto = match (t, token.value.as_deref()) {
+            (TerminalId::Identifier | TerminalId::IdentifierName | TerminalId::StringLiteral, Some("__proto__")) => {
+                Some(token.loc.start)
+            }
+            _ => None,
+        };
+        self.previous = [Some(t), self.previous[0]];
+        self.identifier = match (t, token.value.as_deref()) {
+            (TerminalId::Identifier, Some(name)) => Some((token.loc, name.to_string())),
+            _ => None,
+        };
+    }
+
+    /// The parser reduced `nt`, spanning `loc`.
+    pub fn reduced(&mut self, nt: NonterminalId, loc: SourceLocation, strict: bool) {
+        let kind = match nt {
+            // Parts of a literal, call or assignment whose children are
+            // the elements, arguments or operands themselves.
+            NonterminalId::ElementList
+            | NonterminalId::Elision
+            | NonterminalId::SpreadElement
+            | NonterminalId::PropertyDefinitionList
+            | NonterminalId::Arguments
+            | NonterminalId::ArgumentList
+            | NonterminalId::AssignmentOperator => return,
+            _ if self.nodes.last().is_some_and(|n| n.loc == loc) => {
+                // One name for the same thing, as when an IdentifierReference
+                // is reduced to a PrimaryExpression. The first one says what
+                // it is. An expression statement without a semicolon is
+                // still the end of the expression, though.
+                if nt == NonterminalId::ExpressionStatement {
+                    if let Some((kind, loc)) = self.nodes.last_mut().and_then(|n| n.pending.take()) {
+                        self.error(kind, loc);
+                    }
+                }
+                return;
+            }
+            NonterminalId::IdentifierReference => match &self.identifier {
+                Some((id, name)) if *id == loc => Kind::Name(name == "eval" || name == "arguments"),
+                _ => Kind::Name(false),
+            },
+            NonterminalId::MemberExpression
+            | NonterminalId::CallExpression
+            | NonterminalId::SuperProperty
+            | NonterminalId::CoverCallExpressionAndAsyncArrowHead => match self.previous[0] {
+                Some(TerminalId::IdentifierName)
+                | Some(TerminalId::PrivateIdentifier)
+                | Some(TerminalId::RightSquareBracket) => Kind::Member,
+                Some(TerminalId::RightParenthesis) if nt == NonterminalId::CoverCallExpressionAndAsyncArrowHead => {
+                    Kind::AsyncHead
+                }
+                // A call, `new` expression, or tagged template.
+                _ => Kind::Other,
+            },
+            NonterminalId::ArrayLiteral => Kind::Array,
+            NonterminalId::ObjectLiteral => Kind::Object,
+            NonterminalId::PropertyDefinition => Kind::Property,
+            NonterminalId::CoverInitializedName => Kind::CoverInit(false),
+            NonterminalId::CoverParenthesizedExpressionAndArrowParameterList => Kind::Parens,
+            NonterminalId::Expression => Kind::Sequence,
+            NonterminalId::AssignmentExpression => Kind::Assign,
+            NonterminalId::ArrowFunction | NonterminalId::AsyncArrowFunction => Kind::ArrowFunction,
+            NonterminalId::BindingIdentifier
+            | NonterminalId::BindingPattern
+            | NonterminalId::ArrayBindingPattern
+            | NonterminalId::ObjectBindingPattern
+            | NonterminalId::ForBinding
+            | NonterminalId::ForDeclaration => Kind::Binding,
+            _ => Kind::Other,
+        };
+
+        let first = self
+            .nodes
+            .iter()
+            .rposition(|n| n.loc.start < loc.start || n.loc.end > loc.end)
+            .map_or(0, |i| i + 1);
+        let mut children = self.nodes.split_off(first);
+        while self.spreads.last().is_some_and(|&start| start > loc.start) {
+            self.spreads.pop();
+        }
+        while self.for_heads.last().is_some_and(|&start| start > loc.start) {
+            self.for_heads.pop();
+        }
+        while self.proto_keys.last().is_some_and(|&start| start > loc.start) {
+            self.proto_keys.pop();
+        }
+        let mut node = Node {
+            loc,
+            kind,
+            children: vec![],
+            spread: self.spreads.last() == Some(&loc.start),
+            trailing_comma: self.previous[1] == Some(TerminalId::Comma),
+            pending: None,
+            plain_assignment: None,
+            name: None,
+            proto: false,
+        };
+
+        match kind {
+            Kind::Name(_) => {
+                node.name = self.identifier.clone().filter(|(id, _)| *id == loc).map(|(_, name)| (name, loc));
+            }
+            Kind::Array | Kind::Object => {
+                let duplicate_proto = children.iter().filter(|c| c.proto).nth(1);
+                node.pending = children
+                    .iter()
+                    .find_map(|c| c.pending)
+                    .or(duplicate_proto.map(|c| (EarlyErrorKind::DuplicateProto, c.loc)));
+            }
+            Kind::Parens => {
+                node.pending = children.iter().find_map(|c| match c.kind {
+                    Kind::Binding => Some((EarlyErrorKind::RestOutsideParameters, c.loc)),
+                    _ => c.pending,
+                });
+            }
+            Kind::AsyncHead => {
+                if children.first().and_then(|c| c.name.as_ref()).is_some_and(|(name, _)| name == "async") {
+                    children.remove(0);
+                    node.pending = children.iter().find_map(|c| c.pending);
+                } else {
+                    node.kind = Kind::Other;
+                    self.expressions(&children);
+                    children.clear();
+                }
+            }
+            Kind::Sequence => {
+                // Flatten `a, b, c`, which is parsed as `(a, b), c`.
+                if children.first().is_some_and(|c| c.kind == Kind::Sequence && c.loc.start == loc.start) {
+                    let mut list = children.remove(0).children;
+                    list.append(&mut children);
+                    children = list;
+                }
+                node.pending = children.iter().find_map(|c| c.pending);
+            }
+            Kind::Property => {
+                // The key of `k: v`, if there is one, is an expression.
+                if children.len() > 1 {
+                    let key = children.remove(0);
+                    if self.proto_keys.last() == Some(&key.loc.start) {
+                        self.proto_keys.pop();
+                        node.proto = true;
+                    }
+                    self.expression(&key);
+                }
+                node.pending = children.iter().find_map(|c| c.pending);
+            }
+            Kind::CoverInit(_) => {
+                if let Some(Node { kind: Kind::Name(eval_or_arguments), name, .. }) = children.first() {
+                    node.kind = Kind::CoverInit(*eval_or_arguments);
+                    node.name = name.clone();
+                }
+                for child in &children {
+                    self.expression(child);
+                }
+                children.clear();
+                node.pending = Some((EarlyErrorKind::ShorthandInitializer, loc));
+            }
+            Kind::Assign => {
+                let plain = children.first().and_then(|lhs| lhs.plain_assignment);
+                if let [lhs, rhs] = &mut children[..] {
+                    if plain == Some(true) {
+                        self.target(lhs, strict, true);
+                        lhs.pending = None;
+                    } else if !lhs.is_simple_target() {
+                        self.error(EarlyErrorKind::InvalidAssignmentTarget, lhs.loc);
+                    }
+                    self.expression(rhs);
+                }
+                if plain != Some(true) {
+                    node.kind = Kind::Other;
+                    self.expressions(&children);
+                    children.clear();
+                }
+            }
+            Kind::ArrowFunction => {
+                if let Some(params) = children.first() {
+                    self.arrow_parameters(params, strict);
+                }
+                for child in children.iter().skip(1) {
+                    self.expression(child);
+                }
+                children.clear();
+            }
+            _ => {
+                self.expressions(&children);
+                children.clear();
+            }
+        }
+        node.children = children;
+        self.nodes.push(node);
+    }
+
+    /// If the parser has just reduced an arrow function's parameters, where
+    /// they are and the names they bind in parentheses, in source order.
+    /// A rest parameter's names, and a parameter without parentheses, are
+    /// binding identifiers, which the caller sees as they're reduced.
+    pub fn arrow_parameter_names(&self) -> Option<(SourceLocation, Vec<(String, SourceLocation)>)> {
+        let params = self.nodes.last()?;
+        let mut names = vec![];
+        if matches!(params.kind, Kind::Parens | Kind::AsyncHead) {
+            bound_names(params, &mut names);
+        }
+        Some((params.loc, names))
+    }
+
+    /// True if the arrow function parameters the parser has just reduced
+    /// are simple: names, without default values, a rest parameter or
+    /// patterns.
+    pub fn arrow_parameters_simple(&self) -> bool {
+        self.nodes.last().is_some_and(simple_parameters)
+    }
+
+    /// Take the errors found so far, oldest first.
+    pub fn take_errors(&mut self) -> Vec<EarlyError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn error(&mut self, kind: EarlyErrorKind, loc: SourceLocation) {
+        self.errors.push((kind, None, loc, None));
+    }
+
+    /// `node` turned out to be an expression, not part of a pattern.
+    fn expression(&mut self, node: &Node) {
+        if let Some((kind, loc)) = node.pending {
+            self.error(kind, loc);
+        }
+    }
+
+    fn expressions(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.expression(node);
+        }
+    }
+
+    /// Check `node`, which is being assigned to. `top` is true if it's the
+    /// whole left-hand side of an assignment, where the parser already
+    /// checks for eval and arguments.
+    fn target(&mut self, node: &Node, strict: bool, top: bool) {
+        match node.kind {
+            Kind::Name(true) if strict && !top => self.error(EarlyErrorKind::AssignEvalOrArguments, node.loc),
+            Kind::Array | Kind::Object => self.pattern(node, strict),
+            _ if node.is_simple_target() => {}
+            _ => self.error(EarlyErrorKind::InvalidAssignmentTarget, node.loc),
+        }
+    }
+
+    /// Check an array or object literal that is an assignment pattern.
+    fn pattern(&mut self, node: &Node, strict: bool) {
+        let last = node.children.len().saturating_sub(1);
+        for (i, element) in node.children.iter().enumerate() {
+            let rest = if node.kind == Kind::Object && element.kind == Kind::Property {
+                element.children.first().is_some_and(|v| v.spread)
+            } else {
+                element.spread
+            };
+            if rest {
+                // A rest element comes last, with nothing after it, and for
+                // objects it can't be a pattern itself.
+                let target = match element.kind {
+                    Kind::Property => &element.children[0],
+                    _ => element,
+                };
+                if i != last || node.trailing_comma || target.kind == Kind::Assign {
+                    self.error(EarlyErrorKind::InvalidAssignmentTarget, element.loc);
+                } else if node.kind == Kind::Object && !target.is_simple_target() {
+                    self.error(EarlyErrorKind::InvalidAssignmentTarget, target.loc);
+                } else {
+                    self.target(target, strict, false);
+                }
+                continue;
+            }
+            let target = match (node.kind, element.kind) {
+                (Kind::Object, Kind::Name(true)) | (Kind::Object, Kind::CoverInit(true)) => {
+                    if strict {
+                        self.error(EarlyErrorKind::AssignEvalOrArguments, element.loc);
+                    }
+                    continue;
+                }
+                (Kind::Object, Kind::Name(_)) | (Kind::Object, Kind::CoverInit(_)) => continue,
+                (Kind::Object, Kind::Property) => match element.children.last() {
+                    Some(value) => value,
+                    None => continue,
+                },
+                // A method.
+                (Kind::Object, _) => {
+                    self.error(EarlyErrorKind::InvalidAssignmentTarget, element.loc);
+                    continue;
+                }
+                _ => element,
+            };
+            // The target of an element with a default value, like `a = 1`,
+            // was checked when the assignment was reduced.
+            if target.kind != Kind::Assign {
+                self.target(target, strict, false);
+            }
+        }
+    }
+
+    /// Check the parameters of an arrow function. If they're in
+    /// parentheses, they were parsed as an expression.
+    fn arrow_parameters(&mut self, params: &Node, strict: bool) {
+        if !matches!(params.kind, Kind::Parens | Kind::AsyncHead) {
+            return;
+        }
+        for param in &params.children {
+            match param.kind {
+                // A rest parameter.
+                Kind::Binding => {}
+                Kind::Sequence => {
+                    for param in &param.children {
+                        self.parameter(param, strict);
+                    }
+                }
+                _ => self.parameter(param, strict),
+            }
+        }
+    }
+
+    /// Check `node` as a parameter, or an element of a binding pattern.
+    fn parameter(&mut self, node: &Node, strict: bool) {
+        let target = match node.kind {
+            Kind::Assign => &node.children[0],
+            _ => node,
+        };
+        match target.kind {
+            Kind::Name(true) if strict => self.error(EarlyErrorKind::BindEvalOrArguments, target.loc),
+            Kind::Name(_) => {}
+            Kind::Array | Kind::Object => self.binding_pattern(target, strict),
+            _ => self.error(EarlyErrorKind::InvalidParameter, target.loc),
+        }
+    }
+
+    /// Check an array or object literal that is a binding pattern.
+    fn binding_pattern(&mut self, node: &Node, strict: bool) {
+        let last = node.children.len().saturating_sub(1);
+        for (i, element) in node.children.iter().enumerate() {
+            let (rest, target) = match (node.kind, element.kind) {
+                (Kind::Object, Kind::Property) => match element.children.last() {
+                    Some(value) => (value.spread, value),
+                    None => continue,
+                },
+                (Kind::Object, Kind::CoverInit(eval_or_arguments)) => {
+                    if eval_or_arguments && strict {
+                        self.error(EarlyErrorKind::BindEvalOrArguments, element.loc);
+                    }
+                    continue;
+                }
+                (Kind::Object, Kind::Name(_)) => (false, element),
+                (Kind::Object, _) => {
+                    self.error(EarlyErrorKind::InvalidParameter, element.loc);
+                    continue;
+                }
+                _ => (element.spread, element),
+            };
+            if rest {
+                let allowed = match node.kind {
+                    Kind::Object => matches!(target.kind, Kind::Name(_)),
+                    _ => matches!(target.kind, Kind::Name(_)) || target.is_literal(),
+                };
+                if i != last || node.trailing_comma || !allowed {
+                    self.error(EarlyErrorKind::InvalidParameter, element.loc);
+                    continue;
+                }
+            }
+            self.parameter(target, strict);
+        }
+    }
+}
+
+/// Add the names that `node` would bind as a parameter to `names`.
+fn simple_parameters(node: &Node) -> bool {
+    match node.kind {
+        // A parameter without parentheses, or a rest parameter, which in
+        // an async arrow function's head is a name after `...`.
+        Kind::Binding | Kind::Name(_) => !node.spread,
+        Kind::Parens | Kind::AsyncHead | Kind::Sequence => node.children.iter().all(simple_parameters),
+        _ => false,
+    }
+}
+
+fn bound_names(node: &Node, names: &mut Vec<(String, SourceLocation)>) {
+    match node.kind {
+        Kind::Name(_) | Kind::CoverInit(_) => names.extend(node.name.clone()),
+        // A default value binds nothing.
+        Kind::Assign => {
+            if let Some(target) = node.children.first() {
+                bound_names(target, names);
+            }
+        }
+        // The key of `k: v` was dropped; the value is what's bound.
+        Kind::Array | Kind::Object | Kind::Property | Kind::Parens | Kind::AsyncHead | Kind::Sequence => {
+            for child in &node.children {
+                bound_names(child, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::early_errors::EarlyErrorKind;
+    use crate::parser::Parser;
+    use crate::parser_generated::DefaultHandler;
+    use crate::testing::early_error;
+
+    #[test]
+    fn destructuring() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("[a, , b = 1, ...c] = d; ({a, b: [c], d = 1, ...e} = f);", None),
+            ("[a.b, c[0], ...d.e] = f; ({a: b.c, d: e[0] = 1} = g);", None),
+            ("[(a), ((b.c))] = d; [a = {b: 1}] = c; ({a: {b} = {}} = c);", None),
+            ("var [a, {b, c: [d] = []}, ...e] = f; let {g = 1, ...h} = i;", None),
+            ("function f([a, b] = [], {c, d = 1}, ...[e]) {}", None),
+            ("f = ([a, b], {c = 1, ...d}, ...[e]) => 1;", None),
+            ("for ([a, b] of c); for ({a = 1} of b); for (a.b in c);", None),
+            ("x = {a = 1} = b; f(({a = 1}) => a);", None),
+            ("a += 1; a.b -= 1; a[0] **= 2; (a) *= 3;", None),
+            ("[...a, b] = c;", Some(InvalidAssignmentTarget)),
+            ("[...a,] = b;", Some(InvalidAssignmentTarget)),
+            ("({...a,} = b);", Some(InvalidAssignmentTarget)),
+            ("({a: 1} = b);", Some(InvalidAssignmentTarget)),
+            ("({a(){}} = b);", Some(InvalidAssignmentTarget)),
+            ("({...{a}} = b);", Some(InvalidAssignmentTarget)),
+            ("[([a])] = b;", Some(InvalidAssignmentTarget)),
+            ("f() = 1;", Some(InvalidAssignmentTarget)),
+            ("for (f() of x);", Some(InvalidAssignmentTarget)),
+            ("[a] += 1;", Some(InvalidAssignmentTarget)),
+            ("f() += 1;", Some(InvalidAssignmentTarget)),
+            ("'use strict'; [eval] = 1;", Some(AssignEvalOrArguments)),
+            ("'use strict'; ([arguments]) => 1;", Some(BindEvalOrArguments)),
+            ("(a + b) => 1;", Some(InvalidParameter)),
+            ("([a.b]) => 1;", Some(InvalidParameter)),
+            ("({a = 1});", Some(ShorthandInitializer)),
+            ("f({a = 1});", Some(ShorthandInitializer)),
+            ("x = [{a = 1}];", Some(ShorthandInitializer)),
+            ("[a = {b = 1}] = c;", Some(ShorthandInitializer)),
+            ("x = {__proto__: a, '__proto__': b};", Some(DuplicateProto)),
+            ("f({__proto__: a, b, __proto__: c});", Some(DuplicateProto)),
+            ("x = {__proto__: {__proto__: a, __proto__: b}};", Some(DuplicateProto)),
+            ("x = {__proto__: a, ['__proto__']: b, __proto__() {}, get __proto__() {}, __proto__};", None),
+            ("({__proto__: a, __proto__: b} = c); [{__proto__: a, __proto__: b}] = c;", None),
+            ("f = ({__proto__: a, __proto__: b}) => a; for ({__proto__: a, __proto__: b} of c);", None),
+            // An async arrow function's head is parsed as a call.
+            ("f = async ({a = 1}, [b], ...c) => 1; g = async (a) => a;", None),
+            ("async ([f()]) => 1;", Some(InvalidParameter)),
+            ("async (a, a) => 1;", Some(DuplicateParameter)),
+            ("'use strict'; async (eval) => 1;", Some(BindEvalOrArguments)),
+            ("async({a = 1});", Some(ShorthandInitializer)),
+            ("async(a).b({c = 1});", Some(ShorthandInitializer)),
+            ("async(a) = 1;", Some(InvalidAssignmentTarget)),
+            ("f(a, a); async(a, a); async(...a, ...b);", None),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+
+        // Patterns in a skipped function body are still checked.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        for &(src, ok) in &[
+            ("function f() { [a, ...b] = c; }", true),
+            ("function f() { [...a, b] = c; }", false),
+            ("function f() { return {a = 1}; }", false),
+        ] {
+            let mut parser = Parser::new_script(&handler);
+            parser.set_lazy(true);
+            let result = parser.parse_script(src);
+            assert_eq!(result.is_ok(), ok, "{}", src);
+        }
+    }
+
+    #[test]
+    fn spread_and_rest() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            ("f(...a, b, ...c); new F(...a); o.m(a, ...b);", None),
+            ("x = [...a, , ...b]; y = {...a, b, ...c};", None),
+            ("function f(a, ...b) {} function g(...[a, b]) {} function h(...{length}) {}", None),
+            ("f = (...a) => a; g = (a, ...[b, c]) => b; h = (...a) => (...b) => 1;", None),
+            ("[a, ...b] = c; ({a, ...b} = c); ({...a.b} = c);", None),
+            ("(...a);", Some(RestOutsideParameters)),
+            ("(a, ...b) + 1;", Some(RestOutsideParameters)),
+            ("x = [(...a)];", Some(RestOutsideParameters)),
+            ("(...a) = 1;", Some(InvalidAssignmentTarget)),
+            ("({...[a]} = b);", Some(InvalidAssignmentTarget)),
+            ("[...a = 1] = b;", Some(InvalidAssignmentTarget)),
+            ("([...a, b]) => 1;", Some(InvalidParameter)),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+    }
+}
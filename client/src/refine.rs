@@ -0,0 +1,679 @@
+//! Cover grammars.
+//!
+//! Some constructs can't be told apart until the parser is past them. `(a,
+//! b)` is a parenthesized expression unless `=>` follows, and `[a, b]` is an
+//! array literal unless `=` does. The grammar parses both as expressions,
+//! the first as a CoverParenthesizedExpressionAndArrowParameterList, and the
+//! code that knows what came next refines the node into what it really is.
+//! The functions here do that for the concrete AST. They're also where the
+//! early errors for expressions that can't be refined are found: `(a + b) =>
+//! a` and `[f()] = x` parse, but neither is JavaScript.
+//!
+//! `Error` nodes from error recovery are passed through, since the parser
+//! has already reported them.
+
+use crate::ast_arena::{Arena, ArenaRef};
+use crate::early_errors::EarlyErrorKind;
+use crate::parser_generated::concrete::{
+    ArrayAssignmentPattern, ArrayBindingPattern, AssignmentElement, AssignmentElementList, AssignmentElisionElement,
+    AssignmentPattern, AssignmentProperty, AssignmentPropertyList, AssignmentRestElement, AssignmentRestProperty,
+    BindingElement, BindingElementList, BindingElisionElement, BindingIdentifier, BindingPattern, BindingProperty,
+    BindingPropertyList, BindingRestElement, BindingRestProperty, CoverInitializedName,
+    CoverParenthesizedExpressionAndArrowParameterList as Cover, DestructuringAssignmentTarget, ElementList, Elision,
+    Expression, FormalParameter, FormalParameterList, FormalParameters, FunctionRestParameter, IdentifierReference,
+    Initializer, ObjectAssignmentPattern, ObjectBindingPattern, PropertyDefinition, PropertyDefinitionList,
+    SingleNameBinding, SpreadElement,
+};
+
+pub type Result<T> = std::result::Result<T, EarlyErrorKind>;
+
+/// What the left-hand side of an `=` turned out to be.
+#[derive(Clone, Copy, Debug)]
+pub enum AssignmentTarget<'alloc> {
+    /// An identifier or a property access, possibly in parentheses.
+    Simple(ArenaRef<'alloc, Expression<'alloc>>),
+    /// An array or object literal, refined into a destructuring pattern.
+    Pattern(ArenaRef<'alloc, AssignmentPattern<'alloc>>),
+}
+
+/// Refine the parenthesized part of an arrow function, `(a, [b], c = 1)`,
+/// into the parameter list it would have been if the parser had known.
+pub fn expression_to_parameters<'alloc>(
+    arena: &'alloc Arena,
+    cover: &Cover<'alloc>,
+) -> Result<ArenaRef<'alloc, FormalParameters<'alloc>>> {
+    let rest = |element| arena.alloc(FunctionRestParameter::FunctionRestParameter(arena.alloc(element)));
+    let params = match cover {
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP0(expr) => {
+            FormalParameters::FormalParametersP2(parameter_list(arena, *expr)?)
+        }
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP1(expr) => {
+            FormalParameters::FormalParametersP3(parameter_list(arena, *expr)?)
+        }
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP2() => FormalParameters::FormalParametersP0(),
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP3(name) => {
+            FormalParameters::FormalParametersP1(rest(BindingRestElement::BindingRestElementP0(*name)))
+        }
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP4(pattern) => {
+            FormalParameters::FormalParametersP1(rest(BindingRestElement::BindingRestElementP1(*pattern)))
+        }
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP5(expr, name) => FormalParameters::FormalParametersP4(
+            parameter_list(arena, *expr)?,
+            rest(BindingRestElement::BindingRestElementP0(*name)),
+        ),
+        Cover::CoverParenthesizedExpressionAndArrowParameterListP6(expr, pattern) => {
+            FormalParameters::FormalParametersP4(
+                parameter_list(arena, *expr)?,
+                rest(BindingRestElement::BindingRestElementP1(*pattern)),
+            )
+        }
+        Cover::Error(loc) => FormalParameters::Error(*loc),
+    };
+    Ok(arena.alloc(params))
+}
+
+/// Refine the left-hand side of an `=`. An array or object literal becomes
+/// a destructuring pattern, and anything else has to be something that can
+/// be assigned to.
+pub fn expression_to_assignment_target<'alloc>(
+    arena: &'alloc Arena,
+    expr: ArenaRef<'alloc, Expression<'alloc>>,
+) -> Result<AssignmentTarget<'alloc>> {
+    let pattern = match &*expr {
+        Expression::ArrayLiteralP0(..) | Expression::ArrayLiteralP1(..) | Expression::ArrayLiteralP2(..) => {
+            AssignmentPattern::AssignmentPatternP1(array_assignment_pattern(arena, &expr)?)
+        }
+        Expression::ObjectLiteralP0() | Expression::ObjectLiteralP1(..) | Expression::ObjectLiteralP2(..) => {
+            AssignmentPattern::AssignmentPatternP0(object_assignment_pattern(arena, &expr)?)
+        }
+        Expression::Error(_) => return Ok(AssignmentTarget::Simple(expr)),
+        _ if is_simple_target(&expr) => return Ok(AssignmentTarget::Simple(expr)),
+        _ => return Err(EarlyErrorKind::InvalidAssignmentTarget),
+    };
+    Ok(AssignmentTarget::Pattern(arena.alloc(pattern)))
+}
+
+/// True if `expr` is what the spec calls a simple assignment target.
+fn is_simple_target(expr: &Expression) -> bool {
+    match expr {
+        Expression::PrimaryExpressionP1(_)
+        | Expression::MemberExpressionP1(..)
+        | Expression::MemberExpressionP2(..)
+        | Expression::MemberExpressionP3(..)
+        | Expression::MemberExpressionP5(_)
+        | Expression::CallExpressionP4(..)
+        | Expression::CallExpressionP5(..)
+        | Expression::CallExpressionP6(..) => true,
+        Expression::PrimaryExpressionP12(cover) => match &**cover {
+            Cover::CoverParenthesizedExpressionAndArrowParameterListP0(inner) => is_simple_target(inner),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_pattern_literal(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::ArrayLiteralP0(..)
+            | Expression::ArrayLiteralP1(..)
+            | Expression::ArrayLiteralP2(..)
+            | Expression::ObjectLiteralP0()
+            | Expression::ObjectLiteralP1(..)
+            | Expression::ObjectLiteralP2(..)
+    )
+}
+
+/// Build a left-recursive list node out of `items`, or return None if there
+/// aren't any.
+fn list<'alloc, T, L: Send>(
+    arena: &'alloc Arena,
+    items: Vec<T>,
+    one: impl Fn(T) -> L,
+    more: impl Fn(ArenaRef<'alloc, L>, T) -> L,
+) -> Option<ArenaRef<'alloc, L>> {
+    let mut items = items.into_iter();
+    let mut list = arena.alloc(one(items.next()?));
+    for item in items {
+        list = arena.alloc(more(list, item));
+    }
+    Some(list)
+}
+
+/// The operands of a comma expression, in order. Anything else is a list
+/// of one.
+fn comma_operands<'alloc>(expr: ArenaRef<'alloc, Expression<'alloc>>) -> Vec<ArenaRef<'alloc, Expression<'alloc>>> {
+    let mut operands = vec![];
+    let mut current = expr;
+    while let Expression::ExpressionP1(left, right) = &*current {
+        operands.push(*right);
+        current = *left;
+    }
+    operands.push(current);
+    operands.reverse();
+    operands
+}
+
+type Hole<'alloc> = Option<ArenaRef<'alloc, Elision<'alloc>>>;
+
+/// The elements of an array literal, in order, each with the holes before
+/// it and a flag that's true if it's spread.
+fn elements<'alloc>(
+    arena: &'alloc Arena,
+    list: &ElementList<'alloc>,
+) -> Vec<(Hole<'alloc>, ArenaRef<'alloc, Expression<'alloc>>, bool)> {
+    let spread = |spread: &SpreadElement<'alloc>| match spread {
+        SpreadElement::SpreadElement(expr) => *expr,
+        SpreadElement::Error(loc) => arena.alloc(Expression::Error(*loc)),
+    };
+    let mut elements = vec![];
+    let mut current = Some(list);
+    while let Some(list) = current {
+        let (rest, element) = match list {
+            ElementList::ElementListP0(hole, expr) => (None, (*hole, *expr, false)),
+            ElementList::ElementListP1(hole, s) => (None, (*hole, spread(s), true)),
+            ElementList::ElementListP2(rest, hole, expr) => (Some(&**rest), (*hole, *expr, false)),
+            ElementList::ElementListP3(rest, hole, s) => (Some(&**rest), (*hole, spread(s), true)),
+            ElementList::Error(loc) => (None, (None, arena.alloc(Expression::Error(*loc)), false)),
+        };
+        elements.push(element);
+        current = rest;
+    }
+    elements.reverse();
+    elements
+}
+
+/// An array literal taken apart: the elements before any rest element, the
+/// rest element with the holes before it, and the holes after a trailing
+/// comma, if there is one.
+struct ArrayParts<'alloc> {
+    elements: Vec<(Hole<'alloc>, ArenaRef<'alloc, Expression<'alloc>>)>,
+    rest: Option<(Hole<'alloc>, ArenaRef<'alloc, Expression<'alloc>>)>,
+    trailing: Option<Hole<'alloc>>,
+}
+
+/// Take apart the array literal `expr`. Only the last element can be spread,
+/// and only if no comma follows it; otherwise it's `error`.
+fn array_parts<'alloc>(
+    arena: &'alloc Arena,
+    expr: &Expression<'alloc>,
+    error: EarlyErrorKind,
+) -> Result<ArrayParts<'alloc>> {
+    let (mut elements, trailing) = match expr {
+        Expression::ArrayLiteralP0(hole) => (vec![], Some(*hole)),
+        Expression::ArrayLiteralP1(list) => (elements(arena, list), None),
+        Expression::ArrayLiteralP2(list, hole) => (elements(arena, list), Some(*hole)),
+        _ => return Err(error),
+    };
+    let rest = match elements.last() {
+        Some(&(hole, expr, true)) if trailing.is_none() => {
+            elements.pop();
+            Some((hole, expr))
+        }
+        _ => None,
+    };
+    if elements.iter().any(|&(_, _, spread)| spread) {
+        return Err(error);
+    }
+    Ok(ArrayParts {
+        elements: elements.into_iter().map(|(hole, expr, _)| (hole, expr)).collect(),
+        rest,
+        trailing,
+    })
+}
+
+/// The properties of an object literal, in order.
+fn properties<'alloc>(list: &PropertyDefinitionList<'alloc>) -> Vec<ArenaRef<'alloc, PropertyDefinition<'alloc>>> {
+    let mut properties = vec![];
+    let mut current = list;
+    loop {
+        match current {
+            PropertyDefinitionList::PropertyDefinitionListP0(p) => properties.push(*p),
+            PropertyDefinitionList::PropertyDefinitionListP1(rest, p) => {
+                properties.push(*p);
+                current = rest;
+                continue;
+            }
+            PropertyDefinitionList::Error(_) => {}
+        }
+        break;
+    }
+    properties.reverse();
+    properties
+}
+
+/// An object literal taken apart: the properties before any rest property,
+/// the rest property, and whether there's a trailing comma.
+struct ObjectParts<'alloc> {
+    properties: Vec<ArenaRef<'alloc, PropertyDefinition<'alloc>>>,
+    rest: Option<ArenaRef<'alloc, Expression<'alloc>>>,
+    trailing_comma: bool,
+}
+
+/// Take apart the object literal `expr`. Only the last property can be
+/// spread, and only if no comma follows it; otherwise it's `error`.
+fn object_parts<'alloc>(expr: &Expression<'alloc>, error: EarlyErrorKind) -> Result<ObjectParts<'alloc>> {
+    let (mut properties, trailing_comma) = match expr {
+        Expression::ObjectLiteralP0() => (vec![], false),
+        Expression::ObjectLiteralP1(list) => (properties(list), false),
+        Expression::ObjectLiteralP2(list) => (properties(list), true),
+        _ => return Err(error),
+    };
+    let rest = match properties.last().map(|p| &**p) {
+        Some(PropertyDefinition::PropertyDefinitionP4(expr)) if !trailing_comma => {
+            let expr = *expr;
+            properties.pop();
+            Some(expr)
+        }
+        _ => None,
+    };
+    if properties
+        .iter()
+        .any(|p| matches!(**p, PropertyDefinition::PropertyDefinitionP4(_)))
+    {
+        return Err(error);
+    }
+    Ok(ObjectParts {
+        properties,
+        rest,
+        trailing_comma,
+    })
+}
+
+/// Split `target = init` into its two halves.
+fn split_initializer<'alloc>(
+    arena: &'alloc Arena,
+    expr: ArenaRef<'alloc, Expression<'alloc>>,
+) -> (
+    ArenaRef<'alloc, Expression<'alloc>>,
+    Option<ArenaRef<'alloc, Initializer<'alloc>>>,
+) {
+    match &*expr {
+        Expression::AssignmentExpressionP4(target, init) => {
+            (*target, Some(arena.alloc(Initializer::Initializer(*init))))
+        }
+        _ => (expr, None),
+    }
+}
+
+// Assignment patterns.
+
+fn array_assignment_pattern<'alloc>(
+    arena: &'alloc Arena,
+    expr: &Expression<'alloc>,
+) -> Result<ArenaRef<'alloc, ArrayAssignmentPattern<'alloc>>> {
+    let parts = array_parts(arena, expr, EarlyErrorKind::InvalidAssignmentTarget)?;
+    let mut elements = vec![];
+    for (hole, expr) in parts.elements {
+        let element = assignment_element(arena, expr)?;
+        elements.push(arena.alloc(AssignmentElisionElement::AssignmentElisionElement(hole, element)));
+    }
+    let rest = match parts.rest {
+        Some((hole, expr)) => {
+            let target = destructuring_target(arena, expr)?;
+            Some((hole, arena.alloc(AssignmentRestElement::AssignmentRestElement(target))))
+        }
+        None => None,
+    };
+    let elements = list(
+        arena,
+        elements,
+        AssignmentElementList::AssignmentElementListP0,
+        AssignmentElementList::AssignmentElementListP1,
+    );
+    let pattern = match (elements, rest, parts.trailing) {
+        (None, None, hole) => ArrayAssignmentPattern::ArrayAssignmentPatternP0(hole.flatten(), None),
+        (None, Some((hole, rest)), _) => ArrayAssignmentPattern::ArrayAssignmentPatternP0(hole, Some(rest)),
+        (Some(elements), None, None) => ArrayAssignmentPattern::ArrayAssignmentPatternP1(elements),
+        (Some(elements), None, Some(hole)) => ArrayAssignmentPattern::ArrayAssignmentPatternP2(elements, hole, None),
+        (Some(elements), Some((hole, rest)), _) => {
+            ArrayAssignmentPattern::ArrayAssignmentPatternP2(elements, hole, Some(rest))
+        }
+    };
+    Ok(arena.alloc(pattern))
+}
+
+fn object_assignment_pattern<'alloc>(
+    arena: &'alloc Arena,
+    expr: &Expression<'alloc>,
+) -> Result<ArenaRef<'alloc, ObjectAssignmentPattern<'alloc>>> {
+    let parts = object_parts(expr, EarlyErrorKind::InvalidAssignmentTarget)?;
+    let mut properties = vec![];
+    for p in parts.properties {
+        properties.push(arena.alloc(assignment_property(arena, &p)?));
+    }
+    let rest = match parts.rest {
+        // `...{a}` and `...[a]` are patterns in arrays, but not here.
+        Some(expr) if is_pattern_literal(&expr) => return Err(EarlyErrorKind::InvalidAssignmentTarget),
+        Some(expr) => {
+            let target = destructuring_target(arena, expr)?;
+            Some(arena.alloc(AssignmentRestProperty::AssignmentRestProperty(target)))
+        }
+        None => None,
+    };
+    let properties = list(
+        arena,
+        properties,
+        AssignmentPropertyList::AssignmentPropertyListP0,
+        AssignmentPropertyList::AssignmentPropertyListP1,
+    );
+    let pattern = match (properties, rest) {
+        (None, None) => ObjectAssignmentPattern::ObjectAssignmentPatternP0(),
+        (None, Some(rest)) => ObjectAssignmentPattern::ObjectAssignmentPatternP1(rest),
+        (Some(properties), None) if !parts.trailing_comma => {
+            ObjectAssignmentPattern::ObjectAssignmentPatternP2(properties)
+        }
+        (Some(properties), rest) => ObjectAssignmentPattern::ObjectAssignmentPatternP3(properties, rest),
+    };
+    Ok(arena.alloc(pattern))
+}
+
+fn assignment_property<'alloc>(
+    arena: &'alloc Arena,
+    p: &PropertyDefinition<'alloc>,
+) -> Result<AssignmentProperty<'alloc>> {
+    Ok(match p {
+        PropertyDefinition::PropertyDefinitionP0(name) => AssignmentProperty::AssignmentPropertyP0(*name, None),
+        PropertyDefinition::PropertyDefinitionP1(cover) => match &**cover {
+            CoverInitializedName::CoverInitializedName(name, init) => {
+                AssignmentProperty::AssignmentPropertyP0(*name, Some(*init))
+            }
+            CoverInitializedName::Error(loc) => AssignmentProperty::Error(*loc),
+        },
+        PropertyDefinition::PropertyDefinitionP2(name, expr) => {
+            AssignmentProperty::AssignmentPropertyP1(*name, assignment_element(arena, *expr)?)
+        }
+        PropertyDefinition::PropertyDefinitionP3(_) | PropertyDefinition::PropertyDefinitionP4(_) => {
+            return Err(EarlyErrorKind::InvalidAssignmentTarget)
+        }
+        PropertyDefinition::Error(loc) => AssignmentProperty::Error(*loc),
+    })
+}
+
+fn assignment_element<'alloc>(
+    arena: &'alloc Arena,
+    expr: ArenaRef<'alloc, Expression<'alloc>>,
+) -> Result<ArenaRef<'alloc, AssignmentElement<'alloc>>> {
+    let (target, init) = split_initializer(arena, expr);
+    let target = destructuring_target(arena, target)?;
+    Ok(arena.alloc(AssignmentElement::AssignmentElement(target, init)))
+}
+
+/// Check that `expr` can be assigned to. A DestructuringAssignmentTarget
+/// keeps the expression, so a nested pattern is refined again when it's
+/// used.
+fn destructuring_target<'alloc>(
+    arena: &'alloc Arena,
+    expr: ArenaRef<'alloc, Expression<'alloc>>,
+) -> Result<ArenaRef<'alloc, DestructuringAssignmentTarget<'alloc>>> {
+    expression_to_assignment_target(arena, expr)?;
+    Ok(arena.alloc(DestructuringAssignmentTarget::DestructuringAssignmentTarget(expr)))
+}
+
+// Binding patterns, for parameters.
+
+fn parameter_list<'alloc>(
+    arena: &'alloc Arena,
+    expr: ArenaRef<'alloc, Expression<'alloc>>,
+) -> Result<ArenaRef<'alloc, FormalParameterList<'alloc>>> {
+    let mut params = vec![];
+    for operand in comma_operands(expr) {
+        let element = binding_element(arena, operand)?;
+        params.push(arena.alloc(FormalParameter::FormalParameter(element)));
+    }
+    // There's always at least one operand.
+    Ok(list(
+        arena,
+        params,
+        FormalParameterList::FormalParameterListP0,
+        FormalParameterList::FormalParameterListP1,
+    )
+    .unwrap())
+}
+
+fn binding_identifier<'alloc>(
+    arena: &'alloc Arena,
+    name: &IdentifierReference<'alloc>,
+) -> ArenaRef<'alloc, BindingIdentifier<'alloc>> {
+    arena.alloc(match name {
+        IdentifierReference::IdentifierReference(token) => BindingIdentifier::BindingIdentifierP0(token.clone()),
+        IdentifierReference::Error(loc) => BindingIdentifier::Error(*loc),
+    })
+}
+
+fn binding_element<'alloc>(
+    arena: &'alloc Arena,
+    expr: ArenaRef<'alloc, Expression<'alloc>>,
+) -> Result<ArenaRef<'alloc, BindingElement<'alloc>>> {
+    let (target, init) = split_initializer(arena, expr);
+    let element = match &*target {
+        Expression::PrimaryExpressionP1(name) => {
+            let name = binding_identifier(arena, name);
+            BindingElement::BindingElementP0(arena.alloc(SingleNameBinding::SingleNameBinding(name, init)))
+        }
+        Expression::Error(loc) => BindingElement::Error(*loc),
+        _ => BindingElement::BindingElementP1(binding_pattern(arena, &target)?, init),
+    };
+    Ok(arena.alloc(element))
+}
+
+fn binding_pattern<'alloc>(
+    arena: &'alloc Arena,
+    expr: &Expression<'alloc>,
+) -> Result<ArenaRef<'alloc, BindingPattern<'alloc>>> {
+    let pattern =
+        if let Expression::ObjectLiteralP0() | Expression::ObjectLiteralP1(_) | Expression::ObjectLiteralP2(_) = expr {
+            BindingPattern::BindingPatternP0(object_binding_pattern(arena, expr)?)
+        } else {
+            BindingPattern::BindingPatternP1(array_binding_pattern(arena, expr)?)
+        };
+    Ok(arena.alloc(pattern))
+}
+
+fn array_binding_pattern<'alloc>(
+    arena: &'alloc Arena,
+    expr: &Expression<'alloc>,
+) -> Result<ArenaRef<'alloc, ArrayBindingPattern<'alloc>>> {
+    let parts = array_parts(arena, expr, EarlyErrorKind::InvalidParameter)?;
+    let mut elements = vec![];
+    for (hole, expr) in parts.elements {
+        let element = binding_element(arena, expr)?;
+        elements.push(arena.alloc(BindingElisionElement::BindingElisionElement(hole, element)));
+    }
+    let rest = match parts.rest {
+        Some((hole, expr)) => {
+            let rest = match &*expr {
+                Expression::PrimaryExpressionP1(name) => {
+                    BindingRestElement::BindingRestElementP0(binding_identifier(arena, name))
+                }
+                Expression::Error(loc) => BindingRestElement::Error(*loc),
+                _ => BindingRestElement::BindingRestElementP1(binding_pattern(arena, &expr)?),
+            };
+            Some((hole, arena.alloc(rest)))
+        }
+        None => None,
+    };
+    let elements = list(
+        arena,
+        elements,
+        BindingElementList::BindingElementListP0,
+        BindingElementList::BindingElementListP1,
+    );
+    let pattern = match (elements, rest, parts.trailing) {
+        (None, None, hole) => ArrayBindingPattern::ArrayBindingPatternP0(hole.flatten(), None),
+        (None, Some((hole, rest)), _) => ArrayBindingPattern::ArrayBindingPatternP0(hole, Some(rest)),
+        (Some(elements), None, None) => ArrayBindingPattern::ArrayBindingPatternP1(elements),
+        (Some(elements), None, Some(hole)) => ArrayBindingPattern::ArrayBindingPatternP2(elements, hole, None),
+        (Some(elements), Some((hole, rest)), _) => {
+            ArrayBindingPattern::ArrayBindingPatternP2(elements, hole, Some(rest))
+        }
+    };
+    Ok(arena.alloc(pattern))
+}
+
+fn object_binding_pattern<'alloc>(
+    arena: &'alloc Arena,
+    expr: &Expression<'alloc>,
+) -> Result<ArenaRef<'alloc, ObjectBindingPattern<'alloc>>> {
+    let parts = object_parts(expr, EarlyErrorKind::InvalidParameter)?;
+    let mut properties = vec![];
+    for p in parts.properties {
+        let property = match &*p {
+            PropertyDefinition::PropertyDefinitionP0(name) => {
+                let name = binding_identifier(arena, name);
+                BindingProperty::BindingPropertyP0(arena.alloc(SingleNameBinding::SingleNameBinding(name, None)))
+            }
+            PropertyDefinition::PropertyDefinitionP1(cover) => match &**cover {
+                CoverInitializedName::CoverInitializedName(name, init) => {
+                    let name = binding_identifier(arena, name);
+                    let binding = SingleNameBinding::SingleNameBinding(name, Some(*init));
+                    BindingProperty::BindingPropertyP0(arena.alloc(binding))
+                }
+                CoverInitializedName::Error(loc) => BindingProperty::Error(*loc),
+            },
+            PropertyDefinition::PropertyDefinitionP2(name, expr) => {
+                BindingProperty::BindingPropertyP1(*name, binding_element(arena, *expr)?)
+            }
+            PropertyDefinition::PropertyDefinitionP3(_) | PropertyDefinition::PropertyDefinitionP4(_) => {
+                return Err(EarlyErrorKind::InvalidParameter)
+            }
+            PropertyDefinition::Error(loc) => BindingProperty::Error(*loc),
+        };
+        properties.push(arena.alloc(property));
+    }
+    let rest = match parts.rest.as_deref() {
+        Some(Expression::PrimaryExpressionP1(name)) => {
+            let name = binding_identifier(arena, name);
+            Some(arena.alloc(BindingRestProperty::BindingRestProperty(name)))
+        }
+        Some(Expression::Error(loc)) => Some(arena.alloc(BindingRestProperty::Error(*loc))),
+        Some(_) => return Err(EarlyErrorKind::InvalidParameter),
+        None => None,
+    };
+    let properties = list(
+        arena,
+        properties,
+        BindingPropertyList::BindingPropertyListP0,
+        BindingPropertyList::BindingPropertyListP1,
+    );
+    let pattern = match (properties, rest) {
+        (None, None) => ObjectBindingPattern::ObjectBindingPatternP0(),
+        (None, Some(rest)) => ObjectBindingPattern::ObjectBindingPatternP1(rest),
+        (Some(properties), None) if !parts.trailing_comma => ObjectBindingPattern::ObjectBindingPatternP2(properties),
+        (Some(properties), rest) => ObjectBindingPattern::ObjectBindingPatternP3(properties, rest),
+    };
+    Ok(arena.alloc(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::early_errors::EarlyErrorKind;
+    use crate::parser::{ParseError, Parser};
+    use crate::parser_generated::{concrete, reduce, DefaultHandler, EntryPoint, TABLES};
+    use crate::testing::{early_error, expression, function_declaration};
+
+    #[test]
+    fn cover_grammars() {
+        use EarlyErrorKind::*;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        // Recover, so that the parser builds the AST for the invalid cases
+        // as well, past the early errors it reports for them.
+        // The trees borrow their tokens' text from the source, so the
+        // sources, some of them formatted on the spot, go in the arena.
+        let parse = |src: &str| {
+            let src: &str = arena.alloc(src.to_string()).get();
+            let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+            match parser.parse_script(src) {
+                Ok(script) => script.into_script(),
+                Err(error) => panic!("{}: {}", src, error.message()),
+            }
+        };
+
+        // Arrow function parameters refine into what the same parameters of
+        // a function declaration parse as. The arrow function is indented so
+        // that its parameters' tokens are where the declaration's are.
+        let params = |src: &str| match &*expression(parse(&format!("          ({}) => 1;", src))) {
+            concrete::Expression::AssignmentExpressionP2(arrow) => match &**arrow {
+                concrete::ArrowFunction::ArrowFunction(params, _) => match &**params {
+                    concrete::ArrowParameters::ArrowParametersP1(cover) => {
+                        super::expression_to_parameters(&arena, cover).map(|p| format!("{:?}", p))
+                    }
+                    params => panic!("expected parentheses, got {:?}", params),
+                },
+                arrow => panic!("expected an arrow function, got {:?}", arrow),
+            },
+            expr => panic!("expected an arrow function, got {:?}", expr),
+        };
+        let declared = |src: &str| format!("{:?}", function_declaration(parse(&format!("function f({}) {{}}", src))).0);
+        // Recovery mode reports each of the errors below once.
+        let errors = |src: &str| {
+            let mut parser = Parser::new(&TABLES, reduce, &handler, EntryPoint::Script.state(), true);
+            parser.parse_script(src).ok().unwrap();
+            let kind = |error: &ParseError| match error {
+                ParseError::EarlyError { kind, .. } => *kind,
+                error => panic!("{}: {}", src, error.message()),
+            };
+            parser.errors().iter().map(kind).collect::<Vec<_>>()
+        };
+        for src in &[
+            "",
+            "a",
+            "a, b",
+            "a, b,",
+            "a = 1, b",
+            "...a",
+            "a, ...[b, c]",
+            "[a, , b = 1, ...c]",
+            "[a, b], [, ,]",
+            "{}, {a, b = 1, ...c}",
+            "{'a': [b], 'c': {d} = e}",
+        ] {
+            assert_eq!(params(src), Ok(declared(src)), "{}", src);
+        }
+        for src in &["a + b", "(a)", "a[0]", "[a[0]]", "[...a, b]", "[...a,]", "[...a = 1]", "{...[a]}", "{a, ...b,}"] {
+            assert_eq!(params(src), Err(InvalidParameter), "{}", src);
+            assert_eq!(early_error(&format!("({}) => 1;", src), false), Some(InvalidParameter), "{}", src);
+            assert_eq!(errors(&format!("({}) => 1;", src)), [InvalidParameter], "{}", src);
+        }
+
+        // The left-hand side of an assignment is a pattern, a simple target,
+        // or an error.
+        let target = |src: &str| match &*expression(parse(&format!("({});", src))) {
+            concrete::Expression::AssignmentExpressionP4(target, _) => {
+                super::expression_to_assignment_target(&arena, *target).map(|target| match target {
+                    super::AssignmentTarget::Simple(_) => "simple",
+                    super::AssignmentTarget::Pattern(_) => "pattern",
+                })
+            }
+            expr => panic!("expected an assignment, got {:?}", expr),
+        };
+        for src in &["a = 1", "(a) = 1", "a[0] = 1", "f()[0] = 1", "((a[b])) = 1"] {
+            assert_eq!(target(src), Ok("simple"), "{}", src);
+        }
+        for src in &[
+            "[a, b] = c",
+            "[a[0] = 1, [c], ...d[0]] = e",
+            "[, ...[a]] = b",
+            "{a, b = 1, ...c} = d",
+            "{'a': [b]} = c",
+        ] {
+            assert_eq!(target(src), Ok("pattern"), "{}", src);
+        }
+        for src in &[
+            "1 = 2",
+            "f() = 1",
+            "(a + b) = 1",
+            "([a]) = 1",
+            "[f()] = 1",
+            "[...a, b] = c",
+            "{...{a}} = b",
+            "{'a': 1} = b",
+        ] {
+            assert_eq!(target(src), Err(InvalidAssignmentTarget), "{}", src);
+            assert_eq!(early_error(&format!("({});", src), false), Some(InvalidAssignmentTarget), "{}", src);
+            assert_eq!(errors(&format!("({});", src)), [InvalidAssignmentTarget], "{}", src);
+        }
+    }
+}
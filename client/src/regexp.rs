@@ -0,0 +1,878 @@
+//! Early errors for regular expression literals.
+//!
+//! The lexer only finds where a RegularExpressionLiteral ends. This checks
+//! its body against the spec's Pattern grammar and its flags against the
+//! ones the spec allows, so that `/a**/` and `/a/gg` are errors when the
+//! script is parsed rather than when the literal is evaluated.
+//!
+//! Without the `u` or `v` flag, patterns follow the web-compatible grammar
+//! in Annex B, which accepts things like `/]/`, `/a{/` and `/\1/` that the
+//! Unicode grammars don't. With `v`, character classes can nest and have
+//! set operations, as in `/[\w--\d]/v`.
+//!
+//! Property escapes like `\p{Lu}` are checked against the property names
+//! and General_Category values in the spec's tables. Script and
+//! Script_Extensions values are only checked to look like names, since the
+//! list of scripts grows with every Unicode version.
+
+use crate::lexer::{is_identifier_part, is_identifier_start};
+
+/// What's wrong with a regular expression literal, and where: a byte
+/// offset from the literal's opening `/`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegExpError {
+    pub message: &'static str,
+    pub offset: usize,
+}
+
+type Result<T> = std::result::Result<T, RegExpError>;
+
+/// Check `text`, the whole of a RegularExpressionLiteral token: `/`, the
+/// body, `/` and the flags.
+pub fn check_literal(text: &str) -> Result<()> {
+    let slash = match text.rfind('/') {
+        Some(slash) if slash > 0 => slash,
+        _ => {
+            return Err(RegExpError {
+                message: "missing closing /",
+                offset: 0,
+            })
+        }
+    };
+    let flags = &text[slash + 1..];
+    let at = |offset: usize| move |error: RegExpError| RegExpError {
+        offset: error.offset + offset,
+        ..error
+    };
+    let (unicode, sets) = check_flags(flags).map_err(at(slash + 1))?;
+    check_pattern(&text[1..slash], unicode || sets, sets).map_err(at(1))
+}
+
+/// Check RegularExpressionFlags. Return whether they include `u` and `v`.
+pub fn check_flags(flags: &str) -> Result<(bool, bool)> {
+    let mut seen = String::new();
+    for (offset, flag) in flags.char_indices() {
+        let message = if !"dgimsuyv".contains(flag) {
+            "invalid flag"
+        } else if seen.contains(flag) {
+            "duplicate flag"
+        } else {
+            seen.push(flag);
+            continue;
+        };
+        return Err(RegExpError { message, offset });
+    }
+    let unicode = seen.contains('u');
+    let sets = seen.contains('v');
+    if unicode && sets {
+        let offset = flags.rfind(['u', 'v']).unwrap_or(0);
+        return Err(RegExpError {
+            message: "the u and v flags can't be used together",
+            offset,
+        });
+    }
+    Ok((unicode, sets))
+}
+
+/// Check a Pattern. `unicode` is true with either the `u` or the `v` flag,
+/// and `sets` with `v`.
+pub fn check_pattern(pattern: &str, unicode: bool, sets: bool) -> Result<()> {
+    let (group_count, has_names) = count_groups(pattern);
+    let mut parser = PatternParser {
+        src: pattern,
+        chars: pattern.char_indices().collect(),
+        pos: 0,
+        unicode,
+        sets,
+        named_groups: unicode || has_names,
+        group_count,
+        group_names: vec![],
+        name_references: vec![],
+        in_class_escape: false,
+    };
+    parser.disjunction()?;
+    if parser.pos < parser.chars.len() {
+        // disjunction() only stops early at a `)`.
+        return Err(parser.error("unmatched )"));
+    }
+    for (name, offset) in std::mem::take(&mut parser.name_references) {
+        if !parser.group_names.contains(&name) {
+            return Err(RegExpError {
+                message: "reference to an undefined capture group name",
+                offset,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// How many capturing groups `pattern` has, and whether any of them are
+/// named. Backreferences can come before the groups they refer to, so
+/// this has to be known before the real parse.
+fn count_groups(pattern: &str) -> (usize, bool) {
+    let mut chars = pattern.chars().peekable();
+    let (mut count, mut named, mut in_class) = (0, false, false);
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '(' if !in_class => {
+                if chars.peek() != Some(&'?') {
+                    count += 1;
+                    continue;
+                }
+                chars.next();
+                if chars.next() == Some('<') && !matches!(chars.peek(), Some('=') | Some('!')) {
+                    count += 1;
+                    named = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    (count, named)
+}
+
+const SYNTAX_CHARACTERS: &str = "^$\\.*+?()[]{}|";
+
+/// Characters that have to be escaped in a character class with the `v`
+/// flag.
+const CLASS_SET_SYNTAX_CHARACTERS: &str = "()[]{}/-\\|";
+
+/// Characters that can't appear twice in a row in a character class with
+/// the `v` flag, and that can be escaped there.
+const CLASS_SET_RESERVED_PUNCTUATORS: &str = "&-!#%,:;<=>@`~";
+const CLASS_SET_RESERVED_DOUBLES: &str = "&!#$%*+,.:;<=>?@^`~";
+
+const BINARY_PROPERTIES: &[&str] = &[
+    "ASCII", "ASCII_Hex_Digit", "AHex", "Alphabetic", "Alpha", "Any", "Assigned", "Bidi_Control",
+    "Bidi_C", "Bidi_Mirrored", "Bidi_M", "Case_Ignorable", "CI", "Cased", "Changes_When_Casefolded",
+    "CWCF", "Changes_When_Casemapped", "CWCM", "Changes_When_Lowercased", "CWL",
+    "Changes_When_NFKC_Casefolded", "CWKCF", "Changes_When_Titlecased", "CWT",
+    "Changes_When_Uppercased", "CWU", "Dash", "Default_Ignorable_Code_Point", "DI", "Deprecated",
+    "Dep", "Diacritic", "Dia", "Emoji", "Emoji_Component", "EComp", "Emoji_Modifier", "EMod",
+    "Emoji_Modifier_Base", "EBase", "Emoji_Presentation", "EPres", "Extended_Pictographic",
+    "ExtPict", "Extender", "Ext", "Grapheme_Base", "Gr_Base", "Grapheme_Extend", "Gr_Ext",
+    "Hex_Digit", "Hex", "IDS_Binary_Operator", "IDSB", "IDS_Trinary_Operator", "IDST", "ID_Continue",
+    "IDC", "ID_Start", "IDS", "Ideographic", "Ideo", "Join_Control", "Join_C",
+    "Logical_Order_Exception", "LOE", "Lowercase", "Lower", "Math", "Noncharacter_Code_Point",
+    "NChar", "Pattern_Syntax", "Pat_Syn", "Pattern_White_Space", "Pat_WS", "Quotation_Mark",
+    "QMark", "Radical", "Regional_Indicator", "RI", "Sentence_Terminal", "STerm", "Soft_Dotted", "SD",
+    "Terminal_Punctuation", "Term", "Unified_Ideograph", "UIdeo", "Uppercase", "Upper",
+    "Variation_Selector", "VS", "White_Space", "space", "XID_Continue", "XIDC", "XID_Start", "XIDS",
+];
+
+const GENERAL_CATEGORY_VALUES: &[&str] = &[
+    "Cased_Letter", "LC", "Close_Punctuation", "Pe", "Connector_Punctuation", "Pc", "Control", "Cc",
+    "cntrl", "Currency_Symbol", "Sc", "Dash_Punctuation", "Pd", "Decimal_Number", "Nd", "digit",
+    "Enclosing_Mark", "Me", "Final_Punctuation", "Pf", "Format", "Cf", "Initial_Punctuation", "Pi",
+    "Letter", "L", "Letter_Number", "Nl", "Line_Separator", "Zl", "Lowercase_Letter", "Ll", "Mark",
+    "M", "Combining_Mark", "Math_Symbol", "Sm", "Modifier_Letter", "Lm", "Modifier_Symbol", "Sk",
+    "Nonspacing_Mark", "Mn", "Number", "N", "Open_Punctuation", "Ps", "Other", "C", "Other_Letter",
+    "Lo", "Other_Number", "No", "Other_Punctuation", "Po", "Other_Symbol", "So",
+    "Paragraph_Separator", "Zp", "Private_Use", "Co", "Punctuation", "P", "punct", "Separator", "Z",
+    "Space_Separator", "Zs", "Spacing_Mark", "Mc", "Surrogate", "Cs", "Symbol", "S",
+    "Titlecase_Letter", "Lt", "Unassigned", "Cn", "Uppercase_Letter", "Lu",
+];
+
+/// Properties of strings, which only `\p` with the `v` flag allows.
+const STRING_PROPERTIES: &[&str] = &[
+    "Basic_Emoji",
+    "Emoji_Keycap_Sequence",
+    "RGI_Emoji_Modifier_Sequence",
+    "RGI_Emoji_Flag_Sequence",
+    "RGI_Emoji_Tag_Sequence",
+    "RGI_Emoji_ZWJ_Sequence",
+    "RGI_Emoji",
+];
+
+struct PatternParser<'a> {
+    src: &'a str,
+    chars: Vec<(usize, char)>,
+    /// An index into chars.
+    pos: usize,
+    unicode: bool,
+    sets: bool,
+    /// True if `\k` starts a named backreference: always in Unicode mode,
+    /// and otherwise only in patterns with named groups.
+    named_groups: bool,
+    group_count: usize,
+    group_names: Vec<String>,
+    /// Named backreferences, and where they are, to check once all the
+    /// names are known.
+    name_references: Vec<(String, usize)>,
+    /// True while parsing an escape in a character class, where a few more
+    /// characters can be escaped.
+    in_class_escape: bool,
+}
+
+impl<'a> PatternParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).map(|&(_, c)| c)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn looking_at(&self, s: &str) -> bool {
+        self.src[self.offset()..].starts_with(s)
+    }
+
+    /// The byte offset of the next character.
+    fn offset(&self) -> usize {
+        self.offset_of(self.pos)
+    }
+
+    fn offset_of(&self, pos: usize) -> usize {
+        self.chars.get(pos).map_or(self.src.len(), |&(offset, _)| offset)
+    }
+
+    fn error(&self, message: &'static str) -> RegExpError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: &'static str) -> RegExpError {
+        RegExpError {
+            message,
+            offset: self.offset_of(pos),
+        }
+    }
+
+    fn disjunction(&mut self) -> Result<()> {
+        loop {
+            while !matches!(self.peek(), None | Some('|') | Some(')')) {
+                self.term()?;
+            }
+            if !self.eat('|') {
+                return Ok(());
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<()> {
+        let start = self.pos;
+        let quantifiable = match self.peek() {
+            Some('^') | Some('$') => {
+                self.bump();
+                false
+            }
+            Some('\\') if matches!(self.peek_at(1), Some('b') | Some('B')) => {
+                self.pos += 2;
+                false
+            }
+            Some('(') => self.group()?,
+            Some('*') | Some('+') | Some('?') => return Err(self.error("nothing to repeat")),
+            Some('{') => {
+                if self.braced_quantifier().is_some() {
+                    self.pos = start;
+                    return Err(self.error("nothing to repeat"));
+                }
+                if self.unicode {
+                    return Err(self.error("lone {"));
+                }
+                self.bump();
+                true
+            }
+            Some('}') | Some(']') if self.unicode => {
+                return Err(self.error("lone ] or }"));
+            }
+            Some('[') => {
+                self.class()?;
+                true
+            }
+            Some('\\') => {
+                self.atom_escape()?;
+                true
+            }
+            _ => {
+                self.bump();
+                true
+            }
+        };
+        if !quantifiable {
+            return Ok(());
+        }
+        match self.peek() {
+            Some('*') | Some('+') | Some('?') => {
+                self.bump();
+            }
+            Some('{') => {
+                let brace = self.pos;
+                match self.braced_quantifier() {
+                    Some((min, Some(max))) if max < min => {
+                        return Err(self.error_at(brace, "numbers out of order in quantifier"));
+                    }
+                    Some(_) => {}
+                    None if self.unicode => return Err(self.error("incomplete quantifier")),
+                    None => return Ok(()),
+                }
+            }
+            _ => return Ok(()),
+        }
+        self.eat('?');
+        Ok(())
+    }
+
+    /// At a `{`, parse `{n}`, `{n,}` or `{n,m}` if that's what follows, and
+    /// return n and m. Otherwise, leave the position where it was.
+    fn braced_quantifier(&mut self) -> Option<(u64, Option<u64>)> {
+        let start = self.pos;
+        self.bump();
+        let min = self.digits();
+        let max = if self.eat(',') { self.digits() } else { min };
+        match min {
+            Some(min) if self.eat('}') => Some((min, max)),
+            _ => {
+                self.pos = start;
+                None
+            }
+        }
+    }
+
+    /// Parse decimal digits, saturating rather than overflowing.
+    fn digits(&mut self) -> Option<u64> {
+        let mut value: Option<u64> = None;
+        while let Some(d) = self.peek().and_then(|c| c.to_digit(10)) {
+            self.bump();
+            value = Some(value.unwrap_or(0).saturating_mul(10).saturating_add(d as u64));
+        }
+        value
+    }
+
+    /// Parse a group or a lookaround assertion, starting at its `(`. Return
+    /// whether a quantifier can follow it.
+    fn group(&mut self) -> Result<bool> {
+        let start = self.pos;
+        let quantifiable = if self.looking_at("(?=") || self.looking_at("(?!") {
+            self.pos += 3;
+            // Annex B allows `(?=a)*`, but only without the u flag.
+            !self.unicode
+        } else if self.looking_at("(?<=") || self.looking_at("(?<!") {
+            self.pos += 4;
+            false
+        } else if self.looking_at("(?:") {
+            self.pos += 3;
+            true
+        } else if self.looking_at("(?<") {
+            self.pos += 3;
+            let name_start = self.pos;
+            let name = self.group_name()?;
+            if self.group_names.contains(&name) {
+                return Err(self.error_at(name_start, "duplicate capture group name"));
+            }
+            self.group_names.push(name);
+            true
+        } else if self.looking_at("(?") {
+            return Err(self.error_at(start + 1, "invalid group"));
+        } else {
+            self.pos += 1;
+            true
+        };
+        self.disjunction()?;
+        if !self.eat(')') {
+            return Err(self.error_at(start, "unterminated group"));
+        }
+        Ok(quantifiable)
+    }
+
+    /// Parse a RegExpIdentifierName and the `>` after it.
+    fn group_name(&mut self) -> Result<String> {
+        let mut name = String::new();
+        loop {
+            let pos = self.pos;
+            let c = match self.bump() {
+                Some('>') if !name.is_empty() => return Ok(name),
+                Some('\\') => {
+                    if self.bump() != Some('u') {
+                        return Err(self.error_at(pos, "invalid capture group name"));
+                    }
+                    match self.unicode_escape(true) {
+                        Some(c) => c,
+                        None => return Err(self.error_at(pos, "invalid capture group name")),
+                    }
+                }
+                Some(c) => c,
+                None => return Err(self.error_at(pos, "invalid capture group name")),
+            };
+            let valid = if name.is_empty() { is_identifier_start(c) } else { is_identifier_part(c) };
+            if !valid {
+                return Err(self.error_at(pos, "invalid capture group name"));
+            }
+            name.push(c);
+        }
+    }
+
+    /// After `\u`, parse the rest of a Unicode escape and return the
+    /// character it stands for. `braces` allows the `\u{...}` form. Return
+    /// None, with the position unchanged, if there's no escape here.
+    fn unicode_escape(&mut self, braces: bool) -> Option<char> {
+        let start = self.pos;
+        if braces && self.eat('{') {
+            let mut value: u32 = 0;
+            let mut any = false;
+            while let Some(d) = self.peek().and_then(|c| c.to_digit(16)) {
+                self.bump();
+                value = value.saturating_mul(16).saturating_add(d);
+                any = true;
+            }
+            if !any || !self.eat('}') || value > 0x10ffff {
+                self.pos = start;
+                return None;
+            }
+            return Some(char::from_u32(value).unwrap_or('\u{fffd}'));
+        }
+        let lead = self.hex4()?;
+        // A surrogate pair spelled as two escapes is one character.
+        if (0xd800..0xdc00).contains(&lead) && self.looking_at("\\u") {
+            let before = self.pos;
+            self.pos += 2;
+            match self.hex4() {
+                Some(trail) if (0xdc00..0xe000).contains(&trail) => {
+                    let c = 0x10000 + ((lead - 0xd800) << 10) + (trail - 0xdc00);
+                    return char::from_u32(c);
+                }
+                _ => self.pos = before,
+            }
+        }
+        Some(char::from_u32(lead).unwrap_or('\u{fffd}'))
+    }
+
+    /// Parse four hex digits, or leave the position where it was.
+    fn hex4(&mut self) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..4 {
+            value = value * 16 + self.peek_at(i)?.to_digit(16)?;
+        }
+        self.pos += 4;
+        Some(value)
+    }
+
+    /// Parse an AtomEscape, starting at the `\`.
+    fn atom_escape(&mut self) -> Result<()> {
+        let start = self.pos;
+        self.bump();
+        match self.peek() {
+            None => Err(self.error_at(start, "\\ at end of pattern")),
+            Some('k') if self.named_groups => {
+                self.bump();
+                let name_start = self.pos;
+                if !self.eat('<') {
+                    return Err(self.error_at(start, "invalid named reference"));
+                }
+                let name = self.group_name()?;
+                self.name_references.push((name, self.offset_of(name_start)));
+                Ok(())
+            }
+            Some('1'..='9') => {
+                let number = self.digits().unwrap_or(0);
+                if self.unicode && number > self.group_count as u64 {
+                    return Err(self.error_at(start, "invalid backreference"));
+                }
+                Ok(())
+            }
+            _ => self.class_escape(start).map(|_| ()),
+        }
+    }
+
+    /// Parse the rest of an escape that can appear in a character class as
+    /// well as outside, after the `\` at `start`. Return the character it
+    /// stands for, or if it stands for a set of characters, whether the
+    /// set can contain strings.
+    fn class_escape(&mut self, start: usize) -> Result<Escaped> {
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Err(self.error_at(start, "\\ at end of pattern")),
+        };
+        let invalid = |p: &Self| Err(p.error_at(start, "invalid escape"));
+        let value = match c {
+            'd' | 'D' | 's' | 'S' | 'w' | 'W' => return Ok(Escaped::Set(false)),
+            'p' | 'P' if self.unicode => return self.property_escape(start, c == 'P'),
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\u{b}',
+            'c' => match self.peek() {
+                Some(letter) if letter.is_ascii_alphabetic() => {
+                    self.bump();
+                    char::from_u32(letter as u32 % 32).unwrap_or('\0')
+                }
+                // Annex B reads `\c` without a letter as a backslash and a c.
+                _ if !self.unicode => {
+                    self.pos -= 1;
+                    '\\'
+                }
+                _ => return invalid(self),
+            },
+            '0' if !self.peek().is_some_and(|d| d.is_ascii_digit()) => '\0',
+            '0'..='7' if !self.unicode => {
+                // A legacy octal escape, up to \377.
+                let mut value = c.to_digit(8).unwrap_or(0);
+                let max_digits = if c <= '3' { 2 } else { 1 };
+                for _ in 0..max_digits {
+                    match self.peek().and_then(|d| d.to_digit(8)) {
+                        Some(d) => {
+                            self.bump();
+                            value = value * 8 + d;
+                        }
+                        None => break,
+                    }
+                }
+                char::from_u32(value).unwrap_or('\0')
+            }
+            'x' => match (self.peek().and_then(|d| d.to_digit(16)), self.peek_at(1).and_then(|d| d.to_digit(16))) {
+                (Some(high), Some(low)) => {
+                    self.pos += 2;
+                    char::from_u32(high * 16 + low).unwrap_or('\0')
+                }
+                _ if !self.unicode => 'x',
+                _ => return invalid(self),
+            },
+            'u' => match self.unicode_escape(self.unicode) {
+                Some(c) => c,
+                None if !self.unicode => 'u',
+                None => return invalid(self),
+            },
+            'k' if self.named_groups => return invalid(self),
+            c if self.unicode => {
+                let allowed = SYNTAX_CHARACTERS.contains(c)
+                    || c == '/'
+                    || (c == '-' && self.in_class_escape)
+                    || (self.sets && self.in_class_escape && CLASS_SET_RESERVED_PUNCTUATORS.contains(c));
+                if !allowed {
+                    return invalid(self);
+                }
+                c
+            }
+            c => c,
+        };
+        Ok(Escaped::Char(value))
+    }
+
+    /// After `\p` or `\P`, parse `{Name}` or `{Name=Value}`.
+    fn property_escape(&mut self, start: usize, negated: bool) -> Result<Escaped> {
+        let invalid = |p: &Self| Err(p.error_at(start, "invalid property name"));
+        if !self.eat('{') {
+            return invalid(self);
+        }
+        let body_start = self.offset();
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '=') {
+            self.bump();
+        }
+        let body = &self.src[body_start..self.offset()];
+        if !self.eat('}') {
+            return invalid(self);
+        }
+        let valid = match body.split_once('=') {
+            Some(("General_Category", value)) | Some(("gc", value)) => GENERAL_CATEGORY_VALUES.contains(&value),
+            Some(("Script", value))
+            | Some(("sc", value))
+            | Some(("Script_Extensions", value))
+            | Some(("scx", value)) => !value.is_empty() && !value.contains('='),
+            Some(_) => false,
+            None => {
+                if self.sets && STRING_PROPERTIES.contains(&body) {
+                    if negated {
+                        return Err(self.error_at(start, "negated property of strings"));
+                    }
+                    return Ok(Escaped::Set(true));
+                }
+                GENERAL_CATEGORY_VALUES.contains(&body) || BINARY_PROPERTIES.contains(&body)
+            }
+        };
+        if !valid {
+            return invalid(self);
+        }
+        Ok(Escaped::Set(false))
+    }
+
+    /// Parse a character class, starting at its `[`.
+    fn class(&mut self) -> Result<()> {
+        if self.sets {
+            return self.class_set().map(|_| ());
+        }
+        let start = self.pos;
+        self.bump();
+        self.eat('^');
+        loop {
+            match self.peek() {
+                None => return Err(self.error_at(start, "unterminated character class")),
+                Some(']') => {
+                    self.bump();
+                    return Ok(());
+                }
+                _ => {}
+            }
+            let first_pos = self.pos;
+            let first = self.class_atom()?;
+            if self.peek() == Some('-') && !matches!(self.peek_at(1), None | Some(']')) {
+                self.bump();
+                let last = self.class_atom()?;
+                match (first, last) {
+                    (Escaped::Char(a), Escaped::Char(b)) if a > b => {
+                        return Err(self.error_at(first_pos, "range out of order in character class"));
+                    }
+                    (Escaped::Char(_), Escaped::Char(_)) => {}
+                    // Annex B reads `[\d-z]` as three alternatives.
+                    _ if !self.unicode => {}
+                    _ => return Err(self.error_at(first_pos, "invalid character class range")),
+                }
+            }
+        }
+    }
+
+    /// A ClassAtom: a character, or an escape.
+    fn class_atom(&mut self) -> Result<Escaped> {
+        let start = self.pos;
+        match self.bump() {
+            Some('\\') => match self.peek() {
+                Some('b') => {
+                    self.bump();
+                    Ok(Escaped::Char('\u{8}'))
+                }
+                // Annex B: `\8` and `\9` stand for themselves, and in a
+                // class, so do `\c` followed by a digit or `_`.
+                Some(c @ '8') | Some(c @ '9') if !self.unicode => {
+                    self.bump();
+                    Ok(Escaped::Char(c))
+                }
+                Some('c') if !self.unicode && self.peek_at(1).is_some_and(|c| c.is_ascii_digit() || c == '_') => {
+                    self.pos += 2;
+                    Ok(Escaped::Char('\u{0}'))
+                }
+                _ => self.escape_in_class(start),
+            },
+            Some(c) => Ok(Escaped::Char(c)),
+            None => Err(self.error_at(start, "unterminated character class")),
+        }
+    }
+
+    fn escape_in_class(&mut self, start: usize) -> Result<Escaped> {
+        self.in_class_escape = true;
+        let result = self.class_escape(start);
+        self.in_class_escape = false;
+        result
+    }
+
+    /// With the `v` flag, parse a character class, starting at its `[`.
+    /// Return whether it can match strings rather than single characters.
+    fn class_set(&mut self) -> Result<bool> {
+        let start = self.pos;
+        self.bump();
+        let negated = self.eat('^');
+        // A union has no operator between its operands; an intersection or
+        // a difference has `&&` or `--` between each, and no ranges.
+        let mut operator = None;
+        let mut operands = 0;
+        let mut ranges = false;
+        let mut strings = false;
+        loop {
+            match self.peek() {
+                None => return Err(self.error_at(start, "unterminated character class")),
+                Some(']') => {
+                    self.bump();
+                    break;
+                }
+                _ => {}
+            }
+            if operands > 0 {
+                let op = ["&&", "--"].iter().copied().find(|&op| self.looking_at(op));
+                match (op, operator) {
+                    (None, None) => {}
+                    (Some(op), None) if operands == 1 => {
+                        self.pos += 2;
+                        operator = Some(op);
+                    }
+                    (Some(op), Some(previous)) if op == previous => self.pos += 2,
+                    _ => return Err(self.error("invalid set operation in character class")),
+                }
+            }
+            let operand = self.pos;
+            let (operand_strings, range) = self.class_set_operand()?;
+            ranges |= range;
+            if operator.is_some() && ranges {
+                return Err(self.error_at(operand, "invalid set operation in character class"));
+            }
+            strings = match operator {
+                _ if operands == 0 => operand_strings,
+                None => strings || operand_strings,
+                Some("&&") => strings && operand_strings,
+                Some(_) => strings,
+            };
+            operands += 1;
+        }
+        if negated && strings {
+            return Err(self.error_at(start, "negated character class may contain strings"));
+        }
+        Ok(strings)
+    }
+
+    /// With the `v` flag, one operand of a character class: a nested class,
+    /// `\q{...}`, an escape, or a character or range of characters. Return
+    /// whether it can match strings, and whether it's a range.
+    fn class_set_operand(&mut self) -> Result<(bool, bool)> {
+        let start = self.pos;
+        if self.peek() == Some('[') {
+            return self.class_set().map(|strings| (strings, false));
+        }
+        if self.looking_at("\\q{") {
+            self.pos += 3;
+            let mut strings = false;
+            let mut length = 0;
+            loop {
+                match self.peek() {
+                    None => return Err(self.error_at(start, "unterminated class string disjunction")),
+                    Some('}') => {
+                        self.bump();
+                        return Ok((strings || length != 1, false));
+                    }
+                    Some('|') => {
+                        self.bump();
+                        strings |= length != 1;
+                        length = 0;
+                    }
+                    _ => {
+                        self.class_set_character()?;
+                        length += 1;
+                    }
+                }
+            }
+        }
+        if self.peek() == Some('\\') && !matches!(self.peek_at(1), Some('b')) {
+            let before = self.pos;
+            self.bump();
+            if let Some('d' | 'D' | 's' | 'S' | 'w' | 'W' | 'p' | 'P') = self.peek() {
+                if let Escaped::Set(strings) = self.escape_in_class(before)? {
+                    return Ok((strings, false));
+                }
+            }
+            self.pos = before;
+        }
+        let first = self.class_set_character()?;
+        if self.peek() == Some('-') && self.peek_at(1) != Some('-') {
+            self.bump();
+            let last = self.class_set_character()?;
+            if first > last {
+                return Err(self.error_at(start, "range out of order in character class"));
+            }
+            return Ok((false, true));
+        }
+        Ok((false, false))
+    }
+
+    /// With the `v` flag, a ClassSetCharacter.
+    fn class_set_character(&mut self) -> Result<char> {
+        let start = self.pos;
+        match self.peek() {
+            None => Err(self.error_at(start, "unterminated character class")),
+            Some('\\') => {
+                self.bump();
+                if self.eat('b') {
+                    return Ok('\u{8}');
+                }
+                match self.escape_in_class(start)? {
+                    Escaped::Char(c) => Ok(c),
+                    Escaped::Set(_) => Err(self.error_at(start, "invalid character class range")),
+                }
+            }
+            Some(c) if CLASS_SET_SYNTAX_CHARACTERS.contains(c) => {
+                Err(self.error("character in class needs to be escaped with the v flag"))
+            }
+            Some(c) if CLASS_SET_RESERVED_DOUBLES.contains(c) && self.peek_at(1) == Some(c) => {
+                Err(self.error("reserved double punctuator in character class"))
+            }
+            Some(c) => {
+                self.bump();
+                Ok(c)
+            }
+        }
+    }
+}
+
+/// What an escape stands for.
+enum Escaped {
+    Char(char),
+    /// A set, like `\d` or `\p{L}`. The flag is true if it can contain
+    /// strings.
+    Set(bool),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::early_errors::EarlyErrorKind;
+    use crate::parser::{ParseError, Parser};
+    use crate::parser_generated::DefaultHandler;
+    use crate::testing::early_error;
+
+    #[test]
+    fn regular_expressions() {
+        use EarlyErrorKind::*;
+        let cases: &[(&str, Option<EarlyErrorKind>)] = &[
+            (r"x = /a|b*c+?d{2,3}(e)(?:f)(?=g)(?!h)(?<=i)(?<!j)^$\b\B./dgimsy;", None),
+            (r"x = /[a-z\d\-]\1\k<n>(?<n>x)(?<\u{61}b>y)\u{1F600}/u;", None),
+            // Annex B, without the u flag.
+            (r"x = /]}{a{\c\8\k(?=a)*[\d-z]\07\x4\u12[\c_]/;", None),
+            (r"x = /\p{L}\p{Script=Greek}\P{gc=Lu}\p{ASCII_Hex_Digit}/u;", None),
+            (r"x = /[\w--\d][[a-z]&&[aeiou]]\p{RGI_Emoji}[\q{abc|d}][\&\-]/v;", None),
+            ("x = /a**/;", Some(InvalidRegExp("nothing to repeat"))),
+            ("x = /{1}/;", Some(InvalidRegExp("nothing to repeat"))),
+            ("x = /(?<=a)*/;", Some(InvalidRegExp("nothing to repeat"))),
+            ("x = /(?=a)*/u;", Some(InvalidRegExp("nothing to repeat"))),
+            ("x = /a{2,1}/;", Some(InvalidRegExp("numbers out of order in quantifier"))),
+            ("x = /a{/u;", Some(InvalidRegExp("incomplete quantifier"))),
+            ("x = /]/u;", Some(InvalidRegExp("lone ] or }"))),
+            ("x = /(/;", Some(InvalidRegExp("unterminated group"))),
+            ("x = /a)/;", Some(InvalidRegExp("unmatched )"))),
+            ("x = /(?x)/;", Some(InvalidRegExp("invalid group"))),
+            ("x = /(?<a>x)(?<a>y)/;", Some(InvalidRegExp("duplicate capture group name"))),
+            (r"x = /\k<b>(?<a>x)/;", Some(InvalidRegExp("reference to an undefined capture group name"))),
+            (r"x = /\a/u;", Some(InvalidRegExp("invalid escape"))),
+            (r"x = /\u{110000}/u;", Some(InvalidRegExp("invalid escape"))),
+            (r"x = /\1/u;", Some(InvalidRegExp("invalid backreference"))),
+            ("x = /[z-a]/;", Some(InvalidRegExp("range out of order in character class"))),
+            (r"x = /[\d-z]/u;", Some(InvalidRegExp("invalid character class range"))),
+            (r"x = /\p{Foo}/u;", Some(InvalidRegExp("invalid property name"))),
+            (r"x = /\p{RGI_Emoji}/u;", Some(InvalidRegExp("invalid property name"))),
+            ("x = /[a&&&b]/v;", Some(InvalidRegExp("invalid set operation in character class"))),
+            ("x = /[a-z&&b]/v;", Some(InvalidRegExp("invalid set operation in character class"))),
+            ("x = /[(]/v;", Some(InvalidRegExp("character in class needs to be escaped with the v flag"))),
+            (r"x = /[^\q{ab}]/v;", Some(InvalidRegExp("negated character class may contain strings"))),
+            ("x = /a/gg;", Some(InvalidRegExp("duplicate flag"))),
+            ("x = /a/x;", Some(InvalidRegExp("invalid flag"))),
+            ("x = /a/uv;", Some(InvalidRegExp("the u and v flags can't be used together"))),
+        ];
+        for &(src, expected) in cases {
+            assert_eq!(early_error(src, false), expected, "{}", src);
+        }
+
+        // The error points into the literal.
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        match Parser::new_script(&handler).parse_script("x = 1;\ny = /é(?<a>)b**/;") {
+            Err(ParseError::EarlyError { loc, .. }) => {
+                assert_eq!((loc.start, loc.end, loc.line, loc.column), (22, 23, 2, 14));
+            }
+            result => panic!("expected an early error, got ok: {}", result.is_ok()),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Fast paths for the lexer's hot loops. Each function here returns the
+//! length of the run of bytes at the start of `bytes` that the lexer can
+//! read all at once, instead of a character at a time. The runs are only
+//! ever ASCII other than line terminators, so reading one moves the column
+//! by its length and leaves the line alone; anything else ends the run, and
+//! the lexer goes back to reading characters.
+//!
+//! With the `simd` feature, which needs nightly, these look at 16 bytes at a
+//! time with std::simd. Without it, they look at one byte at a time, which
+//! is still quicker than the lexer's character loop.
+
+#[cfg(feature = "simd")]
+use std::simd::{
+    cmp::{SimdPartialEq, SimdPartialOrd},
+    Mask, Simd,
+};
+
+#[cfg(feature = "simd")]
+type Chunk = Simd<u8, 16>;
+
+#[cfg(feature = "simd")]
+fn splat(byte: u8) -> Chunk {
+    Chunk::splat(byte)
+}
+
+/// The length of the run of bytes before the first one that `stops`. The
+/// SIMD version of the test, `stops_chunk`, must agree with it.
+#[cfg(feature = "simd")]
+#[inline(always)]
+fn run(bytes: &[u8], stops_chunk: impl Fn(Chunk) -> Mask<i8, 16>, stops: impl Fn(u8) -> bool) -> usize {
+    let mut chunks = bytes.chunks_exact(16);
+    let mut len = 0;
+    for chunk in &mut chunks {
+        let mask = stops_chunk(Chunk::from_slice(chunk)).to_bitmask();
+        if mask != 0 {
+            return len + mask.trailing_zeros() as usize;
+        }
+        len += 16;
+    }
+    let rest = chunks.remainder();
+    len + rest.iter().position(|&b| stops(b)).unwrap_or(rest.len())
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+fn run(bytes: &[u8], stops: impl Fn(u8) -> bool) -> usize {
+    bytes.iter().position(|&b| stops(b)).unwrap_or(bytes.len())
+}
+
+/// `run!(bytes, |c| ..., |b| ...)` calls run() with the test of a chunk `c`
+/// only if there's SIMD to run it, and the test of a byte `b` in any case.
+#[cfg(feature = "simd")]
+macro_rules! run {
+    ($bytes:expr, |$c:ident| $chunk:expr, |$b:ident| $byte:expr) => {
+        run($bytes, |$c: Chunk| $chunk, |$b: u8| $byte)
+    };
+}
+
+#[cfg(not(feature = "simd"))]
+macro_rules! run {
+    ($bytes:expr, |$c:ident| $chunk:expr, |$b:ident| $byte:expr) => {
+        run($bytes, |$b: u8| $byte)
+    };
+}
+
+/// Spaces and tabs.
+pub fn spaces(bytes: &[u8]) -> usize {
+    run!(bytes, |c| !(c.simd_eq(splat(b' ')) | c.simd_eq(splat(b'\t'))), |b| b != b' ' && b != b'\t')
+}
+
+/// ASCII IdentifierPart characters: letters, digits, `$`, and `_`.
+pub fn identifier_part(bytes: &[u8]) -> usize {
+    run!(
+        bytes,
+        |c| {
+            let lower = c | splat(0x20);
+            let letter = lower.simd_ge(splat(b'a')) & lower.simd_le(splat(b'z'));
+            let digit = c.simd_ge(splat(b'0')) & c.simd_le(splat(b'9'));
+            !(letter | digit | c.simd_eq(splat(b'$')) | c.simd_eq(splat(b'_')))
+        },
+        |b| !(b.is_ascii_alphanumeric() || b == b'$' || b == b'_')
+    )
+}
+
+/// The text of a single-line comment.
+pub fn line_comment(bytes: &[u8]) -> usize {
+    run!(
+        bytes,
+        |c| c.simd_eq(splat(b'\n')) | c.simd_eq(splat(b'\r')) | c.simd_ge(splat(0x80)),
+        |b| b == b'\n' || b == b'\r' || b >= 0x80
+    )
+}
+
+/// The text of a multi-line comment, up to a `*` that might end it.
+pub fn block_comment(bytes: &[u8]) -> usize {
+    run!(
+        bytes,
+        |c| c.simd_eq(splat(b'*')) | c.simd_eq(splat(b'\n')) | c.simd_eq(splat(b'\r')) | c.simd_ge(splat(0x80)),
+        |b| b == b'*' || b == b'\n' || b == b'\r' || b >= 0x80
+    )
+}
+
+/// The characters of a string literal delimited by `quote`, up to the
+/// closing quote or an escape.
+pub fn string(bytes: &[u8], quote: u8) -> usize {
+    run!(
+        bytes,
+        |c| {
+            c.simd_eq(splat(quote))
+                | c.simd_eq(splat(b'\\'))
+                | c.simd_eq(splat(b'\n'))
+                | c.simd_eq(splat(b'\r'))
+                | c.simd_ge(splat(0x80))
+        },
+        |b| b == quote || b == b'\\' || b == b'\n' || b == b'\r' || b >= 0x80
+    )
+}
+
+/// The characters of a template, up to its end, an escape, or a `$` that
+/// might start a substitution.
+pub fn template(bytes: &[u8]) -> usize {
+    run!(
+        bytes,
+        |c| {
+            c.simd_eq(splat(b'`'))
+                | c.simd_eq(splat(b'$'))
+                | c.simd_eq(splat(b'\\'))
+                | c.simd_eq(splat(b'\n'))
+                | c.simd_eq(splat(b'\r'))
+                | c.simd_ge(splat(0x80))
+        },
+        |b| b == b'`' || b == b'$' || b == b'\\' || b == b'\n' || b == b'\r' || b >= 0x80
+    )
+}
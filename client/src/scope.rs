@@ -0,0 +1,469 @@
+//! Scope analysis.
+//!
+//! With scope analysis turned on, `EarlyErrors` records each scope the
+//! parser enters, the names declared in it, and every identifier reference,
+//! in a `ScopeLog`. Once the input is parsed, `ScopeLog::finish` resolves
+//! the references and decides where each binding lives at run time: in a
+//! slot of the stack frame, or, if an inner function or a direct eval can
+//! see it, in the scope's environment object. The emitter uses this to pick
+//! between local and aliased accesses instead of looking everything up by
+//! name.
+
+use crate::parser_runtime::SourceLocation;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScopeKind {
+    /// The whole script or module.
+    Top,
+    /// A function's parameters and body.
+    Function,
+    /// A block, the cases of a switch statement, or the head of a for
+    /// statement.
+    Block,
+    /// A catch clause's parameter.
+    Catch,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BindingKind {
+    Var,
+    Let,
+    Const,
+    Param,
+    Function,
+    Class,
+    CatchParameter,
+    Import,
+    /// The name of a function expression, which is only bound inside it.
+    Callee,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slot {
+    /// Looked up by name. This is where everything declared at the top
+    /// level goes.
+    Global,
+    /// A slot in the stack frame of the nearest enclosing function, or of
+    /// the script.
+    Frame(usize),
+    /// A slot in the scope's environment object.
+    Environment(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BindingData {
+    pub name: String,
+    pub kind: BindingKind,
+    /// Where the name is first declared.
+    pub loc: SourceLocation,
+    /// True if an inner function uses the binding, or a direct eval might.
+    pub captured: bool,
+    pub slot: Slot,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScopeData {
+    pub kind: ScopeKind,
+    pub parent: Option<usize>,
+    pub bindings: Vec<BindingData>,
+    /// For the top scope and function scopes, the number of frame slots the
+    /// scope and the blocks in it need. Zero for other scopes.
+    pub frame_size: usize,
+    /// True if `eval` is used directly in the scope and doesn't name a
+    /// binding.
+    pub has_direct_eval: bool,
+}
+
+/// The scopes of a script or module. The top scope is the first one, and
+/// every other scope comes after its parent.
+#[derive(Clone, Debug)]
+pub struct ScopeTree {
+    pub scopes: Vec<ScopeData>,
+}
+
+impl ScopeTree {
+    /// Look `name` up as code in scope `scope` would. Return the index of
+    /// the scope that binds it and the binding, or None for a global.
+    pub fn lookup(&self, scope: usize, name: &str) -> Option<(usize, &BindingData)> {
+        let mut current = Some(scope);
+        while let Some(i) = current {
+            if let Some(binding) = self.scopes[i].bindings.iter().find(|b| b.name == name) {
+                return Some((i, binding));
+            }
+            current = self.scopes[i].parent;
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    kind: ScopeKind,
+    parent: Option<usize>,
+    /// Where the token that starts the scope starts.
+    start: usize,
+    /// For the body of an arrow function, where its `=>` starts.
+    arrow: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Declaration {
+    scope: usize,
+    name: String,
+    kind: BindingKind,
+    loc: SourceLocation,
+}
+
+#[derive(Clone, Debug)]
+struct Reference {
+    scope: usize,
+    name: String,
+    loc: SourceLocation,
+}
+
+/// What the parser has seen so far. Scopes are numbered in the order
+/// they're added, starting with the top scope, 0.
+#[derive(Clone, Debug)]
+pub struct ScopeLog {
+    scopes: Vec<Entry>,
+    declarations: Vec<Declaration>,
+    /// In source order.
+    references: Vec<Reference>,
+}
+
+impl ScopeLog {
+    pub fn new() -> ScopeLog {
+        ScopeLog {
+            scopes: vec![Entry {
+                kind: ScopeKind::Top,
+                parent: None,
+                start: 0,
+                arrow: None,
+            }],
+            declarations: vec![],
+            references: vec![],
+        }
+    }
+
+    /// The number of the next scope to be added.
+    pub fn next_scope(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn add_scope(&mut self, kind: ScopeKind, parent: usize, start: usize, arrow: Option<usize>) {
+        self.scopes.push(Entry {
+            kind,
+            parent: Some(parent),
+            start,
+            arrow,
+        });
+    }
+
+    pub fn declare(&mut self, scope: usize, name: &str, kind: BindingKind, loc: SourceLocation) {
+        self.declarations.push(Declaration {
+            scope,
+            name: name.to_string(),
+            kind,
+            loc,
+        });
+    }
+
+    pub fn reference(&mut self, scope: usize, name: &str, loc: SourceLocation) {
+        self.references.push(Reference {
+            scope,
+            name: name.to_string(),
+            loc,
+        });
+    }
+
+    /// The function scope whose body starts just after the `=>` at
+    /// `arrow`, if the body is a block.
+    pub fn arrow_body(&self, arrow: usize) -> Option<usize> {
+        self.scopes.iter().rposition(|s| s.arrow == Some(arrow))
+    }
+
+    /// The first scope in `parent` that starts at or after `start`.
+    pub fn first_scope_after(&self, parent: usize, start: usize) -> Option<usize> {
+        let mut first = None;
+        for (i, entry) in self.scopes.iter().enumerate().rev() {
+            if entry.start < start {
+                break;
+            }
+            if entry.parent == Some(parent) {
+                first = Some(i);
+            }
+        }
+        first
+    }
+
+    /// The arrow function at `loc`, which was parsed in scope `outer`, has
+    /// scope `arrow`. Move everything the parser put in `outer` while
+    /// parsing the arrow function into `arrow`. The parameter list at
+    /// `params` was parsed as an expression, so the names it refers to are
+    /// really parameters.
+    ///
+    /// Names used in parameter default values are taken as parameters too.
+    pub fn arrow_function(&mut self, outer: usize, arrow: usize, loc: SourceLocation, params: SourceLocation) {
+        for i in (0..self.scopes.len()).rev() {
+            if self.scopes[i].start < loc.start {
+                break;
+            }
+            if i != arrow && self.scopes[i].parent == Some(outer) {
+                self.scopes[i].parent = Some(arrow);
+            }
+        }
+        let first = self
+            .references
+            .iter()
+            .rposition(|r| r.loc.start < loc.start)
+            .map_or(0, |i| i + 1);
+        for reference in self.references.split_off(first) {
+            if reference.scope != outer {
+                self.references.push(reference);
+            } else if params.start <= reference.loc.start && reference.loc.end <= params.end {
+                self.declarations.push(Declaration {
+                    scope: arrow,
+                    name: reference.name,
+                    kind: BindingKind::Param,
+                    loc: reference.loc,
+                });
+            } else {
+                self.references.push(Reference {
+                    scope: arrow,
+                    ..reference
+                });
+            }
+        }
+    }
+
+    /// Resolve every reference and assign each binding a slot.
+    pub fn finish(self) -> ScopeTree {
+        let mut scopes: Vec<ScopeData> = self
+            .scopes
+            .iter()
+            .map(|entry| ScopeData {
+                kind: entry.kind,
+                parent: entry.parent,
+                bindings: vec![],
+                frame_size: 0,
+                has_direct_eval: false,
+            })
+            .collect();
+
+        for d in self.declarations {
+            let bindings = &mut scopes[d.scope].bindings;
+            match bindings.iter_mut().find(|b| b.name == d.name) {
+                // A function declaration or a parameter takes over a var of
+                // the same name; anything else is a redeclaration that's
+                // either allowed or already reported.
+                Some(b) if b.kind == BindingKind::Var && d.kind != BindingKind::Var => b.kind = d.kind,
+                Some(b) if b.kind == BindingKind::Param && d.kind == BindingKind::Function => b.kind = d.kind,
+                Some(_) => {}
+                None => bindings.push(BindingData {
+                    name: d.name,
+                    kind: d.kind,
+                    loc: d.loc,
+                    captured: false,
+                    slot: Slot::Global,
+                }),
+            }
+        }
+
+        for r in &self.references {
+            let mut current = Some(r.scope);
+            let mut crossed_function = false;
+            let mut found = false;
+            while let Some(i) = current {
+                if let Some(b) = scopes[i].bindings.iter_mut().find(|b| b.name == r.name) {
+                    b.captured |= crossed_function;
+                    found = true;
+                    break;
+                }
+                crossed_function |= scopes[i].kind == ScopeKind::Function;
+                current = scopes[i].parent;
+            }
+            if !found && r.name == "eval" {
+                // Assume it's called. Code it runs can see every binding
+                // around it.
+                scopes[r.scope].has_direct_eval = true;
+                let mut current = Some(r.scope);
+                while let Some(i) = current {
+                    for b in &mut scopes[i].bindings {
+                        b.captured = true;
+                    }
+                    current = scopes[i].parent;
+                }
+            }
+        }
+
+        assign_slots(&mut scopes);
+        ScopeTree { scopes: sort_parents_first(scopes) }
+    }
+}
+
+impl Default for ScopeLog {
+    fn default() -> ScopeLog {
+        ScopeLog::new()
+    }
+}
+
+/// Give each binding outside the top scope a frame slot, or an environment
+/// slot if it's captured. Blocks get frame slots after those of the scopes
+/// around them, up to the nearest function, so sibling blocks share slots.
+fn assign_slots(scopes: &mut [ScopeData]) {
+    let mut children = vec![vec![]; scopes.len()];
+    for (i, scope) in scopes.iter().enumerate() {
+        if let Some(parent) = scope.parent {
+            children[parent].push(i);
+        }
+    }
+
+    // For each scope, the first frame slot not used by it or the scopes
+    // around it, and the function scope whose frame it's in.
+    let mut frame_end = vec![0; scopes.len()];
+    let mut owner = vec![0; scopes.len()];
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+        let (base, function) = match (scopes[i].kind, scopes[i].parent) {
+            (ScopeKind::Top, _) | (ScopeKind::Function, _) | (_, None) => (0, i),
+            (_, Some(parent)) => (frame_end[parent], owner[parent]),
+        };
+        let top = scopes[i].kind == ScopeKind::Top;
+        let mut frame = base;
+        let mut environment = 0;
+        for b in &mut scopes[i].bindings {
+            b.slot = if top {
+                Slot::Global
+            } else if b.captured {
+                environment += 1;
+                Slot::Environment(environment - 1)
+            } else {
+                frame += 1;
+                Slot::Frame(frame - 1)
+            };
+        }
+        frame_end[i] = frame;
+        owner[i] = function;
+        scopes[function].frame_size = scopes[function].frame_size.max(frame);
+        stack.extend(children[i].iter().rev());
+    }
+}
+
+/// Renumber `scopes` so that each scope comes after its parent. Arrow
+/// function scopes are only added once the parser gets to the end of them,
+/// after the scopes inside.
+fn sort_parents_first(scopes: Vec<ScopeData>) -> Vec<ScopeData> {
+    if scopes.iter().enumerate().all(|(i, s)| s.parent.is_none_or(|p| p < i)) {
+        return scopes;
+    }
+    let mut children = vec![vec![]; scopes.len()];
+    for (i, scope) in scopes.iter().enumerate() {
+        if let Some(parent) = scope.parent {
+            children[parent].push(i);
+        }
+    }
+    let mut order = vec![];
+    let mut stack = vec![0];
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        stack.extend(children[i].iter().rev());
+    }
+    let mut new_index = vec![0; scopes.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_index[old] = new;
+    }
+    let mut slots: Vec<Option<ScopeData>> = scopes.into_iter().map(Some).collect();
+    order
+        .iter()
+        .map(|&old| {
+            let mut scope = slots[old].take().unwrap();
+            scope.parent = scope.parent.map(|p| new_index[p]);
+            scope
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BindingData, BindingKind, ScopeData, ScopeKind, ScopeTree, Slot};
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::DefaultHandler;
+
+    /// Parse `src` as a script with scope analysis turned on.
+    fn scopes(src: &str, lazy: bool) -> ScopeTree {
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.set_lazy(lazy);
+        parser.set_analyze_scopes(true);
+        if let Err(error) = parser.parse_script(src) {
+            panic!("{}: {}", src, error.message());
+        }
+        parser.take_scopes().unwrap()
+    }
+
+    /// The scope that binds `name`, which only one scope in `tree` does,
+    /// and its binding.
+    fn binding<'t>(tree: &'t ScopeTree, name: &str) -> (&'t ScopeData, &'t BindingData) {
+        let mut found = tree
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.bindings.iter().map(move |b| (scope, b)))
+            .filter(|(_, b)| b.name == name);
+        let result = found.next().unwrap_or_else(|| panic!("{} isn't bound", name));
+        assert!(found.next().is_none(), "{} is bound twice", name);
+        result
+    }
+
+    #[test]
+    fn scope_analysis() {
+        let tree = scopes("var a; let b; function f(x) { let y; { let z; } return x + y + a; }", false);
+        assert_eq!(tree.scopes[0].kind, ScopeKind::Top);
+        for &(name, kind) in &[("a", BindingKind::Var), ("b", BindingKind::Let), ("f", BindingKind::Function)] {
+            let (scope, b) = binding(&tree, name);
+            assert_eq!((scope.kind, b.kind, b.slot), (ScopeKind::Top, kind, Slot::Global), "{}", name);
+        }
+        let (function, x) = binding(&tree, "x");
+        assert_eq!((function.kind, x.kind, x.slot), (ScopeKind::Function, BindingKind::Param, Slot::Frame(0)));
+        assert_eq!(binding(&tree, "y").1.slot, Slot::Frame(1));
+        let (block, z) = binding(&tree, "z");
+        assert_eq!((block.kind, z.slot), (ScopeKind::Block, Slot::Frame(2)));
+        assert_eq!(function.frame_size, 3);
+
+        // Sibling blocks share frame slots.
+        let tree = scopes("function f() { { let a; } { const b = 1; } }", false);
+        assert_eq!(binding(&tree, "a").1.slot, Slot::Frame(0));
+        assert_eq!(binding(&tree, "b").1.kind, BindingKind::Const);
+        assert_eq!(binding(&tree, "b").1.slot, Slot::Frame(0));
+
+        // Bindings that inner functions use go in an environment.
+        let tree = scopes("function f(a, b) { var c, d; return function g() { return a + c + g; }; }", false);
+        let slots: Vec<_> = ["a", "b", "c", "d"].iter().map(|name| binding(&tree, name).1.slot).collect();
+        assert_eq!(slots, [Slot::Environment(0), Slot::Frame(0), Slot::Environment(1), Slot::Frame(1)]);
+        assert_eq!(binding(&tree, "g").1.kind, BindingKind::Callee);
+
+        let tree = scopes("function f(a, b) { let c = (d, e) => a + d; return x => { let y; return b + x; }; }", false);
+        for &(name, captured) in &[("a", true), ("b", true), ("c", false), ("d", false), ("e", false), ("x", false)] {
+            assert_eq!(binding(&tree, name).1.captured, captured, "{}", name);
+        }
+        let (arrow, x) = binding(&tree, "x");
+        assert_eq!((arrow.kind, x.kind), (ScopeKind::Function, BindingKind::Param));
+        assert!(std::ptr::eq(arrow, binding(&tree, "y").0));
+        let (arrow, d) = binding(&tree, "d");
+        assert_eq!((arrow.kind, d.kind), (ScopeKind::Function, BindingKind::Param));
+        let f = arrow.parent.unwrap();
+        assert_eq!(tree.lookup(f, "a").map(|(i, _)| i), Some(f));
+        assert!(tree.scopes.iter().enumerate().all(|(i, s)| s.parent.is_none_or(|p| p < i)));
+
+        let tree = scopes("function f() { var a; try {} catch (e) { eval(e); } }", false);
+        assert!(binding(&tree, "a").1.captured);
+        let (catch, e) = binding(&tree, "e");
+        assert_eq!((catch.kind, e.kind, e.captured), (ScopeKind::Catch, BindingKind::CatchParameter, true));
+        assert!(tree.scopes.iter().any(|s| s.has_direct_eval));
+
+        // Skipped function bodies are analyzed too.
+        let tree = scopes("function f() { let a; return function () { return a; }; }", true);
+        assert_eq!(binding(&tree, "a").1.slot, Slot::Environment(0));
+    }
+}
@@ -0,0 +1,182 @@
+//! Turning byte offsets into lines and columns.
+//!
+//! Token locations count columns in characters, but different consumers of
+//! positions count differently: DevTools and the Language Server Protocol
+//! want UTF-16 code units, and editors working on UTF-8 buffers may want
+//! bytes. A SourceMapper knows where each line starts, so it can give a
+//! column in any of these units.
+
+use crate::lexer::Lexer;
+
+/// What a column counts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnUnit {
+    /// Bytes of UTF-8, as in Rust strings.
+    Utf8Bytes,
+    /// UTF-16 code units, as in JS strings, DevTools and LSP.
+    Utf16CodeUnits,
+    /// Unicode code points, like SourceLocation::column.
+    CodePoints,
+}
+
+/// The lines of a source text, for finding the line and column of a byte
+/// offset in it.
+pub struct SourceMapper<'a> {
+    source: &'a str,
+    /// The byte offset where each line starts. The first is 0.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMapper<'a> {
+    /// Find the lines of `source`. Line terminators are the same ones the
+    /// lexer counts: LF, CR, CR LF, U+2028 and U+2029.
+    pub fn new(source: &'a str) -> SourceMapper<'a> {
+        let mut line_starts = vec![0];
+        let mut chars = source.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\u{d}' if chars.peek().is_some_and(|&(_, next)| next == '\u{a}') => {}
+                '\u{a}' | '\u{d}' | '\u{2028}' | '\u{2029}' => line_starts.push(i + c.len_utf8()),
+                _ => {}
+            }
+        }
+        SourceMapper { source, line_starts }
+    }
+
+    /// Use the line starts `lexer` recorded, instead of finding them again.
+    /// `lexer` must have been made with Lexer::new() and have read all of
+    /// `source`.
+    pub fn from_lexer(source: &'a str, lexer: &Lexer) -> SourceMapper<'a> {
+        SourceMapper {
+            source,
+            line_starts: lexer.line_starts().to_vec(),
+        }
+    }
+
+    /// The number of lines. A text that ends with a line terminator has an
+    /// empty line after it.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The byte offset where `line`, which is 1-based, starts, or None if
+    /// there's no such line.
+    pub fn line_start(&self, line: u32) -> Option<usize> {
+        let index = (line as usize).checked_sub(1)?;
+        self.line_starts.get(index).copied()
+    }
+
+    /// The 1-based line that `offset` is on. An offset past the end is on
+    /// the last line.
+    pub fn line(&self, offset: usize) -> u32 {
+        self.line_starts.partition_point(|&start| start <= offset) as u32
+    }
+
+    /// The 1-based line and 0-based column, counted in `unit`, of the byte
+    /// at `offset`. An offset inside a character counts as the start of
+    /// that character, and one past the end as the end.
+    pub fn position(&self, offset: usize, unit: ColumnUnit) -> (u32, u32) {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line(offset);
+        let text = &self.source[self.line_starts[line as usize - 1]..offset];
+        let column = match unit {
+            ColumnUnit::Utf8Bytes => text.len(),
+            ColumnUnit::Utf16CodeUnits => text.chars().map(char::len_utf16).sum(),
+            ColumnUnit::CodePoints => text.chars().count(),
+        };
+        (line, column as u32)
+    }
+
+    /// The byte offset of the position at `line`, which is 1-based, and
+    /// `column`, which is 0-based and counted in `unit`. This is the inverse
+    /// of position(). A column past the end of the line is the end of the
+    /// line, not counting its line terminator, and one in the middle of a
+    /// character is the start of that character.
+    pub fn offset(&self, line: u32, column: u32, unit: ColumnUnit) -> Option<usize> {
+        let start = self.line_start(line)?;
+        let end = match self.line_start(line + 1) {
+            Some(next) => {
+                let text = &self.source[start..next];
+                let text = text
+                    .strip_suffix("\r\n")
+                    .or_else(|| text.strip_suffix(['\u{a}', '\u{d}', '\u{2028}', '\u{2029}']))
+                    .unwrap_or(text);
+                start + text.len()
+            }
+            None => self.source.len(),
+        };
+        let mut remaining = column as usize;
+        for (i, c) in self.source[start..end].char_indices() {
+            let width = match unit {
+                ColumnUnit::Utf8Bytes => c.len_utf8(),
+                ColumnUnit::Utf16CodeUnits => c.len_utf16(),
+                ColumnUnit::CodePoints => 1,
+            };
+            if remaining < width {
+                return Some(start + i);
+            }
+            remaining -= width;
+        }
+        Some(end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn source_mapper() {
+        use super::{ColumnUnit::*, SourceMapper};
+        let src = "a = 'é';\r\nb = '😀' +\n\u{2028}c;";
+        let mut lexer = Lexer::new(src);
+        assert_eq!(lexer.by_ref().count(), 10);
+        let mapper = SourceMapper::new(src);
+        assert_eq!(mapper.line_count(), 4);
+        assert_eq!(lexer.line_starts(), [0, 11, 24, 27]);
+        assert_eq!((1..=4).map(|line| mapper.line_start(line).unwrap()).collect::<Vec<_>>(), lexer.line_starts());
+        assert_eq!(SourceMapper::from_lexer(src, &lexer).line_count(), 4);
+
+        let c = src.find('c').unwrap();
+        assert_eq!(mapper.position(c, Utf8Bytes), (4, 0));
+        let plus = src.find('+').unwrap();
+        assert_eq!(mapper.position(plus, Utf8Bytes), (2, 11));
+        assert_eq!(mapper.position(plus, Utf16CodeUnits), (2, 9));
+        assert_eq!(mapper.position(plus, CodePoints), (2, 8));
+        // The middle of the emoji, and past the end.
+        assert_eq!(mapper.position(plus - 3, CodePoints), (2, 5));
+        assert_eq!(mapper.position(src.len() + 1, CodePoints), (4, 2));
+
+        for unit in [Utf8Bytes, Utf16CodeUnits, CodePoints] {
+            let (line, column) = mapper.position(plus, unit);
+            assert_eq!(mapper.offset(line, column, unit), Some(plus));
+        }
+        assert_eq!(mapper.offset(1, 100, CodePoints), Some(src.find('\r').unwrap()));
+        assert_eq!(mapper.offset(5, 0, CodePoints), None);
+    }
+
+    #[test]
+    fn empty_lines() {
+        use super::{ColumnUnit::*, SourceMapper};
+        let mapper = SourceMapper::new("");
+        assert_eq!(mapper.line_count(), 1);
+        assert_eq!((mapper.line_start(0), mapper.line_start(1), mapper.line_start(2)), (None, Some(0), None));
+        assert_eq!(mapper.position(0, CodePoints), (1, 0));
+        assert_eq!(mapper.offset(1, 3, Utf16CodeUnits), Some(0));
+
+        // CR LF is one line terminator; CR and LF on their own are each one.
+        let src = "a\r\n\r\n\n\rb\u{2029}";
+        let mapper = SourceMapper::new(src);
+        assert_eq!(mapper.line_count(), 6);
+        assert_eq!((2..=6).map(|line| mapper.line_start(line).unwrap()).collect::<Vec<_>>(), [3, 5, 6, 7, 11]);
+        assert_eq!(mapper.position(2, Utf8Bytes), (1, 2));
+        assert_eq!(mapper.position(src.len(), Utf8Bytes), (6, 0));
+        assert_eq!(mapper.offset(2, 0, Utf8Bytes), Some(3));
+        assert_eq!(mapper.offset(4, 9, CodePoints), Some(6));
+        assert_eq!(mapper.offset(5, 9, CodePoints), Some(8));
+        assert_eq!(mapper.offset(0, 0, CodePoints), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! The parser's stack.
+//!
+//! An LR parser has a state for each node it holds, plus the one it started
+//! in. Rather than keep the states, the nodes, and their locations in three
+//! vectors that have to be pushed, popped and truncated together, the
+//! parser keeps one vector of entries: each node, with its location and the
+//! state it was pushed in. The state the parser is in now is kept apart, as
+//! `top`, since it's the one looked at for every action.
+
+use crate::parser_runtime::SourceLocation;
+
+/// A node on the stack.
+#[derive(Clone, Debug)]
+struct Entry<V> {
+    /// The state the parser was in when the node was pushed.
+    state: usize,
+    node: V,
+    loc: SourceLocation,
+}
+
+/// The parser's states, nodes, and the nodes' locations.
+#[derive(Clone, Debug)]
+pub(crate) struct Stack<V> {
+    entries: Vec<Entry<V>>,
+    top: usize,
+}
+
+impl<V> Stack<V> {
+    /// An empty stack, in `state`.
+    pub fn new(state: usize) -> Stack<V> {
+        Stack { entries: vec![], top: state }
+    }
+
+    /// The state the parser is in.
+    pub fn state(&self) -> usize {
+        self.top
+    }
+
+    /// Move to `state` without pushing anything, as error handling does when
+    /// it shifts an ErrorToken.
+    pub fn set_state(&mut self, state: usize) {
+        self.top = state;
+    }
+
+    /// How many nodes there are.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The location of the node at `index`, counting from the bottom.
+    pub fn loc(&self, index: usize) -> Option<SourceLocation> {
+        self.entries.get(index).map(|entry| entry.loc)
+    }
+
+    /// The location of the top node.
+    pub fn last_loc(&self) -> Option<SourceLocation> {
+        self.entries.last().map(|entry| entry.loc)
+    }
+
+    /// Push `node`, spanning `loc`, and move to `state`.
+    pub fn push(&mut self, node: V, loc: SourceLocation, state: usize) {
+        self.entries.push(Entry { state: self.top, node, loc });
+        self.top = state;
+    }
+
+    /// Pop the top node, and go back to the state it was pushed in.
+    pub fn pop(&mut self) -> Option<V> {
+        let entry = self.entries.pop()?;
+        self.top = entry.state;
+        Some(entry.node)
+    }
+
+    /// Let a reduce action pop the nodes of a production's right-hand side,
+    /// which was pushed in `state`, and push the one it makes of them, which
+    /// spans `loc`. The caller checks that it did, then moves to the state
+    /// after it with set_state().
+    pub fn reducing(&mut self, state: usize, loc: SourceLocation) -> ReduceStack<'_, V> {
+        ReduceStack { entries: &mut self.entries, state, loc }
+    }
+
+    /// The states from the bottom up, ending with the current one.
+    pub fn states(&self) -> Vec<usize> {
+        self.entries.iter().map(|entry| entry.state).chain(Some(self.top)).collect()
+    }
+
+    /// True if the states from the bottom up are `states`.
+    pub fn has_states(&self, states: &[usize]) -> bool {
+        states.len() == self.depth() && (0..states.len()).all(|depth| self.state_at(depth) == Some(states[depth]))
+    }
+}
+
+/// The top of the stack, as a reduce action sees it: the values of the
+/// production's right-hand side, to be popped and replaced by the value the
+/// handler makes of them.
+pub struct ReduceStack<'s, V> {
+    entries: &'s mut Vec<Entry<V>>,
+    /// The state below the right-hand side, and the location of the whole.
+    state: usize,
+    loc: SourceLocation,
+}
+
+impl<'s, V> ReduceStack<'s, V> {
+    pub fn pop(&mut self) -> Option<V> {
+        self.entries.pop().map(|entry| entry.node)
+    }
+
+    pub fn push(&mut self, node: V) {
+        self.entries.push(Entry { state: self.state, node, loc: self.loc });
+    }
+}
+
+/// A stack of parser states, as early errors and the simulator see it.
+/// Depths count from the bottom, and the top state is the current one.
+pub(crate) trait States {
+    /// How many states there are.
+    fn depth(&self) -> usize;
+
+    /// The state at `depth`, if the stack is that deep.
+    fn state_at(&self, depth: usize) -> Option<usize>;
+}
+
+impl<V> States for Stack<V> {
+    fn depth(&self) -> usize {
+        self.entries.len() + 1
+    }
+
+    fn state_at(&self, depth: usize) -> Option<usize> {
+        match self.entries.get(depth) {
+            Some(entry) => Some(entry.state),
+            None if depth == self.entries.len() => Some(self.top),
+            None => None,
+        }
+    }
+}
+
+impl States for Vec<usize> {
+    fn depth(&self) -> usize {
+        self.len()
+    }
+
+    fn state_at(&self, depth: usize) -> Option<usize> {
+        self.get(depth).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Stack, States};
+    use crate::parser_runtime::SourceLocation;
+
+    #[test]
+    fn push_reduce_pop() {
+        let mut stack = Stack::new(0);
+        stack.push('a', SourceLocation::new(0, 1, 1, 0), 5);
+        stack.push('b', SourceLocation::new(2, 3, 1, 2), 7);
+        assert_eq!((stack.state(), stack.len(), stack.depth()), (7, 2, 3));
+        assert_eq!(stack.states(), [0, 5, 7]);
+        assert!(stack.has_states(&[0, 5, 7]));
+        assert!(!stack.has_states(&[0, 5]));
+        assert_eq!(stack.state_at(3), None);
+
+        // A reduce replaces both nodes with one pushed in the state below
+        // them, then moves on from there.
+        let loc = SourceLocation::new(0, 3, 1, 0);
+        let mut reduce = stack.reducing(0, loc);
+        assert_eq!((reduce.pop(), reduce.pop()), (Some('b'), Some('a')));
+        reduce.push('c');
+        stack.set_state(9);
+        assert_eq!(stack.states(), [0, 9]);
+        assert_eq!(stack.last_loc(), Some(loc));
+
+        assert_eq!(stack.pop(), Some('c'));
+        assert_eq!((stack.pop(), stack.state(), stack.loc(0)), (None, 0, None));
+    }
+}
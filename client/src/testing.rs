@@ -0,0 +1,137 @@
+//! Helpers shared by the unit tests.
+
+use crate::ast_arena::{Arena, ArenaRef};
+use crate::early_errors::EarlyErrorKind;
+use crate::parser::{ParseError, Parser};
+use crate::parser_generated::{concrete, DefaultHandler};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+/// Counts the bytes currently allocated, so tests can check that
+/// everything allocated while parsing was freed again.
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+pub fn assert_no_leaks(f: impl FnOnce()) {
+    let before = LIVE_BYTES.load(Ordering::SeqCst);
+    f();
+    assert_eq!(LIVE_BYTES.load(Ordering::SeqCst), before);
+}
+
+pub const INVALID: &[&str] = &["f(1, 2;", "{ a b", "x = ;", "a = b(c, d) +", "if (x) { y = [1, 2"];
+
+/// The parameters and body of the function declaration that `script`
+/// consists of.
+pub fn function_declaration<'alloc>(
+    script: ArenaRef<'alloc, concrete::Script<'alloc>>,
+) -> (
+    ArenaRef<'alloc, concrete::FormalParameters<'alloc>>,
+    ArenaRef<'alloc, concrete::FunctionBody<'alloc>>,
+) {
+    if let concrete::Script::Script(Some(body)) = &*script {
+        if let concrete::ScriptBody::ScriptBody(list) = &**body {
+            if let concrete::StatementList::StatementListP0(item) = &**list {
+                if let concrete::ModuleItem::FunctionDeclarationP0(_, params, body) = &**item {
+                    return (*params, *body);
+                }
+            }
+        }
+    }
+    panic!("expected a function declaration, got {:?}", script);
+}
+
+pub fn function_body<'alloc>(
+    script: ArenaRef<'alloc, concrete::Script<'alloc>>,
+) -> ArenaRef<'alloc, concrete::FunctionBody<'alloc>> {
+    function_declaration(script).1
+}
+
+pub fn async_function_body<'alloc>(
+    script: ArenaRef<'alloc, concrete::Script<'alloc>>,
+) -> ArenaRef<'alloc, concrete::FunctionBody<'alloc>> {
+    if let concrete::Script::Script(Some(body)) = &*script {
+        if let concrete::ScriptBody::ScriptBody(list) = &**body {
+            if let concrete::StatementList::StatementListP0(item) = &**list {
+                if let concrete::ModuleItem::AsyncFunctionDeclarationP0(_, _, body) = &**item {
+                    if let concrete::AsyncFunctionBody::AsyncFunctionBody(body) = &**body {
+                        return *body;
+                    }
+                }
+            }
+        }
+    }
+    panic!("expected an async function declaration, got {:?}", script);
+}
+
+pub fn module_function_body<'alloc>(
+    module: ArenaRef<'alloc, concrete::Module<'alloc>>,
+) -> ArenaRef<'alloc, concrete::FunctionBody<'alloc>> {
+    if let concrete::Module::Module(Some(body)) = &*module {
+        if let concrete::ModuleBody::ModuleBody(list) = &**body {
+            if let concrete::ModuleItemList::ModuleItemListP0(item) = &**list {
+                if let concrete::ModuleItem::FunctionDeclarationP0(_, _, body) = &**item {
+                    return *body;
+                }
+            }
+        }
+    }
+    panic!("expected a function declaration, got {:?}", module);
+}
+
+/// Parse `src` as a script, or if `module` is true, as a module, and
+/// return the kind of early error it has, if any.
+pub fn early_error(src: &str, module: bool) -> Option<EarlyErrorKind> {
+    let arena = Arena::new();
+    let handler = DefaultHandler::new(&arena);
+    let mut parser = if module {
+        Parser::new_module(&handler)
+    } else {
+        Parser::new_script(&handler)
+    };
+    match parser.parse_script(src) {
+        Ok(_) => None,
+        Err(ParseError::EarlyError { kind, .. }) => Some(kind),
+        Err(error) => panic!("{}: {}", src, error.message()),
+    }
+}
+
+/// The expression that `script` consists of, without any parentheses
+/// around it.
+pub fn expression<'alloc>(
+    script: ArenaRef<'alloc, concrete::Script<'alloc>>,
+) -> ArenaRef<'alloc, concrete::Expression<'alloc>> {
+    if let concrete::Script::Script(Some(body)) = &*script {
+        if let concrete::ScriptBody::ScriptBody(list) = &**body {
+            if let concrete::StatementList::StatementListP0(item) = &**list {
+                if let concrete::ModuleItem::ExpressionStatement(expr) = &**item {
+                    let mut expr = *expr;
+                    use concrete::CoverParenthesizedExpressionAndArrowParameterList as Cover;
+                    while let concrete::Expression::PrimaryExpressionP12(cover) = &*expr {
+                        match &**cover {
+                            Cover::CoverParenthesizedExpressionAndArrowParameterListP0(inner) => expr = *inner,
+                            _ => break,
+                        }
+                    }
+                    return expr;
+                }
+            }
+        }
+    }
+    panic!("expected an expression statement, got {:?}", script);
+}
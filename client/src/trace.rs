@@ -0,0 +1,129 @@
+//! A log of what the parser does with its tables, for debugging: each
+//! shift, reduce and goto, with the states involved. When a parse goes
+//! wrong only with newly generated tables, comparing the traces of the old
+//! and new tables shows where they part ways.
+//!
+//! Parser::set_trace() turns the logging on, and take_trace() returns the
+//! events. Each displays as one line.
+
+use crate::parser_generated::{NonterminalId, TerminalId};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceEvent<'a> {
+    /// In `state`, `terminal` was shifted, going to state `next`.
+    Shift { state: usize, terminal: TerminalId, next: usize },
+    /// In `state`, the production at `prod_index`, named `production`,
+    /// was reduced.
+    Reduce { state: usize, prod_index: usize, production: &'a str },
+    /// After popping back to `state`, `nonterminal` took the parser to
+    /// state `next`.
+    Goto { state: usize, nonterminal: NonterminalId, next: usize },
+}
+
+impl fmt::Display for TraceEvent<'_> {
+    /// Like `state 12: shift `;` -> state 40`, `state 40: reduce 17.
+    /// Statement ::= ExpressionStatement` or `state 5: goto Statement ->
+    /// state 33`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceEvent::Shift { state, terminal, next } => {
+                write!(f, "state {}: shift {} -> state {}", state, terminal, next)
+            }
+            TraceEvent::Reduce { state, prod_index, production } => {
+                write!(f, "state {}: reduce {}. {}", state, prod_index, production)
+            }
+            TraceEvent::Goto { state, nonterminal, next } => {
+                write!(f, "state {}: goto {:?} -> state {}", state, nonterminal, next)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast_arena::Arena;
+    use crate::parser::Parser;
+    use crate::parser_generated::{DefaultHandler, EntryPoint, TerminalId, TABLES};
+    use crate::parser_runtime::{ParserTables, TableProblem};
+
+    #[test]
+    fn trace() {
+        use crate::parser_generated::NonterminalId;
+        use super::TraceEvent;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        parser.set_trace(true);
+        assert!(parser.parse_script("x;").is_ok());
+        let trace = parser.take_trace().unwrap();
+        let start = EntryPoint::Script.state();
+        let next = match trace[0] {
+            TraceEvent::Shift { next, .. } => next,
+            _ => panic!("expected a shift, got {:?}", trace[0]),
+        };
+        let production = "IdentifierReference ::= Identifier";
+        let prod_index = TABLES.production_names.iter().position(|&n| n == production).unwrap();
+        assert_eq!(
+            trace[..3],
+            [
+                TraceEvent::Shift {
+                    state: start,
+                    terminal: TerminalId::Identifier,
+                    next,
+                },
+                TraceEvent::Reduce {
+                    state: next,
+                    prod_index,
+                    production,
+                },
+                TraceEvent::Goto {
+                    state: start,
+                    nonterminal: NonterminalId::IdentifierReference,
+                    next: TABLES.goto_table[start * TABLES.goto_width + NonterminalId::IdentifierReference as usize],
+                },
+            ]
+        );
+        assert_eq!(trace[0].to_string(), format!("state {}: shift Identifier -> state {}", start, next));
+        assert!(trace.iter().any(|e| matches!(e, TraceEvent::Shift { terminal: TerminalId::Semicolon, .. })));
+        assert_eq!(parser.take_trace(), Some(vec![]));
+
+        let unnamed = ParserTables {
+            production_names: &[],
+            ..TABLES
+        };
+        assert_eq!(
+            unnamed.validate(),
+            Err(vec![TableProblem::NamesSize {
+                kind: "production",
+                expected: TABLES.reduce_simulator.len(),
+                actual: 0
+            }])
+        );
+    }
+
+    #[test]
+    fn trace_off_and_errors() {
+        use super::TraceEvent;
+        let arena = Arena::new();
+        let handler = DefaultHandler::new(&arena);
+        let mut parser = Parser::new_script(&handler);
+        assert!(parser.parse_script("x;").is_ok());
+        assert_eq!(parser.take_trace(), None);
+
+        // A parse that fails keeps the events up to the token it failed on,
+        // which was never shifted.
+        let mut parser = Parser::new_script(&handler);
+        parser.set_trace(true);
+        assert!(parser.parse_script("(x;").is_err());
+        let trace = parser.take_trace().unwrap();
+        let shifted: Vec<TerminalId> = trace
+            .iter()
+            .filter_map(|e| match e {
+                TraceEvent::Shift { terminal, .. } => Some(*terminal),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shifted, [TerminalId::LeftParenthesis, TerminalId::Identifier]);
+    }
+}
@@ -0,0 +1,30 @@
+//! The parser, for JavaScript. Build with
+//! `wasm-pack build --target web` to get a `pkg/` directory the demo page
+//! or a bundler can import.
+
+use wasm_bindgen::prelude::*;
+
+/// How parse() parses. Both options are off by default.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Parse a module instead of a script.
+    pub module: bool,
+    /// Keep going after syntax errors, reporting each one.
+    pub recover: bool,
+}
+
+#[wasm_bindgen]
+impl ParseOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ParseOptions {
+        ParseOptions::default()
+    }
+}
+
+/// Parse `source` and return JSON: `{"ast": ..., "diagnostics": [...]}`,
+/// where `ast` is an ESTree Program, or `null` if `source` doesn't parse.
+#[wasm_bindgen]
+pub fn parse(source: &str, options: &ParseOptions) -> String {
+    client::estree::parse_to_estree_json(source, options.module, options.recover)
+}
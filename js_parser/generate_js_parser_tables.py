@@ -10,6 +10,9 @@ from .parse_esgrammar import parse_esgrammar
 ECMASCRIPT_GOAL_NTS = [
     'Script',
     'Module',
+    jsparagus.grammar.Nt('Expression', (('In', True),
+                                        ('Yield', False),
+                                        ('Await', False))),
     # 'FormalParameters',
     # 'FunctionBody',
 ]
@@ -18,7 +21,8 @@ ECMASCRIPT_GOAL_NTS = [
 def hack_grammar(g):
     # We throw away all parameterized productions, as the current parser
     # generator's approach of fully expanding them is a huge pain. We also
-    # throw away "in" expressions to avoid a shift-reduce conflict.
+    # throw away "in" expressions to avoid a shift-reduce conflict, except
+    # `#x in obj`: a private name can't start the head of a for-in loop.
 
     def strip_args(e):
         """ Strip nt arguments. """
@@ -36,14 +40,20 @@ def hack_grammar(g):
 
     nonterminals = {}
     for nt, nt_def in g.nonterminals.items():
+        if isinstance(nt, jsparagus.grammar.InitNt):
+            # Dropped. The Grammar constructor synthesizes new ones for the
+            # stripped goals.
+            continue
         rhs_list = [
             strip_args_in_production(p)
             for p in nt_def.rhs_list
-            if p.condition != ('In', True)
+            if p.condition != ('In', True) or p.body[0] == 'PrivateIdentifier'
         ]
         nt_def = jsparagus.grammar.NtDef([], rhs_list)
         nonterminals[nt] = nt_def
-    return g.with_nonterminals(nonterminals)
+    goals = [strip_args(goal) for goal in g.goals()]
+    return jsparagus.grammar.Grammar(nonterminals, goals, g.variable_terminals,
+                                     (g.nt_types, g.methods))
 
 
 def main():
@@ -65,6 +75,14 @@ def main():
     parser.add_argument(
         '--progress', action='store_true',
         help="print a dot each time a state is analyzed (thousands of them)")
+    parser.add_argument(
+        '--keep-conflicts', action='store_true',
+        help="instead of failing on a grammar conflict, keep the losing "
+        "actions for the Rust parser's GLR mode")
+    parser.add_argument(
+        '--compress-actions', action='store_true',
+        help="pack the Rust action table by row displacement instead of "
+        "writing out every entry")
     args = parser.parse_args()
 
     # Check filenames.
@@ -85,6 +103,11 @@ def main():
         target = 'dump'
     else:
         raise ValueError("-o file extension should be .py, .rs, or .jsparagus_dump")
+    if args.compress_actions and target != 'rust':
+        raise ValueError("--compress-actions only applies to .rs output")
+    if args.keep_conflicts and not from_source:
+        raise ValueError("--keep-conflicts needs an .esgrammar file; a "
+                         ".jsparagus_dump has already been analyzed")
 
     # Load input and analyze it.
     if from_source:
@@ -98,7 +121,8 @@ def main():
             grammar.dump()
 
         states = jsparagus.gen.generate_parser_states(
-            grammar, verbose=args.verbose, progress=args.progress)
+            grammar, verbose=args.verbose, progress=args.progress,
+            keep_conflicts=args.keep_conflicts)
     else:
         states = jsparagus.gen.ParserStates.load(in_filename)
 
@@ -108,6 +132,7 @@ def main():
             with open(out_filename, 'w') as f:
                 jsparagus.gen.generate_parser(f, states,
                                               target=target,
+                                              compress_actions=args.compress_actions,
                                               verbose=args.verbose)
         else:
             assert target == 'dump'
@@ -0,0 +1,159 @@
+"""generate_keyword_table.py - Generate the lexer's keyword table.
+
+Finds a perfect hash for the words that can be keywords in JS, and writes it
+as a Rust table that takes one string comparison to look a word up in:
+
+    python3 -m js_parser.generate_keyword_table -o client/src/keywords_generated.rs
+
+The hash only looks at a word's length and its first two and last bytes, so
+it's cheap to compute, and it's perfect by construction: the search below
+tries multipliers until no two keywords land in the same slot.
+"""
+
+import argparse
+import itertools
+
+
+# The words the lexer always returns as keywords, and the terminal each is.
+# `yield` and `await` are among them; the parser turns them back into
+# identifiers where they aren't keywords.
+RESERVED = [
+    ('await', 'Await'),
+    ('break', 'Break'),
+    ('case', 'Case'),
+    ('catch', 'Catch'),
+    ('class', 'Class'),
+    ('const', 'Const'),
+    ('continue', 'Continue'),
+    ('debugger', 'Debugger'),
+    ('default', 'Default'),
+    ('delete', 'Delete'),
+    ('do', 'Do'),
+    ('else', 'Else'),
+    ('export', 'Export'),
+    ('extends', 'Extends'),
+    ('finally', 'Finally'),
+    ('for', 'For'),
+    ('function', 'Function'),
+    ('if', 'If'),
+    ('import', 'Import'),
+    ('in', 'In'),
+    ('instanceof', 'Instanceof'),
+    ('new', 'New'),
+    ('return', 'Return'),
+    ('super', 'Super'),
+    ('switch', 'Switch'),
+    ('this', 'This'),
+    ('throw', 'Throw'),
+    ('try', 'Try'),
+    ('typeof', 'Typeof'),
+    ('var', 'Var'),
+    ('void', 'Void'),
+    ('while', 'While'),
+    ('with', 'With'),
+    ('yield', 'Yield'),
+    ('null', 'NullLiteral'),
+    ('true', 'BooleanLiteral'),
+    ('false', 'BooleanLiteral'),
+]
+
+# The words that are keywords only in some places. The lexer returns them as
+# identifiers, and Parser::contextual_word() decides.
+CONTEXTUAL = [
+    ('as', 'As'),
+    ('async', 'Async'),
+    ('from', 'From'),
+    ('get', 'Get'),
+    ('let', 'Let'),
+    ('meta', 'Meta'),
+    ('of', 'Of'),
+    ('set', 'Set'),
+    ('static', 'Static'),
+    ('target', 'Target'),
+]
+
+FLAGS = [
+    ('RESERVED', 'The lexer returns the word as a keyword.'),
+    ('CONTEXTUAL', 'The word is a keyword only in some places; see Parser::contextual_word().'),
+]
+
+
+def hash_word(word, size, k):
+    b = word.encode()
+    return (len(b) * k[0] + b[0] * k[1] + b[1] * k[2] + b[-1] * k[3]) & (size - 1)
+
+
+def find_hash(words):
+    """Return the smallest power-of-two table size, and multipliers for it,
+    that give each of `words` a slot of its own."""
+    size = 1
+    while size < len(words):
+        size *= 2
+    while True:
+        for k in itertools.product(range(1, 16), repeat=4):
+            slots = {hash_word(word, size, k) for word in words}
+            if len(slots) == len(words):
+                return size, k
+        size *= 2
+
+
+def write_rust(out):
+    entries = [(word, terminal, 'RESERVED') for word, terminal in RESERVED]
+    entries += [(word, terminal, 'CONTEXTUAL') for word, terminal in CONTEXTUAL]
+    size, k = find_hash([word for word, _, _ in entries])
+    slots = [None] * size
+    for entry in entries:
+        slots[hash_word(entry[0], size, k)] = entry
+    lengths = [len(word) for word, _, _ in entries]
+
+    out.write("// THIS FILE IS AUTOGENERATED by js_parser/generate_keyword_table.py.\n")
+    out.write("\n")
+    out.write("use crate::parser_runtime::TerminalId;\n")
+    for bit, (name, doc) in enumerate(FLAGS):
+        out.write("\n")
+        out.write("/// {}\n".format(doc))
+        out.write("pub(crate) const {}: u8 = {};\n".format(name, 1 << bit))
+    out.write("\n")
+    out.write("/// The keywords, each in the slot keyword_slot() picks for it, with the\n")
+    out.write("/// terminal it is as a keyword and its flags. Empty slots have \"\".\n")
+    out.write("const SLOTS: [(&str, TerminalId, u8); {}] = [\n".format(size))
+    for slot in slots:
+        if slot is None:
+            out.write("    (\"\", TerminalId::Identifier, 0),\n")
+        else:
+            out.write("    (\"{}\", TerminalId::{}, {}),\n".format(*slot))
+    out.write("];\n")
+    out.write("\n")
+    out.write("/// The slot `word` would be in, if it's a keyword.\n")
+    out.write("fn keyword_slot(word: &[u8]) -> usize {\n")
+    terms = ['word.len()', 'word[0] as usize', 'word[1] as usize', 'word[word.len() - 1] as usize']
+    terms = [term if m == 1 else '{} * {}'.format(term, m) for term, m in zip(terms, k)]
+    out.write("    ({}) & {}\n".format(' + '.join(terms), size - 1))
+    out.write("}\n")
+    out.write("\n")
+    out.write("/// The terminal `word` is as a keyword, and its flags, if it's one.\n")
+    out.write("pub(crate) fn lookup_keyword(word: &str) -> Option<(TerminalId, u8)> {\n")
+    out.write("    if !({}..={}).contains(&word.len()) {{\n".format(min(lengths), max(lengths)))
+    out.write("        return None;\n")
+    out.write("    }\n")
+    out.write("    let (keyword, terminal_id, flags) = SLOTS[keyword_slot(word.as_bytes())];\n")
+    out.write("    if keyword == word {\n")
+    out.write("        Some((terminal_id, flags))\n")
+    out.write("    } else {\n")
+    out.write("        None\n")
+    out.write("    }\n")
+    out.write("}\n")
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Generate the lexer's keyword table.")
+    parser.add_argument('-o', '--output', metavar='FILE', default='client/src/keywords_generated.rs',
+                        help="where to write the table")
+    args = parser.parse_args()
+
+    with open(args.output, 'w', encoding='utf-8') as out:
+        write_rust(out)
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,75 @@
+"""generate_unicode_tables.py - Generate the lexer's identifier tables.
+
+Reads DerivedCoreProperties.txt from the Unicode Character Database, which is
+at <https://www.unicode.org/Public/UCD/latest/ucd/DerivedCoreProperties.txt>,
+and writes the ID_Start and ID_Continue properties as Rust tables of ranges:
+
+    python3 -m js_parser.generate_unicode_tables \\
+        DerivedCoreProperties.txt -o client/src/unicode_generated.rs
+"""
+
+import argparse
+import re
+
+
+PROPERTIES = ['ID_Start', 'ID_Continue']
+
+
+def read_properties(lines):
+    """Return a dict mapping each property in PROPERTIES to a sorted list of
+    (first, last) code point ranges, merged where they touch."""
+    ranges = {name: [] for name in PROPERTIES}
+    version = None
+    for line in lines:
+        if version is None:
+            match = re.match(r'# DerivedCoreProperties-(\d+\.\d+\.\d+)\.txt', line)
+            if match:
+                version = match.group(1)
+        line = line.split('#', 1)[0].strip()
+        if not line:
+            continue
+        points, name = [field.strip() for field in line.split(';')]
+        if name not in ranges:
+            continue
+        first, _, last = points.partition('..')
+        ranges[name].append((int(first, 16), int(last or first, 16)))
+
+    for name, rs in ranges.items():
+        merged = []
+        for first, last in sorted(rs):
+            if merged and first <= merged[-1][1] + 1:
+                merged[-1] = (merged[-1][0], max(last, merged[-1][1]))
+            else:
+                merged.append((first, last))
+        ranges[name] = merged
+    return version, ranges
+
+
+def write_rust(out, version, ranges):
+    out.write("// THIS FILE IS AUTOGENERATED by js_parser/generate_unicode_tables.py\n")
+    out.write("// from DerivedCoreProperties.txt, Unicode {}.\n".format(version or "(unknown version)"))
+    for name in PROPERTIES:
+        out.write("\n")
+        out.write("/// The ranges of code points with the {} property, in order.\n".format(name))
+        out.write("pub(crate) const {}: &[(char, char)] = &[\n".format(name.upper()))
+        for first, last in ranges[name]:
+            out.write("    ('\\u{{{:x}}}', '\\u{{{:x}}}'),\n".format(first, last))
+        out.write("];\n")
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Generate the lexer's Unicode tables.")
+    parser.add_argument('ucd_file', metavar='DerivedCoreProperties.txt',
+                        help="the file from the Unicode Character Database")
+    parser.add_argument('-o', '--output', metavar='FILE', default='client/src/unicode_generated.rs',
+                        help="where to write the tables")
+    args = parser.parse_args()
+
+    with open(args.ucd_file, encoding='utf-8') as f:
+        version, ranges = read_properties(f)
+    with open(args.output, 'w', encoding='utf-8') as out:
+        write_rust(out, version, ranges)
+
+
+if __name__ == '__main__':
+    main()
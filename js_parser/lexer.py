@@ -172,7 +172,7 @@ class JSLexer(jsparagus.lexer.FlatStringLexer):
         c = token[0]
         t = None
         if c.isdigit() or c == '.' and token != '.':
-            t = 'NumericLiteral'
+            t = 'BigIntLiteral' if token.endswith('n') else 'NumericLiteral'
         elif c.isalpha() or c in '$_':
             if self.parser.can_accept_terminal('IdentifierName'):
                 t = 'IdentifierName'
@@ -183,7 +183,7 @@ class JSLexer(jsparagus.lexer.FlatStringLexer):
                     t = 'BooleanLiteral'
                 else:
                     t = token
-            elif (token in ('let', 'static', 'yield', 'async', 'of')
+            elif (token in ('let', 'static', 'yield', 'async', 'of', 'meta')
                   and self.parser.can_accept_terminal(token)):
                 # This is not what the standard says but eh
                 t = token
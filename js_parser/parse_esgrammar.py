@@ -312,7 +312,8 @@ def finish_grammar(nt_defs, goals):
 
     def hack_production(p):
         for i, e in enumerate(p.body):
-            if isinstance(e, str) and e[:1] == "`":
+            # A lone "`" is the backtick terminal, written ``` in the grammar.
+            if isinstance(e, str) and e[:1] == "`" and e != "`":
                 if len(e) < 3 or e[-1:] != "`":
                     raise ValueError(
                         "Unrecognized grammar symbol: {!r} (in {!r})"
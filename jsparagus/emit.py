@@ -1,16 +1,34 @@
 """Emit code pelled the saor parser tables in either Python or Rust. """
 
+import collections
 import re
 import unicodedata
 
-from .runtime import ERROR
+from .runtime import ACCEPT, ERROR
 from .ordered import OrderedSet
 
 from .grammar import (InitNt, CallMethod, Some, is_concrete_element, Nt,
-                      Optional, ErrorToken)
+                      Optional, ErrorToken, LookaheadRule)
 
 from . import types
 
+# The nonterminal that a lazy parse checks but doesn't build. The handler's
+# `lazy_function_body()` stands in for it (see `Parser::set_lazy`).
+LAZY_NONTERMINAL = 'FunctionBody'
+
+# The action table entry for accepting the input in Rust tables, whose
+# entries are i16 rather than Python ints. ERROR is one less.
+RUST_ACCEPT = -0x7fff
+
+# Goal symbols that are parsed as modules: strict mode code from the start,
+# where import.meta is allowed and imports and exports are recorded.
+MODULE_GOALS = {'Module'}
+
+# The nonterminal whose type is the type of expressions. When a node is
+# unparsed, its expressions go to the Unparser along with the nonterminal
+# that the grammar expects in their place, so that it can add parentheses.
+EXPRESSION_NONTERMINAL = 'Expression'
+
 
 def write_python_parser(out, parser_states):
     grammar = parser_states.grammar
@@ -88,13 +106,58 @@ TERMINAL_NAMES = {
 }
 
 
+def rust_str(s):
+    """Format a Python string as a Rust string literal."""
+    return '"' + s.replace('\\', '\\\\').replace('"', '\\"') + '"'
+
+
+def displace_rows(rows, width):
+    """Pack the sparse `rows` of a table into one array by row displacement.
+
+    Each row is a dict mapping column numbers, less than `width`, to
+    entries. Rows are placed, densest first, at the lowest offset where
+    their entries land on free slots. Returns `(base, entries, check)`:
+    row `i`, column `c` is `entries[base[i] + c]` if `check[base[i] + c]`
+    is `i`, and missing otherwise. Free slots hold None in `entries` and
+    `len(rows)` in `check`.
+    """
+    base = [0] * len(rows)
+    entries = []
+    check = []
+    used = bytearray()
+    for i in sorted(range(len(rows)), key=lambda i: -len(rows[i])):
+        columns = sorted(rows[i])
+        if not columns:
+            continue
+        assert columns[-1] < width
+        # Try offsets that put the first entry on a free slot.
+        first = used.find(0)
+        b = max(0, (len(used) if first == -1 else first) - columns[0])
+        while any(b + c < len(used) and used[b + c] for c in columns):
+            free = used.find(0, b + columns[0] + 1)
+            b = (len(used) if free == -1 else free) - columns[0]
+        end = b + columns[-1] + 1
+        if end > len(used):
+            entries.extend([None] * (end - len(used)))
+            check.extend([len(rows)] * (end - len(used)))
+            used.extend(bytes(end - len(used)))
+        for c in columns:
+            entries[b + c] = rows[i][c]
+            check[b + c] = i
+            used[b + c] = 1
+        base[i] = b
+    return base, entries, check
+
+
 class RustParserWriter:
-    def __init__(self, out, parser_states):
+    def __init__(self, out, parser_states, compress_actions=False):
         self.out = out
+        self.compress_actions = compress_actions
         self.grammar = parser_states.grammar
         self.prods = parser_states.prods
         self.states = parser_states.states
         self.init_state_map = parser_states.init_state_map
+        self.concrete_types_with_refs = None
         self.terminals = list(OrderedSet(
             t for state in self.states for t in state.action_row))
         self.nonterminals = list(OrderedSet(
@@ -103,16 +166,22 @@ class RustParserWriter:
     def emit(self):
         self.header()
         self.terminal_id()
-        self.token()
         self.actions()
+        self.alternatives()
         self.check_camel_case()
         self.handler_trait()
         self.nt_node()
         self.nt_node_impl()
+        self.unparse()
+        self.visitors()
+        self.syntax_only_handler()
+        self.cst_handler()
+        self.stack_value()
         self.nonterminal_id()
         self.goto()
-        self.reduce(False)
-        self.entry(False)
+        self.reduce_simulator()
+        self.reduce()
+        self.entry()
 
     def write(self, indentation, string, *format_args):
         if len(format_args) == 0:
@@ -124,11 +193,15 @@ class RustParserWriter:
     def header(self):
         self.write(0, "// THIS FILE IS AUTOGENERATED -- HAHAHAHA")
         self.write(0, "")
-        self.write(
-            0, "use super::parser_runtime::{self, ParserTables, TokenStream};")
-        self.write(0, "use super::parser::Result;")
+        self.write(0, "use std::fmt;")
+        self.write(0, "use std::marker::PhantomData;")
         self.write(0, "")
-        self.write(0, "const ERROR: i64 = {};", hex(ERROR))
+        self.write(
+            0, "use super::parser_runtime::{self, ActionTable, ParserTables, SourceLocation, Token, TokenStream};")
+        self.write(0, "use super::parser_runtime::{ReduceStack, Reducer, ACCEPT, ERROR};")
+        self.write(0, "use super::parser::{LazyFunction, Parser, Result};")
+        self.write(0, "use super::ast_arena::{Arena, ArenaRef, NodeLocations};")
+        self.write(0, "use super::cst::{CstElement, CstNode};")
         self.write(0, "")
 
     def terminal_name(self, value):
@@ -161,42 +234,110 @@ class RustParserWriter:
         self.write(0, "}")
         self.write(0, "")
 
-    def token(self):
-        self.write(0, "#[derive(Clone, Debug, PartialEq)]")
-        self.write(0, "pub enum Token {")
+        self.write(0, "impl TerminalId {")
+        self.write(1, "pub fn from_usize(index: usize) -> Option<TerminalId> {")
+        self.write(2, "match index {")
         for i, t in enumerate(self.terminals):
-            name = self.terminal_name(t)
-            value = "(String)" if t in self.grammar.variable_terminals else ""
-            self.write(1, "{}{}, // {}", name, value, repr(t))
+            self.write(3, "{} => Some(TerminalId::{}),", i, self.terminal_name(t))
+        self.write(3, "_ => None,")
+        self.write(2, "}")
+        self.write(1, "}")
         self.write(0, "}")
         self.write(0, "")
 
-        self.write(0, "impl Token {")
-        self.write(1, "pub fn get_id(&self) -> TerminalId {")
-        self.write(2, "// This switch should be optimized away.")
-        self.write(2, "match self {")
-        for i, t in enumerate(self.terminals):
-            name = self.terminal_name(t)
-            value = "(_)" if t in self.grammar.variable_terminals else ""
-            self.write(3, "Token::{}{} => TerminalId::{},",
-                       name, value, name)
-        self.write(2, "}")
+        self.write(0, "/// How each terminal is shown in messages, like `` `;` `` or")
+        self.write(0, "/// `Identifier`, by TerminalId.")
+        self.write(0, "pub static TERMINAL_NAMES: [&str; {}] = [", len(self.terminals))
+        for t in self.terminals:
+            self.write(1, "{},", rust_str(self.terminal_display(t)))
+        self.write(0, "];")
+        self.write(0, "")
+
+        self.write(0, "impl fmt::Display for TerminalId {")
+        self.write(1, "fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {")
+        self.write(2, "f.write_str(TERMINAL_NAMES[*self as usize])")
         self.write(1, "}")
         self.write(0, "}")
         self.write(0, "")
 
+    def terminal_display(self, value):
+        """How a terminal is described to the user in error messages."""
+        if value is None:
+            return "end of input"
+        elif value is ErrorToken:
+            return "error token"
+        elif value.isalpha() and not value.islower():
+            # Named token, like Identifier or NumericLiteral.
+            return value
+        else:
+            return "`{}`".format(value)
+
     def actions(self):
-        self.write(0, "static ACTIONS: [i64; {}] = [",
-                   len(self.states) * len(self.terminals))
+        if self.compress_actions:
+            self.displaced_actions()
+            return
+        self.write(0, "static ACTIONS: [[i16; {}]; {}] = [",
+                   len(self.terminals), len(self.states))
         for i, state in enumerate(self.states):
             self.write(1, "// {}. {}", i, state.traceback() or "<empty>")
-            self.write(1, "{}",
-                       ' '.join("{},".format(state.action_row.get(t, "ERROR")) for t in self.terminals))
+            self.write(1, "[{}],",
+                       ' '.join("{},".format(self.action_entry(state.action_row.get(t)))
+                                for t in self.terminals))
             if i < len(self.states) - 1:
                 self.write(0, "")
         self.write(0, "];")
         self.write(0, "")
 
+    def alternatives(self):
+        column = {t: i for i, t in enumerate(self.terminals)}
+        entries = sorted(
+            ((i, column[t], action)
+             for i, state in enumerate(self.states)
+             for t, actions in state.alternatives.items()
+             for action in actions),
+            key=lambda entry: entry[:2])
+        self.write(0, "/// Actions that lost a conflict, as (state, terminal, action), in order.")
+        self.write(0, "static ALTERNATIVES: [(usize, usize, i16); {}] = [", len(entries))
+        for state, t, action in entries:
+            self.write(1, "({}, {}, {}),", state, t, self.action_entry(action))
+        self.write(0, "];")
+        self.write(0, "")
+
+    def action_entry(self, action):
+        """The Rust expression for an action table entry, an i16. None is an
+        error entry."""
+        if action is None or action == ERROR:
+            return "ERROR"
+        elif action == ACCEPT:
+            return "ACCEPT"
+        elif not RUST_ACCEPT < action <= 0x7fff:
+            raise ValueError("too many states or productions for i16 action "
+                             "table entries: {}".format(action))
+        return str(action)
+
+    def displaced_actions(self):
+        column = {t: i for i, t in enumerate(self.terminals)}
+        rows = [{column[t]: action for t, action in state.action_row.items()}
+                for state in self.states]
+        base, entries, check = displace_rows(rows, len(self.terminals))
+        self.write(0, "static ACTION_BASE: [usize; {}] = [", len(base))
+        for i in range(0, len(base), 16):
+            self.write(1, "{}", ' '.join("{},".format(b) for b in base[i:i + 16]))
+        self.write(0, "];")
+        self.write(0, "")
+        self.write(0, "static ACTIONS: [i16; {}] = [", len(entries))
+        for i in range(0, len(entries), 16):
+            self.write(1, "{}", ' '.join(
+                "{},".format(self.action_entry(a))
+                for a in entries[i:i + 16]))
+        self.write(0, "];")
+        self.write(0, "")
+        self.write(0, "static ACTION_CHECK: [usize; {}] = [", len(check))
+        for i in range(0, len(check), 16):
+            self.write(1, "{}", ' '.join("{},".format(c) for c in check[i:i + 16]))
+        self.write(0, "];")
+        self.write(0, "")
+
     def nonterminal_to_snake(self, ident):
         if isinstance(ident, Nt):
             base_name = self.to_snek_case(ident.name)
@@ -255,17 +396,21 @@ class RustParserWriter:
             visit_type(method.return_type)
         return names
 
-    def type_to_rust(self, ty, handler, boxed=False):
+    def type_to_rust(self, ty, handler, boxed=False, lifetime="'src"):
         """Convert a jsparagus type (see types.py) to Rust.
 
         Pass boxed=True if you're dealing with concrete types.
         This is necessary because
-        DefaultHandler::Expression = Box<concrete::Expression>.
+        DefaultHandler::Expression = ArenaRef<'alloc, concrete::Expression<'alloc>>.
+        `lifetime` is the lifetime of the source text that tokens borrow from;
+        in concrete types, it's 'alloc.
         """
         if ty is types.UnitType:
             return '()'
         elif ty == 'str':
-            return 'String'
+            # A variable terminal. Nodes keep the whole token, so that its
+            # value, raw text and location are all on hand.
+            return "Token<{}>".format("'alloc" if boxed else lifetime)
         elif ty == 'bool':
             return 'bool'
         elif isinstance(ty, types.NtType):
@@ -274,16 +419,18 @@ class RustParserWriter:
             else:
                 rty = handler + '::' + ty.name
             if boxed:
-                return 'Box<{}>'.format(rty)
+                return "ArenaRef<'alloc, {}{}>".format(
+                    rty, self.concrete_type_params(ty.name))
             else:
                 return rty
         elif isinstance(ty, types.OptionType):
-            return 'Option<{}>'.format(self.type_to_rust(ty.t, handler, boxed))
+            return 'Option<{}>'.format(self.type_to_rust(ty.t, handler, boxed, lifetime))
         else:
             raise TypeError("unexpected type: {!r}".format(ty))
 
     def handler_trait(self):
-        self.write(0, "pub trait Handler {")
+        self.write(0, "pub trait Handler<'src> {")
+        self.write(1, "type Value: From<Token<'src>>;")
 
         for name in self.get_associated_type_names():
             self.write(1, "type {};", name)
@@ -301,18 +448,60 @@ class RustParserWriter:
                 return_type_tag = ' -> ' + \
                     self.type_to_rust(method.return_type, "Self")
 
-            args = ", ".join(("a{}: {}".format(i, t)
-                              for i, t in enumerate(arg_types)))
-            self.write(1, "fn {}(&self, {}){};",
+            args = "".join((", a{}: {}".format(i, t)
+                            for i, t in enumerate(arg_types)))
+            self.write(1, "fn {}(&self, loc: SourceLocation{}){};",
                        method_name, args, return_type_tag)
+
+        self.write(1, "/// Make a placeholder for an `nt` that failed to parse, or return")
+        self.write(1, "/// None if `nt` can't be represented.")
+        self.write(1, "fn error_node(&self, nt: NonterminalId, loc: SourceLocation) -> Option<Self::Value>;")
+        self.write(1, "/// Take back a node that error recovery popped off the stack and won't")
+        self.write(1, "/// use. By default it's just dropped.")
+        self.write(1, "fn discard(&self, _node: Self::Value) {}")
+        if self.lazy_type() is not None:
+            self.write(1, "/// Make a node for a function body that a lazy parse skipped.")
+            self.write(1, "fn lazy_function_body(&self, lazy: LazyFunction) -> Self::Value;")
         self.write(0, "}")
         self.write(0, "")
 
+    def lazy_type(self):
+        """Name of the type of LAZY_NONTERMINAL, or None if the grammar
+        doesn't have it."""
+        ty = self.grammar.nt_types.get(LAZY_NONTERMINAL)
+        if isinstance(ty, types.NtType):
+            return ty.name
+        return None
+
+    def concrete_type_params(self, name):
+        """Generic parameters of concrete::`name`: `<'alloc>` if it can refer
+        to other nodes in the arena, or hold a token, which borrows from source
+        text that lives as long as the arena."""
+        if self.concrete_types_with_refs is None:
+            with_refs = set()
+
+            def has_ref(ty):
+                if isinstance(ty, types.NtType) or ty == 'str':
+                    return True
+                elif isinstance(ty, types.OptionType):
+                    return has_ref(ty.t)
+                return False
+
+            for method in self.grammar.methods.values():
+                if any(has_ref(ty) for ty in method.argument_types):
+                    with_refs.add(method.return_type.name)
+            self.concrete_types_with_refs = with_refs
+        if name in self.concrete_types_with_refs:
+            return "<'alloc>"
+        return ""
+
     def nt_node(self):
         self.write(0, "pub mod concrete {")
+        self.write(0, "use super::{ArenaRef, Token};")
+        self.write(0, "")
         for name in self.get_associated_type_names():
-            self.write(0, "#[derive(Debug)]")
-            self.write(0, "pub enum {} {{", name)
+            self.write(0, "#[derive(Clone, Debug)]")
+            self.write(0, "pub enum {}{} {{", name, self.concrete_type_params(name))
             for tag, method in self.grammar.methods.items():
                 # TODO: Make this check better
                 if method.return_type.name != name:
@@ -324,23 +513,57 @@ class RustParserWriter:
                     if ty != types.UnitType
                 ]
                 self.write(1, "{}({}),", method_name, ", ".join(arg_types))
+            self.write(1, "Error(super::SourceLocation),")
+            if name == self.lazy_type():
+                self.write(1, "Lazy(super::LazyFunction),")
             self.write(0, "}")
             self.write(0, "")
         self.write(0, "}")
         self.write(0, "")
 
     def nt_node_impl(self):
-        self.write(0, "pub struct DefaultHandler {}")
+        self.write(0, "/// Builds a concrete syntax tree, allocating every node in an arena.")
+        self.write(0, "#[derive(Clone, Copy, Debug)]")
+        self.write(0, "pub struct DefaultHandler<'alloc> {")
+        self.write(1, "arena: &'alloc Arena,")
+        self.write(1, "locations: Option<&'alloc NodeLocations>,")
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "impl<'alloc> DefaultHandler<'alloc> {")
+        self.write(1, "pub fn new(arena: &'alloc Arena) -> Self {")
+        self.write(2, "DefaultHandler { arena, locations: None }")
+        self.write(1, "}")
+        self.write(0, "")
+        self.write(1, "/// A handler that also records the location of every node in `locations`.")
+        self.write(1, "pub fn with_locations(arena: &'alloc Arena, locations: &'alloc NodeLocations) -> Self {")
+        self.write(2, "DefaultHandler { arena, locations: Some(locations) }")
+        self.write(1, "}")
+        self.write(0, "")
+        self.write(1, "/// The arena the nodes are allocated in.")
+        self.write(1, "pub fn arena(&self) -> &'alloc Arena {")
+        self.write(2, "self.arena")
+        self.write(1, "}")
         self.write(0, "")
-        self.write(0, "impl Handler for DefaultHandler {")
+        self.write(1, "fn node<T: Send>(&self, loc: SourceLocation, node: T) -> ArenaRef<'alloc, T> {")
+        self.write(2, "let node = self.arena.alloc(node);")
+        self.write(2, "if let Some(locations) = self.locations {")
+        self.write(3, "locations.insert(&*node, loc);")
+        self.write(2, "}")
+        self.write(2, "node")
+        self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "impl<'alloc> Handler<'alloc> for DefaultHandler<'alloc> {")
+        self.write(1, "type Value = StackValue<'alloc, DefaultHandler<'alloc>>;")
         for name in self.get_associated_type_names():
-            self.write(1, "type {} = Box<concrete::{}>;", name, name)
+            self.write(1, "type {} = {};", name,
+                       self.type_to_rust(types.NtType(name), "concrete", boxed=True))
 
         for tag, method in self.grammar.methods.items():
             method_name = self.method_name_to_rust(tag)
             method_name_camel = self.to_camel_case(method_name)
             arg_types = [
-                self.type_to_rust(ty, "Self")
+                self.type_to_rust(ty, "Self", lifetime="'alloc")
                 for ty in method.argument_types
                 if ty != types.UnitType
             ]
@@ -355,14 +578,503 @@ class RustParserWriter:
             params = ", ".join("a{}".format(i)
                                for i, t in enumerate(arg_types))
 
-            self.write(1, "fn {}(&self{}){} {{",
+            self.write(1, "fn {}(&self, loc: SourceLocation{}){} {{",
                        method_name, args, return_type_tag)
-            self.write(2, "Box::new(concrete::{}::{}({}))",
+            self.write(2, "self.node(loc, concrete::{}::{}({}))",
                        method.return_type.name, method_name_camel, params)
             self.write(1, "}")
+
+        self.error_node(
+            "loc",
+            "Some(StackValue::{0}(self.arena.alloc(concrete::{0}::Error(loc))))")
+        lazy_type = self.lazy_type()
+        if lazy_type is not None:
+            self.write(1, "fn lazy_function_body(&self, lazy: LazyFunction) -> Self::Value {")
+            self.write(2, "StackValue::{0}(self.arena.alloc(concrete::{0}::Lazy(lazy)))",
+                       lazy_type)
+            self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+
+    def error_node(self, loc_param, value_format):
+        """Emit a Handler's error_node method. `value_format` is formatted
+        with the name of each nonterminal type to make the value for it."""
+        self.write(1, "fn error_node(&self, nt: NonterminalId, {}: SourceLocation) -> Option<Self::Value> {{",
+                   loc_param)
+        self.write(2, "match nt {")
+        names = set(self.get_associated_type_names())
+        covered = 0
+        for name, nts in self.nonterminals_by_type():
+            if name not in names:
+                continue
+            covered += len(nts)
+            patterns = ["NonterminalId::" + self.nonterminal_to_camel(nt)
+                        for nt in nts]
+            for pattern in patterns[:-1]:
+                self.write(3, "{} |", pattern)
+            self.write(3, "{} => {{", patterns[-1])
+            self.write(4, value_format.format(name))
+            self.write(3, "}")
+        if covered < len(self.nonterminals):
+            self.write(3, "_ => None,")
+        self.write(2, "}")
+        self.write(1, "}")
+
+    def rust_str_literal(self, s):
+        return '"' + s.replace('\\', '\\\\').replace('"', '\\"') + '"'
+
+    def unparse_templates(self):
+        """Map each method to the production whose layout unparsing it
+        follows: the first one that calls it with every argument present.
+        Methods that only wrap another call, like check_asi, map to None.
+        Methods that no production calls are left out."""
+        templates = {}
+        for _i, prod in self.reduced_prods():
+            action = prod.action
+            if not isinstance(action, CallMethod) or templates.get(action.method) is not None:
+                continue
+            if all(isinstance(arg, int)
+                   or (isinstance(arg, Some) and isinstance(arg.inner, int))
+                   for arg in action.args):
+                templates[action.method] = prod
+            elif len(action.args) == 1 and isinstance(action.args[0], CallMethod):
+                templates[action.method] = None
+        return templates
+
+    def unit_derivations(self):
+        """Map each nonterminal to the nonterminals it can be rewritten to
+        using only productions like `A ::= B` with a bare `$0` action."""
+        direct = collections.defaultdict(set)
+        for _i, prod in self.reduced_prods():
+            elements = [e for e in prod.rhs if is_concrete_element(e)]
+            if isinstance(prod.action, int) and len(elements) == 1:
+                e = elements[0]
+                if isinstance(e, Nt) or e in self.grammar.nonterminals:
+                    direct[prod.nt].add(e)
+        closure = {}
+        for nt in direct:
+            seen = set()
+            todo = list(direct[nt])
+            while todo:
+                other = todo.pop()
+                if other not in seen:
+                    seen.add(other)
+                    todo.extend(direct.get(other, ()))
+            closure[nt] = seen
+        return closure
+
+    def unparse(self):
+        expression_type = self.grammar.nt_types.get(EXPRESSION_NONTERMINAL)
+        expression_name = expression_type.name if isinstance(expression_type, types.NtType) else None
+        templates = self.unparse_templates()
+
+        self.write(0, "/// Takes the tokens of a concrete syntax tree, in source order, from the")
+        self.write(0, "/// `unparse` methods below.")
+        self.write(0, "pub trait Unparser {")
+        self.write(1, "/// A terminal that carries no value, like `(` or `while`.")
+        self.write(1, "fn text(&mut self, text: &'static str);")
+        self.write(1, "/// A terminal that carries a value, like an Identifier or a StringLiteral.")
+        self.write(1, "fn token(&mut self, token: &Token);")
+        if expression_name is not None:
+            self.write(1, "/// An expression where the grammar expects a `slot`, which can't start")
+            self.write(1, "/// with any of the terminals in `not_first`. If it isn't one, or does,")
+            self.write(1, "/// it needs parentheses.")
+            self.write(1, "fn expression(&mut self, expr: &concrete::{}, slot: NonterminalId, not_first: &[&'static str]);",
+                       expression_name)
+        self.write(1, "/// Something with no tokens to give: an Error node, a function body that")
+        self.write(1, "/// a lazy parse skipped, or a node that no production makes.")
+        self.write(1, "fn missing(&mut self, loc: Option<SourceLocation>);")
+        self.write(0, "}")
+        self.write(0, "")
+
+        for name in self.get_associated_type_names():
+            params = self.concrete_type_params(name)
+            self.write(0, "impl{} concrete::{}{} {{", params, name, params)
+            self.write(1, "pub fn unparse<W: Unparser>(&self, w: &mut W) {")
+            self.write(2, "match self {")
+            for tag, method in self.grammar.methods.items():
+                if method.return_type.name != name:
+                    continue
+                variant = self.to_camel_case(self.method_name_to_rust(tag))
+                fields = [ty for ty in method.argument_types if ty != types.UnitType]
+                if tag not in templates:
+                    self.write(3, "Self::{}(..) => w.missing(None),", variant)
+                    continue
+                bindings = ", ".join("x{}".format(i) for i in range(len(fields)))
+                prod = templates[tag]
+                if prod is None:
+                    self.write(3, "Self::{}(x0) => x0.unparse(w),", variant)
+                    continue
+                self.write(3, "Self::{}({}) => {{", variant, bindings)
+                self.unparse_production(prod, method, expression_name)
+                self.write(3, "}")
+            self.write(3, "Self::Error(loc) => w.missing(Some(*loc)),")
+            if name == self.lazy_type():
+                self.write(3, "Self::Lazy(lazy) => w.missing(Some(lazy.loc)),")
+            self.write(2, "}")
+            self.write(1, "}")
+            if name == expression_name:
+                self.write(0, "")
+                self.write(1, "/// The nonterminal of the production that made this node.")
+                self.write(1, "pub fn nonterminal(&self) -> Option<NonterminalId> {")
+                self.write(2, "match self {")
+                for tag, method in self.grammar.methods.items():
+                    if method.return_type.name != name:
+                        continue
+                    variant = self.to_camel_case(self.method_name_to_rust(tag))
+                    if templates.get(tag) is None:
+                        self.write(3, "Self::{}(..) => None,", variant)
+                    else:
+                        self.write(3, "Self::{}(..) => Some(NonterminalId::{}),",
+                                   variant, self.nonterminal_to_camel(templates[tag].nt))
+                self.write(3, "Self::Error(_) => None,")
+                self.write(2, "}")
+                self.write(1, "}")
+            self.write(0, "}")
+            self.write(0, "")
+
+        if expression_name is not None:
+            self.write(0, "/// True if a `slot` can be rewritten to an `nt` using only productions like")
+            self.write(0, "/// `A ::= B`, so that an expression made by a production for `nt` can stand")
+            self.write(0, "/// where the grammar expects a `slot` without parentheses.")
+            self.write(0, "pub fn derives_by_unit_productions(slot: NonterminalId, nt: NonterminalId) -> bool {")
+            self.write(1, "match slot {")
+            expression_nts = set(nts for ty_name, group in self.nonterminals_by_type()
+                                 if ty_name == expression_name for nts in group)
+            derivations = self.unit_derivations()
+            for nt in self.nonterminals:
+                if nt not in expression_nts:
+                    continue
+                derived = [other for other in self.nonterminals
+                           if other in derivations.get(nt, ()) and other in expression_nts]
+                if derived:
+                    self.write(2, "NonterminalId::{} => matches!(", self.nonterminal_to_camel(nt))
+                    self.write(3, "nt,")
+                    self.write(3, "NonterminalId::{}", self.nonterminal_to_camel(nt))
+                    for other in derived:
+                        self.write(4, "| NonterminalId::{}", self.nonterminal_to_camel(other))
+                    self.write(2, "),")
+            self.write(2, "_ => slot == nt,")
+            self.write(1, "}")
+            self.write(0, "}")
+            self.write(0, "")
+
+    def unparse_production(self, prod, method, expression_name):
+        """Emit the statements that unparse a node made by `prod`, whose
+        fields are bound to x0, x1, ..."""
+        # Which argument, and so which field, each concrete element is.
+        arg_of_element = {}
+        field_of_arg = {}
+        for i, arg in enumerate(prod.action.args):
+            arg_of_element[arg if isinstance(arg, int) else arg.inner] = i
+        for i, ty in enumerate(method.argument_types):
+            if ty != types.UnitType:
+                field_of_arg[i] = len(field_of_arg)
+
+        not_first = []
+        index = 0
+        for e in prod.rhs:
+            if isinstance(e, LookaheadRule):
+                if not e.positive:
+                    not_first = [t for t in e.set if isinstance(t, str)]
+                continue
+            if not is_concrete_element(e):
+                continue
+            ty = self.element_type(e)
+            arg = arg_of_element.get(index)
+            index += 1
+            if ty == types.UnitType:
+                self.write(4, "w.text({});", self.rust_str_literal(e))
+                continue
+            if arg is None:
+                raise ValueError("unparsing {!r} would lose {!r}, which its action drops"
+                                 .format(self.grammar.production_to_str(prod.nt, prod.rhs, prod.action), e))
+            value = "x{}".format(field_of_arg[arg])
+            if isinstance(method.argument_types[arg], types.OptionType):
+                self.write(4, "if let Some(x) = {} {{", value)
+                self.write(5, self.unparse_value("x", e, method.argument_types[arg].t, expression_name, not_first))
+                self.write(4, "}")
+            else:
+                self.write(4, self.unparse_value(value, e, ty, expression_name, not_first))
+            not_first = []
+
+    def unparse_value(self, value, e, ty, expression_name, not_first):
+        """Rust statement that passes `value`, the value of element `e`, to
+        the Unparser `w`."""
+        if ty == 'str':
+            return "w.token({});".format(value)
+        if ty.name == expression_name:
+            slot = e.inner if isinstance(e, Optional) else e
+            return "w.expression({}, NonterminalId::{}, &[{}]);".format(
+                value, self.nonterminal_to_camel(slot),
+                ", ".join(self.rust_str_literal(t) for t in not_first))
+        return "{}.unparse(w);".format(value)
+
+    def concrete_variants(self, name):
+        """The variants of concrete::`name` that methods make, as pairs of the
+        variant's name and the types of its fields."""
+        for tag, method in self.grammar.methods.items():
+            if method.return_type.name != name:
+                continue
+            variant = self.to_camel_case(self.method_name_to_rust(tag))
+            yield variant, [ty for ty in method.argument_types if ty != types.UnitType]
+
+    def visitors(self):
+        names = list(self.get_associated_type_names())
+
+        def node_type(name):
+            return "concrete::{}{}".format(name, self.concrete_type_params(name))
+
+        def unwrap(ty):
+            return ty.t if isinstance(ty, types.OptionType) else ty
+
+        def visit_method(ty, suffix):
+            ty = unwrap(ty)
+            if ty == 'str':
+                return "visit_token" + suffix
+            if isinstance(ty, types.NtType):
+                return "visit_{}{}".format(self.to_snek_case(ty.name), suffix)
+            return None
+
+        self.write(0, "/// Walks a concrete syntax tree. Each `visit_*` method's default visits the")
+        self.write(0, "/// node's children in order; override one to act on that kind of node, and")
+        self.write(0, "/// call the `walk_*` function of the same name to go on into its children.")
+        self.write(0, "pub trait Visit<'alloc> {")
+        self.write(1, "fn visit_token(&mut self, _token: &'alloc Token<'alloc>) {}")
+        for name in names:
+            snake = self.to_snek_case(name)
+            self.write(1, "fn visit_{}(&mut self, node: &'alloc {}) {{", snake, node_type(name))
+            self.write(2, "walk_{}(self, node)", snake)
+            self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+        for name in names:
+            snake = self.to_snek_case(name)
+            variants = list(self.concrete_variants(name))
+            has_children = any(visit_method(ty, "") for _variant, fields in variants for ty in fields)
+            self.write(0, "pub fn walk_{}<'alloc, V: Visit<'alloc> + ?Sized>({}: &mut V, node: &'alloc {}) {{",
+                       snake, "v" if has_children else "_v", node_type(name))
+            self.write(1, "match node {")
+            for variant, fields in variants:
+                methods = [visit_method(ty, "") for ty in fields]
+                if not any(methods):
+                    self.write(2, "concrete::{}::{}(..) => {{}}", name, variant)
+                    continue
+                bindings = ", ".join("x{}".format(i) if m else "_" for i, m in enumerate(methods))
+                self.write(2, "concrete::{}::{}({}) => {{", name, variant, bindings)
+                for i, (ty, m) in enumerate(zip(fields, methods)):
+                    if m is None:
+                        continue
+                    value = "x" if isinstance(ty, types.OptionType) else "x{}".format(i)
+                    if unwrap(ty) == 'str':
+                        call = "v.{}({});".format(m, value)
+                    else:
+                        call = "v.{}({}.get());".format(m, value)
+                    if isinstance(ty, types.OptionType):
+                        self.write(3, "if let Some(x) = x{} {{", i)
+                        self.write(4, call)
+                        self.write(3, "}")
+                    else:
+                        self.write(3, call)
+                self.write(2, "}")
+            self.write(2, "concrete::{}::Error(_) => {{}}", name)
+            if name == self.lazy_type():
+                self.write(2, "concrete::{}::Lazy(_) => {{}}", name)
+            self.write(1, "}")
+            self.write(0, "}")
+            self.write(0, "")
+
+        self.write(0, "/// Rewrites a concrete syntax tree. Nodes in the arena can't be changed, so")
+        self.write(0, "/// each `visit_*_mut` method gets the reference to a node and can point it at a")
+        self.write(0, "/// replacement allocated in `arena()`. The defaults walk the node's children,")
+        self.write(0, "/// and if any of them is replaced, replace the node with a copy that has the")
+        self.write(0, "/// new children. Tokens are part of the node that holds them, so changing one")
+        self.write(0, "/// replaces that node too.")
+        self.write(0, "pub trait VisitMut<'alloc> {")
+        self.write(1, "fn arena(&self) -> &'alloc Arena;")
+        self.write(0, "")
+        self.write(1, "fn visit_token_mut(&mut self, _token: &mut Token<'alloc>) {}")
+        for name in names:
+            snake = self.to_snek_case(name)
+            self.write(1, "fn visit_{}_mut(&mut self, node: &mut ArenaRef<'alloc, {}>) {{", snake, node_type(name))
+            self.write(2, "walk_{}_mut(self, node)", snake)
+            self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "/// Visit `node` with `visit`, and return true if it was replaced.")
+        self.write(0, "fn rewrite<'alloc, T>(node: &mut ArenaRef<'alloc, T>, visit: impl FnOnce(&mut ArenaRef<'alloc, T>)) -> bool {")
+        self.write(1, "let old = *node;")
+        self.write(1, "visit(node);")
+        self.write(1, "!ArenaRef::ptr_eq(old, *node)")
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "/// Visit `token` with `visit`, and return true if it was changed.")
+        self.write(0, "fn rewrite_token<'alloc>(token: &mut Token<'alloc>, visit: impl FnOnce(&mut Token<'alloc>)) -> bool {")
+        self.write(1, "let old = token.clone();")
+        self.write(1, "visit(token);")
+        self.write(1, "*token != old")
+        self.write(0, "}")
+        self.write(0, "")
+        for name in names:
+            snake = self.to_snek_case(name)
+            variants = list(self.concrete_variants(name))
+            has_children = any(visit_method(ty, "_mut") for _variant, fields in variants for ty in fields)
+            self.write(0, "pub fn walk_{}_mut<'alloc, V: VisitMut<'alloc> + ?Sized>(", snake)
+            self.write(1, "{}: &mut V,", "v" if has_children else "_v")
+            self.write(1, "{}: &mut ArenaRef<'alloc, {}>,", "node" if has_children else "_node", node_type(name))
+            self.write(0, ") {")
+            if not has_children:
+                self.write(0, "}")
+                self.write(0, "")
+                continue
+            self.write(1, "let mut copy = (**node).clone();")
+            self.write(1, "let mut changed = false;")
+            self.write(1, "match &mut copy {")
+            for variant, fields in variants:
+                methods = [visit_method(ty, "_mut") for ty in fields]
+                if not any(methods):
+                    self.write(2, "concrete::{}::{}(..) => {{}}", name, variant)
+                    continue
+                bindings = ", ".join("x{}".format(i) if m else "_" for i, m in enumerate(methods))
+                self.write(2, "concrete::{}::{}({}) => {{", name, variant, bindings)
+                for i, (ty, m) in enumerate(zip(fields, methods)):
+                    if m is None:
+                        continue
+                    value = "x" if isinstance(ty, types.OptionType) else "x{}".format(i)
+                    helper = "rewrite_token" if unwrap(ty) == 'str' else "rewrite"
+                    call = "changed |= {}({}, |x| v.{}(x));".format(helper, value, m)
+                    if isinstance(ty, types.OptionType):
+                        self.write(3, "if let Some(x) = x{} {{", i)
+                        self.write(4, call)
+                        self.write(3, "}")
+                    else:
+                        self.write(3, call)
+                self.write(2, "}")
+            self.write(2, "concrete::{}::Error(_) => {{}}", name)
+            if name == self.lazy_type():
+                self.write(2, "concrete::{}::Lazy(_) => {{}}", name)
+            self.write(1, "}")
+            self.write(1, "if changed {")
+            self.write(2, "*node = v.arena().alloc(copy);")
+            self.write(1, "}")
+            self.write(0, "}")
+            self.write(0, "")
+
+    def syntax_only_handler(self):
+        self.write(0, "/// Builds nothing. Parsing with this handler only checks for syntax errors.")
+        self.write(0, "#[derive(Clone, Copy, Debug, Default)]")
+        self.write(0, "pub struct SyntaxOnlyHandler;")
+        self.write(0, "")
+        self.write(0, "impl<'src> Handler<'src> for SyntaxOnlyHandler {")
+        self.write(1, "type Value = StackValue<'src, SyntaxOnlyHandler>;")
+        for name in self.get_associated_type_names():
+            self.write(1, "type {} = ();", name)
+
+        for tag, method in self.grammar.methods.items():
+            arg_types = [
+                self.type_to_rust(ty, "Self")
+                for ty in method.argument_types
+                if ty != types.UnitType
+            ]
+            if method.return_type == types.UnitType:
+                return_type_tag = ''
+            else:
+                return_type_tag = ' -> ' + \
+                    self.type_to_rust(method.return_type, "Self")
+            args = "".join(", _a{}: {}".format(i, t)
+                           for i, t in enumerate(arg_types))
+            self.write(1, "fn {}(&self, _loc: SourceLocation{}){} {{}}",
+                       self.method_name_to_rust(tag), args, return_type_tag)
+
+        self.error_node("_loc", "Some(StackValue::{}(()))")
+        lazy_type = self.lazy_type()
+        if lazy_type is not None:
+            self.write(1, "fn lazy_function_body(&self, _lazy: LazyFunction) -> Self::Value {")
+            self.write(2, "StackValue::{}(())", lazy_type)
+            self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+
+    def cst_element(self, ty, arg):
+        """Rust expression for the CstElement holding `arg`, of type `ty`,
+        wrapped in an Option if `ty` is optional. None if `ty` isn't kept in
+        the tree."""
+        if ty == 'str':
+            return "CstElement::Token({})".format(arg)
+        elif isinstance(ty, types.NtType):
+            return "CstElement::Node({})".format(arg)
+        elif isinstance(ty, types.OptionType):
+            if ty.t == 'str':
+                return "{}.map(CstElement::Token)".format(arg)
+            elif isinstance(ty.t, types.NtType):
+                return "{}.map(CstElement::Node)".format(arg)
+        return None
+
+    def cst_handler(self):
+        self.write(0, "/// Builds a concrete syntax tree: a CstNode for each production, named")
+        self.write(0, "/// after the method, with its nodes and value tokens as children. See")
+        self.write(0, "/// cst::complete() for filling in the rest of the source text.")
+        self.write(0, "#[derive(Clone, Copy, Debug, Default)]")
+        self.write(0, "pub struct CstHandler;")
+        self.write(0, "")
+        self.write(0, "impl<'src> Handler<'src> for CstHandler {")
+        self.write(1, "type Value = StackValue<'src, CstHandler>;")
+        for name in self.get_associated_type_names():
+            self.write(1, "type {} = CstNode<'src>;", name)
+
+        for tag, method in self.grammar.methods.items():
+            method_name = self.method_name_to_rust(tag)
+            arg_types = [ty for ty in method.argument_types
+                         if ty != types.UnitType]
+            params = []
+            children = []
+            optional = False
+            for i, ty in enumerate(arg_types):
+                arg = "a{}".format(i)
+                child = self.cst_element(ty, arg)
+                if child is None:
+                    arg = "_" + arg
+                else:
+                    optional = optional or isinstance(ty, types.OptionType)
+                    children.append((child, isinstance(ty, types.OptionType)))
+                params.append(", {}: {}".format(arg, self.type_to_rust(ty, "Self")))
+            if method.return_type == types.UnitType:
+                self.write(1, "fn {}(&self, _loc: SourceLocation{}) {{}}",
+                           method_name, "".join(params))
+                continue
+            if optional:
+                items = ", ".join(c if is_option else "Some({})".format(c)
+                                  for c, is_option in children)
+                value = "vec![{}].into_iter().flatten().collect()".format(items)
+            else:
+                value = "vec![{}]".format(", ".join(c for c, _ in children))
+            self.write(1, "fn {}(&self, loc: SourceLocation{}) -> {} {{",
+                       method_name, "".join(params),
+                       self.type_to_rust(method.return_type, "Self"))
+            self.write(2, "CstNode::new({}, loc, {})",
+                       self.rust_str_literal(method_name), value)
+            self.write(1, "}")
+
+        self.error_node("loc", 'Some(StackValue::{}(CstNode::new("error", loc, vec![])))')
+        lazy_type = self.lazy_type()
+        if lazy_type is not None:
+            self.write(1, "fn lazy_function_body(&self, lazy: LazyFunction) -> Self::Value {")
+            self.write(2, 'StackValue::{}(CstNode::new("lazy_function_body", lazy.loc, vec![]))',
+                       lazy_type)
+            self.write(1, "}")
         self.write(0, "}")
         self.write(0, "")
 
+    def nonterminals_by_type(self):
+        """Group self.nonterminals by the name of their NtType, in order."""
+        groups = collections.OrderedDict()
+        for nt in self.nonterminals:
+            name = nt.name if isinstance(nt, Nt) else nt
+            ty = self.grammar.nt_types.get(name)
+            if isinstance(ty, types.NtType):
+                groups.setdefault(ty.name, []).append(nt)
+        return groups.items()
+
     def nonterminal_id(self):
         self.write(0, "#[derive(Clone, Copy, Debug, PartialEq)]")
         self.write(0, "pub enum NonterminalId {")
@@ -370,14 +1082,20 @@ class RustParserWriter:
             self.write(1, "{} = {},", self.nonterminal_to_camel(nt), i)
         self.write(0, "}")
         self.write(0, "")
+        self.write(0, "/// The name of each nonterminal, by NonterminalId.")
+        self.write(0, "pub static NONTERMINAL_NAMES: [&str; {}] = [", len(self.nonterminals))
+        for nt in self.nonterminals:
+            self.write(1, "{},", rust_str(self.nonterminal_to_camel(nt)))
+        self.write(0, "];")
+        self.write(0, "")
 
     def goto(self):
-        self.write(0, "static GOTO: [usize; {}] = [",
-                   len(self.states) * len(self.nonterminals))
+        self.write(0, "static GOTO: [[usize; {}]; {}] = [",
+                   len(self.nonterminals), len(self.states))
         for state in self.states:
             row = state.ctn_row
-            self.write(1, "{}", ' '.join("{},".format(row.get(nt, 0))
-                                         for nt in self.nonterminals))
+            self.write(1, "[{}],", ' '.join("{},".format(row.get(nt, 0))
+                                            for nt in self.nonterminals))
         self.write(0, "];")
         self.write(0, "")
 
@@ -398,112 +1116,299 @@ class RustParserWriter:
         else:
             assert False, "unexpected element type: {!r}".format(e)
 
-    def reduce(self, generic):
-        if generic:
-            self.write(
-                0,
-                "fn reduce<H: Handler>(handler: &H, prod: usize, "
-                "stack: &mut Vec<*mut ()>) -> NonterminalId {")
+    def reduce_simulator(self):
+        """Emit the (pop count, nonterminal) pair for each production, so the
+        runtime can simulate reductions without calling into the handler."""
+        reduced = list(self.reduced_prods())
+        assert [i for i, _prod in reduced] == list(range(len(reduced)))
+        self.write(0, "static REDUCE_SIMULATOR: [(usize, NonterminalId); {}] = [",
+                   len(reduced))
+        for i, prod in reduced:
+            elements = [e for e in prod.rhs if is_concrete_element(e)]
+            self.write(1, "({}, NonterminalId::{}), // {}. {}",
+                       len(elements),
+                       self.nonterminal_to_camel(prod.nt),
+                       i,
+                       self.grammar.production_to_str(prod.nt, prod.rhs))
+        self.write(0, "];")
+        self.write(0, "")
+        self.write(0, "/// The name of each production in REDUCE_SIMULATOR, like")
+        self.write(0, "/// `Script ::= ScriptBody`, for traces and coverage reports.")
+        self.write(0, "pub static PRODUCTION_NAMES: [&str; {}] = [", len(reduced))
+        for i, prod in reduced:
+            self.write(1, "{},", self.rust_str_literal(
+                self.grammar.production_to_str(prod.nt, prod.rhs)))
+        self.write(0, "];")
+        self.write(0, "")
+
+    def stack_value_variant(self, ty):
+        """Name of the StackValue variant that holds values of type `ty`."""
+        if isinstance(ty, types.NtType):
+            return ty.name
         else:
-            self.write(
-                0,
-                "fn reduce(handler: &DefaultHandler, prod: usize, "
-                "stack: &mut Vec<*mut ()>) -> NonterminalId {")
-        self.write(1, "match prod {")
+            raise TypeError("no StackValue variant for type: {!r}".format(ty))
+
+    def stack_value_accessor(self, ty):
+        """Name of the StackValue method that unwraps a value of type `ty`."""
+        if ty == 'str':
+            return "into_token"
+        return "into_" + self.to_snek_case(self.stack_value_variant(ty))
+
+    def reduced_prods(self):
+        # If prod.nt is not in nonterminals, that means it's a goal
+        # nonterminal, only accepted, never reduced.
         for i, prod in enumerate(self.prods):
-            # If prod.nt is not in nonterminals, that means it's a goal
-            # nonterminal, only accepted, never reduced.
             if prod.nt in self.nonterminals:
-                self.write(2, "{} => {{", i)
-                self.write(3, "// {}",
-                           self.grammar.production_to_str(prod.nt, prod.rhs, prod.action))
-
-                elements = [e for e in prod.rhs if is_concrete_element(e)]
-                variable_used = [False] * len(elements)
-
-                def compile_reduce_expr(expr):
-                    """Compile a reduce expression to Rust"""
-                    if isinstance(expr, CallMethod):
-                        method_type = self.grammar.methods[expr.method]
-                        method_name = self.method_name_to_rust(expr.method)
-                        assert len(method_type.argument_types) == len(expr.args)
-                        args = ', '.join(
-                            compile_reduce_expr(arg)
-                            for ty, arg in zip(method_type.argument_types,
-                                               expr.args)
-                            if ty != types.UnitType)
-                        call = "handler.{}({})".format(method_name, args)
-                        return "{}".format(call)
-                    elif isinstance(expr, Some):
-                        return "Some({})".format(compile_reduce_expr(expr.inner))
-                    elif expr is None:
-                        return "None"
-                    else:
-                        # can't be 'accept' because we filter out InitNt productions
-                        assert isinstance(expr, int)
-                        variable_used[expr] = True
-                        return "x{}".format(expr)
+                yield i, prod
 
-                compiled_expr = compile_reduce_expr(prod.action)
+    def unwrapped_types(self):
+        """Types that reduce() and the entry points pull out of StackValues."""
+        unwrapped = OrderedSet()
+        for _i, prod in self.reduced_prods():
+            elements = [e for e in prod.rhs if is_concrete_element(e)]
 
-                for index, e in reversed(list(enumerate(elements))):
-                    ty = self.element_type(e)
-                    if isinstance(ty, types.NtType):
-                        rust_ty = "*mut concrete::" + ty.name
-                    else:
-                        rust_ty = "*mut " + self.type_to_rust(ty, "Self")
-                    if variable_used[index]:
-                        self.write(
-                            3,
-                            "let x{} = unsafe {{"
-                            " Box::from_raw(stack.pop().unwrap() as {}) }};",
-                            index,
-                            rust_ty)
-                    else:
-                        self.write(3, "stack.pop();", index)
+            def visit_expr(expr):
+                if isinstance(expr, CallMethod):
+                    for arg in expr.args:
+                        visit_expr(arg)
+                elif isinstance(expr, Some):
+                    visit_expr(expr.inner)
+                elif isinstance(expr, int):
+                    ty = self.element_type(elements[expr])
+                    if ty != types.UnitType:
+                        unwrapped.add(ty)
+
+            # A bare `$0` action just leaves the value on the stack.
+            if not isinstance(prod.action, int):
+                visit_expr(prod.action)
+        for init_nt in self.init_state_map:
+            unwrapped.add(self.grammar.nt_types[init_nt.name])
+        return unwrapped
+
+    def stack_value(self):
+        self.write(0, "/// The values on the parser's node stack, for a handler of type `H`.")
+        self.write(0, "#[derive(Clone, Debug)]")
+        self.write(0, "pub enum StackValue<'src, H: Handler<'src>> {")
+        self.write(1, "Token(Token<'src>),")
+        for name in self.get_associated_type_names():
+            self.write(1, "{}(H::{}),", name, name)
+        self.write(0, "}")
+        self.write(0, "")
+
+        self.write(0, "impl<'src, H: Handler<'src>> From<Token<'src>> for StackValue<'src, H> {")
+        self.write(1, "fn from(token: Token<'src>) -> StackValue<'src, H> {")
+        self.write(2, "StackValue::Token(token)")
+        self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
 
-                self.write(3, "stack.push(Box::into_raw({}) as *mut ());",
-                           compiled_expr)
+        self.write(0, "impl<'src, H: Handler<'src>> StackValue<'src, H> {")
+        for ty in self.unwrapped_types():
+            method_name = self.stack_value_accessor(ty)
+            if ty == 'str':
+                self.write(1, "pub fn {}(self) -> Token<'src> {{", method_name)
+                self.write(2, "match self {")
+                self.write(3, "StackValue::Token(token) => token,")
+                self.write(3, '_ => panic!("expected a token on the stack"),')
+            else:
+                variant = self.stack_value_variant(ty)
+                self.write(1, "pub fn {}(self) -> {} {{",
+                           method_name,
+                           self.type_to_rust(ty, "H"))
+                self.write(2, "match self {")
+                self.write(3, "StackValue::{}(v) => v,", variant)
+                self.write(3, '_ => panic!("expected {} on the stack"),', variant)
+            self.write(2, "}")
+            self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+
+    def reduce(self):
+        self.write(
+            0,
+            "pub fn reduce<'src, H: Handler<'src, Value = StackValue<'src, H>>>(handler: &H, prod: usize, "
+            "loc: SourceLocation, stack: &mut ReduceStack<StackValue<'src, H>>) -> NonterminalId {")
+        self.write(1, "match prod {")
+        for i, prod in self.reduced_prods():
+            self.write(2, "{} => {{", i)
+            self.write(3, "// {}",
+                       self.grammar.production_to_str(prod.nt, prod.rhs, prod.action))
+
+            elements = [e for e in prod.rhs if is_concrete_element(e)]
+            variable_used = [False] * len(elements)
+
+            def compile_reduce_expr(expr):
+                """Compile a reduce expression to Rust"""
+                if isinstance(expr, CallMethod):
+                    method_type = self.grammar.methods[expr.method]
+                    method_name = self.method_name_to_rust(expr.method)
+                    assert len(method_type.argument_types) == len(expr.args)
+                    args = ''.join(
+                        ', ' + compile_reduce_expr(arg)
+                        for ty, arg in zip(method_type.argument_types,
+                                           expr.args)
+                        if ty != types.UnitType)
+                    call = "handler.{}(loc{})".format(method_name, args)
+                    return "{}".format(call)
+                elif isinstance(expr, Some):
+                    return "Some({})".format(compile_reduce_expr(expr.inner))
+                elif expr is None:
+                    return "None"
+                else:
+                    # can't be 'accept' because we filter out InitNt productions
+                    assert isinstance(expr, int)
+                    if self.element_type(elements[expr]) == types.UnitType:
+                        # Constant terminals carry no value.
+                        return "()"
+                    variable_used[expr] = True
+                    return "x{}".format(expr)
+
+            if isinstance(prod.action, int):
+                # A bare `$n` action: the popped StackValue is pushed back
+                # as-is, without unwrapping it.
+                passthrough = prod.action
+                variable_used[passthrough] = True
+                compiled_expr = "x{}".format(passthrough)
+            else:
+                passthrough = None
+                method_type = self.grammar.methods[prod.action.method]
+                compiled_expr = "StackValue::{}({})".format(
+                    self.stack_value_variant(method_type.return_type),
+                    compile_reduce_expr(prod.action))
+
+            if passthrough == 0 and len(elements) == 1:
+                # The value is already where it belongs.
                 self.write(3, "NonterminalId::{}",
                            self.nonterminal_to_camel(prod.nt))
                 self.write(2, "}")
+                continue
+
+            for index, e in reversed(list(enumerate(elements))):
+                if index == passthrough:
+                    self.write(3, "let x{} = stack.pop().unwrap();", index)
+                elif variable_used[index]:
+                    self.write(3, "let x{} = stack.pop().unwrap().{}();",
+                               index,
+                               self.stack_value_accessor(self.element_type(e)))
+                else:
+                    self.write(3, "stack.pop();")
+
+            self.write(3, "stack.push({});", compiled_expr)
+            self.write(3, "NonterminalId::{}",
+                       self.nonterminal_to_camel(prod.nt))
+            self.write(2, "}")
         self.write(2, '_ => panic!("no such production: {}", prod),')
         self.write(1, "}")
         self.write(0, "}")
         self.write(0, "")
 
-    def entry(self, generic):
-        self.write(0, "static TABLES: ParserTables<'static> = ParserTables {")
-        self.write(1, "state_count: {},", len(self.states))
-        self.write(1, "action_table: &ACTIONS,")
-        self.write(1, "action_width: {},", len(self.terminals))
-        self.write(1, "goto_table: &GOTO,")
-        self.write(1, "goto_width: {},".format(len(self.nonterminals)))
-        self.write(0, "};")
+    def entry(self):
+        self.write(0, "pub static TABLES: ParserTables<'static> = ParserTables::from_static(")
+        if self.compress_actions:
+            self.write(1, "ActionTable::Displaced {")
+            self.write(2, "base: &ACTION_BASE,")
+            self.write(2, "actions: &ACTIONS,")
+            self.write(2, "check: &ACTION_CHECK,")
+            self.write(1, "},")
+        else:
+            self.write(1, "ActionTable::Dense(ACTIONS.as_flattened()),")
+        self.write(1, "{},", len(self.terminals))
+        self.write(1, "&TERMINAL_NAMES,")
+        self.write(1, "&GOTO,")
+        self.write(1, "&NONTERMINAL_NAMES,")
+        self.write(1, "&REDUCE_SIMULATOR,")
+        self.write(1, "&PRODUCTION_NAMES,")
+        self.write(1, "&ALTERNATIVES,")
+        self.write(1, "{},", sum(state.conflicts_resolved for state in self.states))
+        self.write(0, ");")
+        self.write(0, "")
+
+        self.write(0, "/// The goal symbols the tables can parse.")
+        self.write(0, "#[derive(Clone, Copy, Debug, PartialEq)]")
+        self.write(0, "pub enum EntryPoint {")
+        for init_nt in self.init_state_map:
+            self.write(1, "{},", self.nonterminal_to_camel(init_nt))
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "impl EntryPoint {")
+        self.write(1, "/// The state of TABLES in which parsing this goal begins.")
+        self.write(1, "pub fn state(self) -> usize {")
+        self.write(2, "match self {")
+        for init_nt, index in self.init_state_map.items():
+            self.write(3, "EntryPoint::{} => {},",
+                       self.nonterminal_to_camel(init_nt), index)
+        self.write(2, "}")
+        self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+
+        # Reduce names reduce() as a type of its own, so that a parser built
+        # with it calls reduce() directly: a function pointer would cost an
+        # indirect call, which can't be inlined, for every reduce action.
+        self.write(0, "/// reduce(), as a Reducer that parsers can be built with by name.")
+        self.write(0, "pub struct Reduce<'src, H>(PhantomData<fn(&H) -> &'src ()>);")
+        self.write(0, "")
+        self.write(0, "impl<'src, H> Reduce<'src, H> {")
+        self.write(1, "pub const fn new() -> Self {")
+        self.write(2, "Reduce(PhantomData)")
+        self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "impl<'src, H> Default for Reduce<'src, H> {")
+        self.write(1, "fn default() -> Self {")
+        self.write(2, "Reduce::new()")
+        self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+        self.write(0, "impl<'src, H: Handler<'src, Value = StackValue<'src, H>>> Reducer<'src, H> "
+                   "for Reduce<'src, H> {")
+        self.write(1, "#[inline(always)]")
+        self.write(1, "fn reduce(&self, handler: &H, prod_index: usize, loc: SourceLocation, "
+                   "stack: &mut ReduceStack<StackValue<'src, H>>) -> NonterminalId {")
+        self.write(2, "reduce(handler, prod_index, loc, stack)")
+        self.write(1, "}")
+        self.write(0, "}")
+        self.write(0, "")
+        # The constructors tie TABLES, reduce, and an EntryPoint together, so
+        # callers can't start a parser in a state that doesn't belong to the
+        # goal they asked for.
+        self.write(0, "impl<'a, 'h, H: Handler<'a, Value = StackValue<'a, H>>> Parser<'a, 'h, H, Reduce<'a, H>> {")
+        for i, init_nt in enumerate(self.init_state_map):
+            if i > 0:
+                self.write(0, "")
+            self.write(1, "pub fn new_{}(handler: &'h H) -> Self {{",
+                       self.nonterminal_to_snake(init_nt))
+            new = "Parser::new(&TABLES, Reduce::new(), handler, EntryPoint::{}.state(), false)".format(
+                self.nonterminal_to_camel(init_nt))
+            if init_nt.name in MODULE_GOALS:
+                self.write(2, "let mut parser: Self = {};", new)
+                self.write(2, "parser.set_module();")
+                self.write(2, "parser")
+            else:
+                self.write(2, new)
+            self.write(1, "}")
+        self.write(0, "}")
         self.write(0, "")
 
         for init_nt, index in self.init_state_map.items():
             assert init_nt.args == ()
             result_type_jsparagus = self.grammar.nt_types[init_nt.name]
-            if generic:
-                result_type = self.type_to_rust(result_type_jsparagus, "H")
-                self.write(0, "pub fn parse_{}<H: Handler, In: TokenStream<Token = Token>>(",
-                           init_nt.name)
-                self.write(1, "handler: &H,")
-            else:
-                result_type = self.type_to_rust(
-                    result_type_jsparagus, "concrete", boxed=True)
-                self.write(0, "pub fn parse_{}<In: TokenStream<Token = Token>>(",
-                           init_nt.name)
-                self.write(1, "handler: &DefaultHandler,")
+            result_type = self.type_to_rust(result_type_jsparagus, "H")
+            self.write(0, "pub fn parse_{}<'a, H, In>(", init_nt.name)
+            self.write(1, "handler: &'a H,")
             self.write(1, "tokens: In,")
-            self.write(0, ") -> Result<{}> {{", result_type)
-            self.write(1, "let result = parser_runtime::parse(handler, tokens, {}, &TABLES, reduce)?;",
-                       index)
-            self.write(1, "Ok(unsafe { Box::from_raw(result as *mut _) } )")
+            self.write(0, ") -> Result<{}>", result_type)
+            self.write(0, "where")
+            self.write(1, "H: Handler<'a, Value = StackValue<'a, H>>,")
+            self.write(1, "In: TokenStream<Token = Token<'a>>,")
+            self.write(0, "{")
+            self.write(1, "let result = parser_runtime::parse(handler, tokens, EntryPoint::{}.state(), &TABLES, reduce)?;",
+                       self.nonterminal_to_camel(init_nt))
+            self.write(1, "Ok(result.{}())",
+                       self.stack_value_accessor(result_type_jsparagus))
             self.write(0, "}")
             self.write(0, "")
 
 
-def write_rust_parser(out, parser_states):
-    RustParserWriter(out, parser_states).emit()
+def write_rust_parser(out, parser_states, compress_actions=False):
+    RustParserWriter(out, parser_states, compress_actions).emit()
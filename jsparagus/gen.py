@@ -795,12 +795,14 @@ class PgenContext:
     """ The immutable part of the parser generator's data. """
 
     def __init__(self, grammar, prods, prods_with_indexes_by_nt,
-                 start_set_cache, follow):
+                 start_set_cache, follow, keep_conflicts=False):
         self.grammar = grammar
         self.prods = prods
         self.prods_with_indexes_by_nt = prods_with_indexes_by_nt
         self.start_set_cache = start_set_cache
         self.follow = follow
+        # If true, conflicts go in State.alternatives instead of raising.
+        self.keep_conflicts = keep_conflicts
 
     def make_lr_item(self, *args, **kwargs):
         """Create an LRItem tuple and advance it past any lookahead rules.
@@ -1005,6 +1007,8 @@ class State:
         '_hash',        # int, probably useless
         'action_row',   # output of analysis: {terminal: action}
         'ctn_row',      # output of analysis: {nonterminal: state_id}
+        'conflicts_resolved',  # output of analysis: int, resolved by hand
+        'alternatives',  # output of analysis: {terminal: [action]}, see below
         'id'            # int, small unique id
     ]
 
@@ -1167,6 +1171,7 @@ class State:
         ctn_items = collections.defaultdict(
             OrderedSet)  # maps nonterminals to item-sets
         reduce_prods = {}  # maps follow-terminals to production indexes
+        extra_reduces = collections.defaultdict(list)  # kept conflicts
 
         # Each item has three ways to advance.
         # - We can step over a terminal.
@@ -1217,12 +1222,29 @@ class State:
                 for t in item.followed_by:
                     if t in follow[nt]:
                         if t in reduce_prods:
+                            if item.prod_index == reduce_prods[t]:
+                                continue
+                            if context.keep_conflicts:
+                                if item.prod_index not in extra_reduces[t]:
+                                    extra_reduces[t].append(item.prod_index)
+                                continue
                             context.raise_reduce_reduce_conflict(
                                 self, t, reduce_prods[t], item.prod_index)
                         reduce_prods[t] = item.prod_index
 
         # Step 2. Turn that information into table data to drive the parser.
+        # Where there's a conflict that's resolved rather than reported, the
+        # action that loses goes in `alternatives`, for the runtime's GLR
+        # mode to try as well.
+        def reduce_action(prod_index):
+            # Encode reduce actions as negative numbers.
+            # Negative zero is the same as zero, hence the "- 1".
+            return (ACCEPT if isinstance(prods[prod_index].nt.name, InitNt)
+                    else -prod_index - 1)
+
         action_row = {}
+        alternatives = collections.defaultdict(list)
+        conflicts_resolved = 0
         for t, shift_state in shift_items.items():
             shift_state = State(context, shift_state, self)  # freeze the set
             action_row[t] = get_state_index(shift_state)
@@ -1237,19 +1259,22 @@ class State:
                     # shift-reduce conflict in favor of shifting.
                     assert action_row[t] >= 0, \
                         "the action we already have should be a shift"
-                    continue  # leave it alone
-                else:
+                elif not context.keep_conflicts:
                     context.raise_shift_reduce_conflict(
                         self, t, shift_items[t], prod.nt, prod.rhs)
-            # Encode reduce actions as negative numbers.
-            # Negative zero is the same as zero, hence the "- 1".
-            action_row[t] = (
-                ACCEPT if isinstance(prod.nt.name, InitNt)
-                else -prod_index - 1)
+                conflicts_resolved += 1
+                alternatives[t].append(reduce_action(prod_index))
+                continue  # leave the shift alone
+            action_row[t] = reduce_action(prod_index)
+        for t, prod_indexes in extra_reduces.items():
+            conflicts_resolved += len(prod_indexes)
+            alternatives[t] += [reduce_action(i) for i in prod_indexes]
         ctn_row = {nt: get_state_index(State(context, ss, self))
                    for nt, ss in ctn_items.items()}
         self.action_row = action_row
         self.ctn_row = ctn_row
+        self.conflicts_resolved = conflicts_resolved
+        self.alternatives = dict(alternatives)
 
     def traceback(self):
         """Return example input that could have gotten us here.
@@ -1392,7 +1417,12 @@ class ParserStates:
         return obj
 
 
-def generate_parser_states(grammar, *, verbose=False, progress=False):
+def generate_parser_states(grammar, *, verbose=False, progress=False,
+                           keep_conflicts=False):
+    """Analyze `grammar`. If `keep_conflicts` is true, conflicts are resolved
+    in favor of shifting, or of the first production, and the actions that
+    lose are kept as alternatives for GLR parsing instead of being errors.
+    """
     assert isinstance(grammar, Grammar)
 
     # Step by step, we check the grammar and lower it to a more primitive form.
@@ -1409,14 +1439,17 @@ def generate_parser_states(grammar, *, verbose=False, progress=False):
     start_set_cache = make_start_set_cache(grammar, prods, start)
     follow = follow_sets(grammar, prods_with_indexes_by_nt, start_set_cache)
     context = PgenContext(
-        grammar, prods, prods_with_indexes_by_nt, start_set_cache, follow)
+        grammar, prods, prods_with_indexes_by_nt, start_set_cache, follow,
+        keep_conflicts)
 
     # Run the core LR table generation algorithm.
     return analyze_states(context, prods, verbose=verbose, progress=progress)
 
 
-def generate_parser(out, source, *, verbose=False, progress=False, target='python'):
+def generate_parser(out, source, *, verbose=False, progress=False,
+                    target='python', compress_actions=False):
     assert target in ('python', 'rust')
+    assert target == 'rust' or not compress_actions
 
     if isinstance(source, Grammar):
         parser_states = generate_parser_states(
@@ -1427,7 +1460,7 @@ def generate_parser(out, source, *, verbose=False, progress=False, target='pytho
         raise TypeError("unrecognized source: {!r}".format(source))
 
     if target == 'rust':
-        emit.write_rust_parser(out, parser_states)
+        emit.write_rust_parser(out, parser_states, compress_actions)
     else:
         emit.write_python_parser(out, parser_states)
 
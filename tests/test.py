@@ -189,6 +189,33 @@ class GenTestCase(unittest.TestCase):
         self.assertRaisesRegex(ValueError, r"shift-reduce conflict",
                                lambda: gen.generate_parser(out, grammar))
 
+    def testKeepConflicts(self):
+        # The same grammar, with the conflict kept for GLR parsing: after
+        # `s B`, a C can be shifted as the rest of a `y`, or `s B` can be
+        # reduced to an `s` that the C follows.
+        grammar = Grammar({
+            'goal': [
+                ['s', 'y'],
+            ],
+            's': [
+                ['A'],
+                ['s', 'B'],
+            ],
+            'y': [
+                ['C'],
+                ['B', 'C'],
+            ],
+        })
+        states = gen.generate_parser_states(grammar, keep_conflicts=True)
+        kept = [(state.action_row['C'], state.alternatives['C'])
+                for state in states.states if state.alternatives]
+        self.assertEqual(len(kept), 1)
+        [(action, alternatives)] = kept
+        self.assertEqual(len(alternatives), 1)
+        self.assertGreaterEqual(action, 0)
+        self.assertLess(alternatives[0], 0)
+        self.assertEqual(sum(s.conflicts_resolved for s in states.states), 1)
+
     def testAmbiguousEmpty(self):
         """Reject grammars that are ambiguous due to empty productions.
 
@@ -1048,6 +1075,29 @@ class GenTestCase(unittest.TestCase):
         self.compile(tokenize, grammar)
         self.assertParse("{}", ('goal', '{', ('xlist 0',), '}'))
 
+    def testDisplaceRows(self):
+        from jsparagus.emit import displace_rows
+        rows = [
+            {0: 'a', 1: 'b', 3: 'c'},
+            {},
+            {2: 'd'},
+            {0: 'e', 2: 'f'},
+            {1: 'g', 3: 'h'},
+        ]
+        base, entries, check = displace_rows(rows, 4)
+        self.assertEqual(len(entries), len(check))
+        # Five entries fit in fewer slots than a row each would need.
+        self.assertLess(len(entries), 3 * 4)
+        for i, row in enumerate(rows):
+            for c in range(4):
+                j = base[i] + c
+                if j < len(check) and check[j] == i:
+                    self.assertEqual(entries[j], row[c])
+                else:
+                    self.assertNotIn(c, row)
+        for j, owner in enumerate(check):
+            self.assertEqual(entries[j] is None, owner == len(rows))
+
 
 if __name__ == '__main__':
     unittest.main()